@@ -0,0 +1,71 @@
+use crate::speedtest::SpeedTestResult;
+
+/// A headline metric that can be checked against a threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Download,
+    Upload,
+    Ping,
+}
+
+/// Minimum acceptable speeds and maximum acceptable latency. `None` leaves
+/// a metric unchecked.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Thresholds {
+    pub min_download_mbps: Option<f64>,
+    pub min_upload_mbps: Option<f64>,
+    pub max_ping_ms: Option<f64>,
+}
+
+/// A metric that fell outside its threshold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Violation {
+    pub metric: Metric,
+    pub actual: f64,
+    pub limit: f64,
+}
+
+impl Thresholds {
+    pub fn is_empty(&self) -> bool {
+        self.min_download_mbps.is_none() && self.min_upload_mbps.is_none() && self.max_ping_ms.is_none()
+    }
+
+    /// Returns every metric of `result` that violates a threshold.
+    pub fn check(&self, result: &SpeedTestResult) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if let Some(limit) = self.min_download_mbps {
+            if result.download_mbps < limit {
+                violations.push(Violation {
+                    metric: Metric::Download,
+                    actual: result.download_mbps,
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = self.min_upload_mbps {
+            if result.upload_mbps < limit {
+                violations.push(Violation {
+                    metric: Metric::Upload,
+                    actual: result.upload_mbps,
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = self.max_ping_ms {
+            if result.ping_ms > limit {
+                violations.push(Violation {
+                    metric: Metric::Ping,
+                    actual: result.ping_ms,
+                    limit,
+                });
+            }
+        }
+
+        violations
+    }
+
+    pub fn violates(&self, result: &SpeedTestResult, metric: Metric) -> bool {
+        self.check(result).iter().any(|v| v.metric == metric)
+    }
+}
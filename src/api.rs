@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use ericspeed::history::History;
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+// Shared between the HTTP handlers and the daemon loop
+pub struct ApiState {
+    pub history: History,
+    pub trigger_tx: mpsc::Sender<()>,
+    pub running: AtomicBool,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    limit: Option<usize>,
+}
+
+// Serves the control API until the listener fails
+pub async fn serve(addr: SocketAddr, state: Arc<ApiState>) -> Result<()> {
+    let app = Router::new()
+        .route("/test", post(start_test))
+        .route("/results/latest", get(latest_result))
+        .route("/history", get(history))
+        .with_state(state);
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to listen on {}", addr))?;
+    info!(%addr, "api listening");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn start_test(State(state): State<Arc<ApiState>>) -> Response {
+    if state.running.load(Ordering::SeqCst) {
+        return error(StatusCode::CONFLICT, "a test is already running");
+    }
+    match state.trigger_tx.try_send(()) {
+        Ok(()) => (StatusCode::ACCEPTED, Json(json!({ "status": "started" }))).into_response(),
+        // A trigger is already queued, so a test is about to start anyway
+        Err(_) => error(StatusCode::CONFLICT, "a test is already starting"),
+    }
+}
+
+async fn latest_result(State(state): State<Arc<ApiState>>) -> Response {
+    match state.history.latest() {
+        Ok(Some(entry)) => Json(entry).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "no results yet"),
+        Err(err) => internal_error(err),
+    }
+}
+
+async fn history(State(state): State<Arc<ApiState>>, Query(query): Query<HistoryQuery>) -> Response {
+    match state.history.load() {
+        Ok(mut entries) => {
+            if let Some(limit) = query.limit {
+                entries.drain(..entries.len().saturating_sub(limit));
+            }
+            Json(entries).into_response()
+        }
+        Err(err) => internal_error(err),
+    }
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "error": message }))).into_response()
+}
+
+fn internal_error(err: anyhow::Error) -> Response {
+    warn!(error = %err, "api request failed");
+    error(StatusCode::INTERNAL_SERVER_ERROR, &format!("{:#}", err))
+}
@@ -1,21 +1,77 @@
-use crate::settings::{Settings, SettingsField};
-use crate::speedtest::{
-    download::{DownloadProgress, DownloadTest},
-    ping::{PingProgress, PingTest},
-    upload::{UploadProgress, UploadTest},
-    SpeedTestResult, TestPhase,
+use crate::config;
+use crate::export::{self, SampleRecord};
+use crate::remote::RemoteCommand;
+use crate::spoken::{self, Announcer};
+use crate::ui::i18n::strings;
+use crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers};
+use ericspeed::speedtest::{
+    compare::{CompareMode, CompareUpdate},
+    download::DownloadProgress,
+    server::Server, ping::PingProgress, upload::UploadProgress, SpeedTestResult,
+    TestOrchestrator, TestPhase, TestUpdate,
 };
-use anyhow::Result;
-use crossterm::event::{self, Event, KeyCode, KeyEventKind};
-use std::time::Duration;
+use ericspeed::alerts::Metric;
+use ericspeed::history::{History, HistoryEntry, TimeOfDayStats};
+use ericspeed::network::{self, ActiveNetwork, LocalInterface, NetworkSummary};
+use ericspeed::speedtest::bidirectional::BidirectionalProgress;
+use ericspeed::speedtest::monitor::MonitorStats;
+use ericspeed::speedtest::ping::{JitterMethod, PingMode};
+use ericspeed::samples::{Ewma, SampleBuffer};
+use ericspeed::speedtest::stats::TransferStats;
+use ericspeed::speedtest::tcp_info::TcpStats;
+use ericspeed::settings::{ChartMarker, Profile, MAX_STREAMS};
+use ericspeed::Settings;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AppView {
-    Main,
+    Test,
+    Ping,
+    Traffic,
+    History,
+    Servers,
     Settings,
 }
 
+impl AppView {
+    // Tab order; number keys select by position
+    pub const ALL: &'static [AppView] = &[
+        AppView::Test,
+        AppView::Ping,
+        AppView::Traffic,
+        AppView::History,
+        AppView::Servers,
+        AppView::Settings,
+    ];
+
+    pub fn title(self) -> &'static str {
+        let strings = strings();
+        match self {
+            AppView::Test => strings.tab_test,
+            AppView::Ping => strings.tab_ping,
+            AppView::Traffic => strings.tab_traffic,
+            AppView::History => strings.tab_history,
+            AppView::Servers => strings.tab_servers,
+            AppView::Settings => strings.tab_settings,
+        }
+    }
+
+    pub fn position(self) -> usize {
+        Self::ALL.iter().position(|&view| view == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self.position() + 1) % Self::ALL.len()]
+    }
+
+    pub fn prev(self) -> Self {
+        Self::ALL[(self.position() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Panel {
     Download,
@@ -32,6 +88,14 @@ impl Panel {
         }
     }
 
+    pub fn index(self) -> usize {
+        match self {
+            Panel::Download => 0,
+            Panel::Upload => 1,
+            Panel::Ping => 2,
+        }
+    }
+
     pub fn prev(self) -> Self {
         match self {
             Panel::Download => Panel::Ping,
@@ -41,72 +105,607 @@ impl Panel {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsField {
+    Tag,
+    PingCount,
+    PingMode,
+    DownloadSize,
+    UploadSize,
+    ColdConnections,
+    Bidirectional,
+    EarlyExit,
+    DownloadStreams,
+    RampUp,
+    DownloadChunks,
+    UploadStreams,
+    SourceAddress,
+    RateLimit,
+    JitterMethod,
+    PingOutliers,
+    MinDownload,
+    MinUpload,
+    MaxPing,
+    AlertBell,
+    IdleMonitor,
+    ChartMarker,
+}
+
+impl SettingsField {
+    // Display order of the settings view
+    pub const ALL: &'static [SettingsField] = &[
+        SettingsField::Tag,
+        SettingsField::PingCount,
+        SettingsField::PingMode,
+        SettingsField::DownloadSize,
+        SettingsField::UploadSize,
+        SettingsField::ColdConnections,
+        SettingsField::Bidirectional,
+        SettingsField::EarlyExit,
+        SettingsField::DownloadStreams,
+        SettingsField::RampUp,
+        SettingsField::DownloadChunks,
+        SettingsField::UploadStreams,
+        SettingsField::SourceAddress,
+        SettingsField::RateLimit,
+        SettingsField::JitterMethod,
+        SettingsField::PingOutliers,
+        SettingsField::MinDownload,
+        SettingsField::MinUpload,
+        SettingsField::MaxPing,
+        SettingsField::AlertBell,
+        SettingsField::IdleMonitor,
+        SettingsField::ChartMarker,
+    ];
+
+    pub fn position(self) -> usize {
+        Self::ALL.iter().position(|&field| field == self).unwrap_or(0)
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self.position() + 1) % Self::ALL.len()]
+    }
+
+    pub fn prev(self) -> Self {
+        Self::ALL[(self.position() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowStatus {
+    Pending,
+    Running,
+    Done,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ComparisonRow {
+    pub server: Server,
+    pub status: RowStatus,
+    pub progress: f64,
+    // Latest sample while running, final average once done
+    pub speed_mbps: f64,
+    // Measured before the downloads start
+    pub latency_ms: Option<f64>,
+}
+
+impl ComparisonRow {
+    fn new(server: Server) -> Self {
+        Self {
+            server,
+            status: RowStatus::Pending,
+            progress: 0.0,
+            speed_mbps: 0.0,
+            latency_ms: None,
+        }
+    }
+}
+
+// Bandwidth cap choices in Mbps, stepped through from "off"
+const RATE_LIMIT_STEPS: &[u64] = &[5, 10, 25, 50, 100, 250, 500, 1000];
+
+// Download chunk sizes in MB, stepped through from "off" (one request per stream)
+const CHUNK_SIZE_STEPS: &[u64] = &[1, 2, 5, 10, 25];
+
+// Alert threshold choices, also stepped through from "off"
+const MIN_SPEED_STEPS: &[f64] = &[5.0, 10.0, 25.0, 50.0, 100.0, 200.0, 300.0, 500.0, 1000.0];
+const MAX_PING_STEPS: &[f64] = &[10.0, 20.0, 30.0, 50.0, 75.0, 100.0, 150.0, 200.0];
+
+// Number of recent samples kept for each chart
+const MAX_SPEED_SAMPLES: usize = 200;
+// Weight of each new sample in the smoothed headline speed
+const DISPLAY_SMOOTHING: f64 = 0.3;
+const MAX_PING_SAMPLES: usize = 100;
+// Probes behind the monitor percentiles; an hour at the default interval
+const MAX_MONITOR_RTTS: usize = 3600;
+// How often the traffic tab reads the interface counters
+const TRAFFIC_INTERVAL: Duration = Duration::from_millis(500);
+
+// Smallest number of samples the expanded chart can zoom into
+const MIN_CHART_WINDOW: usize = 10;
+// Announcements kept for the accessible test view
+const MAX_ANNOUNCEMENTS: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChartViewport {
+    // Number of visible samples, None plots the full history
+    pub window: Option<usize>,
+    // How many samples the window is scrolled back from the newest
+    pub offset: usize,
+}
+
+impl ChartViewport {
+    pub fn zoom_in(&mut self, len: usize) {
+        let window = self.window.unwrap_or(len);
+        self.window = Some((window / 2).max(MIN_CHART_WINDOW));
+        self.clamp(len);
+    }
+
+    pub fn zoom_out(&mut self, len: usize) {
+        if let Some(window) = self.window {
+            if window * 2 >= len {
+                *self = Self::default();
+            } else {
+                self.window = Some(window * 2);
+                self.clamp(len);
+            }
+        }
+    }
+
+    pub fn pan_back(&mut self, len: usize) {
+        if let Some(window) = self.window {
+            self.offset += (window / 4).max(1);
+            self.clamp(len);
+        }
+    }
+
+    pub fn pan_forward(&mut self) {
+        if let Some(window) = self.window {
+            self.offset = self.offset.saturating_sub((window / 4).max(1));
+        }
+    }
+
+    // Returns the visible samples along with the index of the first one
+    pub fn visible<'a>(&self, data: &'a [f64]) -> (usize, &'a [f64]) {
+        match self.window {
+            Some(window) if window < data.len() => {
+                let end = data.len() - self.offset.min(data.len() - window);
+                let start = end - window;
+                (start, &data[start..end])
+            }
+            _ => (0, data),
+        }
+    }
+
+    fn clamp(&mut self, len: usize) {
+        if let Some(window) = self.window {
+            self.offset = self.offset.min(len.saturating_sub(window));
+        }
+    }
+}
+
 pub struct App {
-    pub phase: TestPhase,
-    pub result: SpeedTestResult,
+    pub test: TestOrchestrator,
     pub should_quit: bool,
 
     // UI state
     pub view: AppView,
     pub selected_panel: Panel,
     pub expanded: bool,
+    pub chart_viewport: ChartViewport,
+    pub log_scale: [bool; 3],
 
     // Settings
     pub settings: Settings,
     pub selected_setting: SettingsField,
+    // Text being typed into the tag field, while it is being edited
+    pub tag_input: Option<String>,
+    pub interfaces: Vec<LocalInterface>,
+    // Built-in profiles plus the custom one from the config file, if saved
+    pub profiles: Vec<(String, Profile)>,
+    pub config_path: PathBuf,
+    // Where the current or last test's traffic went
+    pub active_network: Option<ActiveNetwork>,
+    // Gateway, resolver and public address found at launch, once known
+    pub network_summary: Option<NetworkSummary>,
+
+    // Server comparison
+    comparison_servers: Vec<Server>,
+    pub comparison: Vec<ComparisonRow>,
+    pub compare_mode: CompareMode,
+    pub comparing: bool,
 
     // Progress tracking
     pub download_progress: f64,
     pub upload_progress: f64,
 
     // Speed samples for charts
-    pub download_samples: Vec<f64>,
-    pub upload_samples: Vec<f64>,
-    pub ping_samples: Vec<f64>,
+    pub download_samples: SampleBuffer,
+    pub upload_samples: SampleBuffer,
+    pub ping_samples: SampleBuffer,
+    // Seconds since the phase started, one per sample above
+    pub download_times: SampleBuffer,
+    pub upload_times: SampleBuffer,
+    pub ping_times: SampleBuffer,
+    phase_started: Instant,
+    // Latency monitor: runs on the ping tab, and between tests when the idle monitor is on
+    pub monitor_samples: SampleBuffer,
+    // Recent answered probes, for percentiles; the counts in the stats cover
+    // the whole session
+    pub monitor_rtts: SampleBuffer,
+    pub monitor_stats: MonitorStats,
+    // The same for the default gateway, probed alongside to split LAN
+    // latency from internet latency
+    pub gateway_samples: SampleBuffer,
+    pub gateway_rtts: SampleBuffer,
+    pub gateway_stats: MonitorStats,
+    // System-wide throughput from the OS counters, for the traffic tab
+    pub traffic_down: SampleBuffer,
+    pub traffic_up: SampleBuffer,
+    pub traffic_interfaces: Vec<String>,
+    // When the counters were last read, and the rx/tx byte totals then
+    traffic_last: Option<(Instant, (u64, u64))>,
+    // Smoothed live speeds for the headline numbers; charts use the raw samples
+    // Kernel TCP statistics of the last transfers, where supported
+    pub download_tcp: Option<TcpStats>,
+    pub upload_tcp: Option<TcpStats>,
+    // Latest speeds of the bidirectional phase while it runs
+    pub bidirectional_progress: Option<BidirectionalProgress>,
+    pub download_display: Ewma,
+    pub upload_display: Ewma,
+
+    // Completed runs are appended here; the latest one is the comparison point
+    pub history: History,
+    // Loaded when the history tab opens, newest first
+    pub history_entries: Vec<HistoryEntry>,
+    pub history_selected: usize,
+    // Shown instead of the results table when toggled on
+    pub time_of_day: Option<TimeOfDayStats>,
+    // Newest saved run, shown dimmed on the idle screen until a test starts
+    last_run: Option<HistoryEntry>,
+    pub previous: Option<SpeedTestResult>,
+    // Pinned reference result; takes precedence over the previous run
+    pub baseline: Option<SpeedTestResult>,
+
+    // Every sample received during the test, timestamped for export
+    pub sample_log: Vec<SampleRecord>,
+    // One-off message shown in place of the help line
+    pub notice: Option<String>,
+    // Screen-reader mode: the test view lists announced status lines instead of charts
+    pub accessible: bool,
+    announcer: Announcer,
+    pub announcements: Vec<String>,
+    // Set when a finished test should ring the terminal bell
+    pub ring_bell: bool,
+
+    // Hidden overlay with raw transfer counters, for troubleshooting
+    pub debug_overlay: bool,
+    // QR code of the finished result, shown over the test view until a key is pressed
+    pub show_qr: bool,
+    pub transfer_stats: Option<Arc<TransferStats>>,
+    pub update_backlog: usize,
 
     cancel_tx: Option<mpsc::Sender<()>>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(
+        settings: Settings,
+        history: History,
+        config_path: PathBuf,
+        custom_profile: Option<Profile>,
+        comparison_servers: Vec<Server>,
+    ) -> Self {
+        let last_run = history.latest().ok().flatten();
+        let previous = last_run.as_ref().map(|entry| entry.result.clone());
+        let baseline = history.baseline().ok().flatten().map(|entry| entry.result);
+
         Self {
-            phase: TestPhase::Idle,
-            result: SpeedTestResult::default(),
+            test: TestOrchestrator::new(),
             should_quit: false,
-            view: AppView::Main,
+            view: AppView::Test,
             selected_panel: Panel::Download,
             expanded: false,
-            settings: Settings::default(),
-            selected_setting: SettingsField::PingCount,
+            chart_viewport: ChartViewport::default(),
+            log_scale: [false; 3],
+            settings,
+            selected_setting: SettingsField::Tag,
+            tag_input: None,
+            interfaces: network::local_interfaces(),
+            profiles: profile_list(custom_profile),
+            config_path,
+            active_network: ActiveNetwork::detect(None),
+            network_summary: None,
+            comparison_servers,
+            comparison: Vec::new(),
+            compare_mode: CompareMode::Concurrent,
+            comparing: false,
             download_progress: 0.0,
             upload_progress: 0.0,
-            download_samples: Vec::new(),
-            upload_samples: Vec::new(),
-            ping_samples: Vec::new(),
+            download_samples: SampleBuffer::new(MAX_SPEED_SAMPLES),
+            upload_samples: SampleBuffer::new(MAX_SPEED_SAMPLES),
+            ping_samples: SampleBuffer::new(MAX_PING_SAMPLES),
+            download_times: SampleBuffer::new(MAX_SPEED_SAMPLES),
+            upload_times: SampleBuffer::new(MAX_SPEED_SAMPLES),
+            ping_times: SampleBuffer::new(MAX_PING_SAMPLES),
+            phase_started: Instant::now(),
+            monitor_samples: SampleBuffer::new(MAX_PING_SAMPLES),
+            monitor_rtts: SampleBuffer::new(MAX_MONITOR_RTTS),
+            monitor_stats: MonitorStats::default(),
+            gateway_samples: SampleBuffer::new(MAX_PING_SAMPLES),
+            gateway_rtts: SampleBuffer::new(MAX_MONITOR_RTTS),
+            gateway_stats: MonitorStats::default(),
+            traffic_down: SampleBuffer::new(MAX_SPEED_SAMPLES),
+            traffic_up: SampleBuffer::new(MAX_SPEED_SAMPLES),
+            traffic_interfaces: Vec::new(),
+            traffic_last: None,
+            download_tcp: None,
+            upload_tcp: None,
+            bidirectional_progress: None,
+            download_display: Ewma::new(DISPLAY_SMOOTHING),
+            upload_display: Ewma::new(DISPLAY_SMOOTHING),
+            history,
+            history_entries: Vec::new(),
+            history_selected: 0,
+            time_of_day: None,
+            last_run,
+            previous,
+            baseline,
+            sample_log: Vec::new(),
+            notice: None,
+            accessible: false,
+            announcer: Announcer::default(),
+            announcements: Vec::new(),
+            ring_bell: false,
+            debug_overlay: false,
+            show_qr: false,
+            transfer_stats: None,
+            update_backlog: 0,
             cancel_tx: None,
         }
     }
 
+    pub fn with_accessible(mut self, accessible: bool) -> Self {
+        self.accessible = accessible;
+        self
+    }
+
     pub fn handle_key_event(&mut self, key: event::KeyEvent) -> Option<AppAction> {
         if key.kind != KeyEventKind::Press {
             return None;
         }
 
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Char('c') => {
+                    self.should_quit = true;
+                    return Some(AppAction::Quit);
+                }
+                KeyCode::Char('z') => return Some(AppAction::Suspend),
+                _ => {}
+            }
+        }
+
+        self.notice = None;
+        if self.show_qr {
+            self.show_qr = false;
+            return None;
+        }
+
+        if self.tag_input.is_none() {
+            match key.code {
+                KeyCode::Char('D') => {
+                    self.debug_overlay = !self.debug_overlay;
+                    return None;
+                }
+                KeyCode::Char(c @ '1'..='9') => {
+                    if let Some(&view) = AppView::ALL.get(c as usize - '1' as usize) {
+                        self.switch_view(view);
+                    }
+                    return None;
+                }
+                KeyCode::Char(']') => {
+                    self.switch_view(self.view.next());
+                    return None;
+                }
+                KeyCode::Char('[') => {
+                    self.switch_view(self.view.prev());
+                    return None;
+                }
+                _ => {}
+            }
+        }
+
         match self.view {
-            AppView::Main => self.handle_main_key(key),
+            AppView::Test => self.handle_main_key(key),
+            AppView::Ping => self.handle_ping_key(key),
+            AppView::Traffic => self.handle_traffic_key(key),
+            AppView::History => self.handle_history_key(key),
+            AppView::Servers => self.handle_compare_key(key),
             AppView::Settings => self.handle_settings_key(key),
         }
     }
 
+    pub fn switch_view(&mut self, view: AppView) {
+        if view == self.view {
+            return;
+        }
+        match view {
+            // Settings, comparisons and pings would race the running test
+            AppView::Ping | AppView::Servers | AppView::Settings if self.test.is_running() => {
+                self.notice = Some(strings().notice_busy.to_string());
+                return;
+            }
+            AppView::Servers if !self.comparing => self.reset_comparison(),
+            AppView::History => self.load_history(),
+            _ => {}
+        }
+        self.view = view;
+    }
+
+    fn load_history(&mut self) {
+        match self.history.load() {
+            Ok(mut entries) => {
+                entries.reverse();
+                self.history_entries = entries;
+            }
+            Err(err) => {
+                self.history_entries.clear();
+                self.notice = Some(format!("{}: {:#}", strings().notice_history_load_failed, err));
+            }
+        }
+        self.history_selected = 0;
+        self.time_of_day = None;
+    }
+
+    fn handle_ping_key(&mut self, key: event::KeyEvent) -> Option<AppAction> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.view = AppView::Test;
+                None
+            }
+            KeyCode::Char('r') => {
+                self.clear_monitor();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_traffic_key(&mut self, key: event::KeyEvent) -> Option<AppAction> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.view = AppView::Test;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_history_key(&mut self, key: event::KeyEvent) -> Option<AppAction> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.view = AppView::Test;
+                None
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.history_selected = self.history_selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                if self.history_selected + 1 < self.history_entries.len() {
+                    self.history_selected += 1;
+                }
+                None
+            }
+            KeyCode::Char('t') => {
+                self.time_of_day = match self.time_of_day {
+                    Some(_) => None,
+                    None => Some(TimeOfDayStats::from_entries(&self.history_entries)),
+                };
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // Acts on a command from the control socket and returns the reply line
+    pub fn handle_remote(&mut self, command: RemoteCommand) -> (Option<AppAction>, String) {
+        match command {
+            RemoteCommand::Start if self.test.is_running() => {
+                (None, "error: a test is already running".to_string())
+            }
+            RemoteCommand::Start if self.comparing => {
+                (None, "error: a server comparison is running".to_string())
+            }
+            RemoteCommand::Start => {
+                self.view = AppView::Test;
+                self.expanded = false;
+                (Some(AppAction::StartTest), "ok: test started".to_string())
+            }
+            RemoteCommand::Cancel if self.test.is_running() => {
+                (Some(AppAction::CancelTest), "ok: test cancelled".to_string())
+            }
+            RemoteCommand::Cancel => (None, "error: no test running".to_string()),
+            RemoteCommand::Status => (None, self.status_line()),
+        }
+    }
+
+    fn status_line(&self) -> String {
+        let result = self.result();
+        match self.phase() {
+            TestPhase::Idle => "idle".to_string(),
+            TestPhase::Ping => format!("ping {} samples", self.ping_samples.len()),
+            TestPhase::Download => format!(
+                "download {:.0}% {:.1} Mbps",
+                self.download_progress * 100.0,
+                self.download_display.value().unwrap_or(0.0)
+            ),
+            TestPhase::Upload => format!(
+                "upload {:.0}% {:.1} Mbps",
+                self.upload_progress * 100.0,
+                self.upload_display.value().unwrap_or(0.0)
+            ),
+            TestPhase::Bidirectional => {
+                let progress = self.bidirectional_progress.unwrap_or_default();
+                format!(
+                    "bidirectional {:.0}% down {:.1} Mbps up {:.1} Mbps",
+                    progress.fraction * 100.0,
+                    progress.download_mbps,
+                    progress.upload_mbps
+                )
+            }
+            TestPhase::Complete => format!(
+                "complete down {:.1} Mbps up {:.1} Mbps ping {:.0} ms jitter {:.1} ms",
+                result.download_mbps, result.upload_mbps, result.ping_ms, result.jitter_ms
+            ),
+        }
+    }
+
     fn handle_main_key(&mut self, key: event::KeyEvent) -> Option<AppAction> {
+        if self.expanded && self.handle_chart_key(key) {
+            return None;
+        }
+
         match key.code {
             KeyCode::Char('q') => {
                 self.should_quit = true;
                 Some(AppAction::Quit)
             }
             KeyCode::Char('s') => {
-                if self.phase == TestPhase::Idle || self.phase == TestPhase::Complete {
-                    self.view = AppView::Settings;
+                self.switch_view(AppView::Settings);
+                None
+            }
+            KeyCode::Char('c') => {
+                self.switch_view(AppView::Servers);
+                None
+            }
+            KeyCode::Char('p') => {
+                if !self.test.is_running() {
+                    self.cycle_profile();
+                }
+                None
+            }
+            KeyCode::Char('e') => {
+                if self.phase() == TestPhase::Complete {
+                    self.export_samples();
+                }
+                None
+            }
+            KeyCode::Char('r') => {
+                self.show_qr = self.phase() == TestPhase::Complete;
+                None
+            }
+            KeyCode::Char('b') => {
+                if self.phase() == TestPhase::Complete {
+                    self.save_baseline();
+                }
+                None
+            }
+            KeyCode::Char('B') => {
+                if self.baseline.is_some() && !self.test.is_running() {
+                    self.clear_baseline();
                 }
                 None
             }
@@ -114,11 +713,11 @@ impl App {
                 if self.expanded {
                     self.expanded = false;
                     None
-                } else if self.phase == TestPhase::Idle || self.phase == TestPhase::Complete {
+                } else if !self.test.is_running() {
                     Some(AppAction::StartTest)
                 } else {
                     // Expand current panel during test
-                    self.expanded = true;
+                    self.expand();
                     None
                 }
             }
@@ -126,7 +725,7 @@ impl App {
                 if self.expanded {
                     self.expanded = false;
                     None
-                } else if self.phase != TestPhase::Idle && self.phase != TestPhase::Complete {
+                } else if self.test.is_running() {
                     Some(AppAction::CancelTest)
                 } else {
                     None
@@ -145,17 +744,57 @@ impl App {
                 None
             }
             KeyCode::Char(' ') => {
-                self.expanded = !self.expanded;
+                if self.expanded {
+                    self.expanded = false;
+                } else {
+                    self.expand();
+                }
                 None
             }
             _ => None,
         }
     }
 
+    // Zoom and pan keys for the expanded chart, returns true if the key was consumed
+    fn handle_chart_key(&mut self, key: event::KeyEvent) -> bool {
+        let len = self.selected_samples().len();
+        match key.code {
+            KeyCode::Char('+') | KeyCode::Char('=') => self.chart_viewport.zoom_in(len),
+            KeyCode::Char('-') => self.chart_viewport.zoom_out(len),
+            KeyCode::Left | KeyCode::Char('h') => self.chart_viewport.pan_back(len),
+            KeyCode::Right | KeyCode::Char('l') => self.chart_viewport.pan_forward(),
+            KeyCode::Char('0') => self.chart_viewport = ChartViewport::default(),
+            KeyCode::Char('L') => {
+                let index = self.selected_panel.index();
+                self.log_scale[index] = !self.log_scale[index];
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn expand(&mut self) {
+        self.expanded = true;
+        self.chart_viewport = ChartViewport::default();
+    }
+
+    pub fn selected_samples(&self) -> &[f64] {
+        match self.selected_panel {
+            Panel::Download => &self.download_samples,
+            Panel::Upload => &self.upload_samples,
+            Panel::Ping => &self.ping_samples,
+        }
+    }
+
     fn handle_settings_key(&mut self, key: event::KeyEvent) -> Option<AppAction> {
+        if self.tag_input.is_some() {
+            self.handle_tag_input(key);
+            return None;
+        }
+
         match key.code {
             KeyCode::Char('q') | KeyCode::Esc => {
-                self.view = AppView::Main;
+                self.view = AppView::Test;
                 None
             }
             KeyCode::Up | KeyCode::Char('k') => {
@@ -174,8 +813,105 @@ impl App {
                 self.increase_setting();
                 None
             }
+            KeyCode::Char('p') => {
+                self.cycle_profile();
+                None
+            }
+            KeyCode::Char('P') => {
+                self.save_custom_profile();
+                None
+            }
+            KeyCode::Enter if self.selected_setting == SettingsField::Tag => {
+                self.tag_input = Some(self.settings.tag.clone().unwrap_or_default());
+                None
+            }
+            KeyCode::Enter => {
+                self.view = AppView::Test;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // Name of the profile the current settings match, if any
+    pub fn profile_name(&self) -> Option<&str> {
+        let current = Profile::of(&self.settings);
+        self.profiles
+            .iter()
+            .find(|(_, profile)| *profile == current)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn cycle_profile(&mut self) {
+        let current = Profile::of(&self.settings);
+        let next = self
+            .profiles
+            .iter()
+            .position(|(_, profile)| *profile == current)
+            .map_or(0, |i| (i + 1) % self.profiles.len());
+        let (name, profile) = &self.profiles[next];
+        profile.apply(&mut self.settings);
+        self.notice = Some(format!("{}: {}", strings().notice_profile, name));
+    }
+
+    fn save_custom_profile(&mut self) {
+        let profile = Profile::of(&self.settings);
+        match config::save_profile(&self.config_path, &profile) {
+            Ok(()) => {
+                self.profiles = profile_list(Some(profile));
+                let path = self.config_path.display();
+                self.notice = Some(format!("{} {}", strings().notice_profile_saved, path));
+            }
+            Err(err) => {
+                self.notice = Some(format!("{}: {:#}", strings().notice_profile_failed, err));
+            }
+        }
+    }
+
+    fn handle_tag_input(&mut self, key: event::KeyEvent) {
+        let Some(input) = self.tag_input.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let tag = input.trim().to_string();
+                self.settings.tag = (!tag.is_empty()).then_some(tag);
+                self.tag_input = None;
+            }
+            KeyCode::Esc => self.tag_input = None,
+            _ => {}
+        }
+    }
+
+    fn handle_compare_key(&mut self, key: event::KeyEvent) -> Option<AppAction> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if self.comparing {
+                    Some(AppAction::CancelComparison)
+                } else {
+                    self.view = AppView::Test;
+                    None
+                }
+            }
             KeyCode::Enter => {
-                self.view = AppView::Main;
+                if self.comparing {
+                    None
+                } else if self.settings.demo {
+                    // Comparisons measure real servers
+                    self.notice = Some(strings().notice_demo_compare.to_string());
+                    None
+                } else {
+                    Some(AppAction::StartComparison)
+                }
+            }
+            KeyCode::Char('m') => {
+                if !self.comparing {
+                    self.compare_mode = self.compare_mode.toggle();
+                }
                 None
             }
             _ => None,
@@ -184,65 +920,515 @@ impl App {
 
     fn increase_setting(&mut self) {
         match self.selected_setting {
+            SettingsField::Tag => {}
             SettingsField::PingCount => {
                 self.settings.ping_count = (self.settings.ping_count + 5).min(100);
             }
+            SettingsField::PingMode => {
+                self.settings.ping_mode = match self.settings.ping_mode {
+                    PingMode::Warm => PingMode::Cold,
+                    PingMode::Cold => PingMode::Tcp,
+                    PingMode::Tcp => PingMode::Warm,
+                };
+            }
             SettingsField::DownloadSize => {
                 self.settings.download_size_mb = (self.settings.download_size_mb + 25).min(500);
             }
             SettingsField::UploadSize => {
-                self.settings.upload_size_mb = (self.settings.upload_size_mb + 25).min(250);
+                self.settings.upload_size_mb = (self.settings.upload_size_mb + 25).min(500);
+            }
+            SettingsField::ColdConnections => {
+                self.settings.cold_connections = !self.settings.cold_connections;
+            }
+            SettingsField::Bidirectional => {
+                self.settings.bidirectional = !self.settings.bidirectional;
+            }
+            SettingsField::EarlyExit => {
+                self.settings.early_exit = !self.settings.early_exit;
+            }
+            SettingsField::DownloadStreams => {
+                self.settings.download_streams = (self.settings.download_streams + 1).min(MAX_STREAMS);
+            }
+            SettingsField::RampUp => {
+                self.settings.ramp_up = !self.settings.ramp_up;
+            }
+            SettingsField::DownloadChunks => {
+                self.settings.download_chunk_mb = step_up(self.settings.download_chunk_mb, CHUNK_SIZE_STEPS);
+            }
+            SettingsField::UploadStreams => {
+                self.settings.upload_streams = (self.settings.upload_streams + 1).min(MAX_STREAMS);
+            }
+            SettingsField::SourceAddress => self.cycle_local_address(1),
+            SettingsField::RateLimit => {
+                self.settings.rate_limit_mbps = step_up(self.settings.rate_limit_mbps, RATE_LIMIT_STEPS);
+            }
+            SettingsField::JitterMethod => {
+                self.settings.jitter_method = match self.settings.jitter_method {
+                    JitterMethod::StdDev => JitterMethod::Ipdv,
+                    JitterMethod::Ipdv => JitterMethod::StdDev,
+                };
+            }
+            SettingsField::PingOutliers => {
+                self.settings.filter_ping_outliers = !self.settings.filter_ping_outliers;
+            }
+            SettingsField::MinDownload => {
+                let thresholds = &mut self.settings.thresholds;
+                thresholds.min_download_mbps = step_up(thresholds.min_download_mbps, MIN_SPEED_STEPS);
+            }
+            SettingsField::MinUpload => {
+                let thresholds = &mut self.settings.thresholds;
+                thresholds.min_upload_mbps = step_up(thresholds.min_upload_mbps, MIN_SPEED_STEPS);
+            }
+            SettingsField::MaxPing => {
+                let thresholds = &mut self.settings.thresholds;
+                thresholds.max_ping_ms = step_up(thresholds.max_ping_ms, MAX_PING_STEPS);
+            }
+            SettingsField::AlertBell => {
+                self.settings.alert_bell = !self.settings.alert_bell;
+            }
+            SettingsField::IdleMonitor => {
+                self.settings.idle_monitor = !self.settings.idle_monitor;
+            }
+            SettingsField::ChartMarker => {
+                self.settings.chart_marker = match self.settings.chart_marker {
+                    ChartMarker::Braille => ChartMarker::Block,
+                    ChartMarker::Block => ChartMarker::Dot,
+                    ChartMarker::Dot => ChartMarker::Braille,
+                };
             }
         }
     }
 
     fn decrease_setting(&mut self) {
         match self.selected_setting {
+            SettingsField::Tag => {}
             SettingsField::PingCount => {
                 self.settings.ping_count = self.settings.ping_count.saturating_sub(5).max(5);
             }
+            SettingsField::PingMode => {
+                self.settings.ping_mode = match self.settings.ping_mode {
+                    PingMode::Warm => PingMode::Tcp,
+                    PingMode::Cold => PingMode::Warm,
+                    PingMode::Tcp => PingMode::Cold,
+                };
+            }
             SettingsField::DownloadSize => {
                 self.settings.download_size_mb = self.settings.download_size_mb.saturating_sub(25).max(25);
             }
             SettingsField::UploadSize => {
                 self.settings.upload_size_mb = self.settings.upload_size_mb.saturating_sub(25).max(25);
             }
+            SettingsField::ColdConnections => {
+                self.settings.cold_connections = !self.settings.cold_connections;
+            }
+            SettingsField::Bidirectional => {
+                self.settings.bidirectional = !self.settings.bidirectional;
+            }
+            SettingsField::EarlyExit => {
+                self.settings.early_exit = !self.settings.early_exit;
+            }
+            SettingsField::DownloadStreams => {
+                self.settings.download_streams = self.settings.download_streams.saturating_sub(1).max(1);
+            }
+            SettingsField::RampUp => {
+                self.settings.ramp_up = !self.settings.ramp_up;
+            }
+            SettingsField::DownloadChunks => {
+                self.settings.download_chunk_mb = step_down(self.settings.download_chunk_mb, CHUNK_SIZE_STEPS);
+            }
+            SettingsField::UploadStreams => {
+                self.settings.upload_streams = self.settings.upload_streams.saturating_sub(1).max(1);
+            }
+            SettingsField::SourceAddress => self.cycle_local_address(-1),
+            SettingsField::RateLimit => {
+                self.settings.rate_limit_mbps = step_down(self.settings.rate_limit_mbps, RATE_LIMIT_STEPS);
+            }
+            SettingsField::JitterMethod => {
+                self.settings.jitter_method = match self.settings.jitter_method {
+                    JitterMethod::StdDev => JitterMethod::Ipdv,
+                    JitterMethod::Ipdv => JitterMethod::StdDev,
+                };
+            }
+            SettingsField::PingOutliers => {
+                self.settings.filter_ping_outliers = !self.settings.filter_ping_outliers;
+            }
+            SettingsField::MinDownload => {
+                let thresholds = &mut self.settings.thresholds;
+                thresholds.min_download_mbps = step_down(thresholds.min_download_mbps, MIN_SPEED_STEPS);
+            }
+            SettingsField::MinUpload => {
+                let thresholds = &mut self.settings.thresholds;
+                thresholds.min_upload_mbps = step_down(thresholds.min_upload_mbps, MIN_SPEED_STEPS);
+            }
+            SettingsField::MaxPing => {
+                let thresholds = &mut self.settings.thresholds;
+                thresholds.max_ping_ms = step_down(thresholds.max_ping_ms, MAX_PING_STEPS);
+            }
+            SettingsField::AlertBell => {
+                self.settings.alert_bell = !self.settings.alert_bell;
+            }
+            SettingsField::IdleMonitor => {
+                self.settings.idle_monitor = !self.settings.idle_monitor;
+            }
+            SettingsField::ChartMarker => {
+                self.settings.chart_marker = match self.settings.chart_marker {
+                    ChartMarker::Braille => ChartMarker::Dot,
+                    ChartMarker::Block => ChartMarker::Braille,
+                    ChartMarker::Dot => ChartMarker::Block,
+                };
+            }
+        }
+    }
+
+    // Steps through "auto" followed by each local interface address
+    fn cycle_local_address(&mut self, step: isize) {
+        let options: Vec<_> = std::iter::once(None)
+            .chain(self.interfaces.iter().map(|iface| Some(iface.addr)))
+            .collect();
+        let current = options
+            .iter()
+            .position(|&addr| addr == self.settings.local_address)
+            .unwrap_or(0);
+        let next = (current as isize + step).rem_euclid(options.len() as isize) as usize;
+        self.settings.local_address = options[next];
+    }
+
+    pub fn reset_comparison(&mut self) {
+        self.comparison = self.comparison_servers.iter().cloned().map(ComparisonRow::new).collect();
+    }
+
+    pub fn start_comparison(&mut self) {
+        self.reset_comparison();
+        self.comparing = true;
+    }
+
+    pub fn cancel_comparison(&mut self) {
+        self.comparing = false;
+        for row in &mut self.comparison {
+            if matches!(row.status, RowStatus::Pending | RowStatus::Running) {
+                row.status = RowStatus::Failed("cancelled".to_string());
+            }
+        }
+    }
+
+    pub fn apply_compare_update(&mut self, update: CompareUpdate) {
+        match update {
+            CompareUpdate::Latency { index, latency_ms } => {
+                if let Some(row) = self.comparison.get_mut(index) {
+                    row.latency_ms = Some(latency_ms);
+                }
+            }
+            CompareUpdate::Progress { index, progress } => {
+                if let Some(row) = self.comparison.get_mut(index) {
+                    row.status = RowStatus::Running;
+                    row.progress = progress.downloaded_bytes as f64 / progress.total_bytes as f64;
+                    row.speed_mbps = progress.speed_mbps;
+                }
+            }
+            CompareUpdate::Complete { index, speed_mbps } => {
+                if let Some(row) = self.comparison.get_mut(index) {
+                    row.status = RowStatus::Done;
+                    row.progress = 1.0;
+                    row.speed_mbps = speed_mbps;
+                }
+            }
+            CompareUpdate::Failed { index, error } => {
+                if let Some(row) = self.comparison.get_mut(index) {
+                    row.status = RowStatus::Failed(error);
+                }
+            }
+        }
+    }
+
+    pub fn finish_comparison(&mut self) {
+        self.comparing = false;
+    }
+
+    pub fn export_samples(&mut self) {
+        let path = export::default_csv_path();
+        let tag = self.settings.tag.as_deref();
+        self.notice = Some(match export::write_csv(&path, &self.sample_log, tag) {
+            Ok(()) => format!("{} {}", strings().notice_samples_saved, path.display()),
+            Err(err) => format!("{}: {}", strings().notice_export_failed, err),
+        });
+    }
+
+    pub fn phase(&self) -> TestPhase {
+        self.test.phase()
+    }
+
+    pub fn result(&self) -> &SpeedTestResult {
+        self.test.result()
+    }
+
+    pub fn start_test(&mut self) {
+        if self.phase() == TestPhase::Complete {
+            self.previous = Some(self.result().clone());
+        }
+        self.last_run = None;
+        self.reset_for_new_test();
+        self.active_network = ActiveNetwork::detect(self.settings.local_address);
+        self.test.set_bidirectional(self.settings.bidirectional);
+        if let Err(err) = self.test.start() {
+            self.notice = Some(err.to_string());
         }
     }
 
     pub fn reset_for_new_test(&mut self) {
-        self.phase = TestPhase::Idle;
-        self.result = SpeedTestResult::default();
         self.download_progress = 0.0;
         self.upload_progress = 0.0;
         self.download_samples.clear();
         self.upload_samples.clear();
         self.ping_samples.clear();
+        self.download_times.clear();
+        self.upload_times.clear();
+        self.ping_times.clear();
+        self.phase_started = Instant::now();
+        self.download_display.clear();
+        self.upload_display.clear();
+        self.sample_log.clear();
+        self.download_tcp = None;
+        self.upload_tcp = None;
+        self.bidirectional_progress = None;
+        self.notice = None;
+        self.announcer = Announcer::default();
+        self.announcements.clear();
         self.expanded = false;
+        self.chart_viewport = ChartViewport::default();
     }
 
     pub fn update_ping_progress(&mut self, progress: PingProgress) {
         if let Some(ping) = progress.latest_ping {
             self.ping_samples.push(ping);
-            // Keep last 100 samples
-            if self.ping_samples.len() > 100 {
-                self.ping_samples.remove(0);
-            }
+            self.ping_times.push(self.phase_elapsed());
+            self.sample_log.push(SampleRecord::now(TestPhase::Ping, ping));
         }
     }
 
     pub fn update_download_progress(&mut self, progress: DownloadProgress) {
         self.download_progress = progress.downloaded_bytes as f64 / progress.total_bytes as f64;
-        self.download_samples = progress.speed_samples;
+        self.sample_log.push(SampleRecord::now(TestPhase::Download, progress.speed_mbps));
+        self.download_samples.push(progress.speed_mbps);
+        self.download_times.push(self.phase_elapsed());
+        self.download_display.push(progress.speed_mbps);
     }
 
     pub fn update_upload_progress(&mut self, progress: UploadProgress) {
         self.upload_progress = progress.uploaded_bytes as f64 / progress.total_bytes as f64;
-        self.upload_samples = progress.speed_samples;
+        self.sample_log.push(SampleRecord::now(TestPhase::Upload, progress.speed_mbps));
+        self.upload_samples.push(progress.speed_mbps);
+        self.upload_times.push(self.phase_elapsed());
+        self.upload_display.push(progress.speed_mbps);
+    }
+
+    // Whether the running or last test has the bidirectional phase
+    pub fn test_runs_bidirectional(&self) -> bool {
+        match self.phase() {
+            TestPhase::Idle => self.settings.bidirectional,
+            TestPhase::Complete => self.result().bidirectional.is_some(),
+            _ => self.test.bidirectional(),
+        }
+    }
+
+    // Whether the latency monitor should be running right now; never during
+    // a test or comparison, which need the link to themselves, nor in demo mode
+    pub fn wants_monitor(&self) -> bool {
+        (self.settings.idle_monitor || self.view == AppView::Ping)
+            && !self.test.is_running()
+            && !self.comparing
+            && !self.settings.demo
+    }
+
+    pub fn push_monitor_ping(&mut self, rtt_ms: Option<f64>) {
+        if let Some(rtt) = rtt_ms {
+            self.monitor_samples.push(rtt);
+            self.monitor_rtts.push(rtt);
+        }
+        update_monitor_stats(&mut self.monitor_stats, &self.monitor_rtts, rtt_ms.is_none());
+    }
+
+    pub fn push_gateway_ping(&mut self, rtt_ms: Option<f64>) {
+        if let Some(rtt) = rtt_ms {
+            self.gateway_samples.push(rtt);
+            self.gateway_rtts.push(rtt);
+        }
+        update_monitor_stats(&mut self.gateway_stats, &self.gateway_rtts, rtt_ms.is_none());
+    }
+
+    pub fn clear_monitor(&mut self) {
+        self.monitor_samples.clear();
+        self.monitor_rtts.clear();
+        self.monitor_stats = MonitorStats::default();
+        self.gateway_samples.clear();
+        self.gateway_rtts.clear();
+        self.gateway_stats = MonitorStats::default();
+    }
+
+    // Samples the interface counters while the traffic tab is open. Called
+    // every frame; only reads them once per TRAFFIC_INTERVAL, returning
+    // whether it did.
+    pub fn poll_traffic(&mut self) -> bool {
+        if self.view != AppView::Traffic {
+            // Resume from a fresh reading rather than averaging over the gap
+            self.traffic_last = None;
+            return false;
+        }
+        if self
+            .traffic_last
+            .as_ref()
+            .is_some_and(|(at, _)| at.elapsed() < TRAFFIC_INTERVAL)
+        {
+            return false;
+        }
+
+        let counters = network::interface_counters();
+        self.traffic_interfaces = counters.iter().map(|iface| iface.name.clone()).collect();
+        let rx = counters.iter().map(|iface| iface.rx_bytes).sum::<u64>();
+        let tx = counters.iter().map(|iface| iface.tx_bytes).sum::<u64>();
+        let now = Instant::now();
+        if let Some((at, (last_rx, last_tx))) = self.traffic_last {
+            let seconds = now.duration_since(at).as_secs_f64();
+            // Counters reset when an interface goes away; treat that as idle
+            let mbps = |bytes: u64| bytes as f64 * 8.0 / seconds / 1_000_000.0;
+            self.traffic_down.push(mbps(rx.saturating_sub(last_rx)));
+            self.traffic_up.push(mbps(tx.saturating_sub(last_tx)));
+        }
+        self.traffic_last = Some((now, (rx, tx)));
+        true
     }
 
-    pub fn complete_test(&mut self) {
-        self.phase = TestPhase::Complete;
+    fn phase_elapsed(&self) -> f64 {
+        self.phase_started.elapsed().as_secs_f64()
+    }
+
+    pub fn apply_update(&mut self, update: TestUpdate) {
+        let phase = self.phase();
+        if let Err(err) = self.test.apply(&update) {
+            self.notice = Some(err.to_string());
+            return;
+        }
+        if self.phase() != phase {
+            self.phase_started = Instant::now();
+        }
+        if self.accessible {
+            if let Some(sentence) = self.announcer.announce(&update) {
+                self.announce(sentence);
+            }
+        }
+
+        match update {
+            TestUpdate::PingProgress(p) => self.update_ping_progress(p),
+            TestUpdate::DownloadProgress(p) => self.update_download_progress(p),
+            TestUpdate::UploadProgress(p) => self.update_upload_progress(p),
+            TestUpdate::DownloadComplete { tcp, .. } => self.download_tcp = tcp,
+            TestUpdate::UploadComplete { tcp, .. } => self.upload_tcp = tcp,
+            TestUpdate::BidirectionalProgress(p) => self.bidirectional_progress = Some(p),
+            TestUpdate::Warning { message, .. } => {
+                self.notice = Some(format!("{}: {}", strings().notice_warning, message));
+            }
+            TestUpdate::Failed { error, .. } => {
+                self.notice = Some(format!("{}: {}", strings().notice_test_failed, error));
+            }
+            TestUpdate::PingComplete { .. } | TestUpdate::BidirectionalComplete(_) => {}
+        }
+
+        if phase != TestPhase::Complete && self.phase() == TestPhase::Complete {
+            self.record_history();
+            self.check_thresholds();
+            if self.accessible {
+                for sentence in spoken::summary(self.result()) {
+                    self.announce(sentence);
+                }
+            }
+        }
+    }
+
+    fn announce(&mut self, sentence: String) {
+        if self.announcements.len() == MAX_ANNOUNCEMENTS {
+            self.announcements.remove(0);
+        }
+        self.announcements.push(sentence);
+    }
+
+    /// The newest saved run while nothing has been tested yet this session.
+    pub fn last_run(&self) -> Option<&HistoryEntry> {
+        self.last_run.as_ref().filter(|_| self.phase() == TestPhase::Idle)
+    }
+
+    /// What the results are compared against: the baseline if set,
+    /// otherwise the previous run.
+    pub fn reference(&self) -> Option<&SpeedTestResult> {
+        self.baseline.as_ref().or(self.previous.as_ref())
+    }
+
+    fn save_baseline(&mut self) {
+        let entry = self.history_entry();
+        match self.history.save_baseline(&entry) {
+            Ok(()) => {
+                self.baseline = Some(entry.result);
+                self.notice = Some(strings().notice_baseline_saved.to_string());
+            }
+            Err(err) => {
+                self.notice = Some(format!("{}: {:#}", strings().notice_baseline_save_failed, err));
+            }
+        }
+    }
+
+    fn clear_baseline(&mut self) {
+        match self.history.clear_baseline() {
+            Ok(()) => {
+                self.baseline = None;
+                self.notice = Some(strings().notice_baseline_cleared.to_string());
+            }
+            Err(err) => {
+                let failed = strings().notice_baseline_clear_failed;
+                self.notice = Some(format!("{}: {:#}", failed, err));
+            }
+        }
+    }
+
+    fn history_entry(&self) -> HistoryEntry {
+        HistoryEntry::now(self.result().clone())
+            .with_tag(self.settings.tag.clone())
+            .with_network(self.active_network.clone())
+    }
+
+    fn record_history(&mut self) {
+        // Simulated results would skew the trends of real ones
+        if self.settings.demo {
+            return;
+        }
+        let entry = self.history_entry();
+        if let Err(err) = self.history.append(&entry) {
+            self.notice = Some(format!("{}: {:#}", strings().notice_history_save_failed, err));
+        }
+    }
+
+    fn check_thresholds(&mut self) {
+        let violations = self.settings.thresholds.check(self.result());
+        if violations.is_empty() {
+            return;
+        }
+
+        let strings = strings();
+        let metrics: Vec<&str> = violations
+            .iter()
+            .map(|v| match v.metric {
+                Metric::Download => strings.download,
+                Metric::Upload => strings.upload,
+                Metric::Ping => strings.latency,
+            })
+            .collect();
+        let below = strings.notice_below_expectations;
+        self.notice = Some(format!("{}: {}", below, metrics.join(", ")));
+        self.ring_bell = self.settings.alert_bell;
+    }
+
+    // The update stream closed, flag it if the test didn't reach completion
+    pub fn finish_test(&mut self) {
+        if let Err(err) = self.test.finish() {
+            self.notice = Some(err.to_string());
+        }
     }
 
     pub fn set_cancel_tx(&mut self, tx: mpsc::Sender<()>) {
@@ -253,108 +1439,47 @@ impl App {
         if let Some(tx) = self.cancel_tx.take() {
             let _ = tx.try_send(());
         }
-        self.phase = TestPhase::Idle;
+        self.test.cancel();
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum AppAction {
-    Quit,
-    StartTest,
-    CancelTest,
+// Steps an optional setting up through `steps`, where None ("off") sits below the first step
+fn profile_list(custom: Option<Profile>) -> Vec<(String, Profile)> {
+    Profile::PRESETS
+        .iter()
+        .map(|&(name, profile)| (name.to_string(), profile))
+        .chain(custom.map(|profile| ("custom".to_string(), profile)))
+        .collect()
 }
 
-pub enum TestUpdate {
-    PingProgress(PingProgress),
-    PingComplete { avg_ms: f64, jitter_ms: f64 },
-    DownloadProgress(DownloadProgress),
-    DownloadComplete { speed_mbps: f64 },
-    UploadProgress(UploadProgress),
-    UploadComplete { speed_mbps: f64 },
+fn step_up<T: Copy + PartialOrd>(current: Option<T>, steps: &[T]) -> Option<T> {
+    match current {
+        None => steps.first().copied(),
+        Some(value) => steps.iter().copied().find(|&step| step > value).or(Some(value)),
+    }
 }
 
-pub async fn run_speed_test(
-    update_tx: mpsc::Sender<TestUpdate>,
-    mut cancel_rx: mpsc::Receiver<()>,
-    settings: Settings,
-) -> Result<()> {
-    // Ping test
-    let ping_count = settings.ping_count;
-    let (ping_tx, mut ping_rx) = mpsc::channel::<PingProgress>(32);
-    let ping_handle = tokio::spawn(async move {
-        let mut test = PingTest::new(ping_count);
-        test.run(ping_tx).await
-    });
-
-    while let Some(progress) = ping_rx.recv().await {
-        if cancel_rx.try_recv().is_ok() {
-            ping_handle.abort();
-            return Ok(());
-        }
-        let _ = update_tx.send(TestUpdate::PingProgress(progress)).await;
-    }
-
-    let ping_result = ping_handle.await??;
-    let _ = update_tx
-        .send(TestUpdate::PingComplete {
-            avg_ms: ping_result.avg_ms,
-            jitter_ms: ping_result.jitter_ms,
-        })
-        .await;
-
-    // Download test
-    let download_size = settings.download_size_bytes();
-    let (download_tx, mut download_rx) = mpsc::channel::<DownloadProgress>(32);
-    let download_handle = tokio::spawn(async move {
-        let mut test = DownloadTest::new(download_size);
-        test.run(download_tx).await
-    });
-
-    while let Some(progress) = download_rx.recv().await {
-        if cancel_rx.try_recv().is_ok() {
-            download_handle.abort();
-            return Ok(());
-        }
-        let _ = update_tx.send(TestUpdate::DownloadProgress(progress)).await;
-    }
-
-    let download_result = download_handle.await??;
-    let _ = update_tx
-        .send(TestUpdate::DownloadComplete {
-            speed_mbps: download_result.avg_speed_mbps,
-        })
-        .await;
-
-    // Upload test
-    let upload_size = settings.upload_size_bytes();
-    let (upload_tx, mut upload_rx) = mpsc::channel::<UploadProgress>(32);
-    let upload_handle = tokio::spawn(async move {
-        let mut test = UploadTest::new(upload_size);
-        test.run(upload_tx).await
-    });
-
-    while let Some(progress) = upload_rx.recv().await {
-        if cancel_rx.try_recv().is_ok() {
-            upload_handle.abort();
-            return Ok(());
-        }
-        let _ = update_tx.send(TestUpdate::UploadProgress(progress)).await;
-    }
-
-    let upload_result = upload_handle.await??;
-    let _ = update_tx
-        .send(TestUpdate::UploadComplete {
-            speed_mbps: upload_result.avg_speed_mbps,
-        })
-        .await;
-
-    Ok(())
+fn step_down<T: Copy + PartialOrd>(current: Option<T>, steps: &[T]) -> Option<T> {
+    current.and_then(|value| steps.iter().copied().rev().find(|&step| step < value))
 }
 
-pub fn poll_event(timeout: Duration) -> Result<Option<Event>> {
-    if event::poll(timeout)? {
-        Ok(Some(event::read()?))
-    } else {
-        Ok(None)
-    }
+// Counts one more probe into a monitor's stats and recomputes the percentiles
+// over the round trips still retained; once per probe, not per frame
+fn update_monitor_stats(stats: &mut MonitorStats, rtts: &[f64], lost: bool) {
+    let sent = stats.sent + 1;
+    let lost = stats.lost + u32::from(lost);
+    *stats = MonitorStats::new(rtts, lost);
+    stats.sent = sent;
 }
+
+#[derive(Debug, Clone, Copy)]
+pub enum AppAction {
+    Quit,
+    // Ctrl+Z: stop in the background like other terminal programs
+    Suspend,
+    StartTest,
+    CancelTest,
+    StartComparison,
+    CancelComparison,
+}
+
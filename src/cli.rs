@@ -0,0 +1,616 @@
+use crate::config::Config;
+use crate::remote::RemoteCommand;
+use crate::template::Template;
+use crate::ui::i18n::Locale;
+use crate::ui::theme::Theme;
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ericspeed::settings::{Auth, PlanSpeed, MAX_STREAMS};
+use ericspeed::speedtest::dns::DNS_PORT;
+use ericspeed::speedtest::ping::{PingMode, MAX_PING_CONCURRENCY};
+use ericspeed::speedtest::ports::TCP_TEST_HOST;
+use ericspeed::speedtest::server::CLOUDFLARE_URL;
+use ericspeed::speedtest::throughput::{MAX_SAMPLE_INTERVAL, MIN_SAMPLE_INTERVAL};
+use ericspeed::Settings;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing_subscriber::filter::LevelFilter;
+
+#[derive(Debug, Parser)]
+#[command(name = "ericspeed", version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file [default: platform config dir/ericspeed/config.toml]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Write a diagnostic log at this level (error, warn, info, debug, trace)
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+
+    /// Directory for log files, rotated daily [default: platform data dir]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    /// Base URL of the server to test against, e.g. one running `ericspeed serve`
+    #[arg(long, global = true, value_name = "URL", default_value = CLOUDFLARE_URL)]
+    pub server: String,
+
+    /// Ask Cloudflare to answer from this location, by IATA airport code, e.g. FRA
+    #[arg(long, global = true, value_name = "CODE", value_parser = parse_colo)]
+    pub colo: Option<String>,
+
+    /// Label stored with each result, e.g. "home-wifi" or "office-ethernet"
+    #[arg(long, global = true)]
+    pub tag: Option<String>,
+
+    /// Number of latency probes in the ping phase
+    #[arg(long, global = true, value_name = "N")]
+    pub ping_count: Option<usize>,
+
+    /// Pause after each latency probe before the next, in milliseconds [default: 200]
+    #[arg(long, global = true, value_name = "MS")]
+    pub ping_interval: Option<u64>,
+
+    /// Latency probes kept in flight at once, shortening the ping phase [default: 1]
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..=MAX_PING_CONCURRENCY as i64)
+    )]
+    pub ping_concurrency: Option<u16>,
+
+    /// Open a new connection for every latency probe, measuring TCP and TLS setup
+    /// instead of request round trips
+    #[arg(long, global = true)]
+    pub cold_ping: bool,
+
+    /// Time raw TCP connects to the server's host for latency, without HTTP
+    #[arg(long, global = true, conflicts_with = "cold_ping")]
+    pub tcp_ping: bool,
+
+    /// Amount of data to download, in megabytes
+    #[arg(long, global = true, value_name = "MB")]
+    pub download_size: Option<u64>,
+
+    /// Amount of data to upload, in megabytes
+    #[arg(long, global = true, value_name = "MB")]
+    pub upload_size: Option<u64>,
+
+    /// Download in many requests of this many megabytes instead of one per connection
+    #[arg(long, global = true, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub chunk_size: Option<u64>,
+
+    /// Stop each transfer phase after this many seconds; for `game`, how long to probe [default: 60]
+    #[arg(long, global = true, value_name = "SECS")]
+    pub duration: Option<u64>,
+
+    /// Fail the test if it hasn't finished after this many seconds [default: timeouts.total_secs]
+    #[arg(long, global = true, value_name = "SECS")]
+    pub timeout: Option<u64>,
+
+    /// Retries after a connection error or 5xx response [default: retry.retries, else 2]
+    #[arg(long, global = true, value_name = "N")]
+    pub retries: Option<u32>,
+
+    /// Keep transfer buffers within this many megabytes [default: memory_budget_mb]
+    #[arg(long, global = true, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub memory_budget: Option<u64>,
+
+    /// Milliseconds between progress samples of the download and upload [default: 100]
+    #[arg(
+        long,
+        global = true,
+        value_name = "MS",
+        value_parser = clap::value_parser!(u64).range(
+            MIN_SAMPLE_INTERVAL.as_millis() as u64..=MAX_SAMPLE_INTERVAL.as_millis() as u64
+        )
+    )]
+    pub sample_interval: Option<u64>,
+
+    /// Concurrent connections for both download and upload
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..=MAX_STREAMS as i64)
+    )]
+    pub connections: Option<u16>,
+
+    /// Bearer token for a private test server [default: auth.token]
+    #[arg(long, global = true, value_name = "TOKEN", conflicts_with = "user")]
+    pub token: Option<String>,
+
+    /// Basic auth credentials for a private test server [default: auth.username]
+    #[arg(long, global = true, value_name = "USER[:PASSWORD]")]
+    pub user: Option<String>,
+
+    /// Extra header sent with every test request; repeatable
+    #[arg(long = "header", global = true, value_name = "NAME: VALUE", value_parser = parse_header)]
+    pub headers: Vec<(String, String)>,
+
+    /// PEM file with extra root certificates to trust [default: tls.ca_cert]
+    #[arg(long, global = true, value_name = "FILE")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// After the upload, also test download and upload running at the same time
+    #[arg(long, global = true)]
+    pub bidirectional: bool,
+
+    /// Pick the download stream count by trying 1, 2, 4 and 8 streams first
+    #[arg(long, global = true)]
+    pub ramp_up: bool,
+
+    /// End the download and upload once their speed holds steady for a few seconds
+    #[arg(long, global = true)]
+    pub early_exit: bool,
+
+    /// Leave latency probes far off the median out of the reported ping and jitter
+    #[arg(long, global = true)]
+    pub filter_outliers: bool,
+
+    /// Skip the captive portal check before each test, e.g. on networks without internet;
+    /// it only runs against the default server unless portal_check is set
+    #[arg(long, global = true)]
+    pub no_portal_check: bool,
+
+    /// Simulate tests instead of measuring the network, for demos and screenshots
+    #[arg(long, global = true)]
+    pub demo: bool,
+
+    /// DANGEROUS: skip TLS certificate validation for every request
+    #[arg(long, global = true)]
+    pub insecure: bool,
+
+    /// Advertised speeds of your internet plan in Mbps, e.g. 500/50
+    #[arg(long, global = true, value_name = "DOWN/UP")]
+    pub plan: Option<PlanSpeed>,
+
+    /// Start a test as soon as the TUI opens
+    #[arg(long)]
+    pub start: bool,
+
+    /// Quit the TUI once the first test finishes
+    #[arg(long)]
+    pub exit_on_complete: bool,
+
+    /// With --exit-on-complete, print the result as JSON after quitting
+    #[arg(long, requires = "exit_on_complete")]
+    pub json: bool,
+
+    /// Draw below the prompt instead of on the alternate screen, leaving the
+    /// final result in the scrollback after quitting
+    #[arg(long)]
+    pub inline: bool,
+
+    /// Color theme; color-blind draws download, upload and latency in blue,
+    /// orange and purple [default: theme]
+    #[arg(long, value_enum)]
+    pub theme: Option<Theme>,
+
+    /// Language of the TUI [default: from LC_ALL, LC_MESSAGES or LANG, else en]
+    #[arg(long, value_enum)]
+    pub locale: Option<Locale>,
+
+    /// Draw in the terminal's default colors, with only bold and dim; also
+    /// set by a non-empty NO_COLOR environment variable
+    #[arg(long)]
+    pub no_color: bool,
+
+    /// Screen-reader friendly output: the TUI lists plain-text status lines
+    /// instead of charts, and check and --no-tui speak in whole sentences
+    #[arg(long, global = true)]
+    pub accessible: bool,
+
+    /// Run a single test and print the result without the TUI, like `check`
+    #[arg(long)]
+    pub no_tui: bool,
+}
+
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| "expected NAME: VALUE".to_string())?;
+    let (name, value) = (name.trim(), value.trim());
+    reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|err| err.to_string())?;
+    reqwest::header::HeaderValue::from_str(value).map_err(|err| err.to_string())?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+// Three-letter IATA codes, as Cloudflare names its locations
+fn parse_colo(colo: &str) -> Result<String, String> {
+    if colo.len() == 3 && colo.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(colo.to_ascii_uppercase())
+    } else {
+        Err("expected a three-letter IATA airport code, e.g. FRA".to_string())
+    }
+}
+
+// "1.1.1.1", "1.1.1.1:5353" or "[2606:4700::1111]:53"
+fn parse_resolver(resolver: &str) -> Result<SocketAddr, String> {
+    resolver
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .or_else(|_| resolver.parse())
+        .map_err(|_| "expected an IP address with an optional port".to_string())
+}
+
+// Ages like "90m", "12h", "7d" or "2w"
+fn parse_age(age: &str) -> Result<Duration, String> {
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (count, unit) = age.split_at(split);
+    let count: u64 = count
+        .parse()
+        .map_err(|_| "expected a number followed by s, m, h, d or w".to_string())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown unit {:?}; use s, m, h, d or w", unit)),
+    };
+    Ok(Duration::from_secs(count * seconds))
+}
+
+impl Cli {
+    /// Test settings from the defaults, then the config file, then the
+    /// command-line overrides.
+    pub fn settings(&self, config: &Config) -> Result<Settings> {
+        let mut settings = Settings {
+            server_url: self.server.clone(),
+            colo: self.colo.clone(),
+            tag: self.tag.clone(),
+            credentials: config.auth.credentials()?,
+            ca_cert: self.ca_cert.clone().or_else(|| config.tls.ca_cert.clone()),
+            accept_invalid_certs: self.insecure || config.tls.accept_invalid_certs,
+            plan: self.plan.or(config.plan),
+            bidirectional: self.bidirectional,
+            ramp_up: self.ramp_up,
+            early_exit: self.early_exit,
+            filter_ping_outliers: self.filter_outliers,
+            detect_captive_portal: !self.no_portal_check
+                && config.portal_check.unwrap_or(self.server == CLOUDFLARE_URL),
+            demo: self.demo,
+            timeouts: config.timeouts.timeouts(),
+            retry: config.retry.policy(),
+            memory_budget_mb: self.memory_budget.or(config.memory_budget_mb),
+            chart_marker: config.chart_marker.unwrap_or_default(),
+            ..Settings::default()
+        };
+        if let Some(profile) = &config.profile {
+            profile.apply(&mut settings);
+        }
+
+        if let Some(ping_count) = self.ping_count {
+            settings.ping_count = ping_count;
+        }
+        if let Some(interval) = self.ping_interval {
+            settings.ping_interval = Duration::from_millis(interval);
+        }
+        if let Some(concurrency) = self.ping_concurrency {
+            settings.ping_concurrency = concurrency as usize;
+        }
+        if self.cold_ping {
+            settings.ping_mode = PingMode::Cold;
+        }
+        if self.tcp_ping {
+            settings.ping_mode = PingMode::Tcp;
+        }
+        if let Some(download_size) = self.download_size {
+            settings.download_size_mb = download_size;
+        }
+        if let Some(upload_size) = self.upload_size {
+            settings.upload_size_mb = upload_size;
+        }
+        if let Some(chunk_size) = self.chunk_size {
+            settings.download_chunk_mb = Some(chunk_size);
+        }
+        if let Some(interval) = self.sample_interval.or(config.sample_interval_ms) {
+            settings.sample_interval = Duration::from_millis(interval);
+        }
+        if let Some(duration) = self.duration {
+            settings.transfer_duration = Some(Duration::from_secs(duration));
+        }
+        if let Some(timeout) = self.timeout {
+            settings.timeouts.total = Some(Duration::from_secs(timeout));
+        }
+        if let Some(retries) = self.retries {
+            settings.retry.retries = retries;
+        }
+        if let Some(connections) = self.connections {
+            settings.download_streams = connections as usize;
+            settings.upload_streams = connections as usize;
+        }
+
+        let credentials = &mut settings.credentials;
+        if let Some(token) = &self.token {
+            credentials.auth = Some(Auth::Bearer(token.clone()));
+        }
+        if let Some(user) = &self.user {
+            let (username, password) = match user.split_once(':') {
+                Some((username, password)) => (username, Some(password.to_string())),
+                None => (user.as_str(), None),
+            };
+            credentials.auth = Some(Auth::Basic {
+                username: username.to_string(),
+                password,
+            });
+        }
+        // A header given on the command line replaces one of the same name from the config
+        credentials.headers.retain(|(existing, _)| {
+            !self.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case(existing))
+        });
+        credentials.headers.extend(self.headers.iter().cloned());
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a test without the TUI and check it against expected speeds
+    Check(CheckArgs),
+    /// Run tests on the schedule from the config file and record them in history
+    Daemon(DaemonArgs),
+    /// Control a running TUI: start or cancel a test, or print its status
+    Ctl(CtlArgs),
+    /// Serve the download and upload endpoints for other machines to test against
+    Serve(ServeArgs),
+    /// Measure UDP jitter, reordering and loss against `ericspeed serve`
+    Udp(UdpArgs),
+    /// Probe latency continuously and report the spikes that hurt online games
+    Game(GameArgs),
+    /// Ping a host until interrupted, then print loss and latency percentiles
+    Ping(PingArgs),
+    /// Compare how fast DNS resolvers answer lookups from this network
+    Dns(DnsArgs),
+    /// Download at 1 to 16 connections and chart how throughput scales
+    Sweep(SweepArgs),
+    /// Check which common outbound ports this network lets through
+    Ports(PortsArgs),
+    /// Measure latency to cloud regions, closest first, e.g. to pick where to deploy
+    Regions(RegionsArgs),
+    /// Check an exported JSON result against its integrity digest
+    Verify(VerifyArgs),
+    /// Print the latest saved result for a waybar or i3blocks status bar module
+    Status(StatusArgs),
+    /// Summarise past results, optionally only recent ones or those with --tag
+    History(HistoryArgs),
+}
+
+#[derive(Debug, Default, Args)]
+pub struct CheckArgs {
+    /// Minimum acceptable download speed in Mbps
+    #[arg(long, value_name = "MBPS")]
+    pub expect_down: Option<f64>,
+
+    /// Minimum acceptable upload speed in Mbps
+    #[arg(long, value_name = "MBPS")]
+    pub expect_up: Option<f64>,
+
+    /// Maximum acceptable latency in milliseconds
+    #[arg(long, value_name = "MS")]
+    pub expect_ping: Option<f64>,
+
+    /// Output format; speedtest-json matches Ookla's `speedtest -f json`
+    /// [default: text, template with --template, or spoken with --accessible]
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Line printed by --format template, e.g. '{download} / {upload} @ {ping}ms'.
+    /// Placeholders: download, upload, ping, jitter, loss, mos, download_ping,
+    /// upload_ping, ttfb, server, tag and time; {download:.2} sets the decimals
+    #[arg(long, value_name = "TEMPLATE", required_if_eq("format", "template"))]
+    pub template: Option<Template>,
+
+    /// How to report progress on stderr while the test runs; plain prints
+    /// a line at a time for CI logs. Defaults to spoken with --accessible,
+    /// else bar on a terminal, else none
+    #[arg(long, value_enum, value_name = "STYLE")]
+    pub progress: Option<ProgressStyle>,
+
+    /// Record the result in history, like the TUI and daemon do
+    #[arg(long)]
+    pub save: bool,
+}
+
+impl CheckArgs {
+    // Speaks progress and the result unless a format or style was picked
+    pub fn accessible(mut self) -> Self {
+        if self.template.is_none() {
+            self.format = self.format.or(Some(OutputFormat::Spoken));
+        }
+        self.progress = self.progress.or(Some(ProgressStyle::Spoken));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    SpeedtestJson,
+    // The --template line, for scripts and status bars
+    Template,
+    // Whole sentences with units spelled out, for screen readers
+    Spoken,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProgressStyle {
+    None,
+    Plain,
+    Bar,
+    // A sentence every few seconds and at the end of each phase, for screen readers
+    Spoken,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "0.0.0.0:8080")]
+    pub listen: SocketAddr,
+}
+
+#[derive(Debug, Args)]
+pub struct UdpArgs {
+    /// Number of packets to send
+    #[arg(long, default_value_t = 500)]
+    pub packets: u32,
+
+    /// Milliseconds between packets
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 20,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub interval: u64,
+
+    /// Packet payload size in bytes
+    #[arg(long, value_name = "BYTES", default_value_t = 160)]
+    pub size: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct GameArgs {
+    /// Milliseconds between probes
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 50,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub interval: u64,
+
+    /// Latency above the median, in milliseconds, that counts as a spike
+    #[arg(long, value_name = "MS", default_value_t = 30.0)]
+    pub spike_threshold: f64,
+
+    /// Run a rate-limited download in the background, in Mbps
+    #[arg(long, value_name = "MBPS")]
+    pub background: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+pub struct PingArgs {
+    /// Host or URL to probe [default: the test server]
+    pub host: Option<String>,
+
+    /// Milliseconds between probes
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    pub interval: u64,
+
+    /// Stop after this many probes instead of running until Ctrl+C
+    #[arg(long, value_name = "N")]
+    pub count: Option<u32>,
+
+    /// Time TCP connects to the host, port 443 unless given, instead of HTTP requests
+    #[arg(long)]
+    pub tcp: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DnsArgs {
+    /// Also benchmark this resolver, by IP address with an optional port; repeatable
+    #[arg(long = "resolver", value_name = "IP[:PORT]", value_parser = parse_resolver)]
+    pub resolvers: Vec<SocketAddr>,
+
+    /// Domain to look up, replacing the built-in list; repeatable
+    #[arg(long = "domain", value_name = "NAME")]
+    pub domains: Vec<String>,
+
+    /// Times each domain is looked up per resolver
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub rounds: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct SweepArgs {
+    /// Seconds to download for at each connection count [default: 5, or --duration]
+    #[arg(long, value_name = "SECS")]
+    pub stage: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+pub struct PortsArgs {
+    /// Also check this TCP port; repeatable
+    #[arg(long = "port", value_name = "PORT")]
+    pub ports: Vec<u16>,
+
+    /// Host to connect to for TCP ports; it must accept connections on all of them
+    #[arg(long, value_name = "HOST", default_value = TCP_TEST_HOST)]
+    pub host: String,
+}
+
+#[derive(Debug, Args)]
+pub struct RegionsArgs {
+    /// Requests timed per region, after one that sets up the connection
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub probes: usize,
+
+    /// Only regions of this provider, e.g. aws; repeatable
+    #[arg(long = "provider", value_name = "NAME")]
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// File holding a result from --json or `check --format speedtest-json`; - for stdin
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct StatusArgs {
+    /// JSON for a waybar custom module, or for i3blocks with format=json
+    #[arg(long, value_enum, default_value_t = StatusFormat::Waybar)]
+    pub format: StatusFormat,
+
+    /// Text shown in the bar, with the placeholders of `check --template`
+    /// [default: '↓{download} ↑{upload} {ping}ms']
+    #[arg(long, value_name = "TEMPLATE")]
+    pub template: Option<Template>,
+
+    /// Start a test in the background when the latest result is older than
+    /// this, e.g. 30m or 2h; the bar shows it on its next poll
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    pub refresh: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusFormat {
+    Waybar,
+    I3blocks,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// Only results from this long ago or later, e.g. 12h, 7d or 2w
+    #[arg(long, value_name = "AGE", value_parser = parse_age, conflicts_with = "purge")]
+    pub since: Option<Duration>,
+
+    /// Delete results older than AGE, e.g. 30d, instead of summarising them;
+    /// --tag limits it to one tag
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    pub purge: Option<Option<Duration>>,
+
+    /// Let --purge without an age or --tag delete the whole history
+    #[arg(long, requires = "purge")]
+    pub all: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// Serve the HTTP control API on this address [default: daemon.api_listen]
+    #[arg(long, value_name = "ADDR")]
+    pub api: Option<SocketAddr>,
+}
+
+#[derive(Debug, Args)]
+pub struct CtlArgs {
+    #[arg(value_enum)]
+    pub command: RemoteCommand,
+}
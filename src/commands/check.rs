@@ -0,0 +1,195 @@
+use super::progress::Progress;
+use crate::cli::{CheckArgs, OutputFormat, ProgressStyle};
+use crate::config::Config;
+use crate::spoken::{self, megabits, milliseconds};
+use crate::{export, metrics};
+use anyhow::Result;
+use ericspeed::alerts::{Metric, Thresholds, Violation};
+use ericspeed::history::HistoryEntry;
+use ericspeed::network::ActiveNetwork;
+use ericspeed::speedtest::bidirectional::COLLAPSED_PERCENT;
+use ericspeed::speedtest::{asymmetry_label, mtu, SLOW_SERVER_MS};
+use ericspeed::speedtest::quality::mos_label;
+use ericspeed::{Settings, SpeedTest, SpeedTestResult, TestUpdate};
+use std::io::{self, IsTerminal};
+use std::process::ExitCode;
+
+// Exit codes: 0 all checks passed, 1 a check failed, 2 the test itself failed
+const EXIT_FAILED_CHECK: u8 = 1;
+const EXIT_TEST_ERROR: u8 = 2;
+
+pub async fn run(args: CheckArgs, config: &Config, settings: Settings) -> Result<ExitCode> {
+    let thresholds = Thresholds {
+        min_download_mbps: args.expect_down,
+        min_upload_mbps: args.expect_up,
+        max_ping_ms: args.expect_ping,
+    };
+
+    let network = ActiveNetwork::detect(settings.local_address);
+    let style = args.progress.unwrap_or(if io::stderr().is_terminal() {
+        ProgressStyle::Bar
+    } else {
+        ProgressStyle::None
+    });
+    let mut progress = Progress::new(style);
+    let mut handle = SpeedTest::new(settings.clone()).spawn();
+    while let Some(update) = handle.updates.recv().await {
+        progress.update(&update);
+        if let TestUpdate::Warning { message, .. } = update {
+            eprintln!("warning: {}", message);
+        }
+    }
+    progress.clear_bar();
+
+    let result = match handle.join().await {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("speed test failed: {:#}", err);
+            return Ok(ExitCode::from(EXIT_TEST_ERROR));
+        }
+    };
+
+    // Simulated results must not reach metrics or the history
+    if !settings.demo {
+        metrics::publish(config, &result).await;
+    }
+    if args.save && !settings.demo {
+        let entry = HistoryEntry::now(result.clone())
+            .with_tag(settings.tag.clone())
+            .with_network(network.clone());
+        if let Err(err) = config.history.open().append(&entry) {
+            eprintln!("failed to save history: {:#}", err);
+        }
+    }
+
+    let violations = thresholds.check(&result);
+    let exit_code = if violations.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_FAILED_CHECK)
+    };
+    let format = args.format.unwrap_or(if args.template.is_some() {
+        OutputFormat::Template
+    } else {
+        OutputFormat::Text
+    });
+    match format {
+        OutputFormat::SpeedtestJson => {
+            let document = export::speedtest_json(&result, &settings, network.as_ref());
+            println!("{}", export::signed(document, &settings, config.integrity.key()));
+            return Ok(exit_code);
+        }
+        OutputFormat::Template => {
+            // Required with this format by clap
+            if let Some(template) = &args.template {
+                println!("{}", template.render(&result, &settings));
+            }
+            return Ok(exit_code);
+        }
+        OutputFormat::Spoken => {
+            print_spoken(&result, &thresholds, &violations);
+            return Ok(exit_code);
+        }
+        OutputFormat::Text => {}
+    }
+
+    let mos = result.mos();
+    let rows = [
+        (Metric::Download, format!("{:.1} Mbps", result.download_mbps), thresholds.min_download_mbps),
+        (Metric::Upload, format!("{:.1} Mbps", result.upload_mbps), thresholds.min_upload_mbps),
+        (Metric::Ping, format!("{:.0} ms", result.ping_ms), thresholds.max_ping_ms),
+    ];
+    // 95% confidence margins, after the verdict so the columns stay put
+    let margins = [
+        result.download_margin_mbps.map(|m| format!("±{:.1} Mbps", m)),
+        result.upload_margin_mbps.map(|m| format!("±{:.1} Mbps", m)),
+        result.ping_margin_ms.map(|m| format!("±{:.1} ms", m)),
+    ];
+
+    let mut checked = 0;
+    for ((metric, value, limit), margin) in rows.into_iter().zip(margins) {
+        let (label, op) = match metric {
+            Metric::Download => ("Download", ">="),
+            Metric::Upload => ("Upload", ">="),
+            Metric::Ping => ("Latency", "<="),
+        };
+        let verdict = match limit {
+            Some(limit) => {
+                checked += 1;
+                let failed = violations.iter().any(|v| v.metric == metric);
+                format!("expected {} {:<8} {}", op, limit, if failed { "FAIL" } else { "PASS" })
+            }
+            None => "unchecked".to_string(),
+        };
+        match margin {
+            Some(margin) => println!("{:<10} {:>14}   {:<27} {}", label, value, verdict, margin),
+            None => println!("{:<10} {:>14}   {}", label, value, verdict),
+        }
+    }
+
+    println!("{:<10} {:>14}   {}", "MOS", format!("{:.1}", mos), mos_label(mos));
+    println!(
+        "{:<10} {:>14}   {}",
+        "Ping mode",
+        result.ping_mode.label(),
+        result.ping_mode.describe()
+    );
+    if let (Some(ttfb), Some(delay)) = (result.download_ttfb_ms, result.server_delay_ms()) {
+        let hint = if delay > SLOW_SERVER_MS {
+            "server slow to answer; latency alone doesn't explain it"
+        } else {
+            ""
+        };
+        println!("{:<10} {:>14}   {}", "TTFB", format!("{:.0} ms", ttfb), hint);
+    }
+    if let Some(size) = result.path_mtu {
+        let hint = mtu::overhead_hint(size).unwrap_or_default();
+        println!("{:<10} {:>14}   {}", "Path MTU", format!("{} B", size), hint);
+    }
+    if let Some(ratio) = result.asymmetry_ratio() {
+        let value = format!("{:.1}:1", ratio);
+        println!("{:<10} {:>14}   {}", "Down:up", value, asymmetry_label(ratio));
+    }
+    if let (Some(both), Some((download, upload))) =
+        (result.bidirectional, result.bidirectional_retained_percent())
+    {
+        for (label, speed, retained) in [
+            ("Both down", both.download_mbps, download),
+            ("Both up", both.upload_mbps, upload),
+        ] {
+            let collapsed = if retained < COLLAPSED_PERCENT { " (collapsed)" } else { "" };
+            let value = format!("{:.1} Mbps", speed);
+            println!("{:<10} {:>14}   {:.0}% of one-way{}", label, value, retained, collapsed);
+        }
+    }
+
+    if violations.is_empty() {
+        println!("Result: PASS ({} of {} checks passed)", checked, checked);
+    } else {
+        println!("Result: FAIL ({} of {} checks failed)", violations.len(), checked);
+    }
+    Ok(exit_code)
+}
+
+// The result as sentences, then how each expected figure was met
+fn print_spoken(result: &SpeedTestResult, thresholds: &Thresholds, violations: &[Violation]) {
+    for sentence in spoken::summary(result) {
+        println!("{}", sentence);
+    }
+    let checks = [
+        (Metric::Download, "Download", "minimum", thresholds.min_download_mbps.map(megabits)),
+        (Metric::Upload, "Upload", "minimum", thresholds.min_upload_mbps.map(megabits)),
+        (Metric::Ping, "Latency", "maximum", thresholds.max_ping_ms.map(milliseconds)),
+    ];
+    for (metric, label, bound, limit) in checks {
+        let Some(limit) = limit else {
+            continue;
+        };
+        let outcome = if violations.iter().any(|v| v.metric == metric) { "missed" } else { "met" };
+        println!("{} {} the expected {} of {}.", label, outcome, bound, limit);
+    }
+    if !thresholds.is_empty() {
+        let result = if violations.is_empty() { "passed" } else { "failed" };
+        println!("Check {}.", result);
+    }
+}
@@ -0,0 +1,14 @@
+use crate::cli::CtlArgs;
+use crate::remote;
+use anyhow::Result;
+use std::process::ExitCode;
+
+pub async fn run(args: CtlArgs) -> Result<ExitCode> {
+    let reply = remote::send(&remote::socket_path()?, args.command).await?;
+    println!("{}", reply);
+    if reply.starts_with("error") {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
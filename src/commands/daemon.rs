@@ -0,0 +1,135 @@
+use crate::api::{self, ApiState};
+use crate::cli::DaemonArgs;
+use crate::config::Config;
+use crate::metrics;
+use crate::notify::{Alert, Notifier};
+use anyhow::{bail, Result};
+use chrono::Local;
+use ericspeed::alerts::Thresholds;
+use ericspeed::history::{History, HistoryEntry};
+use ericspeed::network::ActiveNetwork;
+use ericspeed::{Settings, SpeedTest, TestUpdate};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+// Runs tests on the configured schedule until interrupted, appending each
+// result to the history file
+pub async fn run(args: DaemonArgs, config: Config, settings: Settings) -> Result<ExitCode> {
+    let schedule = config.daemon.schedule()?;
+    let history = config.history.open();
+    let thresholds = config.notify.thresholds();
+    let notifier = Notifier::new(&config.notify, settings.tag.clone())?;
+    if !notifier.is_empty() && thresholds.is_empty() {
+        println!("Alerts are only sent for failed tests; set notify thresholds to alert on slow ones");
+    }
+    println!(
+        "Testing {}, saving results to {}",
+        schedule.describe(),
+        history.path().display()
+    );
+    info!(schedule = %schedule.describe(), "daemon started");
+
+    // Tests can also be started on demand through the API
+    let (trigger_tx, mut trigger_rx) = mpsc::channel(1);
+    let state = Arc::new(ApiState {
+        history: history.clone(),
+        trigger_tx,
+        running: AtomicBool::new(false),
+    });
+    if let Some(addr) = args.api.or(config.daemon.api_listen) {
+        println!("Control API on http://{}", addr);
+        let state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(err) = api::serve(addr, state).await {
+                warn!(error = %err, "api stopped");
+                eprintln!("control API stopped: {:#}", err);
+            }
+        });
+    }
+
+    loop {
+        let Some(next) = schedule.next_after(Local::now()) else {
+            bail!("schedule has no upcoming runs");
+        };
+        println!("Next test at {}", next.format("%Y-%m-%d %H:%M"));
+        let wait = (next - Local::now()).to_std().unwrap_or_default();
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            Some(()) = trigger_rx.recv() => info!("test requested through api"),
+            _ = tokio::signal::ctrl_c() => break,
+        }
+
+        state.running.store(true, Ordering::SeqCst);
+        tokio::select! {
+            _ = run_once(&config, &settings, &history, &thresholds, &notifier) => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        state.running.store(false, Ordering::SeqCst);
+    }
+
+    info!("daemon stopped");
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn run_once(
+    config: &Config,
+    settings: &Settings,
+    history: &History,
+    thresholds: &Thresholds,
+    notifier: &Notifier,
+) {
+    let previous = history.latest().ok().flatten();
+    let network = ActiveNetwork::detect(settings.local_address);
+    let mut handle = SpeedTest::new(settings.clone()).spawn();
+    while let Some(update) = handle.updates.recv().await {
+        if let TestUpdate::Warning { message, .. } = update {
+            eprintln!("{} warning: {}", Local::now().format("%H:%M"), message);
+        }
+    }
+
+    let result = match handle.join().await {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("{} test failed: {:#}", Local::now().format("%H:%M"), err);
+            if !settings.demo {
+                notifier.send(&Alert::Failed(&err)).await;
+            }
+            return;
+        }
+    };
+
+    println!(
+        "{} down {:.1} Mbps · up {:.1} Mbps · ping {:.0} ms",
+        Local::now().format("%H:%M"),
+        result.download_mbps,
+        result.upload_mbps,
+        result.ping_ms
+    );
+    // Simulated results must not reach metrics, alerts or the history
+    if settings.demo {
+        return;
+    }
+    metrics::publish(config, &result).await;
+
+    let violations = thresholds.check(&result);
+    if !violations.is_empty() {
+        notifier
+            .send(&Alert::BelowThreshold {
+                result: &result,
+                violations: &violations,
+                previous: previous.as_ref().map(|entry| &entry.result),
+            })
+            .await;
+    }
+    let entry = HistoryEntry::now(result)
+        .with_tag(settings.tag.clone())
+        .with_network(network);
+    if let Err(err) = history.append(&entry) {
+        warn!(error = %err, "failed to save result");
+        eprintln!("failed to save result: {:#}", err);
+    }
+}
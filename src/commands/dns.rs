@@ -0,0 +1,57 @@
+use crate::cli::DnsArgs;
+use anyhow::Result;
+use ericspeed::speedtest::dns::{DnsBenchmark, Resolver, DEFAULT_DOMAINS};
+use ericspeed::Settings;
+use std::process::ExitCode;
+
+const EXIT_TEST_ERROR: u8 = 2;
+
+pub async fn run(args: DnsArgs, settings: Settings) -> Result<ExitCode> {
+    let mut resolvers = Resolver::defaults();
+    resolvers.extend(args.resolvers.iter().map(|&addr| Resolver::with_port("Custom", addr)));
+    let domains = if args.domains.is_empty() {
+        DEFAULT_DOMAINS.iter().map(|domain| domain.to_string()).collect()
+    } else {
+        args.domains
+    };
+    println!(
+        "Looking up {} domains on {} resolvers, {} rounds",
+        domains.len(),
+        resolvers.len(),
+        args.rounds.max(1)
+    );
+
+    let mut results = DnsBenchmark::new(resolvers, domains)
+        .with_rounds(args.rounds)
+        .with_local_address(settings.local_address)
+        .run()
+        .await;
+    // Fastest first; resolvers that never answered go last
+    results.sort_by(|a, b| {
+        let median = |ms: Option<f64>| ms.unwrap_or(f64::INFINITY);
+        median(a.median_ms).total_cmp(&median(b.median_ms))
+    });
+
+    let format_ms = |ms: Option<f64>| ms.map_or("—".to_string(), |ms| format!("{:.1} ms", ms));
+    println!(
+        "{:<12} {:<24} {:>10} {:>10} {:>10} {:>7}",
+        "Resolver", "Address", "Median", "Mean", "Max", "Failed"
+    );
+    for result in &results {
+        println!(
+            "{:<12} {:<24} {:>10} {:>10} {:>10} {:>7}",
+            result.resolver.name,
+            result.resolver.addr.to_string(),
+            format_ms(result.median_ms),
+            format_ms(result.mean_ms),
+            format_ms(result.max_ms),
+            format!("{}/{}", result.failed, result.answered + result.failed)
+        );
+    }
+
+    if results.iter().all(|result| result.answered == 0) {
+        eprintln!("no resolver answered");
+        return Ok(ExitCode::from(EXIT_TEST_ERROR));
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,59 @@
+use crate::cli::GameArgs;
+use anyhow::Result;
+use ericspeed::speedtest::gaming::GamingTest;
+use ericspeed::speedtest::http_client;
+use ericspeed::speedtest::server::{self, Server};
+use ericspeed::Settings;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const EXIT_TEST_ERROR: u8 = 2;
+// Used unless --duration is given
+const DEFAULT_DURATION: Duration = Duration::from_secs(60);
+
+pub async fn run(args: GameArgs, settings: Settings) -> Result<ExitCode> {
+    let mut test = GamingTest::new(http_client(&settings)?, server::ping_url(&settings.server_url))
+        .with_duration(settings.transfer_duration.unwrap_or(DEFAULT_DURATION))
+        .with_interval(Duration::from_millis(args.interval))
+        .with_spike_threshold(args.spike_threshold);
+    if let Some(mbps) = args.background {
+        let url = Server::from_base("Test server", &settings.server_url).download_url(25_000_000);
+        test = test.with_background_traffic(url, mbps);
+    }
+
+    let (progress_tx, mut progress_rx) = mpsc::channel(32);
+    let (result, ()) = tokio::join!(test.run(progress_tx), async {
+        while let Some(progress) = progress_rx.recv().await {
+            let latest = progress.latest_ms.map_or("lost".to_string(), |ms| format!("{:.0} ms", ms));
+            print!(
+                "\r{:>3}s / {}s   latest {:<8}",
+                progress.elapsed.as_secs(),
+                progress.duration.as_secs(),
+                latest
+            );
+            let _ = io::stdout().flush();
+        }
+        println!();
+    });
+
+    let result = match result {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("gaming test failed: {:#}", err);
+            return Ok(ExitCode::from(EXIT_TEST_ERROR));
+        }
+    };
+
+    println!("{:<12} {} ({} lost)", "Probes", result.probes, result.lost);
+    println!("{:<12} {:.1} ms", "Min", result.min_ms);
+    println!("{:<12} {:.1} ms", "Median", result.median_ms);
+    println!("{:<12} {:.1} ms", "p99", result.p99_ms);
+    println!("{:<12} {:.1} ms", "Max", result.max_ms);
+    println!(
+        "{:<12} {} ({:.1} per minute)",
+        "Spikes", result.spikes, result.spikes_per_minute
+    );
+    Ok(ExitCode::SUCCESS)
+}
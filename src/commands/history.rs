@@ -0,0 +1,85 @@
+use crate::cli::HistoryArgs;
+use crate::config::Config;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use ericspeed::history::{HistoryEntry, HistoryFilter};
+use ericspeed::samples::percentile;
+use ericspeed::Settings;
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub fn run(args: HistoryArgs, config: &Config, settings: Settings) -> Result<ExitCode> {
+    let history = config.history.open();
+    if let Some(age) = args.purge {
+        let before = age.map(ago);
+        if before.is_none() && settings.tag.is_none() && !args.all {
+            bail!("--purge without an age or --tag deletes every result; add --all to confirm");
+        }
+        let deleted = history.purge(before, settings.tag.as_deref())?;
+        println!("Deleted {} results from {}", deleted, history.path().display());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let filter = HistoryFilter {
+        since: args.since.map(ago),
+        tag: settings.tag.clone(),
+    };
+
+    let entries = history.query(&filter)?;
+
+    let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+        println!("No results in {}", history.path().display());
+        return Ok(ExitCode::SUCCESS);
+    };
+    let mut summary = format!(
+        "{} results from {} to {}",
+        entries.len(),
+        format_time(first.timestamp),
+        format_time(last.timestamp)
+    );
+    if let Some(tag) = &settings.tag {
+        summary.push_str(&format!(" tagged {}", tag));
+    }
+    println!("{}\n", summary);
+
+    println!(
+        "{:<10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "", "Min", "Median", "Mean", "p90", "Max"
+    );
+    let values = |metric: fn(&HistoryEntry) -> f64| entries.iter().map(metric).collect::<Vec<_>>();
+    let metrics = [
+        ("Download", "Mbps", values(|entry| entry.result.download_mbps)),
+        ("Upload", "Mbps", values(|entry| entry.result.upload_mbps)),
+        ("Ping", "ms", values(|entry| entry.result.ping_ms)),
+        ("Jitter", "ms", values(|entry| entry.result.jitter_ms)),
+    ];
+    for (label, unit, mut values) in metrics {
+        values.sort_by(f64::total_cmp);
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        println!(
+            "{:<10} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1}  {}",
+            label,
+            values[0],
+            percentile(&values, 50.0),
+            mean,
+            percentile(&values, 90.0),
+            values[values.len() - 1],
+            unit
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn format_time(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+// The Unix time `age` before now
+fn ago(age: Duration) -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.saturating_sub(age).as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,15 @@
+pub mod check;
+pub mod ctl;
+pub mod daemon;
+pub mod dns;
+pub mod game;
+pub mod history;
+pub mod ping;
+pub mod ports;
+pub mod regions;
+mod progress;
+pub mod serve;
+pub mod status;
+pub mod sweep;
+pub mod udp;
+pub mod verify;
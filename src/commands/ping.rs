@@ -0,0 +1,73 @@
+use crate::cli::PingArgs;
+use anyhow::Result;
+use ericspeed::speedtest::http_client;
+use ericspeed::speedtest::monitor::{LatencyMonitor, MonitorStats};
+use ericspeed::speedtest::ping::tcp_target;
+use ericspeed::speedtest::server;
+use ericspeed::Settings;
+use std::process::ExitCode;
+use std::time::Duration;
+
+pub async fn run(args: PingArgs, settings: Settings) -> Result<ExitCode> {
+    // Any host can be given, so the test server's credentials stay out of it
+    let client_settings = match &args.host {
+        Some(_) => settings.without_credentials(),
+        None => settings.clone(),
+    };
+    let url = match args.host {
+        // Bare hosts are probed over HTTPS at their root
+        Some(host) if host.contains("://") => host,
+        Some(host) => format!("https://{}/", host),
+        None => server::ping_url(&settings.server_url),
+    };
+    let interval = Duration::from_millis(args.interval.max(1));
+    let mut monitor = if args.tcp {
+        let addr = tcp_target(&url, settings.local_address).await?;
+        println!("Connecting to {} over TCP every {} ms", addr, args.interval);
+        LatencyMonitor::spawn_tcp(addr, settings.local_address, interval)
+    } else {
+        println!("Pinging {} every {} ms", url, args.interval);
+        LatencyMonitor::spawn(http_client(&client_settings)?, url, interval)
+    };
+    let mut rtts = Vec::new();
+    let mut lost = 0;
+    let mut seq = 0;
+
+    while args.count.is_none_or(|count| seq < count) {
+        let rtt = tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            rtt = monitor.samples.recv() => match rtt {
+                Some(rtt) => rtt,
+                None => break,
+            },
+        };
+        seq += 1;
+        match rtt {
+            Some(ms) => {
+                rtts.push(ms);
+                println!("seq={:<5} time={:.1} ms", seq, ms);
+            }
+            None => {
+                lost += 1;
+                println!("seq={:<5} lost", seq);
+            }
+        }
+    }
+    drop(monitor);
+
+    let stats = MonitorStats::new(&rtts, lost);
+    println!();
+    println!(
+        "{:<8} {} sent, {} lost ({:.1}%)",
+        "Probes",
+        stats.sent,
+        stats.lost,
+        stats.loss_percent()
+    );
+    println!("{:<8} {:.1} ms", "Min", stats.min_ms);
+    println!("{:<8} {:.1} ms", "p50", stats.p50_ms);
+    println!("{:<8} {:.1} ms", "p90", stats.p90_ms);
+    println!("{:<8} {:.1} ms", "p99", stats.p99_ms);
+    println!("{:<8} {:.1} ms", "Max", stats.max_ms);
+    Ok(ExitCode::SUCCESS)
+}
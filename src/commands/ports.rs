@@ -0,0 +1,37 @@
+use crate::cli::PortsArgs;
+use anyhow::Result;
+use ericspeed::speedtest::ports::{check_ports, PortCheck, PortStatus};
+use ericspeed::Settings;
+use std::process::ExitCode;
+
+// Some port is blocked
+const EXIT_BLOCKED: u8 = 1;
+
+pub async fn run(args: PortsArgs, settings: Settings) -> Result<ExitCode> {
+    let mut checks = PortCheck::common(&args.host);
+    checks.extend(args.ports.iter().map(|&port| PortCheck::tcp("Custom", &args.host, port)));
+    println!("Checking {} outbound ports", checks.len());
+
+    let results = check_ports(checks, settings.local_address).await;
+    println!("{:<24} {:>9}  {:<8} Detail", "Service", "Port", "Status");
+    for result in &results {
+        let port = format!("{}/{}", result.check.port, result.check.probe.protocol());
+        let (status, detail) = match &result.status {
+            PortStatus::Open { ms } => ("open", format!("{:.0} ms", ms)),
+            PortStatus::Blocked { reason } => ("BLOCKED", reason.clone()),
+        };
+        println!("{:<24} {:>9}  {:<8} {}", result.check.service, port, status, detail);
+    }
+
+    let blocked: Vec<String> = results
+        .iter()
+        .filter(|result| matches!(result.status, PortStatus::Blocked { .. }))
+        .map(|result| format!("{}/{}", result.check.port, result.check.probe.protocol()))
+        .collect();
+    if blocked.is_empty() {
+        println!("All ports open");
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("Blocked: {}", blocked.join(", "));
+    Ok(ExitCode::from(EXIT_BLOCKED))
+}
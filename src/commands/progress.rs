@@ -0,0 +1,139 @@
+use crate::cli::ProgressStyle;
+use crate::spoken::Announcer;
+use ericspeed::TestUpdate;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+// Plain style prints a phase's progress at most this often, so logs stay short
+const PLAIN_INTERVAL: Duration = Duration::from_secs(1);
+const BAR_WIDTH: usize = 20;
+
+// Reports a running test on stderr, leaving stdout to the result
+pub struct Progress {
+    style: ProgressStyle,
+    // When plain style last printed, reset when a phase completes
+    last_print: Option<Instant>,
+    // Length of the bar line on screen, to blank it out before other output
+    bar_len: usize,
+    announcer: Announcer,
+}
+
+impl Progress {
+    pub fn new(style: ProgressStyle) -> Self {
+        Self {
+            style,
+            last_print: None,
+            bar_len: 0,
+            announcer: Announcer::default(),
+        }
+    }
+
+    pub fn update(&mut self, update: &TestUpdate) {
+        if self.style == ProgressStyle::Spoken {
+            if let Some(sentence) = self.announcer.announce(update) {
+                eprintln!("{}", sentence);
+            }
+            return;
+        }
+        match update {
+            TestUpdate::PingProgress(p) => {
+                let latest = p.latest_ping.map_or("-".to_string(), |ms| format!("{:.0} ms", ms));
+                self.running("ping", None, format!("latest {}", latest));
+            }
+            TestUpdate::DownloadProgress(p) => {
+                let fraction = fraction(p.downloaded_bytes, p.total_bytes);
+                self.running("download", Some(fraction), format!("{:.1} Mbps", p.speed_mbps));
+            }
+            TestUpdate::UploadProgress(p) => {
+                let fraction = fraction(p.uploaded_bytes, p.total_bytes);
+                self.running("upload", Some(fraction), format!("{:.1} Mbps", p.speed_mbps));
+            }
+            TestUpdate::BidirectionalProgress(p) => {
+                let speeds = format!("down {:.1} up {:.1} Mbps", p.download_mbps, p.upload_mbps);
+                self.running("both", Some(p.fraction), speeds);
+            }
+            TestUpdate::PingComplete { avg_ms, jitter_ms, ping_mode, loss_percent, .. } => {
+                let summary = format!(
+                    "{:.0} ms {}, jitter {:.1} ms, loss {:.1}%",
+                    avg_ms,
+                    ping_mode.label(),
+                    jitter_ms,
+                    loss_percent
+                );
+                self.done("ping", summary);
+            }
+            TestUpdate::DownloadComplete { speed_mbps, .. } => {
+                self.done("download", format!("{:.1} Mbps", speed_mbps));
+            }
+            TestUpdate::UploadComplete { speed_mbps, .. } => {
+                self.done("upload", format!("{:.1} Mbps", speed_mbps));
+            }
+            TestUpdate::BidirectionalComplete(result) => {
+                let speeds =
+                    format!("down {:.1} up {:.1} Mbps", result.download_mbps, result.upload_mbps);
+                self.done("both", speeds);
+            }
+            // Printed by the caller, which needs the bar out of the way
+            TestUpdate::Warning { .. } | TestUpdate::Failed { .. } => self.clear_bar(),
+        }
+    }
+
+    // Blanks out the bar line, if any, so other output starts on a clean line
+    pub fn clear_bar(&mut self) {
+        if self.bar_len > 0 {
+            eprint!("\r{}\r", " ".repeat(self.bar_len));
+            self.bar_len = 0;
+        }
+    }
+
+    fn running(&mut self, phase: &str, fraction: Option<f64>, detail: String) {
+        match self.style {
+            ProgressStyle::None | ProgressStyle::Spoken => {}
+            ProgressStyle::Plain => {
+                if self.last_print.is_some_and(|at| at.elapsed() < PLAIN_INTERVAL) {
+                    return;
+                }
+                self.last_print = Some(Instant::now());
+                let percent = fraction.map_or(String::new(), |f| format!("{:>3.0}%  ", f * 100.0));
+                eprintln!("{:<9} {}{}", phase, percent, detail);
+            }
+            ProgressStyle::Bar => {
+                let bar = fraction.map_or(String::new(), |f| {
+                    let filled = ((f * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+                    format!(
+                        "[{}{}] {:>3.0}%  ",
+                        "#".repeat(filled),
+                        ".".repeat(BAR_WIDTH - filled),
+                        f * 100.0
+                    )
+                });
+                self.draw_bar(format!("{:<9} {}{}", phase, bar, detail));
+            }
+        }
+    }
+
+    fn done(&mut self, phase: &str, summary: String) {
+        self.last_print = None;
+        if self.style != ProgressStyle::None {
+            self.clear_bar();
+            eprintln!("{:<9} done  {}", phase, summary);
+        }
+    }
+
+    // Redraws the bar in place with a carriage return, padding out what's
+    // left of a longer previous line
+    fn draw_bar(&mut self, line: String) {
+        let len = line.chars().count();
+        eprint!("\r{}{}", line, " ".repeat(self.bar_len.saturating_sub(len)));
+        let _ = io::stderr().flush();
+        self.bar_len = len;
+    }
+}
+
+fn fraction(done: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    }
+}
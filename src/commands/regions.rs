@@ -0,0 +1,53 @@
+use crate::cli::RegionsArgs;
+use crate::config::Config;
+use anyhow::{bail, Result};
+use ericspeed::speedtest::http_client;
+use ericspeed::speedtest::regions::RegionLatencyTest;
+use ericspeed::Settings;
+use std::process::ExitCode;
+
+const EXIT_TEST_ERROR: u8 = 2;
+
+pub async fn run(args: RegionsArgs, config: &Config, settings: Settings) -> Result<ExitCode> {
+    let mut regions = config.regions();
+    if !args.providers.is_empty() {
+        regions.retain(|region| {
+            args.providers.iter().any(|provider| provider.eq_ignore_ascii_case(&region.provider))
+        });
+        if regions.is_empty() {
+            bail!("no regions of provider {}", args.providers.join(" or "));
+        }
+    }
+    println!("Probing {} regions, {} requests each", regions.len(), args.probes.max(1));
+
+    // Region endpoints are third parties; keep the test server's credentials away from them
+    let client = http_client(&settings.without_credentials())?;
+    let mut results = RegionLatencyTest::new(client, regions)
+        .with_probes(args.probes)
+        .run()
+        .await;
+    // Closest first; unreachable regions go last
+    results.sort_by(|a, b| {
+        let median = |ms: Option<f64>| ms.unwrap_or(f64::INFINITY);
+        median(a.median_ms).total_cmp(&median(b.median_ms))
+    });
+
+    let format_ms = |ms: Option<f64>| ms.map_or("—".to_string(), |ms| format!("{:.1} ms", ms));
+    println!("{:<8} {:<20} {:>10} {:>10} {:>6}", "Provider", "Region", "Median", "Min", "Lost");
+    for result in &results {
+        println!(
+            "{:<8} {:<20} {:>10} {:>10} {:>6}",
+            result.region.provider,
+            result.region.name,
+            format_ms(result.median_ms),
+            format_ms(result.min_ms),
+            format!("{}/{}", result.lost, args.probes.max(1))
+        );
+    }
+
+    if results.iter().all(|result| result.median_ms.is_none()) {
+        eprintln!("no region answered");
+        return Ok(ExitCode::from(EXIT_TEST_ERROR));
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,90 @@
+use crate::cli::ServeArgs;
+use anyhow::{Context, Result};
+use axum::{
+    body::{Body, Bytes},
+    extract::Query,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use ericspeed::speedtest::udp;
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::process::ExitCode;
+use tokio::net::{TcpListener, UdpSocket};
+use tracing::{info, warn};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+// Guards against a single request tying up the link indefinitely
+const MAX_DOWNLOAD_BYTES: u64 = 10_000_000_000;
+
+static ZEROS: [u8; CHUNK_SIZE] = [0; CHUNK_SIZE];
+
+#[derive(Debug, Deserialize)]
+struct DownloadQuery {
+    bytes: Option<u64>,
+}
+
+// Serves the same `__down`/`__up` endpoints as Cloudflare, so another
+// machine can point `--server` at this one
+pub async fn run(args: ServeArgs) -> Result<ExitCode> {
+    let app = Router::new()
+        .route("/__down", get(download))
+        .route("/__up", post(upload));
+
+    let listener = TcpListener::bind(args.listen)
+        .await
+        .with_context(|| format!("failed to listen on {}", args.listen))?;
+    let addr = listener.local_addr()?;
+
+    // UDP echo for `ericspeed udp`, on the same port as HTTP
+    let socket = UdpSocket::bind(addr)
+        .await
+        .with_context(|| format!("failed to listen on udp {}", addr))?;
+    tokio::spawn(async move {
+        if let Err(err) = udp::reflect(socket).await {
+            warn!(error = %err, "udp echo stopped");
+        }
+    });
+
+    info!(%addr, "test server listening");
+    println!("Serving speed test endpoints on http://{}", addr);
+    println!("Run `ericspeed --server http://<this-host>:{}` on another machine", addr.port());
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn download(Query(query): Query<DownloadQuery>) -> impl IntoResponse {
+    let bytes = query.bytes.unwrap_or(0).min(MAX_DOWNLOAD_BYTES);
+    let chunks = stream::iter((0..bytes).step_by(CHUNK_SIZE)).map(move |offset| {
+        let len = (bytes - offset).min(CHUNK_SIZE as u64) as usize;
+        Ok::<_, Infallible>(Bytes::from_static(&ZEROS[..len]))
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, bytes.to_string()),
+        ],
+        Body::from_stream(chunks),
+    )
+}
+
+async fn upload(body: Body) -> StatusCode {
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        if let Err(err) = chunk {
+            warn!(error = %err, "upload body failed");
+            return StatusCode::BAD_REQUEST;
+        }
+    }
+    StatusCode::OK
+}
@@ -0,0 +1,85 @@
+use crate::cli::SweepArgs;
+use anyhow::Result;
+use ericspeed::speedtest::download::DownloadTest;
+use ericspeed::speedtest::http_client;
+use ericspeed::speedtest::server::Server;
+use ericspeed::speedtest::sweep::{StreamSweep, SweepPoint, DEFAULT_STAGE, SWEEP_STREAMS};
+use ericspeed::Settings;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const EXIT_TEST_ERROR: u8 = 2;
+const BAR_WIDTH: usize = 40;
+// The best stage beating a single stream by this factor means one flow
+// can't fill the link
+const SCALING_FACTOR: f64 = 1.5;
+
+pub async fn run(args: SweepArgs, settings: Settings) -> Result<ExitCode> {
+    let stage = args
+        .stage
+        .map(Duration::from_secs)
+        .or(settings.transfer_duration)
+        .unwrap_or(DEFAULT_STAGE);
+    let download_size = settings.download_size_bytes();
+    let url = Server::from_base("Test server", &settings.server_url).download_url;
+    let test = DownloadTest::new(http_client(&settings)?, url, download_size)
+        .with_chunk_size(settings.download_chunk_bytes())
+        .with_retry(settings.retry);
+    println!(
+        "Downloading for {}s at each of {:?} connections",
+        stage.as_secs(),
+        SWEEP_STREAMS
+    );
+
+    let (progress_tx, mut progress_rx) = mpsc::channel(32);
+    let sweep = StreamSweep::new(test).with_stage(stage);
+    let (result, ()) = tokio::join!(sweep.run(progress_tx), async {
+        while let Some(update) = progress_rx.recv().await {
+            print!(
+                "\r{:>2} connections   {:>9.1} Mbps",
+                update.streams, update.progress.avg_speed_mbps
+            );
+            let _ = io::stdout().flush();
+        }
+        print!("\r{}\r", " ".repeat(32));
+    });
+
+    let points = match result {
+        Ok(points) => points,
+        Err(err) => {
+            eprintln!("sweep failed: {:#}", err);
+            return Ok(ExitCode::from(EXIT_TEST_ERROR));
+        }
+    };
+    print_chart(&points);
+    Ok(ExitCode::SUCCESS)
+}
+
+// Throughput per connection count as horizontal bars, then what the shape says
+fn print_chart(points: &[SweepPoint]) {
+    let best = points.iter().copied().max_by(|a, b| a.mbps.total_cmp(&b.mbps));
+    let Some(best) = best.filter(|best| best.mbps > 0.0) else {
+        println!("No data was downloaded");
+        return;
+    };
+
+    println!("{:>11} {:>12}", "Connections", "Mbps");
+    for point in points {
+        let filled = ((point.mbps / best.mbps * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+        println!("{:>11} {:>12.1}  {}", point.streams, point.mbps, "█".repeat(filled));
+    }
+
+    let single = points.first().map_or(0.0, |point| point.mbps);
+    if single > 0.0 && best.mbps >= single * SCALING_FACTOR {
+        println!(
+            "One connection gets {:.0}% of the best, reached at {}: each flow is held back, \
+             by per-connection shaping or a path too long for one TCP window",
+            single / best.mbps * 100.0,
+            best.streams
+        );
+    } else {
+        println!("One connection nearly fills the link; more don't add much");
+    }
+}
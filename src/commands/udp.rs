@@ -0,0 +1,48 @@
+use crate::cli::UdpArgs;
+use anyhow::{anyhow, Result};
+use ericspeed::speedtest::quality::{estimate_mos, mos_label};
+use ericspeed::speedtest::udp::UdpTest;
+use ericspeed::Settings;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const EXIT_TEST_ERROR: u8 = 2;
+
+pub async fn run(args: UdpArgs, settings: Settings) -> Result<ExitCode> {
+    // The built-in server answers UDP on the same host and port as HTTP
+    let url = reqwest::Url::parse(&settings.server_url)?;
+    let target = url
+        .socket_addrs(|| None)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("could not resolve {}", settings.server_url))?;
+
+    let test = UdpTest::new(target)
+        .with_packets(args.packets)
+        .with_interval(Duration::from_millis(args.interval))
+        .with_payload_size(args.size);
+
+    println!("Sending {} packets to {} ...", args.packets, target);
+    let (progress_tx, mut progress_rx) = mpsc::channel(32);
+    let (result, ()) = tokio::join!(test.run(progress_tx), async {
+        while progress_rx.recv().await.is_some() {}
+    });
+
+    let result = match result {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("udp test failed: {:#}", err);
+            return Ok(ExitCode::from(EXIT_TEST_ERROR));
+        }
+    };
+
+    println!("{:<12} {}/{}", "Received", result.received, result.sent);
+    println!("{:<12} {:.1}%", "Loss", result.loss_percent);
+    println!("{:<12} {:.2} ms", "Jitter", result.jitter_ms);
+    println!("{:<12} {}", "Reordered", result.reordered);
+    println!("{:<12} {:.1} ms", "Avg RTT", result.avg_rtt_ms);
+    let mos = estimate_mos(result.avg_rtt_ms, result.jitter_ms, result.loss_percent);
+    println!("{:<12} {:.1} ({})", "MOS", mos, mos_label(mos));
+    Ok(ExitCode::SUCCESS)
+}
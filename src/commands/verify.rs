@@ -0,0 +1,34 @@
+use crate::cli::VerifyArgs;
+use crate::config::Config;
+use anyhow::{Context, Result};
+use ericspeed::integrity;
+use serde_json::Value;
+use std::io::{self, Read};
+use std::process::ExitCode;
+use std::{fs, path::Path};
+
+// The document doesn't match its digest
+const EXIT_TAMPERED: u8 = 1;
+
+pub fn run(args: VerifyArgs, config: &Config) -> Result<ExitCode> {
+    let text = if args.file == Path::new("-") {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        fs::read_to_string(&args.file)
+            .with_context(|| format!("couldn't read {}", args.file.display()))?
+    };
+    let document: Value = serde_json::from_str(&text).context("not valid JSON")?;
+
+    match integrity::verify(&document, config.integrity.key()) {
+        Ok(()) => {
+            println!("ok: the result matches its digest");
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(err) => {
+            println!("FAILED: {:#}", err);
+            Ok(ExitCode::from(EXIT_TAMPERED))
+        }
+    }
+}
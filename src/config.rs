@@ -0,0 +1,456 @@
+use crate::ui::i18n::Locale;
+use crate::ui::theme::Theme;
+use anyhow::{bail, Context, Result};
+use ericspeed::alerts::Thresholds;
+use ericspeed::history::{History, Retention};
+use ericspeed::schedule::Schedule;
+use ericspeed::speedtest::regions::Region;
+use ericspeed::speedtest::retry::RetryPolicy;
+use ericspeed::speedtest::server::Server;
+use ericspeed::settings::{Auth, ChartMarker, Credentials, PlanSpeed, Profile, Timeouts};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml_edit::{value, DocumentMut, Item, Table};
+
+const DEFAULT_INTERVAL_MINUTES: u64 = 60;
+
+// Settings read from config.toml; every section is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub daemon: DaemonConfig,
+    // Custom profile saved from the settings view; unset fields use the standard profile
+    pub profile: Option<Profile>,
+    // Advertised ISP speeds; --plan takes precedence
+    pub plan: Option<PlanSpeed>,
+    // Megabytes transfer buffers may use; --memory-budget takes precedence
+    pub memory_budget_mb: Option<u64>,
+    // Milliseconds between transfer progress samples, 50 to 1000;
+    // --sample-interval takes precedence
+    pub sample_interval_ms: Option<u64>,
+    // Color theme of the TUI; --theme takes precedence
+    pub theme: Option<Theme>,
+    // Language of the TUI; --locale takes precedence, then LC_ALL, LC_MESSAGES and LANG
+    pub locale: Option<Locale>,
+    // Screen-reader friendly output, as with --accessible
+    pub accessible: bool,
+    // Check for a captive portal before each test. Unset checks only against
+    // the default server: one on the LAN answers with or without a portal
+    pub portal_check: Option<bool>,
+    // How charts draw points: braille, block or dot
+    pub chart_marker: Option<ChartMarker>,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+    pub integrity: IntegrityConfig,
+    pub history: HistoryConfig,
+    pub timeouts: TimeoutConfig,
+    pub retry: RetryConfig,
+    pub notify: NotifyConfig,
+    pub pushgateway: Option<PushgatewayConfig>,
+    pub statsd: Option<StatsdConfig>,
+    pub syslog: Option<SyslogConfig>,
+    // Servers on the comparison view, e.g. test objects on several CDNs;
+    // replaces the built-in list when any are given
+    pub cdn: Vec<CdnConfig>,
+    // Cloud regions for `ericspeed regions`; replaces the built-in AWS ones
+    // when any are given
+    pub region: Vec<RegionConfig>,
+}
+
+impl Config {
+    // Servers to compare, from the [[cdn]] tables or else the built-in ones
+    pub fn comparison_servers(&self) -> Vec<Server> {
+        if self.cdn.is_empty() {
+            return Server::builtin();
+        }
+        self.cdn.iter().map(|cdn| Server::new(&cdn.name, &cdn.url)).collect()
+    }
+
+    // Regions to measure, from the [[region]] tables or else the built-in ones
+    pub fn regions(&self) -> Vec<Region> {
+        if self.region.is_empty() {
+            return Region::defaults();
+        }
+        self.region
+            .iter()
+            .map(|region| Region::new(&region.provider, &region.name, &region.url))
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CdnConfig {
+    pub name: String,
+    // Download URL; "{bytes}" is replaced with the download size, and URLs
+    // without it serve a fixed-size file
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegionConfig {
+    pub provider: String,
+    pub name: String,
+    // Any URL served from inside the region, not from a CDN edge in front of it
+    pub url: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    // Cron expression in local time, e.g. "0 */2 * * *"
+    pub schedule: Option<String>,
+    // Minutes between runs, when no cron schedule is given
+    pub interval_minutes: Option<u64>,
+    // Address for the HTTP control API; off when unset
+    pub api_listen: Option<SocketAddr>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    // PEM bundle of extra root certificates to trust
+    pub ca_cert: Option<PathBuf>,
+    // Disables certificate validation; only for testing against known hosts
+    pub accept_invalid_certs: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntegrityConfig {
+    // Secret for HMAC digests on exported results; unset uses a plain SHA-256,
+    // which catches edits but not anyone who recomputes it
+    pub key: Option<String>,
+}
+
+impl IntegrityConfig {
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref().map(str::as_bytes)
+    }
+}
+
+// Retention for stored results; unset keeps everything. Zero results would
+// delete each one as it is saved, so it is rejected
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub keep_results: Option<NonZeroUsize>,
+    pub keep_days: Option<u64>,
+}
+
+impl HistoryConfig {
+    // The default history file with these limits applied on every save
+    pub fn open(&self) -> History {
+        History::default().with_retention(Retention {
+            max_results: self.keep_results.map(NonZeroUsize::get),
+            max_age: self.keep_days.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        })
+    }
+}
+
+// Seconds each phase, and the whole test, may take before it fails; unset is unlimited
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutConfig {
+    pub ping_secs: Option<u64>,
+    pub download_secs: Option<u64>,
+    pub upload_secs: Option<u64>,
+    pub total_secs: Option<u64>,
+}
+
+impl TimeoutConfig {
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            ping: self.ping_secs.map(Duration::from_secs),
+            download: self.download_secs.map(Duration::from_secs),
+            upload: self.upload_secs.map(Duration::from_secs),
+            total: self.total_secs.map(Duration::from_secs),
+        }
+    }
+}
+
+// Retries after connection errors and 5xx responses, waiting backoff_ms
+// and doubling the wait after each one
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub retries: Option<u32>,
+    pub backoff_ms: Option<u64>,
+}
+
+impl RetryConfig {
+    pub fn policy(&self) -> RetryPolicy {
+        let mut policy = RetryPolicy::default();
+        if let Some(retries) = self.retries {
+            policy.retries = retries;
+        }
+        if let Some(backoff_ms) = self.backoff_ms {
+            policy.initial_backoff = Duration::from_millis(backoff_ms);
+            policy.max_backoff = policy.max_backoff.max(policy.initial_backoff);
+        }
+        policy
+    }
+}
+
+// Prometheus Pushgateway that headless runs push their metrics to
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PushgatewayConfig {
+    // Base URL, e.g. "http://pushgateway:9091"
+    pub url: String,
+    #[serde(default = "default_job")]
+    pub job: String,
+    pub instance: Option<String>,
+    // Extra grouping labels, e.g. { site = "office" }
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+fn default_job() -> String {
+    "ericspeed".to_string()
+}
+
+// StatsD listener that headless runs send their metrics to as gauges
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsdConfig {
+    // host:port of the listener, e.g. "127.0.0.1:8125"
+    pub address: String,
+    #[serde(default = "default_job")]
+    pub prefix: String,
+    // DogStatsD tags appended to every metric; plain StatsD servers may reject them
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+// Local syslog daemon or journald that headless runs log each result to
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyslogConfig {
+    #[serde(default)]
+    pub target: SyslogTarget,
+    // SYSLOG_IDENTIFIER, or APP-NAME in syslog, e.g. for `journalctl -t ericspeed`
+    #[serde(default = "default_job")]
+    pub identifier: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyslogTarget {
+    // journald when its socket exists, else /dev/log
+    #[default]
+    Auto,
+    Journald,
+    Syslog,
+}
+
+// Alerts sent by the daemon when a scheduled test fails or falls below these thresholds
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    pub min_download_mbps: Option<f64>,
+    pub min_upload_mbps: Option<f64>,
+    pub max_ping_ms: Option<f64>,
+    pub email: Option<EmailConfig>,
+    // One [[notify.webhook]] table per target
+    #[serde(rename = "webhook")]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl NotifyConfig {
+    pub fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            min_download_mbps: self.min_download_mbps,
+            min_upload_mbps: self.min_upload_mbps,
+            max_ping_ms: self.max_ping_ms,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    // Defaults to the standard port for `security`
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Sender and recipients, e.g. "ericspeed <alerts@example.com>"
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    // Upgrade a plain connection, usually on port 587
+    #[default]
+    StartTls,
+    // TLS from the start, usually on port 465
+    Tls,
+    // Unencrypted; only for relays on a trusted network
+    None,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    // The alert and full result as plain JSON, for custom receivers
+    #[default]
+    Json,
+    // Slack incoming webhook message with a colored attachment
+    Slack,
+    // Discord webhook message with an embed
+    Discord,
+}
+
+// Slack and Discord webhook URLs embed their secret
+impl std::fmt::Debug for WebhookConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookConfig")
+            .field("url", &"<redacted>")
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+// Keeps the SMTP password out of debug output
+impl std::fmt::Debug for EmailConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmailConfig")
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("security", &self.security)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .finish()
+    }
+}
+
+// Credentials for a private test server
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // Sent as "Authorization: Bearer <token>"
+    pub token: Option<String>,
+    // HTTP basic auth, as an alternative to a token
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Extra headers sent with every test request
+    pub headers: BTreeMap<String, String>,
+}
+
+impl AuthConfig {
+    pub fn credentials(&self) -> Result<Credentials> {
+        let auth = match (&self.token, &self.username) {
+            (Some(_), Some(_)) => bail!("set either auth.token or auth.username, not both"),
+            (Some(token), None) => Some(Auth::Bearer(token.clone())),
+            (None, Some(username)) => Some(Auth::Basic {
+                username: username.clone(),
+                password: self.password.clone(),
+            }),
+            (None, None) if self.password.is_some() => bail!("auth.password needs auth.username"),
+            (None, None) => None,
+        };
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        Ok(Credentials { auth, headers })
+    }
+}
+
+// Keeps secrets out of debug output
+impl std::fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>");
+        f.debug_struct("AuthConfig")
+            .field("token", &redacted(&self.token))
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl DaemonConfig {
+    pub fn schedule(&self) -> Result<Schedule> {
+        match (&self.schedule, self.interval_minutes) {
+            (Some(_), Some(_)) => bail!("set either daemon.schedule or daemon.interval_minutes, not both"),
+            (Some(expression), None) => Schedule::cron(expression),
+            (None, Some(minutes)) => Schedule::interval(minutes),
+            (None, None) => Schedule::interval(DEFAULT_INTERVAL_MINUTES),
+        }
+    }
+}
+
+pub fn default_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("ericspeed")
+        .join("config.toml")
+}
+
+// The file given with --config, or the default one
+pub fn path(explicit: Option<&Path>) -> PathBuf {
+    explicit.map_or_else(default_path, Path::to_path_buf)
+}
+
+// An explicitly given file must exist; the default one is optional
+pub fn load(path: Option<&Path>) -> Result<Config> {
+    let required = path.is_some();
+    let path = self::path(path);
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => {
+            return Ok(Config::default())
+        }
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    toml::from_str(&text).with_context(|| format!("invalid config in {}", path.display()))
+}
+
+// Writes `profile` as the [profile] table, keeping the rest of the file and its comments
+pub fn save_profile(path: &Path, profile: &Profile) -> Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let mut document: DocumentMut = text
+        .parse()
+        .with_context(|| format!("invalid config in {}", path.display()))?;
+
+    let mut table = Table::new();
+    table["ping_count"] = value(profile.ping_count as i64);
+    table["download_size_mb"] = value(profile.download_size_mb as i64);
+    table["upload_size_mb"] = value(profile.upload_size_mb as i64);
+    table["download_streams"] = value(profile.download_streams as i64);
+    table["upload_streams"] = value(profile.upload_streams as i64);
+    document["profile"] = Item::Table(table);
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    fs::write(path, document.to_string()).with_context(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,186 @@
+use ericspeed::integrity;
+use ericspeed::network::{local_interfaces, ActiveNetwork};
+use ericspeed::{Settings, SpeedTestResult, TestPhase};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct SampleRecord {
+    pub phase: TestPhase,
+    pub timestamp: SystemTime,
+    pub value: f64,
+}
+
+impl SampleRecord {
+    pub fn now(phase: TestPhase, value: f64) -> Self {
+        Self {
+            phase,
+            timestamp: SystemTime::now(),
+            value,
+        }
+    }
+}
+
+pub fn default_csv_path() -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("ericspeed-{}.csv", secs))
+}
+
+pub fn write_csv(path: &Path, records: &[SampleRecord], tag: Option<&str>) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "timestamp,phase,value,unit,tag")?;
+    let tag = csv_field(tag.unwrap_or(""));
+
+    for record in records {
+        let timestamp = record
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or(0.0);
+        let (phase, unit) = match record.phase {
+            TestPhase::Ping => ("ping", "ms"),
+            TestPhase::Download => ("download", "mbps"),
+            TestPhase::Upload => ("upload", "mbps"),
+            TestPhase::Idle | TestPhase::Bidirectional | TestPhase::Complete => continue,
+        };
+        writeln!(out, "{:.3},{},{:.3},{},{}", timestamp, phase, record.value, unit, tag)?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+// Tags are free text, so quote them when they contain CSV syntax
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// A result in the schema of Ookla's `speedtest -f json`, so tools built
+// around that output can read ours. Fields we don't know are null.
+pub fn speedtest_json(
+    result: &SpeedTestResult,
+    settings: &Settings,
+    network: Option<&ActiveNetwork>,
+) -> Value {
+    // Bandwidth is in bytes per second; elapsed in milliseconds
+    let transfer = |mbps: f64, bytes: u64, elapsed_ms: f64, loaded_ping: Option<f64>| {
+        let bandwidth = mbps * 1_000_000.0 / 8.0;
+        let mut transfer = json!({
+            "bandwidth": bandwidth.round() as u64,
+            "bytes": bytes,
+            "elapsed": elapsed_ms.round() as u64,
+        });
+        // Ookla reports the interquartile mean; the loaded probe keeps only the mean
+        if let Some(ping) = loaded_ping {
+            transfer["latency"] = json!({ "iqm": ping });
+        }
+        transfer
+    };
+
+    let mut output = json!({
+        "type": "result",
+        "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        "ping": {
+            "jitter": result.jitter_ms,
+            "latency": result.ping_ms,
+            "low": result.ping_min_ms,
+            "high": result.ping_max_ms,
+        },
+        "download": transfer(
+            result.download_mbps,
+            result.download_bytes,
+            result.download_elapsed_ms,
+            result.download_ping_ms,
+        ),
+        "upload": transfer(
+            result.upload_mbps,
+            result.upload_bytes,
+            result.upload_elapsed_ms,
+            result.upload_ping_ms,
+        ),
+        "packetLoss": result.ping_loss_percent,
+        "isp": null,
+        "server": {
+            "id": null,
+            "host": reqwest::Url::parse(&settings.server_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string)),
+            "name": settings.server_url,
+            "location": null,
+            "country": null,
+            "ip": null,
+        },
+        // Results aren't uploaded anywhere, so there is no page for them
+        "result": {
+            "url": null,
+        },
+    });
+
+    if let Some(network) = network {
+        let internal_ip = settings.local_address.or_else(|| {
+            local_interfaces()
+                .into_iter()
+                .find(|iface| iface.name == network.interface)
+                .map(|iface| iface.addr)
+        });
+        output["interface"] = json!({
+            "internalIp": internal_ip,
+            "externalIp": null,
+            "name": network.interface,
+        });
+    }
+    if let Some(tag) = &settings.tag {
+        output["tag"] = json!(tag);
+    }
+    output
+}
+
+// The settings a result was measured with, to sign along with it
+fn config_json(settings: &Settings) -> Value {
+    json!({
+        "server": settings.server_url,
+        "download_bytes": settings.download_size_bytes(),
+        "upload_bytes": settings.upload_size_bytes(),
+        "download_streams": settings.download_streams,
+        "upload_streams": settings.upload_streams,
+        "ramp_up": settings.ramp_up,
+        "duration_secs": settings.transfer_duration.map(|duration| duration.as_secs()),
+        "early_exit": settings.early_exit,
+        "cold_connections": settings.cold_connections,
+        "ping_mode": settings.ping_mode,
+    })
+}
+
+// Adds the settings to an exported result, then an integrity digest over both
+pub fn signed(mut document: Value, settings: &Settings, key: Option<&[u8]>) -> Value {
+    document["config"] = config_json(settings);
+    integrity::sign(&mut document, key);
+    document
+}
+
+// One-line summary of a result, short enough to fit a QR code a terminal can show
+pub fn share_text(result: &SpeedTestResult, tag: Option<&str>) -> String {
+    let mut text = format!(
+        "ericspeed {}: down {:.1} Mbps, up {:.1} Mbps, ping {:.0} ms, jitter {:.1} ms",
+        chrono::Local::now().format("%Y-%m-%d %H:%M"),
+        result.download_mbps,
+        result.upload_mbps,
+        result.ping_ms,
+        result.jitter_ms
+    );
+    if let Some(tag) = tag {
+        text.push_str(&format!(" ({})", tag));
+    }
+    text
+}
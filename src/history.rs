@@ -0,0 +1,350 @@
+use crate::network::ActiveNetwork;
+use crate::speedtest::SpeedTestResult;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// One completed test as stored in the history file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// When the test finished, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Interface (and Wi-Fi network) the test ran over.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<ActiveNetwork>,
+    #[serde(flatten)]
+    pub result: SpeedTestResult,
+}
+
+impl HistoryEntry {
+    /// Stamps `result` with the current time.
+    pub fn now(result: SpeedTestResult) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            timestamp,
+            tag: None,
+            network: None,
+            result,
+        }
+    }
+
+    pub fn with_tag(mut self, tag: Option<String>) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    pub fn with_network(mut self, network: Option<ActiveNetwork>) -> Self {
+        self.network = network;
+        self
+    }
+}
+
+/// Averages over the results that fell in one hour or weekday.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeBucket {
+    pub count: usize,
+    pub download_mbps: f64,
+    pub upload_mbps: f64,
+}
+
+/// Average speeds by local hour of day and day of week, which exposes
+/// congestion patterns such as slow evenings.
+#[derive(Debug, Clone, Default)]
+pub struct TimeOfDayStats {
+    pub by_hour: [TimeBucket; 24],
+    /// Monday first.
+    pub by_weekday: [TimeBucket; 7],
+}
+
+impl TimeOfDayStats {
+    pub fn from_entries(entries: &[HistoryEntry]) -> Self {
+        let mut stats = Self::default();
+        for entry in entries {
+            let Some(time) = DateTime::from_timestamp(entry.timestamp as i64, 0) else {
+                continue;
+            };
+            let time = time.with_timezone(&Local);
+            let hour = &mut stats.by_hour[time.hour() as usize];
+            hour.add(&entry.result);
+            let weekday = &mut stats.by_weekday[time.weekday().num_days_from_monday() as usize];
+            weekday.add(&entry.result);
+        }
+        stats
+    }
+}
+
+impl TimeBucket {
+    // Folds one more result into the running averages
+    fn add(&mut self, result: &SpeedTestResult) {
+        self.count += 1;
+        let n = self.count as f64;
+        self.download_mbps += (result.download_mbps - self.download_mbps) / n;
+        self.upload_mbps += (result.upload_mbps - self.upload_mbps) / n;
+    }
+}
+
+/// Past results in a SQLite database, indexed by time and tag.
+#[derive(Debug, Clone)]
+pub struct History {
+    path: PathBuf,
+    retention: Retention,
+}
+
+/// How much history to keep; results beyond either limit are pruned after
+/// each append. `None` leaves that limit off.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Retention {
+    pub max_results: Option<usize>,
+    pub max_age: Option<Duration>,
+}
+
+/// Narrows [`History::query`] to recent results or one tag.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Only results at or after this Unix time.
+    pub since: Option<u64>,
+    pub tag: Option<String>,
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS results (
+        id INTEGER PRIMARY KEY,
+        timestamp INTEGER NOT NULL,
+        tag TEXT,
+        download_mbps REAL NOT NULL,
+        upload_mbps REAL NOT NULL,
+        ping_ms REAL NOT NULL,
+        jitter_ms REAL NOT NULL,
+        -- The whole entry, so new fields don't need a migration
+        entry TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS results_timestamp ON results (timestamp);
+    CREATE INDEX IF NOT EXISTS results_tag ON results (tag, timestamp);
+";
+
+// The daemon and the TUI may write at the same time
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl History {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            retention: Retention::default(),
+        }
+    }
+
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// `history.db` in the platform data directory.
+    pub fn default_path() -> PathBuf {
+        dirs::data_local_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("ericspeed")
+            .join("history.db")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &HistoryEntry) -> Result<()> {
+        let conn = self.open()?;
+        insert(&conn, entry)?;
+        let pruned = prune(&conn, self.retention)?;
+        if pruned > 0 {
+            debug!(pruned, "pruned old history");
+        }
+        Ok(())
+    }
+
+    /// Deletes entries from before the Unix time `before`, or with `tag`,
+    /// returning how many. With neither set, deletes everything.
+    pub fn purge(&self, before: Option<u64>, tag: Option<&str>) -> Result<usize> {
+        let conn = self.open()?;
+        let deleted = conn.execute(
+            "DELETE FROM results WHERE (?1 IS NULL OR timestamp < ?1) AND (?2 IS NULL OR tag = ?2)",
+            params![before, tag],
+        )?;
+        Ok(deleted)
+    }
+
+    /// All entries, oldest first.
+    pub fn load(&self) -> Result<Vec<HistoryEntry>> {
+        self.query(&HistoryFilter::default())
+    }
+
+    /// Entries matching `filter`, oldest first. Unreadable rows are skipped.
+    pub fn query(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>> {
+        let conn = self.open()?;
+        let mut statement = conn.prepare(
+            "SELECT id, entry FROM results
+             WHERE (?1 IS NULL OR timestamp >= ?1) AND (?2 IS NULL OR tag = ?2)
+             ORDER BY timestamp, id",
+        )?;
+        let rows = statement.query_map(params![filter.since, filter.tag], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (id, json) = row?;
+            match serde_json::from_str(&json) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => warn!(id, error = %err, "skipping bad history row"),
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn latest(&self) -> Result<Option<HistoryEntry>> {
+        let conn = self.open()?;
+        let json: Option<String> = conn
+            .query_row(
+                "SELECT entry FROM results ORDER BY timestamp DESC, id DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        json.map(|json| serde_json::from_str(&json).map_err(Into::into))
+            .transpose()
+    }
+
+    // Opens the database, creating it and importing the old JSON lines
+    // history on first use
+    fn open(&self) -> Result<Connection> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let mut conn = Connection::open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)?;
+
+        let legacy = self.path.with_file_name("history.jsonl");
+        if legacy.exists() {
+            // Holding the write lock while checking again keeps two processes
+            // from both importing
+            let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            if legacy.exists() {
+                let imported = import_jsonl(&transaction, &legacy)?;
+                fs::rename(&legacy, legacy.with_extension("jsonl.imported"))
+                    .with_context(|| format!("failed to rename {}", legacy.display()))?;
+                info!(entries = imported, from = %legacy.display(), "imported history");
+            }
+            transaction.commit()?;
+        }
+        Ok(conn)
+    }
+
+    /// The saved baseline, stored next to the history file.
+    pub fn baseline(&self) -> Result<Option<HistoryEntry>> {
+        let path = self.baseline_path();
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+
+    pub fn save_baseline(&self, entry: &HistoryEntry) -> Result<()> {
+        let path = self.baseline_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(entry)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn clear_baseline(&self) -> Result<()> {
+        match fs::remove_file(self.baseline_path()) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn baseline_path(&self) -> PathBuf {
+        self.path.with_file_name("baseline.json")
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(Self::default_path())
+    }
+}
+
+fn insert(conn: &Connection, entry: &HistoryEntry) -> Result<()> {
+    conn.execute(
+        "INSERT INTO results (timestamp, tag, download_mbps, upload_mbps, ping_ms, jitter_ms, entry)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            entry.timestamp,
+            entry.tag,
+            entry.result.download_mbps,
+            entry.result.upload_mbps,
+            entry.result.ping_ms,
+            entry.result.jitter_ms,
+            serde_json::to_string(entry)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn prune(conn: &Connection, retention: Retention) -> Result<usize> {
+    let mut pruned = 0;
+    if let Some(max_age) = retention.max_age {
+        let cutoff = SystemTime::now()
+            .checked_sub(max_age)
+            .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |cutoff| cutoff.as_secs());
+        pruned += conn.execute("DELETE FROM results WHERE timestamp < ?1", params![cutoff])?;
+    }
+    if let Some(max_results) = retention.max_results {
+        pruned += conn.execute(
+            "DELETE FROM results WHERE id NOT IN
+             (SELECT id FROM results ORDER BY timestamp DESC, id DESC LIMIT ?1)",
+            params![max_results],
+        )?;
+    }
+    Ok(pruned)
+}
+
+// Copies a history file from before the database, one JSON object per line.
+// Unreadable lines are skipped.
+fn import_jsonl(conn: &Connection, path: &Path) -> Result<usize> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut imported = 0;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => {
+                insert(conn, &entry)?;
+                imported += 1;
+            }
+            Err(err) => warn!(line = index + 1, error = %err, "skipping bad history line"),
+        }
+    }
+    Ok(imported)
+}
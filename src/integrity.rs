@@ -0,0 +1,75 @@
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, Mac};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+/// Key of the object holding the digest in a signed document, which lets
+/// collectors tell when an exported result was edited after the test wrote it.
+pub const FIELD: &str = "integrity";
+const SHA256: &str = "sha256";
+const HMAC_SHA256: &str = "hmac-sha256";
+
+/// Adds an [`FIELD`] object to the JSON object `document`, digesting the
+/// rest of it. With a `key` the digest is an HMAC, which only holders of
+/// the key can recompute; without one it only catches accidental edits.
+pub fn sign(document: &mut Value, key: Option<&[u8]>) {
+    let Some(object) = document.as_object_mut() else {
+        return;
+    };
+    object.remove(FIELD);
+    let (algorithm, digest) = digest(&Value::Object(object.clone()), key);
+    object.insert(FIELD.to_string(), json!({ "algorithm": algorithm, "digest": digest }));
+}
+
+/// Checks a document signed by [`sign`] against its digest. With a `key`,
+/// only an HMAC made with it passes: anyone can recompute a plain digest
+/// after editing the document.
+pub fn verify(document: &Value, key: Option<&[u8]>) -> Result<()> {
+    let mut object = document.as_object().context("not a JSON object")?.clone();
+    let integrity = object.remove(FIELD).context("no integrity digest")?;
+    let algorithm = integrity["algorithm"].as_str().context("no digest algorithm")?;
+    let expected = integrity["digest"].as_str().context("no digest")?;
+    match (algorithm, key) {
+        (SHA256, None) | (HMAC_SHA256, Some(_)) => {}
+        (SHA256, Some(_)) => bail!("expected an HMAC with the configured key, found a plain digest"),
+        (HMAC_SHA256, None) => bail!("signed with a key, but none was given"),
+        (other, _) => bail!("unknown digest algorithm {}", other),
+    }
+    let (_, actual) = digest(&Value::Object(object), key);
+    if actual != expected {
+        bail!("digest doesn't match; the document was changed after it was signed");
+    }
+    Ok(())
+}
+
+fn digest(document: &Value, key: Option<&[u8]>) -> (&'static str, String) {
+    let bytes = canonical(document).to_string().into_bytes();
+    match key {
+        Some(key) => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+            mac.update(&bytes);
+            (HMAC_SHA256, hex(&mac.finalize().into_bytes()))
+        }
+        None => (SHA256, hex(&Sha256::digest(&bytes))),
+    }
+}
+
+// The same document with object keys sorted, so the digest doesn't depend
+// on the order a writer or an editor left them in
+fn canonical(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            let sorted: Map<String, Value> =
+                keys.into_iter().map(|key| (key.clone(), canonical(&object[key]))).collect();
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonical).collect()),
+        other => other.clone(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
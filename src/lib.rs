@@ -0,0 +1,36 @@
+//! Measurement engine behind the `ericspeed` terminal UI.
+//!
+//! [`SpeedTest`] runs the ping, download and upload phases in order and
+//! reports progress as a stream of [`TestUpdate`]s, so other programs can
+//! embed the same measurements without the TUI.
+//!
+//! ```no_run
+//! use ericspeed::{Settings, SpeedTest, TestUpdate};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut handle = SpeedTest::new(Settings::default()).spawn();
+//! while let Some(update) = handle.updates.recv().await {
+//!     if let TestUpdate::DownloadComplete { speed_mbps, .. } = update {
+//!         println!("download: {:.1} Mbps", speed_mbps);
+//!     }
+//! }
+//! let result = handle.join().await?;
+//! println!("upload: {:.1} Mbps", result.upload_mbps);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod alerts;
+pub mod history;
+pub mod integrity;
+pub mod network;
+pub mod schedule;
+pub mod samples;
+pub mod settings;
+pub mod speedtest;
+
+pub use settings::Settings;
+pub use speedtest::{
+    SpeedTest, SpeedTestHandle, SpeedTestResult, TestOrchestrator, TestPhase, TestUpdate,
+    TransitionError,
+};
@@ -0,0 +1,36 @@
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+const LOG_FILE_PREFIX: &str = "ericspeed.log";
+
+// Logs go to a daily-rotated file since the TUI owns the terminal
+pub fn default_log_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("ericspeed")
+        .join("logs")
+}
+
+// Installs the file logger; the returned guard flushes pending lines when dropped
+pub fn init(level: LevelFilter, dir: Option<PathBuf>) -> Result<WorkerGuard> {
+    let dir = dir.unwrap_or_else(default_log_dir);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("creating log directory {}", dir.display()))?;
+
+    let appender = tracing_appender::rolling::daily(&dir, LOG_FILE_PREFIX);
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    // Only our own crate, dependency internals are too noisy to be useful here
+    let filter = EnvFilter::new(format!("ericspeed={}", level));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(false)
+        .init();
+
+    Ok(guard)
+}
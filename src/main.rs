@@ -1,73 +1,370 @@
+mod api;
 mod app;
-mod settings;
-mod speedtest;
+mod cli;
+mod commands;
+mod config;
+mod export;
+mod logging;
+mod metrics;
+mod notify;
+mod remote;
+mod runtime;
+mod screen;
+mod spoken;
+#[cfg(unix)]
+mod syslog;
+mod template;
 mod ui;
 
-use anyhow::Result;
-use app::{poll_event, run_speed_test, App, AppAction, TestUpdate};
-use crossterm::event::Event;
+use anyhow::{bail, Result};
+use app::{App, AppAction, AppView};
+use clap::Parser;
+use cli::{CheckArgs, Cli, Command};
+use config::Config;
+use crossterm::event::{Event, EventStream};
+use ericspeed::speedtest::compare::{compare_servers, CompareUpdate};
+use ericspeed::speedtest::http_client;
+use ericspeed::speedtest::monitor::LatencyMonitor;
+use ericspeed::speedtest::server;
+use ericspeed::history::History;
+use ericspeed::network::{self, NetworkSummary};
+use ericspeed::{Settings, SpeedTest, SpeedTestResult, TestPhase, TestUpdate};
 use ratatui::DefaultTerminal;
-use speedtest::TestPhase;
-use std::time::Duration;
-use tokio::sync::mpsc;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use futures::StreamExt;
+use std::future;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tokio::time::MissedTickBehavior;
+use tokio::task::JoinHandle;
+use tracing::warn;
+use screen::{JobSignal, Screen};
 use ui::draw_ui;
+use ui::i18n::Locale;
+
+const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+// Wakes the TUI with nothing else happening, to sample interface traffic
+const TICK: Duration = Duration::from_millis(250);
+// Redraw at least this often with nothing changed, e.g. for "5 min ago"
+const IDLE_REDRAW: Duration = Duration::from_secs(1);
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let mut terminal = ratatui::init();
-    terminal.clear()?;
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    let _log_guard = match cli.log_level {
+        Some(level) => Some(logging::init(level, cli.log_dir.clone())?),
+        None => None,
+    };
+
+    let config = config::load(cli.config.as_deref())?;
+    let settings = cli.settings(&config)?;
+    if settings.accept_invalid_certs {
+        eprintln!("warning: TLS certificate validation is disabled; results could come from an impostor server");
+        warn!("TLS certificate validation disabled");
+    }
+    let accessible = cli.accessible || config.accessible;
+    let command = match cli.command {
+        None if cli.no_tui => Some(Command::Check(CheckArgs::default())),
+        command => command,
+    };
+    match command {
+        Some(Command::Check(args)) => {
+            let args = if accessible { args.accessible() } else { args };
+            commands::check::run(args, &config, settings).await
+        }
+        Some(Command::Ctl(args)) => commands::ctl::run(args).await,
+        Some(Command::Daemon(args)) => commands::daemon::run(args, config, settings).await,
+        Some(Command::Serve(args)) => commands::serve::run(args).await,
+        Some(Command::Udp(args)) => commands::udp::run(args, settings).await,
+        Some(Command::Game(args)) => commands::game::run(args, settings).await,
+        Some(Command::Ping(args)) => commands::ping::run(args, settings).await,
+        Some(Command::Dns(args)) => commands::dns::run(args, settings).await,
+        Some(Command::Sweep(args)) => commands::sweep::run(args, settings).await,
+        Some(Command::Ports(args)) => commands::ports::run(args, settings).await,
+        Some(Command::Regions(args)) => commands::regions::run(args, &config, settings).await,
+        Some(Command::Verify(args)) => commands::verify::run(args, &config),
+        Some(Command::Status(args)) => {
+            commands::status::run(args, &config, cli.config.as_deref(), &settings)
+        }
+        Some(Command::History(args)) => commands::history::run(args, &config, settings),
+        None => {
+            // https://no-color.org: set and not empty
+            let no_color =
+                cli.no_color || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            ui::theme::init(cli.theme.or(config.theme).unwrap_or_default(), !no_color);
+            let locale = cli.locale.or(config.locale).or_else(Locale::from_env);
+            ui::i18n::init(locale.unwrap_or_default());
+            let screen = if cli.inline { Screen::Inline } else { Screen::Fullscreen };
+            let mut terminal = screen.init()?;
+            install_panic_hook();
 
-    let result = run_app(&mut terminal).await;
+            let config_path = config::path(cli.config.as_deref());
+            let integrity_key = config.integrity.key.clone();
+            let export_settings = settings.clone();
+            let history = config.history.open();
+            let launch = Launch {
+                start: cli.start,
+                exit_on_complete: cli.exit_on_complete,
+                screen,
+                accessible,
+            };
+            let result =
+                run_app(&mut terminal, settings, history, config_path, config, launch).await;
 
-    ratatui::restore();
-    result
+            screen.restore(&mut terminal)?;
+            if let (true, Some(result)) = (cli.json, result?) {
+                let document = serde_json::to_value(&result)?;
+                let key = integrity_key.as_deref().map(str::as_bytes);
+                println!("{}", export::signed(document, &export_settings, key));
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+// Logs panics before the hook from ratatui restores the terminal and prints them
+fn install_panic_hook() {
+    let restore_and_print = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!(panic = %info, "panicked");
+        restore_and_print(info);
+    }));
 }
 
-async fn run_app(terminal: &mut DefaultTerminal) -> Result<()> {
-    let mut app = App::new();
+// Command-line flags that script or shape the TUI
+struct Launch {
+    start: bool,
+    // Quit when the first test ends, returning its result
+    exit_on_complete: bool,
+    screen: Screen,
+    accessible: bool,
+}
+
+async fn run_app(
+    terminal: &mut DefaultTerminal,
+    settings: Settings,
+    history: History,
+    config_path: PathBuf,
+    config: Config,
+    launch: Launch,
+) -> Result<Option<SpeedTestResult>> {
+    let servers = config.comparison_servers();
+    let mut app = App::new(settings, history, config_path, config.profile, servers)
+        .with_accessible(launch.accessible);
     let mut test_rx: Option<mpsc::Receiver<TestUpdate>> = None;
+    let mut compare_rx: Option<mpsc::Receiver<CompareUpdate>> = None;
+    let mut compare_task: Option<JoinHandle<()>> = None;
+    let mut monitor: Option<LatencyMonitor> = None;
+    let mut gateway_monitor: Option<LatencyMonitor> = None;
+    let mut pending_start = launch.start;
+
+    // SIGINT from outside the terminal, e.g. `kill -INT`; Ctrl+C itself arrives as a key
+    let (interrupt_tx, mut interrupt_rx) = mpsc::channel(1);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = interrupt_tx.send(()).await;
+        }
+    });
 
+    let mut job_signals = screen::watch()?;
+
+    // Context for the summary bar, gathered while the UI comes up; demo mode
+    // stays off the network
+    let mut summary_rx = None;
+    if !app.settings.demo {
+        let (summary_tx, rx) = mpsc::channel(1);
+        summary_rx = Some(rx);
+        // Settings the test can't build a client from fail when it starts instead
+        let client = http_client(&app.settings).unwrap_or_default();
+        let ping_url = server::ping_url(&app.settings.server_url);
+        let local_address = app.settings.local_address;
+        tokio::spawn(async move {
+            let summary = NetworkSummary::detect(&client, &ping_url, local_address).await;
+            let _ = summary_tx.send(summary).await;
+        });
+    }
+
+    let (remote_tx, mut remote_rx) = mpsc::channel(8);
+    let _remote_listener = remote::listen(remote_tx)
+        .await
+        .inspect_err(|err| warn!(error = %err, "remote control unavailable"))
+        .ok();
+
+    let mut events = EventStream::new();
+    let mut tick = tokio::time::interval(TICK);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // Only redrawn when something changed, or after IDLE_REDRAW
+    let mut dirty = true;
+    let mut last_draw = Instant::now();
+    // Stopped for job control: nothing is drawn until SIGCONT resumes the screen
+    let mut suspended = false;
     loop {
-        terminal.draw(|frame| draw_ui(frame, &app))?;
-
-        // Handle test updates
-        if let Some(rx) = test_rx.as_mut() {
-            match rx.try_recv() {
-                Ok(update) => handle_update(&mut app, update),
-                Err(mpsc::error::TryRecvError::Empty) => {}
-                Err(mpsc::error::TryRecvError::Disconnected) => {
-                    if app.phase != TestPhase::Idle {
-                        app.complete_test();
-                    }
-                    test_rx = None;
+        // Latency monitor for the ping tab and idle screen
+        match (app.wants_monitor(), monitor.is_some()) {
+            (true, false) => match http_client(&app.settings) {
+                Ok(client) => {
+                    let url = server::ping_url(&app.settings.server_url);
+                    monitor = Some(LatencyMonitor::spawn(client, url, MONITOR_INTERVAL));
+                    gateway_monitor = network::default_gateway()
+                        .map(|gateway| LatencyMonitor::spawn_gateway(gateway, MONITOR_INTERVAL));
+                }
+                Err(err) => {
+                    warn!(error = %err, "latency monitor unavailable");
+                    app.settings.idle_monitor = false;
+                    app.view = AppView::Test;
+                    let unavailable = ui::i18n::strings().notice_monitor_unavailable;
+                    app.notice = Some(format!("{}: {:#}", unavailable, err));
+                    dirty = true;
                 }
+            },
+            (false, true) => {
+                monitor = None;
+                gateway_monitor = None;
+                app.clear_monitor();
+                dirty = true;
             }
+            _ => {}
         }
 
-        // Handle input
-        if let Some(Event::Key(key)) = poll_event(Duration::from_millis(30))? {
-            if let Some(action) = app.handle_key_event(key) {
-                match action {
-                    AppAction::Quit => break,
-                    AppAction::StartTest => {
-                        app.reset_for_new_test();
-                        app.phase = TestPhase::Ping;
-
-                        let (tx, rx) = mpsc::channel(32);
-                        let (cancel_tx, cancel_rx) = mpsc::channel(1);
-
-                        app.set_cancel_tx(cancel_tx);
-                        test_rx = Some(rx);
-
-                        let settings = app.settings.clone();
-                        tokio::spawn(async move {
-                            let _ = run_speed_test(tx, cancel_rx, settings).await;
-                        });
+        if app.ring_bell {
+            app.ring_bell = false;
+            let mut stdout = io::stdout();
+            stdout.write_all(b"\x07")?;
+            stdout.flush()?;
+        }
+
+        if !suspended && (dirty || last_draw.elapsed() >= IDLE_REDRAW) {
+            terminal.draw(|frame| draw_ui(frame, &app))?;
+            dirty = false;
+            last_draw = Instant::now();
+        }
+
+        // Wait for whatever comes first: input, a test or comparison update,
+        // a monitor probe, a signal, a remote request or the tick
+        let mut action = None;
+        if pending_start {
+            pending_start = false;
+            action = Some(AppAction::StartTest);
+        }
+        let mut quiet_tick = false;
+        tokio::select! {
+            event = events.next(), if action.is_none() => match event {
+                Some(Ok(Event::Key(key))) => action = app.handle_key_event(key),
+                // Resizes and focus changes need a redraw too
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+                None => break,
+            },
+            update = recv(test_rx.as_mut()) => {
+                let open = test_rx
+                    .as_mut()
+                    .is_some_and(|rx| drain(rx, update, |update| app.apply_update(update)));
+                if !open {
+                    app.finish_test();
+                    test_rx = None;
+                    if launch.exit_on_complete {
+                        return finished_result(&app);
                     }
-                    AppAction::CancelTest => {
+                }
+            }
+            update = recv(compare_rx.as_mut()) => {
+                let open = compare_rx
+                    .as_mut()
+                    .is_some_and(|rx| drain(rx, update, |update| app.apply_compare_update(update)));
+                if !open {
+                    app.finish_comparison();
+                    compare_rx = None;
+                    compare_task = None;
+                }
+            }
+            Some(rtt) = recv(monitor.as_mut().map(|monitor| &mut monitor.samples)) => {
+                app.push_monitor_ping(rtt);
+            }
+            Some(rtt) = recv(gateway_monitor.as_mut().map(|monitor| &mut monitor.samples)) => {
+                app.push_gateway_ping(rtt);
+            }
+            summary = recv(summary_rx.as_mut()) => {
+                app.network_summary = summary;
+                summary_rx = None;
+            }
+            Some(()) = interrupt_rx.recv() => action = Some(AppAction::Quit),
+            Some(signal) = job_signals.recv() => match signal {
+                JobSignal::Stop => suspended = launch.screen.suspend(terminal)?,
+                // A stray SIGCONT without a stop before it leaves the screen be
+                JobSignal::Continue if suspended => {
+                    launch.screen.resume(terminal)?;
+                    suspended = false;
+                }
+                JobSignal::Continue => {}
+            },
+            // Requests from `ericspeed ctl`
+            Some(request) = remote_rx.recv() => {
+                let (remote_action, reply) = app.handle_remote(request.command);
+                let _ = request.reply.send(reply);
+                action = action.or(remote_action);
+            }
+            _ = tick.tick() => quiet_tick = !app.poll_traffic(),
+        }
+        // Anything but a quiet tick changes what's shown
+        dirty |= !quiet_tick;
+        app.update_backlog = test_rx.as_ref().map_or(0, |rx| rx.len());
+
+        if let Some(action) = action {
+            dirty = true;
+            match action {
+                AppAction::Quit => {
+                    if app.test.is_running() {
                         app.cancel_test();
-                        test_rx = None;
                     }
+                    // Scripts waiting for a result must not mistake this for success
+                    if launch.exit_on_complete {
+                        bail!("quit before the speed test completed");
+                    }
+                    break;
+                }
+                AppAction::Suspend => suspended = launch.screen.suspend(terminal)?,
+                AppAction::StartTest => {
+                    app.start_test();
+
+                    let handle = SpeedTest::new(app.settings.clone()).spawn();
+                    app.set_cancel_tx(handle.cancel_sender());
+                    app.transfer_stats = Some(handle.stats());
+                    test_rx = Some(handle.updates);
+                }
+                AppAction::CancelTest => {
+                    app.cancel_test();
+                    test_rx = None;
+                    if launch.exit_on_complete {
+                        bail!("speed test cancelled");
+                    }
+                }
+                AppAction::StartComparison => {
+                    app.start_comparison();
+
+                    let (tx, rx) = mpsc::channel(64);
+                    let servers = app.comparison.iter().map(|row| row.server.clone()).collect();
+                    let download_size = app.settings.download_size_bytes();
+                    let mode = app.compare_mode;
+                    // The comparison servers are public; keep credentials away from them
+                    let client = http_client(&app.settings.without_credentials())?;
+                    compare_task = Some(tokio::spawn(compare_servers(
+                        client,
+                        servers,
+                        download_size,
+                        mode,
+                        tx,
+                    )));
+                    compare_rx = Some(rx);
+                }
+                AppAction::CancelComparison => {
+                    if let Some(task) = compare_task.take() {
+                        task.abort();
+                    }
+                    app.cancel_comparison();
+                    compare_rx = None;
                 }
             }
         }
@@ -76,27 +373,38 @@ async fn run_app(terminal: &mut DefaultTerminal) -> Result<()> {
             break;
         }
     }
+    Ok(None)
+}
 
-    Ok(())
+// Next message on `rx`, or never while there is no channel
+async fn recv<T>(rx: Option<&mut mpsc::Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => future::pending().await,
+    }
 }
 
-fn handle_update(app: &mut App, update: TestUpdate) {
-    match update {
-        TestUpdate::PingProgress(p) => app.update_ping_progress(p),
-        TestUpdate::PingComplete { avg_ms, jitter_ms } => {
-            app.result.ping_ms = avg_ms;
-            app.result.jitter_ms = jitter_ms;
-            app.phase = TestPhase::Download;
-        }
-        TestUpdate::DownloadProgress(p) => app.update_download_progress(p),
-        TestUpdate::DownloadComplete { speed_mbps } => {
-            app.result.download_mbps = speed_mbps;
-            app.phase = TestPhase::Upload;
-        }
-        TestUpdate::UploadProgress(p) => app.update_upload_progress(p),
-        TestUpdate::UploadComplete { speed_mbps } => {
-            app.result.upload_mbps = speed_mbps;
-            app.complete_test();
+// Applies `first` and every message queued behind it, so a burst of progress
+// is on screen by the next frame rather than trickling in one per frame.
+// False once the channel has closed
+fn drain<T>(rx: &mut mpsc::Receiver<T>, first: Option<T>, mut apply: impl FnMut(T)) -> bool {
+    let Some(first) = first else {
+        return false;
+    };
+    apply(first);
+    loop {
+        match rx.try_recv() {
+            Ok(message) => apply(message),
+            Err(TryRecvError::Empty) => return true,
+            Err(TryRecvError::Disconnected) => return false,
         }
     }
 }
+
+// The result of a test that just ended, or why it didn't complete
+fn finished_result(app: &App) -> Result<Option<SpeedTestResult>> {
+    if app.phase() != TestPhase::Complete {
+        bail!("{}", app.notice.as_deref().unwrap_or("speed test did not complete"));
+    }
+    Ok(Some(app.result().clone()))
+}
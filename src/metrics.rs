@@ -0,0 +1,164 @@
+use crate::config::{Config, PushgatewayConfig, StatsdConfig, SyslogConfig};
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use ericspeed::SpeedTestResult;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Sends a finished headless run to every configured metrics sink. Failures
+// are reported but don't fail the run.
+pub async fn publish(config: &Config, result: &SpeedTestResult) {
+    if let Some(pushgateway) = &config.pushgateway {
+        if let Err(err) = push(pushgateway, result).await {
+            warn!(error = %err, "failed to push metrics");
+            eprintln!("{:#}", err);
+        }
+    }
+    if let Some(statsd) = &config.statsd {
+        if let Err(err) = send_statsd(statsd, result).await {
+            warn!(error = %err, "failed to send statsd metrics");
+            eprintln!("{:#}", err);
+        }
+    }
+    if let Some(syslog) = &config.syslog {
+        if let Err(err) = log_syslog(syslog, result) {
+            warn!(error = %err, "failed to log the result to syslog");
+            eprintln!("{:#}", err);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn log_syslog(config: &SyslogConfig, result: &SpeedTestResult) -> Result<()> {
+    crate::syslog::log(config, result)
+}
+
+// Both journald and syslog listen on Unix sockets
+#[cfg(not(unix))]
+fn log_syslog(_config: &SyslogConfig, _result: &SpeedTestResult) -> Result<()> {
+    Err(anyhow!("syslog logging is only available on Unix"))
+}
+
+// A result in the Prometheus text exposition format
+pub fn exposition(result: &SpeedTestResult) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let gauges = [
+        ("ericspeed_download_mbps", "Download throughput in Mbps", Some(result.download_mbps)),
+        ("ericspeed_upload_mbps", "Upload throughput in Mbps", Some(result.upload_mbps)),
+        ("ericspeed_ping_ms", "Idle latency in milliseconds", Some(result.ping_ms)),
+        ("ericspeed_jitter_ms", "Idle latency jitter in milliseconds", Some(result.jitter_ms)),
+        (
+            "ericspeed_ping_loss_percent",
+            "Share of latency probes lost",
+            Some(result.ping_loss_percent),
+        ),
+        (
+            "ericspeed_download_loaded_ping_ms",
+            "Latency during the download in milliseconds",
+            result.download_ping_ms,
+        ),
+        (
+            "ericspeed_upload_loaded_ping_ms",
+            "Latency during the upload in milliseconds",
+            result.upload_ping_ms,
+        ),
+        (
+            "ericspeed_last_run_timestamp_seconds",
+            "Unix time the result was recorded",
+            Some(timestamp as f64),
+        ),
+    ];
+
+    let mut text = String::new();
+    for (name, help, value) in gauges {
+        let Some(value) = value else { continue };
+        let _ = writeln!(text, "# HELP {} {}", name, help);
+        let _ = writeln!(text, "# TYPE {} gauge", name);
+        let _ = writeln!(text, "{} {}", name, value);
+    }
+    text
+}
+
+// Replaces this run's metric group on the Pushgateway
+async fn push(config: &PushgatewayConfig, result: &SpeedTestResult) -> Result<()> {
+    let mut url = reqwest::Url::parse(&config.url).context("invalid pushgateway.url")?;
+    {
+        let mut segments = url
+            .path_segments_mut()
+            .map_err(|()| anyhow!("pushgateway.url can't be a base URL"))?;
+        segments.pop_if_empty().push("metrics");
+        let labels = std::iter::once(("job", &config.job))
+            .chain(config.instance.iter().map(|instance| ("instance", instance)))
+            .chain(config.labels.iter().map(|(name, value)| (name.as_str(), value)));
+        for (name, value) in labels {
+            // Slashes can't appear in a path segment, so such values go base64-encoded
+            if value.contains('/') || value.is_empty() {
+                segments.push(&format!("{}@base64", name)).push(&URL_SAFE.encode(value));
+            } else {
+                segments.push(name).push(value);
+            }
+        }
+    }
+
+    reqwest::Client::new()
+        .put(url.clone())
+        .timeout(PUSH_TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(exposition(result))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to push metrics to {}", config.url))?;
+    info!(url = %url, "metrics pushed");
+    Ok(())
+}
+
+// Gauges for each metric, batched into one datagram
+async fn send_statsd(config: &StatsdConfig, result: &SpeedTestResult) -> Result<()> {
+    let tags = if config.tags.is_empty() {
+        String::new()
+    } else {
+        let tags: Vec<String> = config
+            .tags
+            .iter()
+            .map(|(name, value)| format!("{}:{}", name, value))
+            .collect();
+        format!("|#{}", tags.join(","))
+    };
+    let gauges = [
+        ("download_mbps", Some(result.download_mbps)),
+        ("upload_mbps", Some(result.upload_mbps)),
+        ("ping_ms", Some(result.ping_ms)),
+        ("jitter_ms", Some(result.jitter_ms)),
+        ("ping_loss_percent", Some(result.ping_loss_percent)),
+        ("download_loaded_ping_ms", result.download_ping_ms),
+        ("upload_loaded_ping_ms", result.upload_ping_ms),
+    ];
+    let payload = gauges
+        .into_iter()
+        .filter_map(|(name, value)| Some(format!("{}.{}:{}|g{}", config.prefix, name, value?, tags)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let target = tokio::net::lookup_host(config.address.as_str())
+        .await
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .with_context(|| format!("failed to resolve statsd address {}", config.address))?;
+    let bind_addr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket
+        .send_to(payload.as_bytes(), target)
+        .await
+        .with_context(|| format!("failed to send metrics to {}", config.address))?;
+    info!(address = %config.address, "statsd metrics sent");
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use crate::speedtest::server;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+#[cfg(unix)]
+use std::fs;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::net::Ipv4Addr;
+use std::net::IpAddr;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+// Port the gateway is probed on; most routers serve their admin page there,
+// and one that refuses the connection still times the round trip
+const GATEWAY_PORT: u16 = 80;
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(1);
+// Gateway probes at startup, of which the fastest counts
+const GATEWAY_PROBES: usize = 3;
+
+/// A local address that test traffic can be bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalInterface {
+    pub name: String,
+    pub addr: IpAddr,
+}
+
+/// Lists non-loopback interface addresses, IPv4 first.
+pub fn local_interfaces() -> Vec<LocalInterface> {
+    let mut interfaces: Vec<LocalInterface> = if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|iface| !iface.is_loopback())
+        .map(|iface| LocalInterface {
+            addr: iface.ip(),
+            name: iface.name,
+        })
+        .collect();
+
+    interfaces.sort_by_key(|iface| (iface.addr.is_ipv6(), iface.name.clone()));
+    interfaces
+}
+
+/// The interface test traffic leaves through, recorded with each result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActiveNetwork {
+    pub interface: String,
+    pub wireless: bool,
+    /// Network name when connected over Wi-Fi and it could be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssid: Option<String>,
+}
+
+impl ActiveNetwork {
+    /// Finds the interface carrying traffic: the one owning `local_address`
+    /// if set, otherwise the default route's.
+    pub fn detect(local_address: Option<IpAddr>) -> Option<Self> {
+        let interface = match local_address {
+            Some(addr) => local_interfaces()
+                .into_iter()
+                .find(|iface| iface.addr == addr)
+                .map(|iface| iface.name),
+            None => default_route_interface(),
+        }?;
+
+        let wireless = is_wireless(&interface);
+        let ssid = if wireless { wifi_ssid(&interface) } else { None };
+        Some(Self {
+            interface,
+            wireless,
+            ssid,
+        })
+    }
+}
+
+impl fmt::Display for ActiveNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.ssid, self.wireless) {
+            (Some(ssid), _) => write!(f, "{} · {}", self.interface, ssid),
+            (None, true) => write!(f, "{} · wi-fi", self.interface),
+            (None, false) => write!(f, "{}", self.interface),
+        }
+    }
+}
+
+/// What the machine's connection looks like before any test runs.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkSummary {
+    pub network: Option<ActiveNetwork>,
+    pub gateway: Option<IpAddr>,
+    /// Fastest of a few TCP connects to the gateway.
+    pub gateway_rtt: Option<Duration>,
+    /// First DNS resolver the system is configured with.
+    pub resolver: Option<IpAddr>,
+    /// Address the test server sees requests coming from.
+    pub public_ip: Option<String>,
+}
+
+impl NetworkSummary {
+    /// Gathers the summary, asking the server behind `ping_url` for the
+    /// public address. Parts that can't be found are left `None`.
+    pub async fn detect(
+        client: &reqwest::Client,
+        ping_url: &str,
+        local_address: Option<IpAddr>,
+    ) -> Self {
+        let gateway = default_gateway();
+        let gateway_rtt = async {
+            let mut best: Option<Duration> = None;
+            for _ in 0..GATEWAY_PROBES {
+                if let Some(rtt) = gateway_rtt(gateway?).await {
+                    best = Some(best.map_or(rtt, |best| best.min(rtt)));
+                }
+            }
+            best
+        };
+        let (gateway_rtt, public_ip) =
+            tokio::join!(gateway_rtt, server::public_ip(client, ping_url));
+        Self {
+            network: ActiveNetwork::detect(local_address),
+            gateway,
+            gateway_rtt,
+            resolver: dns_resolver(),
+            public_ip,
+        }
+    }
+}
+
+/// Round trip to `gateway`, timed by opening a TCP connection to it. A
+/// refused connection answers just as fast, so only silence gives `None`.
+pub async fn gateway_rtt(gateway: IpAddr) -> Option<Duration> {
+    let start = Instant::now();
+    let connect = TcpStream::connect((gateway, GATEWAY_PORT));
+    match tokio::time::timeout(GATEWAY_TIMEOUT, connect).await.ok()? {
+        Ok(_) => Some(start.elapsed()),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => Some(start.elapsed()),
+        Err(_) => None,
+    }
+}
+
+/// Cumulative bytes an interface has received and sent since boot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Reads the OS byte counters of every non-loopback interface, so traffic
+/// can be observed without generating any. Empty where unsupported.
+pub fn interface_counters() -> Vec<InterfaceCounters> {
+    read_interface_counters().unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn read_interface_counters() -> Option<Vec<InterfaceCounters>> {
+    // Two header lines, then "iface: rx_bytes rx_packets ... (8 rx columns) tx_bytes ..."
+    let dev = fs::read_to_string("/proc/net/dev").ok()?;
+    Some(
+        dev.lines()
+            .skip(2)
+            .filter_map(|line| {
+                let (name, counters) = line.split_once(':')?;
+                let columns: Vec<u64> = counters
+                    .split_whitespace()
+                    .map(|column| column.parse().ok())
+                    .collect::<Option<_>>()?;
+                Some(InterfaceCounters {
+                    name: name.trim().to_string(),
+                    rx_bytes: *columns.first()?,
+                    tx_bytes: *columns.get(8)?,
+                })
+            })
+            .filter(|iface| iface.name != "lo")
+            .collect(),
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn read_interface_counters() -> Option<Vec<InterfaceCounters>> {
+    // Columns: Name Mtu Network Address Ipkts Ierrs Ibytes Opkts Oerrs Obytes Coll;
+    // the <Link#N> row of each interface carries its totals
+    let output = command_output("netstat", &["-ibn"])?;
+    Some(
+        output
+            .lines()
+            .skip(1)
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split_whitespace().collect();
+                if columns.len() < 10 || !columns[2].starts_with("<Link#") {
+                    return None;
+                }
+                // Interfaces without a hardware address leave the Address column out
+                let offset = if columns.len() >= 11 { 0 } else { 1 };
+                Some(InterfaceCounters {
+                    name: columns[0].to_string(),
+                    rx_bytes: columns[6 - offset].parse().ok()?,
+                    tx_bytes: columns[9 - offset].parse().ok()?,
+                })
+            })
+            .filter(|iface| !iface.name.starts_with("lo"))
+            .collect(),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn read_interface_counters() -> Option<Vec<InterfaceCounters>> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn default_route_interface() -> Option<String> {
+    default_route().map(|(iface, _)| iface)
+}
+
+// Interface and gateway of the IPv4 default route
+#[cfg(target_os = "linux")]
+fn default_route() -> Option<(String, Ipv4Addr)> {
+    // Columns: Iface Destination Gateway ...; the default route has destination 0,
+    // and addresses are hex in host byte order
+    let routes = fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let mut columns = line.split_whitespace();
+        let iface = columns.next()?;
+        if columns.next()? != "00000000" {
+            return None;
+        }
+        let gateway = u32::from_str_radix(columns.next()?, 16).ok()?;
+        Some((iface.to_string(), Ipv4Addr::from(u32::from_be(gateway))))
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn default_route_interface() -> Option<String> {
+    let output = command_output("route", &["-n", "get", "default"])?;
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("interface:"))
+        .map(|iface| iface.trim().to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn default_route_interface() -> Option<String> {
+    None
+}
+
+/// Router the default route leads through, if any.
+#[cfg(target_os = "linux")]
+pub fn default_gateway() -> Option<IpAddr> {
+    let (_, gateway) = default_route()?;
+    (!gateway.is_unspecified()).then_some(IpAddr::V4(gateway))
+}
+
+/// Router the default route leads through, if any.
+#[cfg(target_os = "macos")]
+pub fn default_gateway() -> Option<IpAddr> {
+    let output = command_output("route", &["-n", "get", "default"])?;
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("gateway:"))
+        .and_then(|gateway| gateway.trim().parse().ok())
+}
+
+/// Router the default route leads through, if any.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn default_gateway() -> Option<IpAddr> {
+    None
+}
+
+/// First `nameserver` in the system's resolver configuration.
+#[cfg(unix)]
+pub fn dns_resolver() -> Option<IpAddr> {
+    let config = fs::read_to_string("/etc/resolv.conf").ok()?;
+    config.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        if words.next()? != "nameserver" {
+            return None;
+        }
+        // Scoped IPv6 resolvers carry a "%interface" suffix
+        words.next()?.split('%').next()?.parse().ok()
+    })
+}
+
+/// First `nameserver` in the system's resolver configuration.
+#[cfg(not(unix))]
+pub fn dns_resolver() -> Option<IpAddr> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn is_wireless(interface: &str) -> bool {
+    Path::new("/sys/class/net").join(interface).join("wireless").exists()
+}
+
+#[cfg(target_os = "macos")]
+fn is_wireless(interface: &str) -> bool {
+    command_output("networksetup", &["-getairportnetwork", interface])
+        .is_some_and(|output| !output.contains("not a Wi-Fi interface"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn is_wireless(_interface: &str) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn wifi_ssid(interface: &str) -> Option<String> {
+    command_output("iwgetid", &["-r", interface])
+        .map(|ssid| ssid.trim().to_string())
+        .filter(|ssid| !ssid.is_empty())
+}
+
+#[cfg(target_os = "macos")]
+fn wifi_ssid(interface: &str) -> Option<String> {
+    command_output("networksetup", &["-getairportnetwork", interface])?
+        .trim()
+        .strip_prefix("Current Wi-Fi Network: ")
+        .map(str::to_string)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn wifi_ssid(_interface: &str) -> Option<String> {
+    None
+}
+
+// Platform tools are optional; any failure just means "unknown"
+#[allow(dead_code)]
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
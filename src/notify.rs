@@ -0,0 +1,321 @@
+use crate::config::{EmailConfig, NotifyConfig, SmtpSecurity, WebhookConfig, WebhookFormat};
+use anyhow::{bail, Context, Result};
+use ericspeed::alerts::{Metric, Violation};
+use ericspeed::SpeedTestResult;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+// Attachment and embed colors
+const FAILED_COLOR: u32 = 0xdc7878;
+const WARNING_COLOR: u32 = 0xdcb482;
+
+// Something the daemon reports to the configured notification targets
+pub enum Alert<'a> {
+    Failed(&'a anyhow::Error),
+    BelowThreshold {
+        result: &'a SpeedTestResult,
+        violations: &'a [Violation],
+        // The run before this one, for showing how much worse it got
+        previous: Option<&'a SpeedTestResult>,
+    },
+}
+
+impl Alert<'_> {
+    fn subject(&self) -> String {
+        match self {
+            Alert::Failed(_) => "ericspeed: scheduled speed test failed".to_string(),
+            Alert::BelowThreshold { violations, .. } => {
+                let metrics: Vec<&str> = violations.iter().map(|v| metric_label(v.metric)).collect();
+                format!("ericspeed: {} below expectations", metrics.join(", ").to_lowercase())
+            }
+        }
+    }
+
+    fn body(&self, tag: Option<&str>) -> String {
+        let mut body = String::new();
+        if let Some(tag) = tag {
+            let _ = writeln!(body, "Connection: {}\n", tag);
+        }
+        match self {
+            Alert::Failed(err) => {
+                let _ = writeln!(body, "The scheduled speed test failed:\n\n{:#}", err);
+            }
+            Alert::BelowThreshold { result, violations, .. } => {
+                for violation in violations.iter() {
+                    let _ = writeln!(
+                        body,
+                        "{}: {} (expected {} {})",
+                        metric_label(violation.metric),
+                        format_metric(violation.metric, violation.actual),
+                        expectation(violation.metric),
+                        format_metric(violation.metric, violation.limit)
+                    );
+                }
+                let _ = writeln!(
+                    body,
+                    "\nFull result: down {:.1} Mbps · up {:.1} Mbps · ping {:.0} ms · jitter {:.1} ms",
+                    result.download_mbps, result.upload_mbps, result.ping_ms, result.jitter_ms
+                );
+            }
+        }
+        body
+    }
+
+    // Every headline metric with its change since the previous run, and the
+    // threshold it missed if any
+    fn metric_fields(&self) -> Vec<(&'static str, String)> {
+        let Alert::BelowThreshold { result, violations, previous } = self else {
+            return Vec::new();
+        };
+        [
+            (Metric::Download, result.download_mbps, previous.map(|p| p.download_mbps)),
+            (Metric::Upload, result.upload_mbps, previous.map(|p| p.upload_mbps)),
+            (Metric::Ping, result.ping_ms, previous.map(|p| p.ping_ms)),
+        ]
+        .into_iter()
+        .map(|(metric, value, previous)| {
+            let mut text = format_metric(metric, value);
+            if let Some(previous) = previous {
+                let delta = format_metric(metric, value - previous);
+                let sign = if value >= previous { "+" } else { "" };
+                let _ = write!(text, " ({}{} since last run)", sign, delta);
+            }
+            if let Some(violation) = violations.iter().find(|v| v.metric == metric) {
+                let _ = write!(
+                    text,
+                    "\nexpected {} {}",
+                    expectation(metric),
+                    format_metric(metric, violation.limit)
+                );
+            }
+            (metric_label(metric), text)
+        })
+        .collect()
+    }
+
+    fn error(&self) -> Option<String> {
+        match self {
+            Alert::Failed(err) => Some(format!("{:#}", err)),
+            Alert::BelowThreshold { .. } => None,
+        }
+    }
+
+    fn color(&self) -> u32 {
+        match self {
+            Alert::Failed(_) => FAILED_COLOR,
+            Alert::BelowThreshold { .. } => WARNING_COLOR,
+        }
+    }
+
+    fn json_payload(&self, tag: Option<&str>) -> Value {
+        match self {
+            Alert::Failed(err) => json!({
+                "event": "failed",
+                "tag": tag,
+                "error": format!("{:#}", err),
+            }),
+            Alert::BelowThreshold { result, violations, previous } => json!({
+                "event": "below_threshold",
+                "tag": tag,
+                "result": result,
+                "previous": previous,
+                "violations": violations.iter().map(|v| json!({
+                    "metric": metric_label(v.metric).to_lowercase(),
+                    "actual": v.actual,
+                    "limit": v.limit,
+                })).collect::<Vec<_>>(),
+            }),
+        }
+    }
+
+    fn slack_payload(&self, tag: Option<&str>) -> Value {
+        let fields: Vec<Value> = self
+            .metric_fields()
+            .into_iter()
+            .map(|(title, value)| json!({ "title": title, "value": value, "short": true }))
+            .collect();
+        json!({
+            "text": self.subject(),
+            "attachments": [{
+                "color": format!("#{:06x}", self.color()),
+                "text": self.error(),
+                "fields": fields,
+                "footer": tag,
+            }],
+        })
+    }
+
+    fn discord_payload(&self, tag: Option<&str>) -> Value {
+        let fields: Vec<Value> = self
+            .metric_fields()
+            .into_iter()
+            .map(|(name, value)| json!({ "name": name, "value": value, "inline": true }))
+            .collect();
+        json!({
+            "embeds": [{
+                "title": self.subject(),
+                "description": self.error(),
+                "color": self.color(),
+                "fields": fields,
+                "footer": tag.map(|tag| json!({ "text": tag })),
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            }],
+        })
+    }
+}
+
+fn metric_label(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Download => "Download",
+        Metric::Upload => "Upload",
+        Metric::Ping => "Latency",
+    }
+}
+
+fn expectation(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Ping => "at most",
+        Metric::Download | Metric::Upload => "at least",
+    }
+}
+
+fn format_metric(metric: Metric, value: f64) -> String {
+    match metric {
+        Metric::Ping => format!("{:.0} ms", value),
+        Metric::Download | Metric::Upload => format!("{:.1} Mbps", value),
+    }
+}
+
+// Sends alerts to every configured target; failures are logged, never fatal
+pub struct Notifier {
+    email: Option<EmailNotifier>,
+    webhooks: Vec<(String, WebhookFormat)>,
+    client: reqwest::Client,
+    tag: Option<String>,
+}
+
+impl Notifier {
+    pub fn new(config: &NotifyConfig, tag: Option<String>) -> Result<Self> {
+        let email = config.email.as_ref().map(EmailNotifier::new).transpose()?;
+        let webhooks = config
+            .webhooks
+            .iter()
+            .map(|WebhookConfig { url, format }| {
+                reqwest::Url::parse(url).context("invalid notify.webhook.url")?;
+                Ok((url.clone(), *format))
+            })
+            .collect::<Result<_>>()?;
+        // Webhook receivers are third parties; test server credentials stay out
+        let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+        Ok(Self {
+            email,
+            webhooks,
+            client,
+            tag,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.email.is_none() && self.webhooks.is_empty()
+    }
+
+    pub async fn send(&self, alert: &Alert<'_>) {
+        let subject = alert.subject();
+        let body = alert.body(self.tag.as_deref());
+        if let Some(email) = &self.email {
+            match email.send(&subject, body).await {
+                Ok(()) => info!(subject = %subject, "alert email sent"),
+                Err(err) => {
+                    warn!(error = %err, "failed to send alert email");
+                    eprintln!("failed to send alert email: {:#}", err);
+                }
+            }
+        }
+
+        let tag = self.tag.as_deref();
+        for (url, format) in &self.webhooks {
+            let payload = match format {
+                WebhookFormat::Json => alert.json_payload(tag),
+                WebhookFormat::Slack => alert.slack_payload(tag),
+                WebhookFormat::Discord => alert.discord_payload(tag),
+            };
+            let response = self.client.post(url).json(&payload).send().await;
+            match response.and_then(|response| response.error_for_status()) {
+                Ok(_) => info!(?format, "alert webhook sent"),
+                Err(err) => {
+                    // Without the URL, which holds the webhook's secret
+                    let err = anyhow::Error::from(err.without_url());
+                    warn!(error = %err, ?format, "failed to send alert webhook");
+                    eprintln!("failed to send {:?} alert webhook: {:#}", format, err);
+                }
+            }
+        }
+    }
+}
+
+struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    host: String,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailNotifier {
+    fn new(config: &EmailConfig) -> Result<Self> {
+        let host = config.smtp_host.as_str();
+        let mut builder = match config.security {
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        if let Some(port) = config.smtp_port {
+            builder = builder.port(port);
+        }
+        if let Some(username) = &config.username {
+            let password = config.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        let from = config
+            .from
+            .parse()
+            .with_context(|| format!("invalid notify.email.from address {:?}", config.from))?;
+        let to = config
+            .to
+            .iter()
+            .map(|to| to.parse().with_context(|| format!("invalid notify.email.to address {:?}", to)))
+            .collect::<Result<Vec<Mailbox>>>()?;
+        if to.is_empty() {
+            bail!("notify.email.to needs at least one recipient");
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            host: config.smtp_host.clone(),
+            from,
+            to,
+        })
+    }
+
+    async fn send(&self, subject: &str, body: String) -> Result<()> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        self.transport
+            .send(message.body(body)?)
+            .await
+            .with_context(|| format!("failed to send through {}", self.host))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use crate::runtime;
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::path::PathBuf;
+use tokio::sync::{mpsc, oneshot};
+
+// Commands accepted over the control socket, one per line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RemoteCommand {
+    Start,
+    Cancel,
+    Status,
+}
+
+impl RemoteCommand {
+    fn parse(line: &str) -> Option<Self> {
+        Self::from_str(line.trim(), true).ok()
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RemoteCommand::Start => "start",
+            RemoteCommand::Cancel => "cancel",
+            RemoteCommand::Status => "status",
+        }
+    }
+}
+
+// A command waiting for the UI loop to act on it and answer
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    pub reply: oneshot::Sender<String>,
+}
+
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(runtime::dir()?.join("ericspeed.sock"))
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use std::path::Path;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+    use tracing::{debug, warn};
+
+    // Removes the socket file when the TUI exits
+    pub struct RemoteListener {
+        path: PathBuf,
+    }
+
+    impl Drop for RemoteListener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    pub async fn listen(tx: mpsc::Sender<RemoteRequest>) -> Result<RemoteListener> {
+        let path = socket_path()?;
+        if UnixStream::connect(&path).await.is_ok() {
+            bail!("another instance is listening on {}", path.display());
+        }
+        // Left behind by an instance that didn't shut down cleanly
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("failed to bind {}", path.display()))?;
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(handle_connection(stream, tx.clone()));
+                    }
+                    Err(err) => {
+                        warn!(error = %err, "control socket accept failed");
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(RemoteListener { path })
+    }
+
+    async fn handle_connection(stream: UnixStream, tx: mpsc::Sender<RemoteRequest>) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let reply = match RemoteCommand::parse(&line) {
+                Some(command) => {
+                    debug!(?command, "remote command");
+                    let (reply_tx, reply_rx) = oneshot::channel();
+                    let request = RemoteRequest {
+                        command,
+                        reply: reply_tx,
+                    };
+                    if tx.send(request).await.is_err() {
+                        break;
+                    }
+                    reply_rx.await.unwrap_or_else(|_| "error: no reply".to_string())
+                }
+                None => format!("error: unknown command {:?}", line.trim()),
+            };
+            if writer.write_all(format!("{}\n", reply).as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    pub async fn send(path: &Path, command: RemoteCommand) -> Result<String> {
+        // Only talk to a TUI run by the same user
+        if std::fs::symlink_metadata(path).is_ok() {
+            runtime::check_owner(path)?;
+        }
+        let stream = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("no running ericspeed at {}", path.display()))?;
+        let (reader, mut writer) = stream.into_split();
+        writer.write_all(format!("{}\n", command.name()).as_bytes()).await?;
+
+        let mut lines = BufReader::new(reader).lines();
+        lines.next_line().await?.context("connection closed without a reply")
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{listen, send};
+
+#[cfg(not(unix))]
+pub struct RemoteListener;
+
+#[cfg(not(unix))]
+pub async fn listen(_tx: mpsc::Sender<RemoteRequest>) -> Result<RemoteListener> {
+    bail!("remote control needs unix domain sockets")
+}
+
+#[cfg(not(unix))]
+pub async fn send(_path: &std::path::Path, _command: RemoteCommand) -> Result<String> {
+    bail!("remote control needs unix domain sockets")
+}
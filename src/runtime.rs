@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+// Where the control socket and other per-user runtime files live: the XDG
+// runtime directory, or else a private directory under the data directory.
+// Never the shared temp directory, where another user could create the
+// files first
+pub fn dir() -> Result<PathBuf> {
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir,
+        None => {
+            let dir = dirs::data_local_dir()
+                .context("no directory for runtime files")?
+                .join("ericspeed")
+                .join("run");
+            create_private(&dir)?;
+            dir
+        }
+    };
+    check_private(&dir)?;
+    Ok(dir)
+}
+
+#[cfg(unix)]
+fn create_private(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))
+}
+
+#[cfg(not(unix))]
+fn create_private(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))
+}
+
+// Refuses a directory that isn't ours alone
+#[cfg(unix)]
+fn check_private(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    check_owner(dir)?;
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?;
+    if !metadata.is_dir() || metadata.mode() & 0o077 != 0 {
+        bail!("{} is open to other users", dir.display());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+// Refuses a file or directory another user owns
+#[cfg(unix)]
+pub fn check_owner(path: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    // SAFETY: geteuid has no preconditions and cannot fail
+    if metadata.uid() != unsafe { libc::geteuid() } {
+        bail!("{} belongs to another user", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn check_owner(_path: &Path) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::ops::Deref;
+
+/// Fixed-capacity sample storage that keeps only the newest values.
+///
+/// Backed by a vector with room for twice the capacity: once it fills, the
+/// oldest half is dropped in one step, so pushes are amortized O(1) and the
+/// retained samples are always available as a contiguous slice (via `Deref`).
+#[derive(Debug, Clone)]
+pub struct SampleBuffer {
+    data: Vec<f64>,
+    capacity: usize,
+}
+
+impl SampleBuffer {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            data: Vec::with_capacity(capacity * 2),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.data.len() == self.capacity * 2 {
+            self.data.drain(..self.capacity);
+        }
+        self.data.push(value);
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn as_slice(&self) -> &[f64] {
+        let start = self.data.len().saturating_sub(self.capacity);
+        &self.data[start..]
+    }
+}
+
+impl Deref for SampleBuffer {
+    type Target = [f64];
+
+    fn deref(&self) -> &[f64] {
+        self.as_slice()
+    }
+}
+
+/// Exponentially weighted moving average, for steadying a noisy live value.
+#[derive(Debug, Clone, Copy)]
+pub struct Ewma {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ewma {
+    /// `alpha` in (0, 1] is the weight of each new sample; smaller values
+    /// smooth more but react more slowly.
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha: alpha.clamp(f64::EPSILON, 1.0),
+            value: None,
+        }
+    }
+
+    /// Folds in a sample and returns the new average. The first sample is
+    /// taken as-is.
+    pub fn push(&mut self, sample: f64) -> f64 {
+        let value = match self.value {
+            Some(value) => value + self.alpha * (sample - value),
+            None => sample,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    pub fn clear(&mut self) {
+        self.value = None;
+    }
+}
+
+/// Nearest-rank percentile of already sorted samples; 0 when empty.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Trailing moving average: each output is the mean of up to `window`
+/// samples ending at the same index.
+pub fn moving_average(samples: &[f64], window: usize) -> Vec<f64> {
+    let window = window.max(1);
+    let mut sum = 0.0;
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            sum += value;
+            if i >= window {
+                sum -= samples[i - window];
+            }
+            sum / (i + 1).min(window) as f64
+        })
+        .collect()
+}
+
+// Two-sided 95% critical values of Student's t for 1 to 30 degrees of
+// freedom; beyond that the normal distribution's is close enough
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+const Z_95: f64 = 1.96;
+
+/// Half-width of the 95% confidence interval for the mean of `samples`,
+/// from their sample variance; `None` with fewer than two samples.
+pub fn confidence_margin_95(samples: &[f64]) -> Option<f64> {
+    let n = samples.len();
+    if n < 2 {
+        return None;
+    }
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let variance = samples.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    let critical = T_95.get(n - 2).copied().unwrap_or(Z_95);
+    Some(critical * (variance / n as f64).sqrt())
+}
+
+// Scales the median absolute deviation to estimate the standard deviation
+// of normally distributed samples
+const MAD_SCALE: f64 = 1.4826;
+
+/// `samples` in their original order, less those more than `mads` median
+/// absolute deviations from the median. Returned whole when the deviation
+/// is zero, as with more than half the samples equal.
+pub fn without_outliers(samples: &[f64], mads: f64) -> Vec<f64> {
+    let median = |values: &mut Vec<f64>| {
+        values.sort_by(f64::total_cmp);
+        let mid = values.len() / 2;
+        if values.len().is_multiple_of(2) {
+            (values[mid - 1] + values[mid]) / 2.0
+        } else {
+            values[mid]
+        }
+    };
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let center = median(&mut samples.to_vec());
+    let mad = median(&mut samples.iter().map(|&x| (x - center).abs()).collect()) * MAD_SCALE;
+    if mad == 0.0 {
+        return samples.to_vec();
+    }
+    samples.iter().copied().filter(|&x| (x - center).abs() <= mads * mad).collect()
+}
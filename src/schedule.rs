@@ -0,0 +1,44 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local};
+use croner::Cron;
+use std::time::Duration;
+
+/// When a monitoring daemon should run its tests.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// A fixed gap between the end of one run and the start of the next.
+    Interval(Duration),
+    /// A five-field cron expression, e.g. `0 */2 * * *`, in local time.
+    Cron(Box<Cron>),
+}
+
+impl Schedule {
+    pub fn interval(minutes: u64) -> Result<Self> {
+        if minutes == 0 {
+            bail!("interval must be at least one minute");
+        }
+        Ok(Self::Interval(Duration::from_secs(minutes * 60)))
+    }
+
+    pub fn cron(expression: &str) -> Result<Self> {
+        let cron = expression
+            .parse::<Cron>()
+            .map_err(|err| anyhow!("invalid cron expression {:?}: {}", expression, err))?;
+        Ok(Self::Cron(Box::new(cron)))
+    }
+
+    /// The next time a test should start, strictly after `now`.
+    pub fn next_after(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Self::Interval(interval) => Some(now + *interval),
+            Self::Cron(cron) => cron.find_next_occurrence(&now, false).ok(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Interval(interval) => format!("every {} min", interval.as_secs() / 60),
+            Self::Cron(cron) => format!("cron {:?}", cron.as_str()),
+        }
+    }
+}
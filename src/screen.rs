@@ -0,0 +1,123 @@
+use anyhow::Result;
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen};
+use ratatui::backend::CrosstermBackend;
+use ratatui::{DefaultTerminal, Terminal, TerminalOptions, Viewport};
+use std::io;
+use tokio::sync::mpsc;
+
+// Job control signals the TUI reacts to
+pub enum JobSignal {
+    // SIGTSTP from outside the terminal; Ctrl+Z itself arrives as a key
+    Stop,
+    // SIGCONT, after any kind of stop
+    Continue,
+}
+
+#[cfg(unix)]
+pub fn watch() -> Result<mpsc::Receiver<JobSignal>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut stop = signal(SignalKind::from_raw(libc::SIGTSTP))?;
+    let mut resume = signal(SignalKind::from_raw(libc::SIGCONT))?;
+    let (tx, rx) = mpsc::channel(4);
+    tokio::spawn(async move {
+        loop {
+            let signal = tokio::select! {
+                _ = stop.recv() => JobSignal::Stop,
+                _ = resume.recv() => JobSignal::Continue,
+            };
+            if tx.send(signal).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+#[cfg(not(unix))]
+pub fn watch() -> Result<mpsc::Receiver<JobSignal>> {
+    Ok(mpsc::channel(1).1)
+}
+
+// How the TUI takes over the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    // The alternate screen, which the shell's scrollback replaces on exit
+    Fullscreen,
+    // A viewport of INLINE_HEIGHT rows below the prompt, whose last frame
+    // stays in the scrollback on exit
+    Inline,
+}
+
+// Rows the inline viewport takes, or fewer on a shorter terminal
+const INLINE_HEIGHT: u16 = 24;
+
+impl Screen {
+    pub fn init(self) -> Result<DefaultTerminal> {
+        match self {
+            Screen::Fullscreen => {
+                let mut terminal = ratatui::init();
+                terminal.clear()?;
+                Ok(terminal)
+            }
+            Screen::Inline => Ok(ratatui::init_with_options(inline_options())),
+        }
+    }
+
+    // Hands the terminal back to the shell
+    pub fn restore(self, terminal: &mut DefaultTerminal) -> Result<()> {
+        match self {
+            Screen::Fullscreen => ratatui::restore(),
+            Screen::Inline => {
+                // Leave the cursor below the last frame, so the prompt doesn't overwrite it
+                let bottom = terminal.get_frame().area().bottom();
+                terminal.set_cursor_position((0, bottom.saturating_sub(1)))?;
+                terminal.show_cursor()?;
+                disable_raw_mode()?;
+                println!();
+            }
+        }
+        Ok(())
+    }
+
+    // Hands the terminal back to the shell and stops the process, returning
+    // whether it did. The SIGCONT that continues it arrives through watch(),
+    // and only then does resume() take the terminal over again
+    #[cfg(unix)]
+    pub fn suspend(self, terminal: &mut DefaultTerminal) -> Result<bool> {
+        self.restore(terminal)?;
+        // SIGTSTP is caught by watch(), so stop with the uncatchable SIGSTOP
+        // SAFETY: raise has no preconditions
+        unsafe { libc::raise(libc::SIGSTOP) };
+        Ok(true)
+    }
+
+    #[cfg(not(unix))]
+    pub fn suspend(self, _terminal: &mut DefaultTerminal) -> Result<bool> {
+        Ok(false)
+    }
+
+    // Re-enters raw mode and forces a full redraw: on the alternate screen,
+    // or in a new viewport below whatever the shell printed meanwhile
+    pub fn resume(self, terminal: &mut DefaultTerminal) -> Result<()> {
+        enable_raw_mode()?;
+        match self {
+            Screen::Fullscreen => {
+                execute!(io::stdout(), EnterAlternateScreen)?;
+                terminal.clear()?;
+            }
+            Screen::Inline => {
+                let backend = CrosstermBackend::new(io::stdout());
+                *terminal = Terminal::with_options(backend, inline_options())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn inline_options() -> TerminalOptions {
+    TerminalOptions {
+        viewport: Viewport::Inline(INLINE_HEIGHT),
+    }
+}
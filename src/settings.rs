@@ -1,51 +1,432 @@
+use crate::alerts::Thresholds;
+use crate::speedtest::ping::{JitterMethod, PingMode, DEFAULT_PING_INTERVAL};
+use crate::speedtest::retry::RetryPolicy;
+use crate::speedtest::server::CLOUDFLARE_URL;
+use crate::speedtest::throughput::DEFAULT_SAMPLE_INTERVAL;
+use crate::speedtest::{SpeedTestResult, TestPhase};
+use anyhow::{Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Upper bound on concurrent connections per transfer phase.
+pub const MAX_STREAMS: usize = 16;
+
+/// Share of the plan speeds, in percent, a result must reach for the plan
+/// to count as delivered.
+pub const PLAN_DELIVERED_PERCENT: f64 = 80.0;
+
+/// Parameters for a single speed test run.
 #[derive(Debug, Clone)]
 pub struct Settings {
+    /// Base URL of the server providing the `__down` and `__up` endpoints.
+    pub server_url: String,
+    /// Cloudflare location to test against by IATA code, e.g. "FRA", rather
+    /// than the one anycast routes to. Cloudflare may not honor it; the
+    /// test warns when another location answers.
+    pub colo: Option<String>,
+    /// Number of latency probes sent during the ping phase.
     pub ping_count: usize,
+    /// Pause after each latency probe before the next.
+    pub ping_interval: Duration,
+    /// Latency probes kept in flight at once, 1..=[`MAX_PING_CONCURRENCY`].
+    ///
+    /// [`MAX_PING_CONCURRENCY`]: crate::speedtest::ping::MAX_PING_CONCURRENCY
+    pub ping_concurrency: usize,
+    /// Whether latency probes reuse a connection or open a new one each.
+    pub ping_mode: PingMode,
+    /// Amount of data fetched during the download phase, in megabytes.
     pub download_size_mb: u64,
+    /// Amount of data sent during the upload phase, in megabytes.
     pub upload_size_mb: u64,
+    /// Build a fresh HTTP client for each phase so connection setup is
+    /// included in every measurement, instead of reusing warm connections.
+    pub cold_connections: bool,
+    /// Concurrent connections used by the download phase, 1..=[`MAX_STREAMS`].
+    pub download_streams: usize,
+    /// Pick the download's stream count by ramping up through
+    /// [`RAMP_UP_STREAMS`](crate::speedtest::download::RAMP_UP_STREAMS)
+    /// first, instead of using `download_streams`.
+    pub ramp_up: bool,
+    /// Concurrent connections used by the upload phase, 1..=[`MAX_STREAMS`].
+    pub upload_streams: usize,
+    /// Download in many requests of this many megabytes instead of one
+    /// large response per stream. `None` uses a single request per stream.
+    pub download_chunk_mb: Option<u64>,
+    /// Cuts each transfer phase short after this long; the speed is measured
+    /// over whatever was transferred in time.
+    pub transfer_duration: Option<Duration>,
+    /// How often the download and upload report progress and sample their
+    /// throughput, between
+    /// [`MIN_SAMPLE_INTERVAL`](crate::speedtest::throughput::MIN_SAMPLE_INTERVAL) and
+    /// [`MAX_SAMPLE_INTERVAL`](crate::speedtest::throughput::MAX_SAMPLE_INTERVAL).
+    pub sample_interval: Duration,
+    /// End the download and upload phases as soon as their throughput
+    /// levels off, reporting the speed over the last few seconds.
+    pub early_exit: bool,
+    /// After the upload, run download and upload at the same time to see
+    /// how each holds up while the other is busy.
+    pub bidirectional: bool,
+    /// Check for a captive portal before the test and warn if one answers.
+    pub detect_captive_portal: bool,
+    /// Play out a simulated test from a
+    /// [`MockProvider`](crate::speedtest::mock::MockProvider) instead of
+    /// measuring the network.
+    pub demo: bool,
+    /// Limits after which the test is abandoned as failed.
+    pub timeouts: Timeouts,
+    /// How requests are retried after connection errors and 5xx responses.
+    pub retry: RetryPolicy,
+    /// Local address to send test traffic from, selecting the network path
+    /// on multi-homed machines. `None` lets the OS pick.
+    pub local_address: Option<IpAddr>,
+    /// Upper bound on memory for transfer buffers, in megabytes, for small
+    /// machines like routers. `None` uses the fastest buffer sizes.
+    pub memory_budget_mb: Option<u64>,
+    /// Self-imposed throughput cap for download and upload, in Mbps.
+    pub rate_limit_mbps: Option<u64>,
+    /// How jitter is calculated from the latency samples.
+    pub jitter_method: JitterMethod,
+    /// Leave latency probes far from the rest out of the reported ping and
+    /// jitter; the charts still show every probe.
+    pub filter_ping_outliers: bool,
+    /// Limits a completed test is checked against.
+    pub thresholds: Thresholds,
+    /// Ring the terminal bell when a completed test violates a threshold.
+    pub alert_bell: bool,
+    /// Keep probing latency at a low rate while no test is running.
+    pub idle_monitor: bool,
+    /// How the TUI's charts draw their points.
+    pub chart_marker: ChartMarker,
+    /// Speeds the ISP advertises, which results are shown as a share of.
+    pub plan: Option<PlanSpeed>,
+    /// Free-text label for where the test ran, e.g. "home-wifi", stored
+    /// with the result.
+    pub tag: Option<String>,
+    /// Authentication and extra headers for private test servers.
+    pub credentials: Credentials,
+    /// PEM file with extra root certificates to trust, for servers signed
+    /// by a private CA.
+    pub ca_cert: Option<PathBuf>,
+    /// Skip TLS certificate validation entirely. Dangerous: anyone on the
+    /// path can impersonate the server.
+    pub accept_invalid_certs: bool,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            server_url: CLOUDFLARE_URL.to_string(),
+            colo: None,
             ping_count: 30,
+            ping_interval: DEFAULT_PING_INTERVAL,
+            ping_concurrency: 1,
+            ping_mode: PingMode::Warm,
             download_size_mb: 100,
             upload_size_mb: 50,
+            cold_connections: false,
+            download_streams: 1,
+            ramp_up: false,
+            upload_streams: 1,
+            download_chunk_mb: None,
+            transfer_duration: None,
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            early_exit: false,
+            bidirectional: false,
+            detect_captive_portal: true,
+            demo: false,
+            timeouts: Timeouts::default(),
+            retry: RetryPolicy::default(),
+            local_address: None,
+            memory_budget_mb: None,
+            rate_limit_mbps: None,
+            jitter_method: JitterMethod::StdDev,
+            filter_ping_outliers: false,
+            thresholds: Thresholds::default(),
+            alert_bell: false,
+            idle_monitor: false,
+            chart_marker: ChartMarker::Braille,
+            plan: None,
+            tag: None,
+            credentials: Credentials::default(),
+            ca_cert: None,
+            accept_invalid_certs: false,
         }
     }
 }
 
 impl Settings {
+    /// These settings minus the credentials, for requests to third-party
+    /// servers that must not see them.
+    pub fn without_credentials(&self) -> Settings {
+        Settings {
+            credentials: Credentials::default(),
+            ..self.clone()
+        }
+    }
+
     pub fn download_size_bytes(&self) -> u64 {
         self.download_size_mb * 1_000_000
     }
 
+    pub fn download_chunk_bytes(&self) -> Option<u64> {
+        self.download_chunk_mb.map(|mb| mb * 1_000_000)
+    }
+
     pub fn upload_size_bytes(&self) -> usize {
         (self.upload_size_mb * 1_000_000) as usize
     }
+
+    pub fn memory_budget_bytes(&self) -> Option<usize> {
+        self.memory_budget_mb.map(|mb| (mb * 1_000_000) as usize)
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SettingsField {
-    PingCount,
-    DownloadSize,
-    UploadSize,
+/// How charts draw their points. Braille is the finest, but renders poorly
+/// in some fonts and terminal emulators.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartMarker {
+    #[default]
+    Braille,
+    /// Full blocks, one point per cell.
+    Block,
+    /// Dots, one point per cell.
+    Dot,
 }
 
-impl SettingsField {
-    pub fn next(self) -> Self {
+impl ChartMarker {
+    pub fn label(self) -> &'static str {
         match self {
-            SettingsField::PingCount => SettingsField::DownloadSize,
-            SettingsField::DownloadSize => SettingsField::UploadSize,
-            SettingsField::UploadSize => SettingsField::PingCount,
+            ChartMarker::Braille => "braille",
+            ChartMarker::Block => "block",
+            ChartMarker::Dot => "dot",
         }
     }
+}
 
-    pub fn prev(self) -> Self {
-        match self {
-            SettingsField::PingCount => SettingsField::UploadSize,
-            SettingsField::DownloadSize => SettingsField::PingCount,
-            SettingsField::UploadSize => SettingsField::DownloadSize,
+/// How long a test may take before it fails. Unlike
+/// [`Settings::transfer_duration`], running out of time is an error rather
+/// than the end of the measurement. `None` sets no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    pub ping: Option<Duration>,
+    pub download: Option<Duration>,
+    pub upload: Option<Duration>,
+    /// Deadline for the whole test, counted from its start.
+    pub total: Option<Duration>,
+}
+
+impl Timeouts {
+    /// The limit for a single phase, if any.
+    pub fn phase(&self, phase: TestPhase) -> Option<Duration> {
+        match phase {
+            TestPhase::Ping => self.ping,
+            TestPhase::Download => self.download,
+            TestPhase::Upload => self.upload,
+            // Both transfers run at once, so the more generous limit set applies
+            TestPhase::Bidirectional => self.download.max(self.upload),
+            TestPhase::Idle | TestPhase::Complete => None,
+        }
+    }
+}
+
+/// Advertised download and upload speeds of an internet plan.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanSpeed {
+    pub download_mbps: f64,
+    pub upload_mbps: f64,
+}
+
+impl PlanSpeed {
+    /// Download and upload speed of `result` as a percentage of the plan.
+    pub fn percent_of_plan(&self, result: &SpeedTestResult) -> (f64, f64) {
+        let percent = |actual: f64, plan: f64| if plan > 0.0 { actual / plan * 100.0 } else { 0.0 };
+        (
+            percent(result.download_mbps, self.download_mbps),
+            percent(result.upload_mbps, self.upload_mbps),
+        )
+    }
+
+    /// Whether both speeds reached [`PLAN_DELIVERED_PERCENT`] of the plan.
+    pub fn delivered(&self, result: &SpeedTestResult) -> bool {
+        let (download, upload) = self.percent_of_plan(result);
+        download >= PLAN_DELIVERED_PERCENT && upload >= PLAN_DELIVERED_PERCENT
+    }
+}
+
+/// Parses `DOWN/UP` in Mbps, e.g. `500/50`.
+impl FromStr for PlanSpeed {
+    type Err = anyhow::Error;
+
+    fn from_str(plan: &str) -> Result<Self> {
+        let (download, upload) = plan
+            .split_once('/')
+            .context("expected DOWN/UP in Mbps, e.g. 500/50")?;
+        let parse = |speed: &str| {
+            speed
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|mbps| *mbps > 0.0)
+                .with_context(|| format!("invalid speed {:?}", speed))
+        };
+        Ok(Self {
+            download_mbps: parse(download)?,
+            upload_mbps: parse(upload)?,
+        })
+    }
+}
+
+/// How requests authenticate to the test server.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// `Authorization: Bearer <token>`.
+    Bearer(String),
+    /// HTTP basic authentication.
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+}
+
+/// Authentication and custom headers sent with every test request.
+///
+/// The `Debug` output omits secrets and header values, since settings are
+/// logged at the start of every run.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub auth: Option<Auth>,
+    /// Extra headers as (name, value) pairs.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Credentials {
+    pub fn is_empty(&self) -> bool {
+        self.auth.is_none() && self.headers.is_empty()
+    }
+
+    /// The headers to attach to each request. Fails on a header name or
+    /// value that isn't valid HTTP.
+    pub fn header_map(&self) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name {:?}", name))?;
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("invalid value for header {}", name))?;
+            headers.append(name, value);
+        }
+
+        let authorization = match &self.auth {
+            None => None,
+            Some(Auth::Bearer(token)) => Some(format!("Bearer {}", token)),
+            Some(Auth::Basic { username, password }) => {
+                let pair = format!("{}:{}", username, password.as_deref().unwrap_or(""));
+                Some(format!("Basic {}", BASE64_STANDARD.encode(pair)))
+            }
+        };
+        if let Some(authorization) = authorization {
+            let mut value = HeaderValue::from_str(&authorization).context("invalid credentials")?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(headers)
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let auth = match &self.auth {
+            None => "none",
+            Some(Auth::Bearer(_)) => "bearer",
+            Some(Auth::Basic { .. }) => "basic",
+        };
+        let header_names: Vec<&str> = self.headers.iter().map(|(name, _)| name.as_str()).collect();
+        f.debug_struct("Credentials")
+            .field("auth", &auth)
+            .field("headers", &header_names)
+            .finish()
+    }
+}
+
+/// Test parameters that are tuned together: probe count, transfer sizes and
+/// stream counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub ping_count: usize,
+    pub download_size_mb: u64,
+    pub upload_size_mb: u64,
+    pub download_streams: usize,
+    pub upload_streams: usize,
+}
+
+impl Profile {
+    /// Small transfers over a few streams; done in seconds on most links.
+    pub const QUICK: Profile = Profile {
+        ping_count: 10,
+        download_size_mb: 25,
+        upload_size_mb: 25,
+        download_streams: 4,
+        upload_streams: 4,
+    };
+
+    /// The default settings.
+    pub const STANDARD: Profile = Profile {
+        ping_count: 30,
+        download_size_mb: 100,
+        upload_size_mb: 50,
+        download_streams: 1,
+        upload_streams: 1,
+    };
+
+    /// Large transfers over many streams, to saturate fast links.
+    pub const THOROUGH: Profile = Profile {
+        ping_count: 60,
+        download_size_mb: 500,
+        upload_size_mb: 250,
+        download_streams: 8,
+        upload_streams: 8,
+    };
+
+    /// Built-in profiles by name, in cycling order.
+    pub const PRESETS: &'static [(&'static str, Profile)] = &[
+        ("quick", Profile::QUICK),
+        ("standard", Profile::STANDARD),
+        ("thorough", Profile::THOROUGH),
+    ];
+
+    /// The profile-controlled part of `settings`.
+    pub fn of(settings: &Settings) -> Self {
+        Self {
+            ping_count: settings.ping_count,
+            download_size_mb: settings.download_size_mb,
+            upload_size_mb: settings.upload_size_mb,
+            download_streams: settings.download_streams,
+            upload_streams: settings.upload_streams,
         }
     }
+
+    /// Overwrites the profile-controlled fields of `settings`, clamping
+    /// stream counts to 1..=[`MAX_STREAMS`].
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.ping_count = self.ping_count;
+        settings.download_size_mb = self.download_size_mb;
+        settings.upload_size_mb = self.upload_size_mb;
+        settings.download_streams = self.download_streams.clamp(1, MAX_STREAMS);
+        settings.upload_streams = self.upload_streams.clamp(1, MAX_STREAMS);
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::STANDARD
+    }
 }
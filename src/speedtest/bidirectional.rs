@@ -0,0 +1,91 @@
+use super::download::{DownloadProgress, DownloadTest};
+use super::upload::{UploadProgress, UploadTest};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::info;
+
+/// Share of its one-way speed, in percent, below which a direction counts
+/// as having collapsed under bidirectional load.
+pub const COLLAPSED_PERCENT: f64 = 50.0;
+
+/// Runs a download and an upload at the same time, measuring each direction
+/// while the other competes for the link.
+pub struct BidirectionalTest {
+    download: DownloadTest,
+    upload: UploadTest,
+}
+
+impl BidirectionalTest {
+    pub fn new(download: DownloadTest, upload: UploadTest) -> Self {
+        Self { download, upload }
+    }
+
+    pub async fn run(
+        &mut self,
+        progress_tx: mpsc::Sender<BidirectionalProgress>,
+    ) -> Result<BidirectionalResult> {
+        info!("bidirectional test starting");
+        let (download_tx, mut download_rx) = mpsc::channel::<DownloadProgress>(32);
+        let (upload_tx, mut upload_rx) = mpsc::channel::<UploadProgress>(32);
+
+        // Each direction reports on its own; forward the latest of both together
+        let forward = async {
+            let mut progress = BidirectionalProgress::default();
+            let (mut download_fraction, mut upload_fraction) = (0.0, 0.0);
+            loop {
+                tokio::select! {
+                    Some(p) = download_rx.recv() => {
+                        progress.download_mbps = p.speed_mbps;
+                        download_fraction = fraction(p.downloaded_bytes, p.total_bytes);
+                    }
+                    Some(p) = upload_rx.recv() => {
+                        progress.upload_mbps = p.speed_mbps;
+                        upload_fraction = fraction(p.uploaded_bytes, p.total_bytes);
+                    }
+                    else => break,
+                }
+                progress.fraction = f64::min(download_fraction, upload_fraction);
+                let _ = progress_tx.send(progress).await;
+            }
+        };
+
+        let (download, upload, ()) = tokio::join!(
+            self.download.run(download_tx),
+            self.upload.run(upload_tx),
+            forward
+        );
+        let result = BidirectionalResult {
+            download_mbps: download?.avg_speed_mbps,
+            upload_mbps: upload?.avg_speed_mbps,
+        };
+        info!(?result, "bidirectional test finished");
+        Ok(result)
+    }
+}
+
+fn fraction(done: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    }
+}
+
+/// Periodic progress of both directions, sent whenever either reports.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BidirectionalProgress {
+    /// Download throughput over its latest sampling interval, in Mbps.
+    pub download_mbps: f64,
+    /// Upload throughput over its latest sampling interval, in Mbps.
+    pub upload_mbps: f64,
+    /// Share of the phase done, 0.0..=1.0: that of the direction furthest behind.
+    pub fraction: f64,
+}
+
+/// Throughput of each direction while both ran at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BidirectionalResult {
+    pub download_mbps: f64,
+    pub upload_mbps: f64,
+}
@@ -0,0 +1,136 @@
+use super::download::{DownloadProgress, DownloadTest};
+use super::ping::round_trip_ms;
+use super::server::Server;
+use crate::samples::percentile;
+use futures::future::join_all;
+use reqwest::header::RANGE;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+// Requests timed per server before the downloads; the median counts
+const LATENCY_PROBES: usize = 3;
+const LATENCY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How a comparison runs its downloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    /// All servers download at the same time, sharing the link.
+    Concurrent,
+    /// Servers download one after another, each getting the full link.
+    Sequential,
+}
+
+impl CompareMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            CompareMode::Concurrent => CompareMode::Sequential,
+            CompareMode::Sequential => CompareMode::Concurrent,
+        }
+    }
+}
+
+/// Progress from a server comparison; `index` refers to the server list
+/// passed to [`compare_servers`].
+#[derive(Debug, Clone)]
+pub enum CompareUpdate {
+    /// Median time for the server to answer a one-byte request, in ms.
+    Latency { index: usize, latency_ms: f64 },
+    Progress { index: usize, progress: DownloadProgress },
+    Complete { index: usize, speed_mbps: f64 },
+    Failed { index: usize, error: String },
+}
+
+/// Times a few small requests to each server, then runs the download test
+/// against each and reports per-server progress on `update_tx`. Returns once
+/// every download has finished.
+pub async fn compare_servers(
+    client: reqwest::Client,
+    servers: Vec<Server>,
+    download_size: u64,
+    mode: CompareMode,
+    update_tx: mpsc::Sender<CompareUpdate>,
+) {
+    // Before any download starts, so none of them queue behind the traffic
+    let latencies = servers.iter().map(|server| median_latency_ms(&client, server));
+    for (index, latency_ms) in join_all(latencies).await.into_iter().enumerate() {
+        if let Some(latency_ms) = latency_ms {
+            let _ = update_tx.send(CompareUpdate::Latency { index, latency_ms }).await;
+        }
+    }
+
+    let runs = servers.into_iter().enumerate().map(|(index, server)| {
+        let client = client.clone();
+        let update_tx = update_tx.clone();
+        async move { run_one(client, index, server, download_size, update_tx).await }
+    });
+
+    match mode {
+        CompareMode::Concurrent => {
+            join_all(runs).await;
+        }
+        CompareMode::Sequential => {
+            for run in runs {
+                run.await;
+            }
+        }
+    }
+}
+
+// Median round trip of a ranged request for the first byte of the server's
+// download, which works for generated and fixed-size files alike
+async fn median_latency_ms(client: &reqwest::Client, server: &Server) -> Option<f64> {
+    let url = server.download_url(1);
+    let mut samples = Vec::with_capacity(LATENCY_PROBES);
+    for _ in 0..LATENCY_PROBES {
+        let start = Instant::now();
+        let request = client.get(&url).header(RANGE, "bytes=0-0").timeout(LATENCY_TIMEOUT);
+        match request.send().await {
+            Ok(response) => {
+                samples.push(round_trip_ms(start, &response));
+                // Read the byte so the connection can be reused for the next probe
+                let _ = response.bytes().await;
+            }
+            Err(err) => debug!(server = %server.name, error = %err, "latency probe failed"),
+        }
+    }
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_by(f64::total_cmp);
+    Some(percentile(&samples, 50.0))
+}
+
+async fn run_one(
+    client: reqwest::Client,
+    index: usize,
+    server: Server,
+    download_size: u64,
+    update_tx: mpsc::Sender<CompareUpdate>,
+) {
+    let (progress_tx, mut progress_rx) = mpsc::channel::<DownloadProgress>(32);
+    // One chunk of the whole size, so fixed-size files are fetched with a
+    // range request and cut off at the same size as the generated downloads
+    let mut test = DownloadTest::new(client, server.download_url(download_size), download_size)
+        .with_chunk_size(Some(download_size));
+
+    // Run in this task rather than spawning, so aborting the comparison stops the download
+    let forward = async {
+        while let Some(progress) = progress_rx.recv().await {
+            let _ = update_tx.send(CompareUpdate::Progress { index, progress }).await;
+        }
+    };
+    let (result, ()) = tokio::join!(test.run(progress_tx), forward);
+
+    let update = match result {
+        Ok(result) => CompareUpdate::Complete {
+            index,
+            speed_mbps: result.avg_speed_mbps,
+        },
+        Err(err) => CompareUpdate::Failed {
+            index,
+            error: err.to_string(),
+        },
+    };
+    let _ = update_tx.send(update).await;
+}
@@ -0,0 +1,204 @@
+use crate::network;
+use crate::samples::percentile;
+use anyhow::{bail, Context, Result};
+use futures::future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+
+/// Port resolvers answer on unless another is given.
+pub const DNS_PORT: u16 = 53;
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+// Large enough for any answer to an A query over plain UDP
+const MAX_RESPONSE_BYTES: usize = 512;
+
+/// Public resolvers benchmarked alongside the system's own.
+pub const PUBLIC_RESOLVERS: [(&str, IpAddr); 3] = [
+    ("Cloudflare", IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
+    ("Google", IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))),
+    ("Quad9", IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9))),
+];
+
+/// Domains looked up unless others are given: popular ones, so results
+/// reflect the resolvers' caches as everyday browsing would.
+pub const DEFAULT_DOMAINS: [&str; 5] =
+    ["google.com", "youtube.com", "facebook.com", "wikipedia.org", "amazon.com"];
+
+/// A DNS server to benchmark.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolver {
+    pub name: String,
+    pub addr: SocketAddr,
+}
+
+impl Resolver {
+    /// A resolver answering on the usual [`DNS_PORT`].
+    pub fn new(name: impl Into<String>, ip: IpAddr) -> Self {
+        Self::with_port(name, SocketAddr::new(ip, DNS_PORT))
+    }
+
+    pub fn with_port(name: impl Into<String>, addr: SocketAddr) -> Self {
+        Self {
+            name: name.into(),
+            addr,
+        }
+    }
+
+    /// The system's configured resolver, if it can be read, followed by
+    /// [`PUBLIC_RESOLVERS`].
+    pub fn defaults() -> Vec<Resolver> {
+        network::dns_resolver()
+            .map(|addr| Resolver::new("System", addr))
+            .into_iter()
+            .chain(PUBLIC_RESOLVERS.iter().map(|&(name, addr)| Resolver::new(name, addr)))
+            .collect()
+    }
+}
+
+/// Times lookups of a list of domains against several resolvers, to show
+/// which one answers fastest from this network.
+pub struct DnsBenchmark {
+    resolvers: Vec<Resolver>,
+    domains: Vec<String>,
+    rounds: usize,
+    local_address: Option<IpAddr>,
+}
+
+impl DnsBenchmark {
+    pub fn new(resolvers: Vec<Resolver>, domains: Vec<String>) -> Self {
+        Self {
+            resolvers,
+            domains,
+            rounds: 3,
+            local_address: None,
+        }
+    }
+
+    /// Looks every domain up `rounds` times per resolver. The first round
+    /// may miss the resolver's cache; later ones show its cached speed.
+    pub fn with_rounds(mut self, rounds: usize) -> Self {
+        self.rounds = rounds.max(1);
+        self
+    }
+
+    /// Sends queries from `addr`, like test traffic on multi-homed machines.
+    pub fn with_local_address(mut self, addr: Option<IpAddr>) -> Self {
+        self.local_address = addr;
+        self
+    }
+
+    /// Benchmarks the resolvers side by side, each looking the domains up
+    /// one after another. Results come back in the order resolvers were given.
+    pub async fn run(&self) -> Vec<ResolverResult> {
+        info!(
+            resolvers = self.resolvers.len(),
+            domains = self.domains.len(),
+            rounds = self.rounds,
+            "dns benchmark starting"
+        );
+        future::join_all(self.resolvers.iter().map(|resolver| self.benchmark(resolver))).await
+    }
+
+    async fn benchmark(&self, resolver: &Resolver) -> ResolverResult {
+        let mut times = Vec::new();
+        let mut failed = 0;
+        for _ in 0..self.rounds {
+            for domain in &self.domains {
+                match lookup_ms(resolver.addr, domain, self.local_address).await {
+                    Ok(ms) => times.push(ms),
+                    Err(err) => {
+                        debug!(resolver = %resolver.addr, domain, error = %err, "dns lookup failed");
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        ResolverResult::new(resolver.clone(), &times, failed)
+    }
+}
+
+/// Lookup times of one resolver, in milliseconds.
+#[derive(Debug, Clone)]
+pub struct ResolverResult {
+    pub resolver: Resolver,
+    pub answered: usize,
+    pub failed: usize,
+    /// `None` when no lookup was answered, as for the other times.
+    pub median_ms: Option<f64>,
+    pub mean_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+impl ResolverResult {
+    fn new(resolver: Resolver, times_ms: &[f64], failed: usize) -> Self {
+        let mut sorted = times_ms.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let answered = !sorted.is_empty();
+        Self {
+            resolver,
+            answered: sorted.len(),
+            failed,
+            median_ms: answered.then(|| percentile(&sorted, 50.0)),
+            mean_ms: answered.then(|| sorted.iter().sum::<f64>() / sorted.len() as f64),
+            max_ms: sorted.last().copied(),
+        }
+    }
+}
+
+/// Time for `resolver` to answer an A query for `domain`, in milliseconds.
+/// A "no such domain" answer counts; a server failure or refusal doesn't.
+pub async fn lookup_ms(
+    resolver: SocketAddr,
+    domain: &str,
+    local_address: Option<IpAddr>,
+) -> Result<f64> {
+    let local = local_address.unwrap_or(match resolver {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let socket = UdpSocket::bind(SocketAddr::new(local, 0)).await?;
+    socket.connect(resolver).await?;
+
+    let id: u16 = rand::random();
+    let query = query(id, domain)?;
+    let start = Instant::now();
+    socket.send(&query).await?;
+    let mut response = [0u8; MAX_RESPONSE_BYTES];
+    let rcode = tokio::time::timeout(QUERY_TIMEOUT, async {
+        loop {
+            let len = socket.recv(&mut response).await?;
+            // Skip stray datagrams that aren't the answer to this query
+            if len >= 12 && response[..2] == id.to_be_bytes() && response[2] & 0x80 != 0 {
+                return Ok::<_, std::io::Error>(response[3] & 0x0f);
+            }
+        }
+    })
+    .await
+    .context("no answer")??;
+    let elapsed = start.elapsed();
+
+    match rcode {
+        // No error, or no such domain
+        0 | 3 => Ok(elapsed.as_secs_f64() * 1000.0),
+        rcode => bail!("answered with error code {}", rcode),
+    }
+}
+
+// A recursive A query for `domain` in DNS wire format
+fn query(id: u16, domain: &str) -> Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(64);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // Flags asking for recursion, then counts: one question, no records
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in domain.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("invalid domain name: {}", domain);
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    // End of name, type A, class IN
+    packet.extend_from_slice(&[0, 0, 1, 0, 1]);
+    Ok(packet)
+}
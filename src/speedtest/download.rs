@@ -1,86 +1,416 @@
+use super::retry::RetryPolicy;
+use super::saturation::{self, SaturationDetector};
+use super::stats::TransferStats;
+use super::throttle::RateLimiter;
+use super::throughput::{
+    BlockSpeeds, ThroughputMeter, DEFAULT_SAMPLE_INTERVAL, MAX_SAMPLE_INTERVAL, MIN_SAMPLE_INTERVAL,
+};
+use crate::settings::MAX_STREAMS;
 use anyhow::Result;
-use futures::StreamExt;
+use futures::{future, StreamExt};
+use reqwest::header::RANGE;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
 
-const DOWNLOAD_URL: &str = "https://speed.cloudflare.com/__down";
+/// Stream counts tried in turn when ramping up.
+pub const RAMP_UP_STREAMS: [usize; 4] = [1, 2, 4, 8];
+/// How long each ramp-up stage downloads for.
+const RAMP_UP_STAGE: Duration = Duration::from_secs(2);
+/// Relative throughput gain, in percent, a stage must bring over the best so
+/// far for ramping up to carry on.
+const RAMP_UP_MIN_GAIN_PERCENT: f64 = 10.0;
 
+/// Measures download throughput by streaming a payload from the test server.
+///
+/// A `{bytes}` placeholder in the URL is replaced with the size each request
+/// should fetch; URLs without one serve a fixed payload.
+#[derive(Clone)]
 pub struct DownloadTest {
-    speed_samples: Vec<f64>,
+    client: reqwest::Client,
+    url: String,
     download_size: u64,
+    streams: usize,
+    chunk_size: Option<u64>,
+    duration: Option<Duration>,
+    early_exit: bool,
+    ramp_up: bool,
+    limiter: Option<RateLimiter>,
+    sample_interval: Duration,
+    retry: RetryPolicy,
+    stats: Arc<TransferStats>,
+}
+
+// Reported by each stream to the loop that aggregates progress
+enum StreamEvent {
+    // Response headers arrived for a request that asked for `expected` bytes
+    Headers { expected: u64, content_length: Option<u64> },
+    // Body bytes, and when they arrived
+    Bytes { len: u64, at: Instant },
 }
 
 impl DownloadTest {
-    pub fn new(download_size: u64) -> Self {
+    pub fn new(client: reqwest::Client, url: String, download_size: u64) -> Self {
         Self {
-            speed_samples: Vec::new(),
+            client,
+            url,
             download_size,
+            streams: 1,
+            chunk_size: None,
+            duration: None,
+            early_exit: false,
+            ramp_up: false,
+            limiter: None,
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            retry: RetryPolicy::default(),
+            stats: Arc::default(),
         }
     }
 
+    /// Caps throughput by pacing how fast the response body is read.
+    pub fn with_rate_limit(mut self, limiter: Option<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Retries requests that fail transiently, before any of the response
+    /// body has arrived, according to `retry`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Records request and byte counters into `stats`.
+    pub fn with_stats(mut self, stats: Arc<TransferStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Fetches over `streams` concurrent requests, clamped to
+    /// 1..=[`MAX_STREAMS`], each asking for an equal share of the download.
+    pub fn with_streams(mut self, streams: usize) -> Self {
+        self.streams = streams.clamp(1, MAX_STREAMS);
+        self
+    }
+
+    /// Splits the download into many requests of `chunk_size` bytes, shared
+    /// out among the streams, instead of one large response per stream.
+    /// URLs without a `{bytes}` placeholder are fetched with Range requests.
+    pub fn with_chunk_size(mut self, chunk_size: Option<u64>) -> Self {
+        self.chunk_size = chunk_size.filter(|&size| size > 0);
+        self
+    }
+
+    /// Stops the download after `duration` even if data remains, measuring the
+    /// speed over what was transferred by then.
+    pub fn with_duration(mut self, duration: Option<Duration>) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Reports progress and samples throughput every `interval`, clamped to
+    /// [`MIN_SAMPLE_INTERVAL`]..=[`MAX_SAMPLE_INTERVAL`].
+    pub fn with_sample_interval(mut self, interval: Duration) -> Self {
+        self.sample_interval = interval.clamp(MIN_SAMPLE_INTERVAL, MAX_SAMPLE_INTERVAL);
+        self
+    }
+
+    /// Stops the download once its throughput has levelled off, measuring
+    /// the speed over the last few seconds only.
+    pub fn with_early_exit(mut self, early_exit: bool) -> Self {
+        self.early_exit = early_exit;
+        self
+    }
+
+    /// Before measuring, tries each of [`RAMP_UP_STREAMS`] for a couple of
+    /// seconds while it still raises throughput, and measures with the
+    /// fastest instead of the configured stream count. High-latency links
+    /// often need more streams to fill than a fixed count guesses.
+    pub fn with_ramp_up(mut self, ramp_up: bool) -> Self {
+        self.ramp_up = ramp_up;
+        self
+    }
+
     pub async fn run(&mut self, progress_tx: mpsc::Sender<DownloadProgress>) -> Result<DownloadResult> {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(120))
-            .connect_timeout(Duration::from_secs(10))
-            .build()?;
-
-        let url = format!("{}?bytes={}", DOWNLOAD_URL, self.download_size);
-        let response = client.get(&url).send().await?;
-        let total_size = response.content_length().unwrap_or(self.download_size);
-        let mut stream = response.bytes_stream();
+        if self.ramp_up {
+            self.streams = self.ramp_up(&progress_tx).await?;
+        }
+        self.transfer(progress_tx).await
+    }
+
+    // Downloads for a short stage at each stream count, until doubling the
+    // streams stops paying off, and returns the fastest count. Progress shows
+    // the stages' speed but none of the download done.
+    async fn ramp_up(&self, progress_tx: &mpsc::Sender<DownloadProgress>) -> Result<usize> {
+        let mut best = (1, 0.0);
+        for streams in RAMP_UP_STREAMS {
+            let (stage_tx, mut stage_rx) = mpsc::channel::<DownloadProgress>(32);
+            let mut stage = self.clone().with_streams(streams).with_duration(Some(RAMP_UP_STAGE));
+            let forward = async {
+                while let Some(progress) = stage_rx.recv().await {
+                    let _ = progress_tx
+                        .send(DownloadProgress {
+                            downloaded_bytes: 0,
+                            total_bytes: self.download_size,
+                            ..progress
+                        })
+                        .await;
+                }
+            };
+            let (result, ()) = tokio::join!(stage.transfer(stage_tx), forward);
+            let mbps = result?.avg_speed_mbps;
+            debug!(streams, mbps, "ramp-up stage finished");
+
+            let gain_percent = (mbps / best.1 - 1.0) * 100.0;
+            if mbps > best.1 {
+                best = (streams, mbps);
+            }
+            if streams > 1 && gain_percent < RAMP_UP_MIN_GAIN_PERCENT {
+                break;
+            }
+        }
+        info!(streams = best.0, mbps = best.1, "ramp-up picked stream count");
+        Ok(best.0)
+    }
 
-        let start = Instant::now();
+    async fn transfer(&mut self, progress_tx: mpsc::Sender<DownloadProgress>) -> Result<DownloadResult> {
+        info!(
+            url = %self.url,
+            bytes = self.download_size,
+            streams = self.streams,
+            chunk_size = self.chunk_size,
+            "download starting"
+        );
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let next_offset = AtomicU64::new(0);
+        let transfers = future::try_join_all(
+            (0..self.streams).map(|index| self.run_stream(index, &next_offset, event_tx.clone())),
+        );
+        drop(event_tx);
+        tokio::pin!(transfers);
+        let time_limit = tokio::time::sleep(self.duration.unwrap_or_default());
+        tokio::pin!(time_limit);
+
+        // Requested sizes are replaced by each response's Content-Length once known
+        let mut total_size = self.download_size;
+        // Timed from the first body byte so connection setup isn't counted
+        let mut meter = ThroughputMeter::new();
+        // Until that first byte, which does count connection setup
+        let request_start = Instant::now();
+        let mut ttfb = None;
+        let mut saturation = self.early_exit.then(SaturationDetector::new);
+        let mut saturated_mbps = None;
+        let mut blocks = BlockSpeeds::new();
+        let mut started = false;
         let mut downloaded: u64 = 0;
         let mut last_update = Instant::now();
         let mut last_downloaded: u64 = 0;
+        let mut finished = false;
 
-        self.speed_samples.clear();
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            downloaded += chunk.len() as u64;
+        loop {
+            let event = tokio::select! {
+                result = &mut transfers, if !finished => {
+                    result?;
+                    finished = true;
+                    continue;
+                }
+                _ = &mut time_limit, if self.duration.is_some() => {
+                    info!("download time limit reached");
+                    break;
+                }
+                event = event_rx.recv() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+            };
+            let chunk_len = match event {
+                StreamEvent::Headers { expected, content_length } => {
+                    total_size = total_size - expected + content_length.unwrap_or(expected);
+                    if !started {
+                        started = true;
+                        last_update = Instant::now();
+                    }
+                    continue;
+                }
+                StreamEvent::Bytes { len, at } => {
+                    ttfb.get_or_insert(at.duration_since(request_start));
+                    meter.record(len, at);
+                    len
+                }
+            };
+            downloaded += chunk_len;
 
             let now = Instant::now();
             let interval = now.duration_since(last_update);
 
-            if interval >= Duration::from_millis(100) {
+            if interval >= self.sample_interval {
                 let bytes_delta = downloaded - last_downloaded;
                 let mbps = (bytes_delta as f64 * 8.0) / interval.as_secs_f64() / 1_000_000.0;
-                self.speed_samples.push(mbps);
-
-                // Keep last 200 samples
-                if self.speed_samples.len() > 200 {
-                    self.speed_samples.remove(0);
-                }
+                let avg_mbps = meter.mbps();
+                blocks.record(downloaded, now);
 
                 let _ = progress_tx
                     .send(DownloadProgress {
                         downloaded_bytes: downloaded,
                         total_bytes: total_size,
-                        speed_samples: self.speed_samples.clone(),
+                        speed_mbps: mbps,
+                        avg_speed_mbps: avg_mbps,
                     })
                     .await;
 
                 last_update = now;
                 last_downloaded = downloaded;
+
+                if let Some(saturation) = &mut saturation {
+                    saturation.record(downloaded, now);
+                    saturated_mbps = saturation.saturated_mbps();
+                    if saturated_mbps.is_some() {
+                        info!("download throughput levelled off");
+                        break;
+                    }
+                }
             }
         }
 
-        let elapsed = start.elapsed();
-        let avg_speed = (downloaded as f64 * 8.0) / elapsed.as_secs_f64() / 1_000_000.0;
+        let elapsed = meter.elapsed();
+        let avg_speed = saturated_mbps.unwrap_or_else(|| meter.mbps());
+        info!(
+            bytes = downloaded,
+            elapsed_ms = elapsed.as_millis() as u64,
+            avg_mbps = avg_speed,
+            ttfb_ms = ttfb.map(|ttfb| ttfb.as_millis() as u64),
+            "download finished"
+        );
 
-        Ok(DownloadResult { avg_speed_mbps: avg_speed })
+        Ok(DownloadResult {
+            avg_speed_mbps: avg_speed,
+            margin_mbps: blocks.margin_95(saturated_mbps.map(|_| saturation::WINDOW)),
+            streams: self.streams,
+            ttfb_ms: ttfb.map(|ttfb| ttfb.as_secs_f64() * 1000.0),
+            bytes: downloaded,
+            elapsed,
+        })
+    }
+
+    // Runs one stream: a single request for its share, or chunks taken from
+    // the shared queue until the whole download is claimed
+    async fn run_stream(
+        &self,
+        index: usize,
+        next_offset: &AtomicU64,
+        event_tx: mpsc::UnboundedSender<StreamEvent>,
+    ) -> Result<()> {
+        let Some(chunk_size) = self.chunk_size else {
+            let streams = self.streams as u64;
+            // The first stream also takes the remainder
+            let share = self.download_size / streams
+                + if index == 0 { self.download_size % streams } else { 0 };
+            return self.fetch(None, share, &event_tx).await;
+        };
+
+        loop {
+            let offset = next_offset.fetch_add(chunk_size, Ordering::Relaxed);
+            if offset >= self.download_size {
+                return Ok(());
+            }
+            let len = chunk_size.min(self.download_size - offset);
+            self.fetch(Some(offset), len, &event_tx).await?;
+        }
+    }
+
+    // Fetches `expected` bytes, reporting headers and every body chunk through
+    // `event_tx`. Chunk requests pass their `offset` and stop reading at
+    // `expected` bytes, in case the server ignores the requested size.
+    async fn fetch(
+        &self,
+        offset: Option<u64>,
+        expected: u64,
+        event_tx: &mpsc::UnboundedSender<StreamEvent>,
+    ) -> Result<()> {
+        let url = self.url.replace("{bytes}", &expected.to_string());
+        let range = offset
+            .filter(|_| !self.url.contains("{bytes}"))
+            .map(|offset| format!("bytes={}-{}", offset, offset + expected - 1));
+        let builder = || {
+            let builder = self.client.get(&url);
+            match &range {
+                Some(range) => builder.header(RANGE, range),
+                None => builder,
+            }
+        };
+
+        let request = self.stats.start_request("download");
+        let request_start = Instant::now();
+        let response = self
+            .retry
+            .send(&self.stats, builder)
+            .await
+            .inspect_err(|err| {
+                warn!(error = %err, "download request failed");
+                self.stats.record_error();
+            })?;
+        request.record_status(response.status().as_u16());
+        request.record_connection(&response);
+        debug!(
+            status = %response.status(),
+            elapsed_ms = request_start.elapsed().as_millis() as u64,
+            "download response headers received"
+        );
+        let response = response.error_for_status()?;
+        let limit = if offset.is_some() { expected } else { u64::MAX };
+        let _ = event_tx.send(StreamEvent::Headers {
+            expected,
+            content_length: response.content_length().map(|len| len.min(limit)),
+        });
+        let mut stream = response.bytes_stream();
+        let mut remaining = limit;
+
+        while let Some(chunk) = stream.next().await {
+            let at = Instant::now();
+            let len = (chunk?.len() as u64).min(remaining);
+            request.add_bytes(len);
+
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire(len as usize).await;
+            }
+            let _ = event_tx.send(StreamEvent::Bytes { len, at });
+
+            remaining -= len;
+            if remaining == 0 {
+                break;
+            }
+        }
+        Ok(())
     }
 }
 
+/// Periodic download progress, sent about every sampling interval.
 #[derive(Debug, Clone)]
 pub struct DownloadProgress {
     pub downloaded_bytes: u64,
     pub total_bytes: u64,
-    pub speed_samples: Vec<f64>,
+    /// Throughput over the latest sampling interval, in Mbps.
+    pub speed_mbps: f64,
+    /// Average throughput since the phase started, in Mbps.
+    pub avg_speed_mbps: f64,
 }
 
+/// Outcome of a completed download phase.
 #[derive(Debug, Clone)]
 pub struct DownloadResult {
     pub avg_speed_mbps: f64,
+    /// Half-width of the 95% confidence interval for the speed, from how it
+    /// varied second by second over the span it covers; `None` with fewer
+    /// than two seconds.
+    pub margin_mbps: Option<f64>,
+    /// Concurrent requests the download was measured over.
+    pub streams: usize,
+    /// Time from sending the first request to its first body byte, in ms,
+    /// including any connection setup; `None` if no bytes arrived.
+    pub ttfb_ms: Option<f64>,
+    /// Bytes received, and the time from the first of them to the last.
+    pub bytes: u64,
+    pub elapsed: Duration,
 }
@@ -0,0 +1,176 @@
+use super::download::DownloadTest;
+use super::ping::round_trip_ms;
+use super::throttle::RateLimiter;
+use crate::samples::percentile;
+use anyhow::Result;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+// Size of each background download; repeated until the test ends
+const BACKGROUND_CHUNK_BYTES: u64 = 25_000_000;
+
+/// Sends small, frequent latency probes for a fixed duration to surface the
+/// spikes that matter for online games but vanish in a 30-probe average.
+pub struct GamingTest {
+    client: reqwest::Client,
+    url: String,
+    duration: Duration,
+    interval: Duration,
+    spike_threshold_ms: f64,
+    background: Option<(String, u64)>,
+}
+
+impl GamingTest {
+    pub fn new(client: reqwest::Client, url: String) -> Self {
+        Self {
+            client,
+            url,
+            duration: Duration::from_secs(60),
+            interval: Duration::from_millis(50),
+            spike_threshold_ms: 30.0,
+            background: None,
+        }
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Time between probes; at least a millisecond, as a zero period has no ticks.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    /// Probes more than `threshold_ms` above the median count as spikes.
+    pub fn with_spike_threshold(mut self, threshold_ms: f64) -> Self {
+        self.spike_threshold_ms = threshold_ms;
+        self
+    }
+
+    /// Keeps a rate-limited download from `download_url` running during the
+    /// test, like a game patch or video stream in the background.
+    pub fn with_background_traffic(mut self, download_url: String, mbps: u64) -> Self {
+        self.background = Some((download_url, mbps));
+        self
+    }
+
+    pub async fn run(&self, progress_tx: mpsc::Sender<GamingProgress>) -> Result<GamingResult> {
+        info!(
+            duration_s = self.duration.as_secs(),
+            interval_ms = self.interval.as_millis() as u64,
+            background = self.background.is_some(),
+            "gaming test starting"
+        );
+
+        let background = self.background.clone().map(|(url, mbps)| {
+            let client = self.client.clone();
+            tokio::spawn(async move {
+                let limiter = RateLimiter::new(mbps);
+                loop {
+                    // Nobody listens to background progress; sends fail fast
+                    let (tx, _) = mpsc::channel(1);
+                    let mut test =
+                        DownloadTest::new(client.clone(), url.clone(), BACKGROUND_CHUNK_BYTES)
+                            .with_rate_limit(Some(limiter.clone()));
+                    if let Err(err) = test.run(tx).await {
+                        debug!(error = %err, "background download failed");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            })
+        });
+
+        let start = Instant::now();
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut samples = Vec::new();
+        let mut lost = 0;
+
+        while start.elapsed() < self.duration {
+            ticker.tick().await;
+            let probe_start = Instant::now();
+            let latest = match self.client.get(&self.url).timeout(REQUEST_TIMEOUT).send().await {
+                Ok(response) => {
+                    let rtt = round_trip_ms(probe_start, &response);
+                    samples.push(rtt);
+                    Some(rtt)
+                }
+                Err(err) => {
+                    debug!(error = %err, "gaming probe lost");
+                    lost += 1;
+                    None
+                }
+            };
+
+            let _ = progress_tx
+                .send(GamingProgress {
+                    elapsed: start.elapsed(),
+                    duration: self.duration,
+                    latest_ms: latest,
+                })
+                .await;
+        }
+
+        if let Some(handle) = background {
+            handle.abort();
+        }
+
+        let result = calculate_result(&samples, lost, self.spike_threshold_ms, start.elapsed());
+        info!(
+            probes = result.probes,
+            p99_ms = result.p99_ms,
+            spikes = result.spikes,
+            "gaming test finished"
+        );
+        Ok(result)
+    }
+}
+
+fn calculate_result(samples: &[f64], lost: u32, spike_threshold_ms: f64, elapsed: Duration) -> GamingResult {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let median = percentile(&sorted, 50.0);
+    let spikes = samples.iter().filter(|&&s| s > median + spike_threshold_ms).count() as u32;
+    let minutes = elapsed.as_secs_f64() / 60.0;
+
+    GamingResult {
+        probes: samples.len() as u32 + lost,
+        lost,
+        min_ms: sorted.first().copied().unwrap_or(0.0),
+        median_ms: median,
+        p99_ms: percentile(&sorted, 99.0),
+        max_ms: sorted.last().copied().unwrap_or(0.0),
+        spikes,
+        spikes_per_minute: if minutes > 0.0 { spikes as f64 / minutes } else { 0.0 },
+    }
+}
+
+/// Sent after every probe.
+#[derive(Debug, Clone)]
+pub struct GamingProgress {
+    pub elapsed: Duration,
+    pub duration: Duration,
+    /// Latency of the latest probe, `None` if it was lost.
+    pub latest_ms: Option<f64>,
+}
+
+/// Outcome of a gaming-mode latency test.
+#[derive(Debug, Clone)]
+pub struct GamingResult {
+    pub probes: u32,
+    /// Probes that failed or timed out.
+    pub lost: u32,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    /// Probes more than the spike threshold above the median.
+    pub spikes: u32,
+    pub spikes_per_minute: f64,
+}
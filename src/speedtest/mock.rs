@@ -0,0 +1,245 @@
+use super::bidirectional::{BidirectionalProgress, BidirectionalResult};
+use super::download::DownloadProgress;
+use super::ping::{PingProgress, PingResult};
+use super::upload::UploadProgress;
+use super::{TestUpdate, TTFB_ROUND_TRIPS};
+use crate::samples::confidence_margin_95;
+use crate::settings::Settings;
+use anyhow::{anyhow, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Simulated time between progress updates, as often as real transfers report.
+pub const STEP: Duration = Duration::from_millis(100);
+// How quickly simulated throughput climbs to the link speed, in seconds,
+// like TCP slow start on a short path
+const RAMP_UP_SECS: f64 = 0.6;
+// Share of the link speed the noise moves each sample by, either way
+const NOISE: f64 = 0.06;
+// Longest a simulated transfer phase runs without a --duration
+const MAX_PHASE: Duration = Duration::from_secs(15);
+// Latency added by queues filling while a transfer runs
+const LOADED_PING_MS: f64 = 25.0;
+// Share of each direction's speed kept while both run at once
+const BIDIRECTIONAL_SHARE: (f64, f64) = (0.85, 0.7);
+
+/// Plays out a speed test without touching the network: ping, download and
+/// upload progress that ramps up and wobbles like a real link, for demos,
+/// screenshots and UI work. The same seed and settings replay the same test.
+#[derive(Debug, Clone)]
+pub struct MockProvider {
+    download_mbps: f64,
+    upload_mbps: f64,
+    ping_ms: f64,
+    jitter_ms: f64,
+    seed: u64,
+    pace: Duration,
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self {
+            download_mbps: 250.0,
+            upload_mbps: 40.0,
+            ping_ms: 18.0,
+            jitter_ms: 2.0,
+            seed: 1,
+            pace: STEP,
+        }
+    }
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The speeds, in Mbps, and idle latency, in ms, the simulation settles around.
+    pub fn with_link(mut self, download_mbps: f64, upload_mbps: f64, ping_ms: f64) -> Self {
+        self.download_mbps = download_mbps;
+        self.upload_mbps = upload_mbps;
+        self.ping_ms = ping_ms;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Real time between updates, [`STEP`] unless set. Zero plays the test
+    /// out as fast as the receiver keeps up, for tests.
+    pub fn with_pace(mut self, pace: Duration) -> Self {
+        self.pace = pace;
+        self
+    }
+
+    /// Sends the updates of a whole test on `update_tx`, in the order a
+    /// real one would for `settings`. Fails if the receiver goes away.
+    pub async fn run(&self, settings: &Settings, update_tx: mpsc::Sender<TestUpdate>) -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        // Ping
+        let mut samples = Vec::with_capacity(settings.ping_count);
+        for _ in 0..settings.ping_count {
+            let ms = self.ping_ms + rng.gen_range(-1.0..=1.0) * self.jitter_ms;
+            samples.push(ms);
+            let progress = PingProgress {
+                latest_ping: Some(ms),
+            };
+            self.send(&update_tx, TestUpdate::PingProgress(progress)).await?;
+        }
+        let ping = PingResult::from_samples(
+            &samples,
+            settings.ping_count,
+            settings.jitter_method,
+            settings.filter_ping_outliers,
+        );
+        let update = TestUpdate::PingComplete {
+            avg_ms: ping.avg_ms,
+            jitter_ms: ping.jitter_ms,
+            jitter_method: ping.jitter_method,
+            ping_mode: settings.ping_mode,
+            loss_percent: ping.loss_percent,
+            margin_ms: ping.margin_ms,
+            min_ms: ping.min_ms,
+            max_ms: ping.max_ms,
+            path_mtu: Some(1500),
+        };
+        self.send(&update_tx, update).await?;
+
+        // Download
+        let total = settings.download_size_bytes();
+        let mut speeds = Vec::new();
+        let mut downloaded = 0;
+        for (bytes, speed_mbps, avg_speed_mbps) in
+            transfer(&mut rng, self.download_mbps, total, settings.transfer_duration)
+        {
+            speeds.push(speed_mbps);
+            downloaded = bytes;
+            let progress = DownloadProgress {
+                downloaded_bytes: bytes,
+                total_bytes: total,
+                speed_mbps,
+                avg_speed_mbps,
+            };
+            self.send(&update_tx, TestUpdate::DownloadProgress(progress)).await?;
+        }
+        let update = TestUpdate::DownloadComplete {
+            speed_mbps: average(&speeds),
+            margin_mbps: confidence_margin_95(&speeds),
+            loaded_ping_ms: Some(self.ping_ms + LOADED_PING_MS * rng.gen_range(0.8..1.2)),
+            ttfb_ms: Some(self.ping_ms * TTFB_ROUND_TRIPS + rng.gen_range(5.0..15.0)),
+            warm_connection: false,
+            tcp: None,
+            bytes: downloaded,
+            elapsed_ms: simulated_ms(speeds.len()),
+        };
+        self.send(&update_tx, update).await?;
+
+        // Upload
+        let total = settings.upload_size_bytes() as u64;
+        let mut speeds = Vec::new();
+        let mut uploaded = 0;
+        for (bytes, speed_mbps, avg_speed_mbps) in
+            transfer(&mut rng, self.upload_mbps, total, settings.transfer_duration)
+        {
+            speeds.push(speed_mbps);
+            uploaded = bytes;
+            let progress = UploadProgress {
+                uploaded_bytes: bytes,
+                total_bytes: total,
+                speed_mbps,
+                avg_speed_mbps,
+            };
+            self.send(&update_tx, TestUpdate::UploadProgress(progress)).await?;
+        }
+        let update = TestUpdate::UploadComplete {
+            speed_mbps: average(&speeds),
+            margin_mbps: confidence_margin_95(&speeds),
+            loaded_ping_ms: Some(self.ping_ms + LOADED_PING_MS * rng.gen_range(1.5..2.5)),
+            tcp: None,
+            bytes: uploaded,
+            elapsed_ms: simulated_ms(speeds.len()),
+        };
+        self.send(&update_tx, update).await?;
+
+        // Both at once
+        if settings.bidirectional {
+            let (down_share, up_share) = BIDIRECTIONAL_SHARE;
+            let down_mbps = self.download_mbps * down_share;
+            let up_mbps = self.upload_mbps * up_share;
+            let total = settings.upload_size_bytes() as u64;
+            let steps: Vec<_> = transfer(&mut rng, up_mbps, total, settings.transfer_duration);
+            let (mut downs, mut ups) = (Vec::new(), Vec::new());
+            for (index, &(bytes, upload_mbps, _)) in steps.iter().enumerate() {
+                let download_mbps = noisy(&mut rng, down_mbps, index);
+                downs.push(download_mbps);
+                ups.push(upload_mbps);
+                let progress = BidirectionalProgress {
+                    download_mbps,
+                    upload_mbps,
+                    fraction: bytes as f64 / total.max(1) as f64,
+                };
+                self.send(&update_tx, TestUpdate::BidirectionalProgress(progress)).await?;
+            }
+            let result = BidirectionalResult {
+                download_mbps: average(&downs),
+                upload_mbps: average(&ups),
+            };
+            self.send(&update_tx, TestUpdate::BidirectionalComplete(result)).await?;
+        }
+        Ok(())
+    }
+
+    async fn send(&self, update_tx: &mpsc::Sender<TestUpdate>, update: TestUpdate) -> Result<()> {
+        if !self.pace.is_zero() {
+            tokio::time::sleep(self.pace).await;
+        }
+        update_tx.send(update).await.map_err(|_| anyhow!("update receiver closed"))
+    }
+}
+
+// Bytes done, latest speed and average speed after each simulated step of
+// moving `total` bytes at about `mbps`, until done or out of time
+fn transfer(
+    rng: &mut StdRng,
+    mbps: f64,
+    total: u64,
+    duration: Option<Duration>,
+) -> Vec<(u64, f64, f64)> {
+    let max_steps = (duration.unwrap_or(MAX_PHASE).as_secs_f64() / STEP.as_secs_f64()) as usize;
+    let mut steps = Vec::new();
+    let mut bytes = 0u64;
+    let mut speeds = Vec::new();
+    while bytes < total && steps.len() < max_steps.max(1) {
+        let speed = noisy(rng, mbps, steps.len());
+        let step_bytes = (speed * 1_000_000.0 / 8.0 * STEP.as_secs_f64()) as u64;
+        bytes = (bytes + step_bytes.max(1)).min(total);
+        speeds.push(speed);
+        steps.push((bytes, speed, average(&speeds)));
+    }
+    steps
+}
+
+// The speed at step `index` of a transfer ramping up to `mbps`, give or take the noise
+fn noisy(rng: &mut StdRng, mbps: f64, index: usize) -> f64 {
+    let elapsed = (index + 1) as f64 * STEP.as_secs_f64();
+    let ramp = 1.0 - (-elapsed / RAMP_UP_SECS).exp();
+    (mbps * ramp * (1.0 + rng.gen_range(-NOISE..=NOISE))).max(0.0)
+}
+
+// Simulated length of a transfer of `steps` steps
+fn simulated_ms(steps: usize) -> f64 {
+    steps as f64 * STEP.as_secs_f64() * 1000.0
+}
+
+fn average(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
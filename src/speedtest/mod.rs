@@ -1,20 +1,224 @@
+pub mod bidirectional;
+pub mod compare;
+pub mod dns;
 pub mod download;
+pub mod gaming;
+pub mod mock;
+pub mod monitor;
+pub mod mtu;
+mod orchestrator;
 pub mod ping;
+pub mod ports;
+pub mod portal;
+pub mod quality;
+pub mod regions;
+pub mod retry;
+mod runner;
+pub mod saturation;
+pub mod server;
+pub mod stats;
+pub mod sweep;
+pub mod tcp_info;
+pub mod throttle;
+pub mod throughput;
+pub mod udp;
 pub mod upload;
 
-#[derive(Debug, Clone, Default)]
+pub use orchestrator::{TestOrchestrator, TransitionError};
+pub use runner::{SpeedTest, SpeedTestHandle, TestUpdate};
+
+use crate::settings::Settings;
+use anyhow::Context;
+use bidirectional::BidirectionalResult;
+use ping::{JitterMethod, PingMode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// A response that delivers nothing for this long is treated as a dead connection
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+// The HTTP/2 default, and the smallest flow control window a memory budget sets
+const MIN_WINDOW_SIZE: usize = 65_535;
+
+/// Builds the HTTP client used by the tests. Request timeouts are set per
+/// request by each phase, so one client can be shared across all of them;
+/// responses that stall for 30 seconds fail regardless.
+///
+/// With a memory budget, HTTP/2 flow control keeps servers from sending
+/// more than a quarter of it ahead of the reads.
+pub fn http_client(settings: &Settings) -> anyhow::Result<reqwest::Client> {
+    Ok(client_builder(settings)?.build()?)
+}
+
+/// Like [`http_client`], but opening a new HTTP/1.1 connection for every
+/// request, for [`PingMode::Cold`] probes.
+pub fn cold_http_client(settings: &Settings) -> anyhow::Result<reqwest::Client> {
+    Ok(client_builder(settings)?.http1_only().pool_max_idle_per_host(0).build()?)
+}
+
+fn client_builder(settings: &Settings) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .read_timeout(STALL_TIMEOUT)
+        .local_address(settings.local_address)
+        .default_headers(settings.credentials.header_map()?)
+        .danger_accept_invalid_certs(settings.accept_invalid_certs);
+
+    if let Some(budget) = settings.memory_budget_bytes() {
+        let streams = settings.download_streams.max(settings.upload_streams).max(1);
+        let window = (budget / 4 / streams).clamp(MIN_WINDOW_SIZE, u32::MAX as usize) as u32;
+        builder = builder
+            .http2_initial_stream_window_size(window)
+            .http2_initial_connection_window_size(window);
+    }
+
+    if let Some(path) = &settings.ca_cert {
+        let pem = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("invalid certificate in {}", path.display()))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+/// Download-to-upload ratio from which a link counts as extremely
+/// asymmetric, as on upload-starved cable and DSL plans.
+pub const EXTREME_ASYMMETRY_RATIO: f64 = 10.0;
+
+/// Download-to-upload ratio up to which a link counts as symmetric, as
+/// fiber usually is.
+pub const SYMMETRIC_RATIO: f64 = 1.5;
+
+/// Round trips before the first byte of a request on a fresh connection:
+/// the TCP handshake, the TLS 1.3 handshake and the request itself.
+pub const TTFB_ROUND_TRIPS: f64 = 3.0;
+
+/// Server delay, see [`SpeedTestResult::server_delay_ms`], above which the
+/// server is slow to answer.
+pub const SLOW_SERVER_MS: f64 = 200.0;
+
+/// What a download-to-upload ratio says about the link, see
+/// [`SpeedTestResult::asymmetry_ratio`].
+pub fn asymmetry_label(ratio: f64) -> &'static str {
+    if ratio <= SYMMETRIC_RATIO {
+        "symmetric, typical of fiber"
+    } else if ratio < EXTREME_ASYMMETRY_RATIO {
+        "asymmetric, typical of cable or DSL"
+    } else {
+        "extremely asymmetric, upload-starved"
+    }
+}
+
+/// Final measurements from a completed speed test.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SpeedTestResult {
     pub download_mbps: f64,
     pub upload_mbps: f64,
     pub ping_ms: f64,
     pub jitter_ms: f64,
+    pub jitter_method: JitterMethod,
+    /// Whether `ping_ms` is request round trips or connection setup.
+    pub ping_mode: PingMode,
+    /// Share of ping-phase probes that failed.
+    pub ping_loss_percent: f64,
+    /// Fastest and slowest ping-phase round trips.
+    pub ping_min_ms: f64,
+    pub ping_max_ms: f64,
+    /// Half-widths of the 95% confidence intervals for the download, upload
+    /// and ping figures, from the spread of their samples.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_margin_mbps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_margin_mbps: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ping_margin_ms: Option<f64>,
+    /// Average latency measured while the download was running.
+    pub download_ping_ms: Option<f64>,
+    /// Average latency measured while the upload was running.
+    pub upload_ping_ms: Option<f64>,
+    /// Bytes each transfer moved, and the milliseconds from its first byte
+    /// to its last.
+    pub download_bytes: u64,
+    pub download_elapsed_ms: f64,
+    pub upload_bytes: u64,
+    pub upload_elapsed_ms: f64,
+    /// Time to the first byte of the download, connection setup included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_ttfb_ms: Option<f64>,
+    /// Whether the download reused an open connection, leaving the
+    /// handshakes out of `download_ttfb_ms`.
+    pub download_warm_connection: bool,
+    /// Estimated path MTU to the server in bytes, see [`mtu::discover`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_mtu: Option<u16>,
+    /// Throughput with download and upload running at once, if that phase ran.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bidirectional: Option<BidirectionalResult>,
+}
+
+impl SpeedTestResult {
+    /// Largest increase over idle latency seen under load, if measured.
+    pub fn latency_increase_ms(&self) -> Option<f64> {
+        let loaded = match (self.download_ping_ms, self.upload_ping_ms) {
+            (Some(down), Some(up)) => down.max(up),
+            (Some(down), None) => down,
+            (None, Some(up)) => up,
+            (None, None) => return None,
+        };
+        Some((loaded - self.ping_ms).max(0.0))
+    }
+
+    /// Time to first byte beyond the round trips the connection needed
+    /// before the server could answer: [`TTFB_ROUND_TRIPS`] on a fresh one,
+    /// only the request on a reused one. Roughly how long the server, or TLS
+    /// on its side, took; large values with a low ping point at the server
+    /// rather than the network.
+    pub fn server_delay_ms(&self) -> Option<f64> {
+        let ttfb = self.download_ttfb_ms?;
+        let round_trips = if self.download_warm_connection { 1.0 } else { TTFB_ROUND_TRIPS };
+        Some((ttfb - self.ping_ms * round_trips).max(0.0))
+    }
+
+    /// Download speed divided by upload speed, if both were measured.
+    pub fn asymmetry_ratio(&self) -> Option<f64> {
+        (self.download_mbps > 0.0 && self.upload_mbps > 0.0)
+            .then(|| self.download_mbps / self.upload_mbps)
+    }
+
+    /// Share of the one-way download and upload speeds, in percent, each
+    /// direction kept under bidirectional load.
+    pub fn bidirectional_retained_percent(&self) -> Option<(f64, f64)> {
+        let both = self.bidirectional?;
+        let retained = |loaded: f64, alone: f64| {
+            if alone > 0.0 {
+                loaded / alone * 100.0
+            } else {
+                0.0
+            }
+        };
+        Some((
+            retained(both.download_mbps, self.download_mbps),
+            retained(both.upload_mbps, self.upload_mbps),
+        ))
+    }
+
+    /// Estimated call quality from the ping phase, see [`quality::estimate_mos`].
+    pub fn mos(&self) -> f64 {
+        quality::estimate_mos(self.ping_ms, self.jitter_ms, self.ping_loss_percent)
+    }
 }
 
+/// The stage a speed test is currently in.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TestPhase {
     Idle,
     Ping,
     Download,
     Upload,
+    /// Download and upload at once; only runs if
+    /// [`Settings::bidirectional`] is set.
+    Bidirectional,
     Complete,
 }
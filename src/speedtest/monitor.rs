@@ -0,0 +1,129 @@
+use super::ping::{round_trip_ms, tcp_connect_ms};
+use crate::network;
+use crate::samples::percentile;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probes latency at a steady rate until dropped, for watching a connection
+/// between or instead of full tests.
+///
+/// Each probe is an HTTP request; its round-trip time in milliseconds, or
+/// `None` if it failed or timed out, arrives on `samples`.
+pub struct LatencyMonitor {
+    pub samples: mpsc::Receiver<Option<f64>>,
+    handle: JoinHandle<()>,
+}
+
+impl LatencyMonitor {
+    pub fn spawn(client: reqwest::Client, url: String, interval: Duration) -> Self {
+        Self::spawn_probe(interval, move || {
+            let request = client.get(&url).timeout(REQUEST_TIMEOUT).send();
+            async move {
+                let start = Instant::now();
+                match request.await {
+                    Ok(response) => Some(round_trip_ms(start, &response)),
+                    Err(err) => {
+                        debug!(error = %err, "monitor probe lost");
+                        None
+                    }
+                }
+            }
+        })
+    }
+
+    /// Times TCP connects to `addr` instead, with no HTTP, sending from
+    /// `local_address` when given. Works against any host accepting
+    /// connections, like port 443 of a web server.
+    pub fn spawn_tcp(addr: SocketAddr, local_address: Option<IpAddr>, interval: Duration) -> Self {
+        Self::spawn_probe(interval, move || async move {
+            match tcp_connect_ms(addr, local_address, REQUEST_TIMEOUT).await {
+                Ok(rtt) => Some(rtt),
+                Err(err) => {
+                    debug!(error = %err, "monitor probe lost");
+                    None
+                }
+            }
+        })
+    }
+
+    /// Probes the local `gateway` instead, timing TCP connects with
+    /// [`network::gateway_rtt`], to tell latency on the LAN from latency
+    /// beyond it.
+    pub fn spawn_gateway(gateway: IpAddr, interval: Duration) -> Self {
+        Self::spawn_probe(interval, move || async move {
+            let rtt = network::gateway_rtt(gateway).await;
+            rtt.map(|rtt| rtt.as_secs_f64() * 1000.0)
+        })
+    }
+
+    // Runs `probe` every `interval`, sending on its results
+    fn spawn_probe<F, Fut>(interval: Duration, mut probe: F) -> Self
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Option<f64>> + Send,
+    {
+        let (tx, samples) = mpsc::channel(64);
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if tx.send(probe().await).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { samples, handle }
+    }
+}
+
+impl Drop for LatencyMonitor {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Loss and latency distribution over a monitoring session.
+#[derive(Debug, Clone, Default)]
+pub struct MonitorStats {
+    pub sent: u32,
+    pub lost: u32,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl MonitorStats {
+    /// Summarises the round-trip times of answered probes plus a count of
+    /// lost ones.
+    pub fn new(rtts_ms: &[f64], lost: u32) -> Self {
+        let mut sorted = rtts_ms.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        Self {
+            sent: sorted.len() as u32 + lost,
+            lost,
+            min_ms: sorted.first().copied().unwrap_or(0.0),
+            p50_ms: percentile(&sorted, 50.0),
+            p90_ms: percentile(&sorted, 90.0),
+            p99_ms: percentile(&sorted, 99.0),
+            max_ms: sorted.last().copied().unwrap_or(0.0),
+        }
+    }
+
+    pub fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            self.lost as f64 / self.sent as f64 * 100.0
+        }
+    }
+}
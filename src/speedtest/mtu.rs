@@ -0,0 +1,65 @@
+use super::tcp_info::TcpInfo;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::net::TcpSocket;
+use tracing::{debug, info};
+
+/// MTU of a plain Ethernet path, which anything smaller is compared against.
+pub const ETHERNET_MTU: u16 = 1500;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Estimates the path MTU to the server behind `server_url` from a TCP
+/// connection to it: the smaller of the kernel's path MTU and the segment
+/// size both ends agreed on, plus headers. Routers that clamp the segment
+/// size for PPPoE or a tunnel show up this way even when ICMP is blocked.
+///
+/// Returns `None` if the server can't be reached or the platform doesn't
+/// expose connection statistics.
+pub async fn discover(server_url: &str, local_address: Option<IpAddr>) -> Option<u16> {
+    let url = reqwest::Url::parse(server_url).ok()?;
+    let port = url.port_or_known_default()?;
+    let host = url.host_str()?.trim_matches(['[', ']']);
+    let addr = tokio::net::lookup_host((host, port)).await.ok()?.next()?;
+
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }
+    .ok()?;
+    if let Some(local) = local_address {
+        socket.bind(SocketAddr::new(local, 0)).ok()?;
+    }
+    let stream = match tokio::time::timeout(CONNECT_TIMEOUT, socket.connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(err)) => {
+            debug!(error = %err, "path MTU probe could not connect");
+            return None;
+        }
+        Err(_) => {
+            debug!("path MTU probe timed out");
+            return None;
+        }
+    };
+
+    let info = TcpInfo::of(&stream)
+        .inspect_err(|err| debug!(error = %err, "path MTU probe unsupported"))
+        .ok()?;
+    let ip_header = if addr.is_ipv4() { 20 } else { 40 };
+    let options = if info.timestamps { 12 } else { 0 };
+    let from_mss = info.send_mss + ip_header + 20 + options;
+    let mtu = from_mss.min(info.path_mtu).min(u16::MAX as u32) as u16;
+    info!(mtu, send_mss = info.send_mss, kernel_pmtu = info.path_mtu, "path MTU estimated");
+    Some(mtu)
+}
+
+/// The usual reason for a path MTU this size, if it's below Ethernet's.
+pub fn overhead_hint(mtu: u16) -> Option<&'static str> {
+    match mtu {
+        ETHERNET_MTU.. => None,
+        1492 => Some("PPPoE"),
+        1480..=1499 => Some("PPPoE or tunnel overhead"),
+        1400..=1479 => Some("VPN or tunnel overhead"),
+        _ => Some("a heavily encapsulated link"),
+    }
+}
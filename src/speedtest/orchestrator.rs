@@ -0,0 +1,337 @@
+use super::{SpeedTestResult, TestPhase, TestUpdate};
+use std::fmt;
+
+/// Tracks which phase a speed test is in and accumulates its result.
+///
+/// Every [`TestUpdate`] is checked against the current phase, so both the
+/// test runner and its consumers agree on the order ping → download →
+/// upload → (bidirectional →) complete, and out-of-order updates are
+/// reported instead of silently corrupting state.
+#[derive(Debug, Clone)]
+pub struct TestOrchestrator {
+    phase: TestPhase,
+    result: SpeedTestResult,
+    bidirectional: bool,
+}
+
+/// An update or action that isn't valid in the current phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionError {
+    /// A test was started while another was still running.
+    AlreadyRunning(TestPhase),
+    /// An update arrived for a phase other than the current one.
+    UnexpectedUpdate { phase: TestPhase, update: TestPhase },
+    /// The update stream ended before the test completed.
+    Interrupted(TestPhase),
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::AlreadyRunning(phase) => {
+                write!(f, "test already running ({:?})", phase)
+            }
+            TransitionError::UnexpectedUpdate { phase, update } => {
+                write!(f, "received {:?} update during {:?}", update, phase)
+            }
+            TransitionError::Interrupted(phase) => write!(f, "test stopped during {:?}", phase),
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+impl TestOrchestrator {
+    pub fn new() -> Self {
+        Self {
+            phase: TestPhase::Idle,
+            result: SpeedTestResult::default(),
+            bidirectional: false,
+        }
+    }
+
+    /// Whether a bidirectional phase follows the upload.
+    pub fn bidirectional(&self) -> bool {
+        self.bidirectional
+    }
+
+    /// Whether tests expect a bidirectional phase after the upload. Takes
+    /// effect from the next upload completion.
+    pub fn set_bidirectional(&mut self, enabled: bool) {
+        self.bidirectional = enabled;
+    }
+
+    pub fn phase(&self) -> TestPhase {
+        self.phase
+    }
+
+    pub fn result(&self) -> &SpeedTestResult {
+        &self.result
+    }
+
+    /// True while a phase is in progress.
+    pub fn is_running(&self) -> bool {
+        !matches!(self.phase, TestPhase::Idle | TestPhase::Complete)
+    }
+
+    /// Clears the previous result and enters the ping phase.
+    pub fn start(&mut self) -> Result<(), TransitionError> {
+        if self.is_running() {
+            return Err(TransitionError::AlreadyRunning(self.phase));
+        }
+        self.result = SpeedTestResult::default();
+        self.phase = TestPhase::Ping;
+        Ok(())
+    }
+
+    /// Applies an update, advancing to the next phase on completion events.
+    pub fn apply(&mut self, update: &TestUpdate) -> Result<TestPhase, TransitionError> {
+        let update_phase = update.phase();
+        if update_phase != self.phase {
+            return Err(TransitionError::UnexpectedUpdate {
+                phase: self.phase,
+                update: update_phase,
+            });
+        }
+
+        match *update {
+            TestUpdate::PingComplete {
+                avg_ms,
+                jitter_ms,
+                jitter_method,
+                ping_mode,
+                loss_percent,
+                margin_ms,
+                min_ms,
+                max_ms,
+                path_mtu,
+            } => {
+                self.result.ping_ms = avg_ms;
+                self.result.jitter_ms = jitter_ms;
+                self.result.jitter_method = jitter_method;
+                self.result.ping_mode = ping_mode;
+                self.result.ping_loss_percent = loss_percent;
+                self.result.ping_margin_ms = margin_ms;
+                self.result.ping_min_ms = min_ms;
+                self.result.ping_max_ms = max_ms;
+                self.result.path_mtu = path_mtu;
+                self.phase = TestPhase::Download;
+            }
+            TestUpdate::DownloadComplete {
+                speed_mbps,
+                margin_mbps,
+                loaded_ping_ms,
+                ttfb_ms,
+                warm_connection,
+                bytes,
+                elapsed_ms,
+                ..
+            } => {
+                self.result.download_mbps = speed_mbps;
+                self.result.download_margin_mbps = margin_mbps;
+                self.result.download_ping_ms = loaded_ping_ms;
+                self.result.download_ttfb_ms = ttfb_ms;
+                self.result.download_warm_connection = warm_connection;
+                self.result.download_bytes = bytes;
+                self.result.download_elapsed_ms = elapsed_ms;
+                self.phase = TestPhase::Upload;
+            }
+            TestUpdate::UploadComplete {
+                speed_mbps,
+                margin_mbps,
+                loaded_ping_ms,
+                bytes,
+                elapsed_ms,
+                ..
+            } => {
+                self.result.upload_mbps = speed_mbps;
+                self.result.upload_margin_mbps = margin_mbps;
+                self.result.upload_ping_ms = loaded_ping_ms;
+                self.result.upload_bytes = bytes;
+                self.result.upload_elapsed_ms = elapsed_ms;
+                self.phase = if self.bidirectional {
+                    TestPhase::Bidirectional
+                } else {
+                    TestPhase::Complete
+                };
+            }
+            TestUpdate::BidirectionalComplete(result) => {
+                self.result.bidirectional = Some(result);
+                self.phase = TestPhase::Complete;
+            }
+            TestUpdate::Failed { .. } => self.phase = TestPhase::Idle,
+            TestUpdate::Warning { .. }
+            | TestUpdate::PingProgress(_)
+            | TestUpdate::DownloadProgress(_)
+            | TestUpdate::UploadProgress(_)
+            | TestUpdate::BidirectionalProgress(_) => {}
+        }
+
+        Ok(self.phase)
+    }
+
+    /// Abandons the running test and returns to idle.
+    pub fn cancel(&mut self) {
+        self.phase = TestPhase::Idle;
+    }
+
+    /// Called when the update stream closes. Returns to idle with an error
+    /// if the test hadn't completed yet.
+    pub fn finish(&mut self) -> Result<(), TransitionError> {
+        if self.is_running() {
+            let phase = self.phase;
+            self.phase = TestPhase::Idle;
+            return Err(TransitionError::Interrupted(phase));
+        }
+        Ok(())
+    }
+}
+
+impl Default for TestOrchestrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::speedtest::bidirectional::BidirectionalResult;
+    use crate::speedtest::ping::{JitterMethod, PingMode};
+
+    fn ping_complete() -> TestUpdate {
+        TestUpdate::PingComplete {
+            avg_ms: 12.0,
+            jitter_ms: 1.5,
+            jitter_method: JitterMethod::StdDev,
+            ping_mode: PingMode::Warm,
+            loss_percent: 0.0,
+            margin_ms: Some(0.4),
+            min_ms: 10.0,
+            max_ms: 15.0,
+            path_mtu: Some(1500),
+        }
+    }
+
+    fn download_complete() -> TestUpdate {
+        TestUpdate::DownloadComplete {
+            speed_mbps: 250.0,
+            margin_mbps: Some(5.0),
+            loaded_ping_ms: Some(30.0),
+            ttfb_ms: Some(20.0),
+            warm_connection: true,
+            tcp: None,
+            bytes: 25_000_000,
+            elapsed_ms: 800.0,
+        }
+    }
+
+    fn upload_complete() -> TestUpdate {
+        TestUpdate::UploadComplete {
+            speed_mbps: 40.0,
+            margin_mbps: None,
+            loaded_ping_ms: Some(60.0),
+            tcp: None,
+            bytes: 10_000_000,
+            elapsed_ms: 2000.0,
+        }
+    }
+
+    fn bidirectional_complete() -> TestUpdate {
+        TestUpdate::BidirectionalComplete(BidirectionalResult {
+            download_mbps: 200.0,
+            upload_mbps: 35.0,
+        })
+    }
+
+    #[test]
+    fn phases_advance_in_order() {
+        let mut orchestrator = TestOrchestrator::new();
+        orchestrator.start().unwrap();
+        assert_eq!(orchestrator.phase(), TestPhase::Ping);
+        assert_eq!(orchestrator.apply(&ping_complete()), Ok(TestPhase::Download));
+        assert_eq!(orchestrator.apply(&download_complete()), Ok(TestPhase::Upload));
+        assert_eq!(orchestrator.apply(&upload_complete()), Ok(TestPhase::Complete));
+        assert!(!orchestrator.is_running());
+        assert!(orchestrator.finish().is_ok());
+
+        let result = orchestrator.result();
+        assert_eq!(result.ping_ms, 12.0);
+        assert_eq!(result.download_mbps, 250.0);
+        assert_eq!(result.upload_mbps, 40.0);
+        assert!(result.bidirectional.is_none());
+    }
+
+    #[test]
+    fn bidirectional_phase_follows_the_upload_when_enabled() {
+        let mut orchestrator = TestOrchestrator::new();
+        orchestrator.set_bidirectional(true);
+        orchestrator.start().unwrap();
+        orchestrator.apply(&ping_complete()).unwrap();
+        orchestrator.apply(&download_complete()).unwrap();
+        assert_eq!(orchestrator.apply(&upload_complete()), Ok(TestPhase::Bidirectional));
+        assert!(orchestrator.is_running());
+        assert_eq!(orchestrator.apply(&bidirectional_complete()), Ok(TestPhase::Complete));
+        assert_eq!(orchestrator.result().bidirectional.map(|b| b.upload_mbps), Some(35.0));
+    }
+
+    #[test]
+    fn bidirectional_updates_are_rejected_when_disabled() {
+        let mut orchestrator = TestOrchestrator::new();
+        orchestrator.start().unwrap();
+        orchestrator.apply(&ping_complete()).unwrap();
+        orchestrator.apply(&download_complete()).unwrap();
+        orchestrator.apply(&upload_complete()).unwrap();
+        assert_eq!(
+            orchestrator.apply(&bidirectional_complete()),
+            Err(TransitionError::UnexpectedUpdate {
+                phase: TestPhase::Complete,
+                update: TestPhase::Bidirectional,
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_order_updates_leave_the_phase_alone() {
+        let mut orchestrator = TestOrchestrator::new();
+        orchestrator.start().unwrap();
+        assert_eq!(
+            orchestrator.apply(&upload_complete()),
+            Err(TransitionError::UnexpectedUpdate {
+                phase: TestPhase::Ping,
+                update: TestPhase::Upload,
+            })
+        );
+        assert_eq!(orchestrator.phase(), TestPhase::Ping);
+        assert_eq!(
+            orchestrator.start(),
+            Err(TransitionError::AlreadyRunning(TestPhase::Ping))
+        );
+    }
+
+    #[test]
+    fn failure_returns_to_idle() {
+        let mut orchestrator = TestOrchestrator::new();
+        orchestrator.start().unwrap();
+        orchestrator.apply(&ping_complete()).unwrap();
+        let failed = |phase| TestUpdate::Failed {
+            phase,
+            error: "timed out".to_string(),
+        };
+        // Only a failure of the running phase counts
+        assert!(orchestrator.apply(&failed(TestPhase::Upload)).is_err());
+        assert_eq!(orchestrator.apply(&failed(TestPhase::Download)), Ok(TestPhase::Idle));
+        assert!(!orchestrator.is_running());
+        assert!(orchestrator.finish().is_ok());
+        assert_eq!(orchestrator.start(), Ok(()));
+    }
+
+    #[test]
+    fn finishing_early_reports_the_interrupted_phase() {
+        let mut orchestrator = TestOrchestrator::new();
+        orchestrator.start().unwrap();
+        orchestrator.apply(&ping_complete()).unwrap();
+        assert_eq!(orchestrator.finish(), Err(TransitionError::Interrupted(TestPhase::Download)));
+        assert_eq!(orchestrator.phase(), TestPhase::Idle);
+    }
+}
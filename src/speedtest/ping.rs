@@ -1,73 +1,488 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use futures::{future, stream, StreamExt};
+use super::server::{self, CLOUDFLARE_URL};
+use super::retry::RetryPolicy;
+use super::stats::TransferStats;
+use crate::samples::{confidence_margin_95, without_outliers};
+use reqwest::header::CONNECTION;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
+use tokio::net::TcpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
 
-const PING_URL: &str = "https://speed.cloudflare.com/__down?bytes=0";
+/// How long a probe waits for its answer before it counts as lost, unless
+/// [`PingTest::with_timeout`] says otherwise.
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const LOADED_PROBE_INTERVAL: Duration = Duration::from_millis(250);
+/// Pause after each probe before the next one goes out.
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_millis(200);
+/// Most probes the ping phase keeps in flight at once.
+pub const MAX_PING_CONCURRENCY: usize = 8;
+/// With outlier filtering, probes further than this many median absolute
+/// deviations from the median are left out of the average and jitter.
+pub const OUTLIER_MADS: f64 = 3.0;
 
+/// How jitter is derived from the latency samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JitterMethod {
+    /// Sample standard deviation of all round-trip times.
+    #[default]
+    StdDev,
+    /// Inter-packet delay variation in the spirit of RFC 3550: the mean
+    /// absolute difference between consecutive round-trip times.
+    Ipdv,
+}
+
+impl JitterMethod {
+    pub fn label(self) -> &'static str {
+        match self {
+            JitterMethod::StdDev => "std dev",
+            JitterMethod::Ipdv => "ipdv",
+        }
+    }
+}
+
+/// Which latency the ping phase measures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PingMode {
+    /// Request round trips over connections opened before the first probe.
+    #[default]
+    Warm,
+    /// A new connection for every probe, so each one includes the TCP and
+    /// TLS handshakes: the latency of setting up a connection.
+    Cold,
+    /// Raw TCP connects to the server's host without any HTTP, which tracks
+    /// the network round trip free of server processing time.
+    Tcp,
+}
+
+impl PingMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            PingMode::Warm => "warm",
+            PingMode::Cold => "cold",
+            PingMode::Tcp => "tcp",
+        }
+    }
+
+    /// What a latency measured in this mode covers.
+    pub fn describe(self) -> &'static str {
+        match self {
+            PingMode::Warm => "request round trip over a reused connection",
+            PingMode::Cold => "new TCP and TLS handshake per probe",
+            PingMode::Tcp => "TCP connect time, no HTTP",
+        }
+    }
+}
+
+/// The address [`PingMode::Tcp`] probes for `url` connect to: its host, on
+/// its port or 443 for https and 80 for http, in the family of
+/// `local_address` if one is given.
+pub async fn tcp_target(url: &str, local_address: Option<IpAddr>) -> Result<SocketAddr> {
+    let url = reqwest::Url::parse(url).with_context(|| format!("invalid URL {}", url))?;
+    let host = url.host_str().context("URL has no host")?;
+    let port = url.port_or_known_default().context("URL has no port")?;
+    let addr = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("couldn't resolve {}", host))?
+        .find(|addr| local_address.is_none_or(|local| local.is_ipv4() == addr.is_ipv4()))
+        .with_context(|| format!("no address for {}", host))?;
+    Ok(addr)
+}
+
+/// Time to open a TCP connection to `addr`, in milliseconds, sending from
+/// `local_address` when given, failing after `timeout`. The connection is
+/// closed straight away.
+pub async fn tcp_connect_ms(
+    addr: SocketAddr,
+    local_address: Option<IpAddr>,
+    timeout: Duration,
+) -> Result<f64> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    if let Some(local) = local_address {
+        socket.bind(SocketAddr::new(local, 0))?;
+    }
+    let start = Instant::now();
+    tokio::time::timeout(timeout, socket.connect(addr))
+        .await
+        .context("no answer")??;
+    Ok(start.elapsed().as_secs_f64() * 1000.0)
+}
+
+/// Round-trip time of a request sent at `start`, in milliseconds, less the
+/// time the server reports spending on it, so only the network is measured.
+pub fn round_trip_ms(start: Instant, response: &reqwest::Response) -> f64 {
+    let elapsed = start.elapsed();
+    let processing = server::processing_time(response.headers()).unwrap_or_default();
+    elapsed.saturating_sub(processing).as_secs_f64() * 1000.0
+}
+
+/// Measures round-trip latency with a series of empty HTTP requests.
 pub struct PingTest {
+    client: reqwest::Client,
+    url: String,
     samples: Vec<f64>,
     ping_count: usize,
+    jitter_method: JitterMethod,
+    filter_outliers: bool,
+    interval: Duration,
+    concurrency: usize,
+    mode: PingMode,
+    local_address: Option<IpAddr>,
+    timeout: Duration,
+    retry: RetryPolicy,
+    stats: Arc<TransferStats>,
 }
 
 impl PingTest {
-    pub fn new(ping_count: usize) -> Self {
+    pub fn new(client: reqwest::Client, ping_count: usize) -> Self {
         Self {
+            client,
+            url: server::ping_url(CLOUDFLARE_URL),
             samples: Vec::new(),
             ping_count,
+            jitter_method: JitterMethod::default(),
+            filter_outliers: false,
+            interval: DEFAULT_PING_INTERVAL,
+            concurrency: 1,
+            mode: PingMode::default(),
+            local_address: None,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            retry: RetryPolicy::default(),
+            stats: Arc::default(),
         }
     }
 
-    pub async fn run(&mut self, progress_tx: mpsc::Sender<PingProgress>) -> Result<PingResult> {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(5))
-            .build()?;
+    /// Probes `url` instead of Cloudflare.
+    pub fn with_url(mut self, url: String) -> Self {
+        self.url = url;
+        self
+    }
 
-        self.samples.clear();
+    /// Records request counters into `stats`.
+    pub fn with_stats(mut self, stats: Arc<TransferStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Retries probes that fail transiently according to `retry`. Only the
+    /// final attempt's round trip is measured; a probe counts as lost once
+    /// its retries run out.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-        for _ in 0..self.ping_count {
-            let start = Instant::now();
-            if client.get(PING_URL).send().await.is_ok() {
-                let elapsed = start.elapsed().as_secs_f64() * 1000.0;
-                self.samples.push(elapsed);
+    pub fn with_jitter_method(mut self, jitter_method: JitterMethod) -> Self {
+        self.jitter_method = jitter_method;
+        self
+    }
+
+    /// Leaves probes beyond [`OUTLIER_MADS`] out of the result, so one
+    /// stalled probe doesn't skew it. Progress still reports every probe.
+    pub fn with_outlier_filter(mut self, filter_outliers: bool) -> Self {
+        self.filter_outliers = filter_outliers;
+        self
+    }
+
+    /// Pause between a probe's answer and the next probe in its place,
+    /// [`DEFAULT_PING_INTERVAL`] unless set.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Keeps up to `concurrency` probes in flight, 1..=[`MAX_PING_CONCURRENCY`],
+    /// shortening the phase. Each needs a connection of its own over
+    /// HTTP/1.1, which warm mode opens with untimed probes first.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.clamp(1, MAX_PING_CONCURRENCY);
+        self
+    }
+
+    /// Measures request round trips or connection setup, [`PingMode::Warm`]
+    /// unless set. Cold probes ask the server to close the connection after
+    /// answering; over HTTP/2, which ignores that, the client must not pool
+    /// connections, like [`cold_http_client`](super::cold_http_client).
+    pub fn with_mode(mut self, mode: PingMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Connects [`PingMode::Tcp`] probes from `local_address`; HTTP probes
+    /// use the client's.
+    pub fn with_local_address(mut self, local_address: Option<IpAddr>) -> Self {
+        self.local_address = local_address;
+        self
+    }
+
+    /// Counts a probe as lost once it has waited `timeout` for its answer,
+    /// [`DEFAULT_PROBE_TIMEOUT`] unless set.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn run(&mut self, progress_tx: mpsc::Sender<PingProgress>) -> Result<PingResult> {
+        self.samples.clear();
+        let mut tcp_addr = None;
+        match self.mode {
+            PingMode::Warm => {
+                let warm_up = (0..self.concurrency)
+                    .map(|_| self.client.get(&self.url).timeout(self.timeout).send());
+                future::join_all(warm_up).await;
             }
+            PingMode::Cold => {}
+            // Resolved once, so DNS isn't timed with the first probe
+            PingMode::Tcp => tcp_addr = Some(tcp_target(&self.url, self.local_address).await?),
+        }
 
+        // The first probe in each place goes out at once, the rest after the interval
+        let this = &*self;
+        let concurrency = self.concurrency;
+        let mut samples = Vec::with_capacity(self.ping_count);
+        let mut probes = stream::iter(0..self.ping_count)
+            .map(|index| async move {
+                if index >= concurrency {
+                    tokio::time::sleep(this.interval).await;
+                }
+                match tcp_addr {
+                    Some(addr) => this.connect_probe(addr).await,
+                    None => this.probe().await,
+                }
+            })
+            .buffer_unordered(concurrency);
+        while let Some(rtt) = probes.next().await {
+            samples.extend(rtt);
             let _ = progress_tx
                 .send(PingProgress {
-                    latest_ping: self.samples.last().copied(),
+                    latest_ping: samples.last().copied(),
                 })
                 .await;
+        }
+        drop(probes);
+        self.samples = samples;
 
-            tokio::time::sleep(Duration::from_millis(200)).await;
+        let result = self.calculate_result();
+        info!(
+            probes = self.ping_count,
+            succeeded = self.samples.len(),
+            concurrency,
+            mode = self.mode.label(),
+            avg_ms = result.avg_ms,
+            jitter_ms = result.jitter_ms,
+            "ping phase finished"
+        );
+        Ok(result)
+    }
+
+    // One probe's round trip, or `None` once it fails for good
+    async fn probe(&self) -> Option<f64> {
+        let request = self.stats.start_request("ping");
+        // Restarted for every attempt, so backoff isn't counted as latency
+        let mut start = Instant::now();
+        let response = self
+            .retry
+            .send(&self.stats, || {
+                start = Instant::now();
+                let request = self.client.get(&self.url).timeout(self.timeout);
+                match self.mode {
+                    PingMode::Cold => request.header(CONNECTION, "close"),
+                    PingMode::Warm | PingMode::Tcp => request,
+                }
+            })
+            .await;
+        match response {
+            Ok(response) => {
+                request.record_status(response.status().as_u16());
+                let rtt = round_trip_ms(start, &response);
+                debug!(rtt_ms = rtt, status = %response.status(), "ping probe");
+                Some(rtt)
+            }
+            Err(err) => {
+                warn!(error = %err, "ping probe failed");
+                self.stats.record_error();
+                None
+            }
         }
+    }
 
-        Ok(self.calculate_result())
+    // One TCP connect time, or `None` if the connection failed
+    async fn connect_probe(&self, addr: SocketAddr) -> Option<f64> {
+        match tcp_connect_ms(addr, self.local_address, self.timeout).await {
+            Ok(rtt) => {
+                debug!(rtt_ms = rtt, %addr, "tcp ping probe");
+                Some(rtt)
+            }
+            Err(err) => {
+                warn!(error = %err, %addr, "tcp ping probe failed");
+                self.stats.record_error();
+                None
+            }
+        }
     }
 
     fn calculate_result(&self) -> PingResult {
-        if self.samples.is_empty() {
-            return PingResult { avg_ms: 0.0, jitter_ms: 0.0 };
-        }
+        PingResult::from_samples(
+            &self.samples,
+            self.ping_count,
+            self.jitter_method,
+            self.filter_outliers,
+        )
+    }
+}
+
+/// Probes latency in the background while a transfer saturates the link.
+///
+/// Uses its own client so probes don't queue behind the transfer on a shared
+/// connection. Dropping the probe stops it.
+pub struct LoadedLatencyProbe {
+    stop_tx: oneshot::Sender<()>,
+    handle: JoinHandle<Vec<f64>>,
+}
 
-        let avg = self.samples.iter().sum::<f64>() / self.samples.len() as f64;
-        let jitter = if self.samples.len() > 1 {
-            let variance: f64 = self.samples.iter().map(|&x| (x - avg).powi(2)).sum::<f64>()
-                / (self.samples.len() - 1) as f64;
-            variance.sqrt()
+impl LoadedLatencyProbe {
+    pub fn spawn(client: reqwest::Client, url: String) -> Self {
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            let mut samples = Vec::new();
+            loop {
+                let start = Instant::now();
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    response = client.get(&url).timeout(DEFAULT_PROBE_TIMEOUT).send() => {
+                        if let Ok(response) = response {
+                            samples.push(round_trip_ms(start, &response));
+                        }
+                    }
+                }
+                tokio::select! {
+                    _ = &mut stop_rx => break,
+                    _ = tokio::time::sleep(LOADED_PROBE_INTERVAL) => {}
+                }
+            }
+            debug!(probes = samples.len(), "loaded latency probe stopped");
+            samples
+        });
+
+        Self { stop_tx, handle }
+    }
+
+    /// Stops probing and returns the average loaded latency, if any probe succeeded.
+    pub async fn finish(self) -> Option<f64> {
+        let _ = self.stop_tx.send(());
+        let samples = self.handle.await.ok()?;
+        if samples.is_empty() {
+            None
         } else {
-            0.0
-        };
+            Some(samples.iter().sum::<f64>() / samples.len() as f64)
+        }
+    }
+}
 
-        PingResult { avg_ms: avg, jitter_ms: jitter }
+/// Grades latency increase under load on an A–F bufferbloat scale.
+pub fn bufferbloat_grade(increase_ms: f64) -> char {
+    if increase_ms < 30.0 {
+        'A'
+    } else if increase_ms < 60.0 {
+        'B'
+    } else if increase_ms < 200.0 {
+        'C'
+    } else if increase_ms < 400.0 {
+        'D'
+    } else {
+        'F'
     }
 }
 
+/// Sent after each latency probe; `latest_ping` is `None` until one succeeds.
 #[derive(Debug, Clone)]
 pub struct PingProgress {
     pub latest_ping: Option<f64>,
 }
 
+/// Average latency and jitter in milliseconds.
 #[derive(Debug, Clone)]
 pub struct PingResult {
     pub avg_ms: f64,
     pub jitter_ms: f64,
+    pub jitter_method: JitterMethod,
+    /// Share of probes that failed or timed out.
+    pub loss_percent: f64,
+    /// Half-width of the 95% confidence interval for `avg_ms`, if at least
+    /// two probes succeeded.
+    pub margin_ms: Option<f64>,
+    /// Fastest and slowest round trips counted; 0 if none were.
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+impl PingResult {
+    /// Summarises the round trips of the `sent` probes that got an answer,
+    /// first dropping outliers if `filter_outliers` is set.
+    pub fn from_samples(
+        samples: &[f64],
+        sent: usize,
+        jitter_method: JitterMethod,
+        filter_outliers: bool,
+    ) -> Self {
+        let loss_percent = if sent > 0 {
+            (sent - samples.len()) as f64 / sent as f64 * 100.0
+        } else {
+            0.0
+        };
+        let kept = if filter_outliers {
+            without_outliers(samples, OUTLIER_MADS)
+        } else {
+            samples.to_vec()
+        };
+        if kept.len() < samples.len() {
+            info!(dropped = samples.len() - kept.len(), "ping outliers filtered");
+        }
+        let samples = kept;
+        if samples.is_empty() {
+            return PingResult {
+                avg_ms: 0.0,
+                jitter_ms: 0.0,
+                jitter_method,
+                loss_percent,
+                margin_ms: None,
+                min_ms: 0.0,
+                max_ms: 0.0,
+            };
+        }
+
+        let avg = samples.iter().sum::<f64>() / samples.len() as f64;
+        let jitter = if samples.len() > 1 {
+            match jitter_method {
+                JitterMethod::StdDev => {
+                    let variance: f64 = samples.iter().map(|&x| (x - avg).powi(2)).sum::<f64>()
+                        / (samples.len() - 1) as f64;
+                    variance.sqrt()
+                }
+                JitterMethod::Ipdv => {
+                    samples.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>()
+                        / (samples.len() - 1) as f64
+                }
+            }
+        } else {
+            0.0
+        };
+
+        PingResult {
+            avg_ms: avg,
+            jitter_ms: jitter,
+            jitter_method,
+            loss_percent,
+            margin_ms: confidence_margin_95(&samples),
+            min_ms: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max_ms: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
 }
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use reqwest::redirect::Policy;
+use reqwest::StatusCode;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Plain-HTTP URL that answers 204 No Content on an open connection.
+/// Portals intercept it with a redirect or a login page.
+pub const PROBE_URL: &str = "http://connectivitycheck.gstatic.com/generate_204";
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Signs that traffic is being intercepted by a captive portal, such as a
+/// hotel or airport Wi-Fi login page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptivePortal {
+    /// Status the probe received instead of 204.
+    pub status: u16,
+    /// Where the portal redirected the probe, if it did.
+    pub location: Option<String>,
+}
+
+impl fmt::Display for CaptivePortal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "captive portal detected")?;
+        if let Some(location) = &self.location {
+            write!(f, " (redirects to {})", location)?;
+        }
+        write!(f, "; results would measure the portal, not the internet")
+    }
+}
+
+/// Requests [`PROBE_URL`] without following redirects, from `local_address`
+/// if given.
+///
+/// Returns `None` when the probe got through untouched, and also when it
+/// couldn't be sent at all, e.g. on a network without internet access.
+pub async fn detect(local_address: Option<IpAddr>) -> Result<Option<CaptivePortal>> {
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .timeout(PROBE_TIMEOUT)
+        .local_address(local_address)
+        .build()?;
+    let response = match client.get(PROBE_URL).send().await {
+        Ok(response) => response,
+        Err(err) => {
+            debug!(error = %err, "captive portal probe failed");
+            return Ok(None);
+        }
+    };
+
+    let status = response.status();
+    if status == StatusCode::NO_CONTENT {
+        return Ok(None);
+    }
+    let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(str::to_string);
+    info!(status = status.as_u16(), ?location, "captive portal detected");
+    Ok(Some(CaptivePortal {
+        status: status.as_u16(),
+        location,
+    }))
+}
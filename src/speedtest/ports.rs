@@ -0,0 +1,198 @@
+use super::dns::{self, DNS_PORT};
+use anyhow::{Context, Result};
+use futures::future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpSocket, UdpSocket};
+use tracing::{debug, info};
+
+/// Host answering on every TCP port, so a failed connection means the port
+/// is blocked on the way there.
+pub const TCP_TEST_HOST: &str = "portquiz.net";
+const DNS_HOST: &str = "1.1.1.1";
+const NTP_HOST: &str = "time.cloudflare.com";
+const STUN_HOST: &str = "stun.cloudflare.com";
+const TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_RESPONSE_BYTES: usize = 512;
+
+/// How a port is probed: a TCP connection, or a UDP request to a service
+/// that always answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    Tcp,
+    Dns,
+    Ntp,
+    Stun,
+}
+
+impl Probe {
+    pub fn protocol(self) -> &'static str {
+        match self {
+            Probe::Tcp => "tcp",
+            Probe::Dns | Probe::Ntp | Probe::Stun => "udp",
+        }
+    }
+}
+
+/// An outbound port to check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortCheck {
+    pub service: String,
+    pub host: String,
+    pub port: u16,
+    pub probe: Probe,
+}
+
+impl PortCheck {
+    /// A TCP port, connected to on `host`, which must accept connections on it.
+    pub fn tcp(service: impl Into<String>, host: impl Into<String>, port: u16) -> Self {
+        Self {
+            service: service.into(),
+            host: host.into(),
+            port,
+            probe: Probe::Tcp,
+        }
+    }
+
+    fn udp(service: &str, host: &str, port: u16, probe: Probe) -> Self {
+        Self {
+            service: service.to_string(),
+            host: host.to_string(),
+            port,
+            probe,
+        }
+    }
+
+    /// Web, SSH, DNS and time, then ports online games and consoles rely
+    /// on. TCP ports are checked against `tcp_host`.
+    pub fn common(tcp_host: &str) -> Vec<PortCheck> {
+        vec![
+            PortCheck::tcp("HTTP", tcp_host, 80),
+            PortCheck::tcp("HTTPS", tcp_host, 443),
+            PortCheck::tcp("SSH", tcp_host, 22),
+            PortCheck::udp("DNS", DNS_HOST, DNS_PORT, Probe::Dns),
+            PortCheck::udp("NTP", NTP_HOST, 123, Probe::Ntp),
+            PortCheck::tcp("Xbox Live", tcp_host, 3074),
+            PortCheck::udp("STUN (consoles, voice)", STUN_HOST, 3478, Probe::Stun),
+            PortCheck::tcp("Minecraft", tcp_host, 25565),
+            PortCheck::tcp("Steam", tcp_host, 27015),
+        ]
+    }
+}
+
+/// Whether a port got through.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortStatus {
+    /// Answered after `ms` milliseconds.
+    Open { ms: f64 },
+    Blocked { reason: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct PortResult {
+    pub check: PortCheck,
+    pub status: PortStatus,
+}
+
+/// Runs the checks side by side, sending from `local_address` when given.
+/// Results come back in the order checks were given.
+pub async fn check_ports(
+    checks: Vec<PortCheck>,
+    local_address: Option<IpAddr>,
+) -> Vec<PortResult> {
+    info!(checks = checks.len(), "port check starting");
+    future::join_all(checks.into_iter().map(|check| async move {
+        let status = match check_port(&check, local_address).await {
+            Ok(ms) => PortStatus::Open { ms },
+            Err(err) => {
+                let protocol = check.probe.protocol();
+                debug!(port = check.port, protocol, error = %err, "port blocked");
+                PortStatus::Blocked {
+                    reason: format!("{:#}", err),
+                }
+            }
+        };
+        PortResult { check, status }
+    }))
+    .await
+}
+
+async fn check_port(check: &PortCheck, local_address: Option<IpAddr>) -> Result<f64> {
+    let addr = tokio::net::lookup_host((check.host.as_str(), check.port))
+        .await
+        .with_context(|| format!("couldn't resolve {}", check.host))?
+        .find(|addr| local_address.is_none_or(|local| local.is_ipv4() == addr.is_ipv4()))
+        .with_context(|| format!("no address for {}", check.host))?;
+    match check.probe {
+        Probe::Tcp => connect_ms(addr, local_address).await,
+        Probe::Dns => dns::lookup_ms(addr, "example.com", local_address).await,
+        Probe::Ntp => exchange_ms(addr, local_address, &ntp_request(), is_ntp_reply).await,
+        Probe::Stun => {
+            let id: [u8; 12] = rand::random();
+            let is_reply = |reply: &[u8]| reply.len() >= 20 && reply[8..20] == id;
+            exchange_ms(addr, local_address, &stun_request(id), is_reply).await
+        }
+    }
+}
+
+async fn connect_ms(addr: SocketAddr, local_address: Option<IpAddr>) -> Result<f64> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    if let Some(local) = local_address {
+        socket.bind(SocketAddr::new(local, 0))?;
+    }
+    let start = Instant::now();
+    tokio::time::timeout(TIMEOUT, socket.connect(addr)).await.context("no answer")??;
+    Ok(start.elapsed().as_secs_f64() * 1000.0)
+}
+
+// Sends `request` over UDP and times the first datagram `is_reply` accepts
+async fn exchange_ms(
+    addr: SocketAddr,
+    local_address: Option<IpAddr>,
+    request: &[u8],
+    is_reply: impl Fn(&[u8]) -> bool,
+) -> Result<f64> {
+    let local = local_address.unwrap_or(match addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let socket = UdpSocket::bind(SocketAddr::new(local, 0)).await?;
+    socket.connect(addr).await?;
+
+    let start = Instant::now();
+    socket.send(request).await?;
+    let mut response = [0u8; MAX_RESPONSE_BYTES];
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let len = socket.recv(&mut response).await?;
+            if is_reply(&response[..len]) {
+                return Ok::<_, std::io::Error>(());
+            }
+        }
+    })
+    .await
+    .context("no answer")??;
+    Ok(start.elapsed().as_secs_f64() * 1000.0)
+}
+
+// An SNTP client request: version 4, client mode, everything else zero
+fn ntp_request() -> [u8; 48] {
+    let mut packet = [0u8; 48];
+    packet[0] = 0x23;
+    packet
+}
+
+fn is_ntp_reply(reply: &[u8]) -> bool {
+    // Server mode
+    reply.len() >= 48 && reply[0] & 0x07 == 4
+}
+
+// A STUN binding request with no attributes
+fn stun_request(id: [u8; 12]) -> [u8; 20] {
+    let mut packet = [0u8; 20];
+    packet[..2].copy_from_slice(&0x0001u16.to_be_bytes());
+    // Message length stays zero; then the magic cookie and transaction ID
+    packet[4..8].copy_from_slice(&0x2112_a442u32.to_be_bytes());
+    packet[8..].copy_from_slice(&id);
+    packet
+}
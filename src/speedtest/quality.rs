@@ -0,0 +1,32 @@
+/// Estimates a Mean Opinion Score (1.0 to 4.5) for a voice call using the
+/// simplified ITU-T G.107 E-model, where round-trip `latency_ms` and
+/// `jitter_ms` are folded into one effective delay.
+pub fn estimate_mos(latency_ms: f64, jitter_ms: f64, loss_percent: f64) -> f64 {
+    // Jitter buffers add roughly twice the jitter, plus codec delay
+    let effective_latency = latency_ms + jitter_ms * 2.0 + 10.0;
+
+    let mut r = if effective_latency < 160.0 {
+        93.2 - effective_latency / 40.0
+    } else {
+        93.2 - (effective_latency - 120.0) / 10.0
+    };
+    r -= loss_percent * 2.5;
+    let r = r.clamp(0.0, 100.0);
+
+    1.0 + 0.035 * r + 0.000007 * r * (r - 60.0) * (100.0 - r)
+}
+
+/// Everyday description of a MOS value.
+pub fn mos_label(mos: f64) -> &'static str {
+    if mos >= 4.3 {
+        "excellent"
+    } else if mos >= 4.0 {
+        "good"
+    } else if mos >= 3.6 {
+        "fair"
+    } else if mos >= 3.1 {
+        "poor"
+    } else {
+        "bad"
+    }
+}
@@ -0,0 +1,143 @@
+use super::ping::round_trip_ms;
+use crate::samples::percentile;
+use futures::future;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// AWS regions measured unless others are configured, by their codes.
+/// Each answers on its DynamoDB health check endpoint.
+pub const AWS_REGIONS: [&str; 16] = [
+    "us-east-1",
+    "us-east-2",
+    "us-west-1",
+    "us-west-2",
+    "ca-central-1",
+    "sa-east-1",
+    "eu-west-1",
+    "eu-west-2",
+    "eu-central-1",
+    "eu-north-1",
+    "me-south-1",
+    "af-south-1",
+    "ap-south-1",
+    "ap-southeast-1",
+    "ap-southeast-2",
+    "ap-northeast-1",
+];
+
+/// A cloud region, reached through any URL served from inside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub provider: String,
+    pub name: String,
+    pub url: String,
+}
+
+impl Region {
+    pub fn new(
+        provider: impl Into<String>,
+        name: impl Into<String>,
+        url: impl Into<String>,
+    ) -> Self {
+        Self {
+            provider: provider.into(),
+            name: name.into(),
+            url: url.into(),
+        }
+    }
+
+    /// An AWS region by its code, e.g. `eu-west-1`.
+    pub fn aws(code: &str) -> Self {
+        Self::new("AWS", code, format!("https://dynamodb.{}.amazonaws.com/ping", code))
+    }
+
+    /// The [`AWS_REGIONS`]. Other providers' regions need URLs that are
+    /// served from the region itself rather than a nearby edge, so they're
+    /// left to configuration.
+    pub fn defaults() -> Vec<Region> {
+        AWS_REGIONS.iter().map(|code| Region::aws(code)).collect()
+    }
+}
+
+/// Measures round-trip latency to a list of cloud regions, to show which
+/// ones are closest to this network.
+pub struct RegionLatencyTest {
+    client: reqwest::Client,
+    regions: Vec<Region>,
+    probes: usize,
+}
+
+impl RegionLatencyTest {
+    pub fn new(client: reqwest::Client, regions: Vec<Region>) -> Self {
+        Self {
+            client,
+            regions,
+            probes: 5,
+        }
+    }
+
+    /// Requests timed per region, after one that sets up the connection.
+    pub fn with_probes(mut self, probes: usize) -> Self {
+        self.probes = probes.max(1);
+        self
+    }
+
+    /// Probes the regions side by side, each one request at a time. Results
+    /// come back in the order regions were given.
+    pub async fn run(&self) -> Vec<RegionLatency> {
+        info!(regions = self.regions.len(), probes = self.probes, "region latency test starting");
+        future::join_all(self.regions.iter().map(|region| self.measure(region))).await
+    }
+
+    async fn measure(&self, region: &Region) -> RegionLatency {
+        // Connection setup would dwarf the round trip, so it isn't timed
+        if let Err(err) = self.probe(&region.url).await {
+            debug!(region = %region.name, error = %err, "region unreachable");
+        }
+        let mut times = Vec::with_capacity(self.probes);
+        let mut lost = 0;
+        for _ in 0..self.probes {
+            match self.probe(&region.url).await {
+                Ok(ms) => times.push(ms),
+                Err(err) => {
+                    debug!(region = %region.name, error = %err, "region probe failed");
+                    lost += 1;
+                }
+            }
+        }
+        RegionLatency::new(region.clone(), times, lost)
+    }
+
+    async fn probe(&self, url: &str) -> reqwest::Result<f64> {
+        let start = Instant::now();
+        let response = self.client.get(url).timeout(PROBE_TIMEOUT).send().await?;
+        let ms = round_trip_ms(start, &response);
+        // Read the body so the connection can be reused for the next probe
+        response.bytes().await?;
+        Ok(ms)
+    }
+}
+
+/// Round-trip times to one region, in milliseconds.
+#[derive(Debug, Clone)]
+pub struct RegionLatency {
+    pub region: Region,
+    pub lost: usize,
+    /// `None` when every probe was lost, as for the minimum.
+    pub median_ms: Option<f64>,
+    pub min_ms: Option<f64>,
+}
+
+impl RegionLatency {
+    fn new(region: Region, mut times_ms: Vec<f64>, lost: usize) -> Self {
+        times_ms.sort_by(f64::total_cmp);
+        Self {
+            region,
+            lost,
+            median_ms: (!times_ms.is_empty()).then(|| percentile(&times_ms, 50.0)),
+            min_ms: times_ms.first().copied(),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::stats::TransferStats;
+use reqwest::{RequestBuilder, Response};
+use std::time::Duration;
+use tracing::debug;
+
+/// How often, and how patiently, a request is retried after a transient
+/// failure: a connection error, a timeout, or a 5xx response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each one after it.
+    pub initial_backoff: Duration,
+    /// Upper bound on the wait between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(4),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// No retries; every failure is final.
+    pub const NONE: RetryPolicy = RetryPolicy {
+        retries: 0,
+        initial_backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+    };
+
+    /// How long to wait before retry number `retry`, counting from 0.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+
+    /// Sends the request built by `request`, building and sending it again
+    /// after transient failures. Each retry is counted in `stats`.
+    ///
+    /// The last attempt's response is returned even if it has a 5xx status.
+    pub async fn send(
+        &self,
+        stats: &TransferStats,
+        mut request: impl FnMut() -> RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        let mut retry = 0;
+        loop {
+            let response = request().send().await;
+            let transient = match &response {
+                Ok(response) => response.status().is_server_error(),
+                Err(err) => is_transient(err),
+            };
+            if !transient || retry >= self.retries {
+                return response;
+            }
+
+            let backoff = self.backoff(retry);
+            match &response {
+                Ok(response) => debug!(status = %response.status(), ?backoff, "retrying request"),
+                Err(err) => debug!(error = %err, ?backoff, "retrying request"),
+            }
+            stats.record_retry();
+            retry += 1;
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+// Failures another attempt might not hit: the connection failing to open,
+// timing out or dropping mid-request. Bad URLs or a body that can't be sent
+// would fail again
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout() || err.is_request()
+}
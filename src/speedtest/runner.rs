@@ -0,0 +1,597 @@
+use super::{
+    bidirectional::{BidirectionalProgress, BidirectionalResult, BidirectionalTest},
+    download::{DownloadProgress, DownloadTest},
+    mock::MockProvider,
+    orchestrator::TestOrchestrator,
+    ping::{
+        DEFAULT_PROBE_TIMEOUT, JitterMethod, LoadedLatencyProbe, PingMode, PingProgress, PingTest,
+    },
+    upload::{self, UploadProgress, UploadTest},
+    cold_http_client, http_client,
+    mtu,
+    portal,
+    server::{self, Server},
+    stats::TransferStats,
+    tcp_info::{TcpSampler, TcpStats},
+    throttle::RateLimiter,
+    SpeedTestResult, TestPhase,
+};
+use crate::settings::Settings;
+use anyhow::{bail, Result};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+/// Progress and completion events emitted while a [`SpeedTest`] runs.
+///
+/// Events arrive in phase order: ping, download, upload, then the optional
+/// bidirectional phase. Each phase sends any number of progress events
+/// followed by exactly one completion.
+#[derive(Debug, Clone)]
+pub enum TestUpdate {
+    PingProgress(PingProgress),
+    PingComplete {
+        avg_ms: f64,
+        jitter_ms: f64,
+        jitter_method: JitterMethod,
+        ping_mode: PingMode,
+        loss_percent: f64,
+        /// Half-width of the 95% confidence interval for `avg_ms`.
+        margin_ms: Option<f64>,
+        /// Fastest and slowest round trips counted.
+        min_ms: f64,
+        max_ms: f64,
+        /// Estimated while the ping phase ran; `None` if unavailable.
+        path_mtu: Option<u16>,
+    },
+    DownloadProgress(DownloadProgress),
+    DownloadComplete {
+        speed_mbps: f64,
+        /// Half-width of the 95% confidence interval for `speed_mbps`.
+        margin_mbps: Option<f64>,
+        loaded_ping_ms: Option<f64>,
+        /// Time to the first byte of the download, see
+        /// [`DownloadResult::ttfb_ms`](super::download::DownloadResult::ttfb_ms).
+        ttfb_ms: Option<f64>,
+        /// Whether the download started on a connection an earlier phase
+        /// opened, so `ttfb_ms` holds no handshakes.
+        warm_connection: bool,
+        /// Statistics of the download's connections, on Linux.
+        tcp: Option<TcpStats>,
+        /// Bytes received, and the time from the first of them to the last.
+        bytes: u64,
+        elapsed_ms: f64,
+    },
+    UploadProgress(UploadProgress),
+    UploadComplete {
+        speed_mbps: f64,
+        /// Half-width of the 95% confidence interval for `speed_mbps`.
+        margin_mbps: Option<f64>,
+        loaded_ping_ms: Option<f64>,
+        /// Statistics of the upload's connections, on Linux.
+        tcp: Option<TcpStats>,
+        /// Bytes sent, and the time from the first request to the last response.
+        bytes: u64,
+        elapsed_ms: f64,
+    },
+    BidirectionalProgress(BidirectionalProgress),
+    BidirectionalComplete(BidirectionalResult),
+    /// Something that may make the results misleading, such as a captive
+    /// portal. The test carries on.
+    Warning { phase: TestPhase, message: String },
+    /// The test stopped with an error during `phase`; no updates follow.
+    Failed { phase: TestPhase, error: String },
+}
+
+impl TestUpdate {
+    /// The phase this update belongs to.
+    pub fn phase(&self) -> TestPhase {
+        match self {
+            TestUpdate::PingProgress(_) | TestUpdate::PingComplete { .. } => TestPhase::Ping,
+            TestUpdate::DownloadProgress(_) | TestUpdate::DownloadComplete { .. } => {
+                TestPhase::Download
+            }
+            TestUpdate::UploadProgress(_) | TestUpdate::UploadComplete { .. } => TestPhase::Upload,
+            TestUpdate::BidirectionalProgress(_) | TestUpdate::BidirectionalComplete(_) => {
+                TestPhase::Bidirectional
+            }
+            TestUpdate::Warning { phase, .. } | TestUpdate::Failed { phase, .. } => *phase,
+        }
+    }
+}
+
+/// Runs the ping, download and upload phases against the test server,
+/// optionally followed by download and upload at once.
+pub struct SpeedTest {
+    settings: Settings,
+    stats: Arc<TransferStats>,
+    mock: Option<MockProvider>,
+}
+
+impl SpeedTest {
+    /// A test of the network, or a simulated one if `settings.demo` is set.
+    pub fn new(settings: Settings) -> Self {
+        let mock = settings.demo.then(MockProvider::new);
+        Self {
+            settings,
+            stats: Arc::default(),
+            mock,
+        }
+    }
+
+    /// Plays out the test from `mock` instead of measuring the network.
+    pub fn with_mock(mut self, mock: MockProvider) -> Self {
+        self.mock = Some(mock);
+        self
+    }
+
+    /// Raw transfer counters, updated live while the test runs.
+    pub fn stats(&self) -> Arc<TransferStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Starts the test on a background task and returns a handle for
+    /// receiving updates and cancelling it.
+    pub fn spawn(self) -> SpeedTestHandle {
+        let (update_tx, updates) = mpsc::channel(32);
+        let (cancel_tx, cancel_rx) = mpsc::channel(1);
+        let stats = self.stats();
+        let task = tokio::spawn(async move {
+            self.run(update_tx, cancel_rx)
+                .await
+                .inspect_err(|err| error!(error = %err, "speed test failed"))
+        });
+
+        SpeedTestHandle {
+            updates,
+            stats,
+            cancel_tx,
+            task,
+        }
+    }
+
+    /// Runs the full test, sending progress to `update_tx`.
+    ///
+    /// Returns an error if any phase fails, runs out of time, or a message
+    /// arrives on `cancel_rx`. Failures after the test started are also
+    /// sent as [`TestUpdate::Failed`].
+    pub async fn run(
+        mut self,
+        update_tx: mpsc::Sender<TestUpdate>,
+        cancel_rx: mpsc::Receiver<()>,
+    ) -> Result<SpeedTestResult> {
+        let mut orchestrator = TestOrchestrator::new();
+        orchestrator.set_bidirectional(self.settings.bidirectional);
+        orchestrator.start()?;
+        let result = match self.mock.take() {
+            Some(mock) => {
+                run_mock(mock, self.settings, &mut orchestrator, &update_tx, cancel_rx).await
+            }
+            None => self.run_phases(&mut orchestrator, &update_tx, cancel_rx).await,
+        };
+        if let Err(err) = &result {
+            let update = TestUpdate::Failed {
+                phase: orchestrator.phase(),
+                error: format!("{:#}", err),
+            };
+            let _ = update_tx.send(update).await;
+        }
+        result
+    }
+
+    async fn run_phases(
+        self,
+        orchestrator: &mut TestOrchestrator,
+        update_tx: &mpsc::Sender<TestUpdate>,
+        mut cancel_rx: mpsc::Receiver<()>,
+    ) -> Result<SpeedTestResult> {
+        let settings = self.settings;
+        let stats = self.stats;
+        let timeouts = settings.timeouts;
+        let retry = settings.retry;
+        let test_deadline = timeouts.total.map(|total| Instant::now() + total);
+
+        // One client is reused across phases unless cold connections were requested
+        let shared_client = http_client(&settings)?;
+        let client_for_phase = || -> Result<reqwest::Client> {
+            if settings.cold_connections {
+                http_client(&settings)
+            } else {
+                Ok(shared_client.clone())
+            }
+        };
+
+        // Download and upload share one bucket, except when they run at the same time
+        let limiter = settings.rate_limit_mbps.map(RateLimiter::new);
+        let transfer_duration = settings.transfer_duration;
+        let sample_interval = settings.sample_interval;
+        let early_exit = settings.early_exit;
+        let colo = settings.colo.as_deref();
+        let ping_url = server::with_colo(&server::ping_url(&settings.server_url), colo);
+        let download_url = Server::from_base("Test server", &settings.server_url).download_url;
+        let download_url = server::with_colo(&download_url, colo);
+        let upload_url = server::with_colo(&server::upload_url(&settings.server_url), colo);
+
+        info!(?settings, "speed test starting");
+
+        // Pre-flight: a portal would answer in place of the test server
+        if settings.detect_captive_portal {
+            if let Some(portal) = portal::detect(settings.local_address).await? {
+                let update = TestUpdate::Warning {
+                    phase: orchestrator.phase(),
+                    message: portal.to_string(),
+                };
+                emit(orchestrator, update_tx, update).await?;
+            }
+        }
+
+        // Anycast may route around the requested location; don't let the result claim it
+        if let Some(requested) = colo {
+            match server::served_colo(&shared_client, &ping_url).await {
+                Some(served) if !served.eq_ignore_ascii_case(requested) => {
+                    let update = TestUpdate::Warning {
+                        phase: orchestrator.phase(),
+                        message: format!(
+                            "asked for Cloudflare location {}, but {} answered; results \
+                             measure the path to {}",
+                            requested, served, served
+                        ),
+                    };
+                    emit(orchestrator, update_tx, update).await?;
+                }
+                served => info!(requested, ?served, "Cloudflare location"),
+            }
+        }
+
+        // The upload payload is generated while the ping and download phases run
+        let memory_budget = settings.memory_budget_bytes();
+        let block_size = upload::payload_block_size(memory_budget);
+        let payload_handle = tokio::spawn(upload::random_payload(block_size));
+
+        // Ping test
+        let ping_count = settings.ping_count;
+        let ping_interval = settings.ping_interval;
+        let ping_concurrency = settings.ping_concurrency;
+        let jitter_method = settings.jitter_method;
+        let filter_outliers = settings.filter_ping_outliers;
+        let ping_mode = settings.ping_mode;
+        let local_address = settings.local_address;
+        // A probe may wait as long as the ping phase is allowed to take
+        let probe_timeout = timeouts.ping.unwrap_or(DEFAULT_PROBE_TIMEOUT);
+        let client = match ping_mode {
+            PingMode::Warm | PingMode::Tcp => client_for_phase()?,
+            PingMode::Cold => cold_http_client(&settings)?,
+        };
+        let probe_url = ping_url.clone();
+        let ping_stats = Arc::clone(&stats);
+        let (ping_tx, mut ping_rx) = mpsc::channel::<PingProgress>(32);
+        let mut ping_handle = tokio::spawn(async move {
+            let mut test = PingTest::new(client, ping_count)
+                .with_url(probe_url)
+                .with_interval(ping_interval)
+                .with_concurrency(ping_concurrency)
+                .with_mode(ping_mode)
+                .with_local_address(local_address)
+                .with_timeout(probe_timeout)
+                .with_jitter_method(jitter_method)
+                .with_outlier_filter(filter_outliers)
+                .with_retry(retry)
+                .with_stats(ping_stats);
+            test.run(ping_tx).await
+        });
+
+        // The path MTU is estimated alongside the latency probes
+        let server_url = settings.server_url.clone();
+        let local_address = settings.local_address;
+        let mtu_handle =
+            tokio::spawn(async move { mtu::discover(&server_url, local_address).await });
+
+        let deadline = Deadline::new(TestPhase::Ping, timeouts.ping, test_deadline);
+        let ping_result = deadline
+            .run(async {
+                while let Some(progress) = ping_rx.recv().await {
+                    check_cancelled(&mut cancel_rx)?;
+                    emit(orchestrator, update_tx, TestUpdate::PingProgress(progress)).await?;
+                }
+                (&mut ping_handle).await?
+            })
+            .await
+            .inspect_err(|_| {
+                ping_handle.abort();
+                mtu_handle.abort();
+            })?;
+
+        let path_mtu = mtu_handle.await.ok().flatten();
+        let unusual_mtu = path_mtu.and_then(|size| Some((size, mtu::overhead_hint(size)?)));
+        if let Some((size, hint)) = unusual_mtu {
+            let update = TestUpdate::Warning {
+                phase: orchestrator.phase(),
+                message: format!(
+                    "path MTU is {} bytes, below the usual {} ({})",
+                    size,
+                    mtu::ETHERNET_MTU,
+                    hint
+                ),
+            };
+            emit(orchestrator, update_tx, update).await?;
+        }
+        let update = TestUpdate::PingComplete {
+            avg_ms: ping_result.avg_ms,
+            jitter_ms: ping_result.jitter_ms,
+            jitter_method: ping_result.jitter_method,
+            ping_mode,
+            loss_percent: ping_result.loss_percent,
+            margin_ms: ping_result.margin_ms,
+            min_ms: ping_result.min_ms,
+            max_ms: ping_result.max_ms,
+            path_mtu,
+        };
+        emit(orchestrator, update_tx, update).await?;
+
+        // Download test. Warm pings, or ramping up, leave the shared client a
+        // connection to start on
+        let warm_connection = !settings.cold_connections
+            && (ping_mode == PingMode::Warm || settings.ramp_up);
+        let download_size = settings.download_size_bytes();
+        let download_streams = settings.download_streams;
+        let ramp_up = settings.ramp_up;
+        let chunk_size = settings.download_chunk_bytes();
+        let client = client_for_phase()?;
+        let download_limiter = limiter.clone();
+        let download_phase_url = download_url.clone();
+        let download_stats = Arc::clone(&stats);
+        let probe = LoadedLatencyProbe::spawn(http_client(&settings)?, ping_url.clone());
+        let sampler = TcpSampler::spawn();
+        let (download_tx, mut download_rx) = mpsc::channel::<DownloadProgress>(32);
+        let mut download_handle = tokio::spawn(async move {
+            let mut test = DownloadTest::new(client, download_phase_url, download_size)
+                .with_streams(download_streams)
+                .with_ramp_up(ramp_up)
+                .with_chunk_size(chunk_size)
+                .with_duration(transfer_duration)
+                .with_sample_interval(sample_interval)
+                .with_early_exit(early_exit)
+                .with_rate_limit(download_limiter)
+                .with_retry(retry)
+                .with_stats(download_stats);
+            test.run(download_tx).await
+        });
+
+        let deadline = Deadline::new(TestPhase::Download, timeouts.download, test_deadline);
+        let download_result = deadline
+            .run(async {
+                while let Some(progress) = download_rx.recv().await {
+                    check_cancelled(&mut cancel_rx)?;
+                    emit(orchestrator, update_tx, TestUpdate::DownloadProgress(progress)).await?;
+                }
+                (&mut download_handle).await?
+            })
+            .await
+            .inspect_err(|_| download_handle.abort())?;
+        let update = TestUpdate::DownloadComplete {
+            speed_mbps: download_result.avg_speed_mbps,
+            margin_mbps: download_result.margin_mbps,
+            loaded_ping_ms: probe.finish().await,
+            ttfb_ms: download_result.ttfb_ms,
+            warm_connection,
+            tcp: sampler.finish(&stats.snapshot().connections("download")).await,
+            bytes: download_result.bytes,
+            elapsed_ms: download_result.elapsed.as_secs_f64() * 1000.0,
+        };
+        emit(orchestrator, update_tx, update).await?;
+
+        // Upload test
+        let payload = payload_handle.await??;
+        let upload_size = settings.upload_size_bytes();
+        let upload_streams = settings.upload_streams;
+        let upload_phase_url = upload_url.clone();
+        let client = client_for_phase()?;
+        let upload_limiter = limiter.clone();
+        let upload_stats = Arc::clone(&stats);
+        let probe = LoadedLatencyProbe::spawn(http_client(&settings)?, ping_url.clone());
+        let sampler = TcpSampler::spawn();
+        let (upload_tx, mut upload_rx) = mpsc::channel::<UploadProgress>(32);
+        let upload_payload = Arc::clone(&payload);
+        let mut upload_handle = tokio::spawn(async move {
+            let mut test = UploadTest::new(client, upload_size)
+                .with_payload(upload_payload)
+                .with_memory_budget(memory_budget)
+                .with_url(upload_phase_url)
+                .with_streams(upload_streams)
+                .with_duration(transfer_duration)
+                .with_sample_interval(sample_interval)
+                .with_early_exit(early_exit)
+                .with_rate_limit(upload_limiter)
+                .with_retry(retry)
+                .with_stats(upload_stats);
+            test.run(upload_tx).await
+        });
+
+        let deadline = Deadline::new(TestPhase::Upload, timeouts.upload, test_deadline);
+        let upload_result = deadline
+            .run(async {
+                while let Some(progress) = upload_rx.recv().await {
+                    check_cancelled(&mut cancel_rx)?;
+                    emit(orchestrator, update_tx, TestUpdate::UploadProgress(progress)).await?;
+                }
+                (&mut upload_handle).await?
+            })
+            .await
+            .inspect_err(|_| upload_handle.abort())?;
+        let update = TestUpdate::UploadComplete {
+            speed_mbps: upload_result.avg_speed_mbps,
+            margin_mbps: upload_result.margin_mbps,
+            loaded_ping_ms: probe.finish().await,
+            tcp: sampler.finish(&stats.snapshot().connections("upload")).await,
+            bytes: upload_result.bytes,
+            elapsed_ms: upload_result.elapsed.as_secs_f64() * 1000.0,
+        };
+        emit(orchestrator, update_tx, update).await?;
+
+        // Bidirectional test, over as many download streams as ramping up settled on
+        let download_streams = download_result.streams;
+        if settings.bidirectional {
+            let client = client_for_phase()?;
+            // Each direction gets its own bucket, so the cap applies to both at once
+            let download_limiter = settings.rate_limit_mbps.map(RateLimiter::new);
+            let (both_tx, mut both_rx) = mpsc::channel::<BidirectionalProgress>(32);
+            let mut both_handle = tokio::spawn(async move {
+                let download = DownloadTest::new(client.clone(), download_url, download_size)
+                    .with_streams(download_streams)
+                    .with_chunk_size(chunk_size)
+                    .with_duration(transfer_duration)
+                    .with_sample_interval(sample_interval)
+                    .with_rate_limit(download_limiter)
+                    .with_retry(retry)
+                    .with_stats(Arc::clone(&stats));
+                let upload = UploadTest::new(client, upload_size)
+                    .with_payload(payload)
+                    .with_memory_budget(memory_budget)
+                    .with_url(upload_url)
+                    .with_streams(upload_streams)
+                    .with_duration(transfer_duration)
+                    .with_sample_interval(sample_interval)
+                    .with_rate_limit(limiter)
+                    .with_retry(retry)
+                    .with_stats(stats);
+                BidirectionalTest::new(download, upload).run(both_tx).await
+            });
+
+            let phase = TestPhase::Bidirectional;
+            let deadline = Deadline::new(phase, timeouts.phase(phase), test_deadline);
+            let both_result = deadline
+                .run(async {
+                    while let Some(progress) = both_rx.recv().await {
+                        check_cancelled(&mut cancel_rx)?;
+                        let update = TestUpdate::BidirectionalProgress(progress);
+                        emit(orchestrator, update_tx, update).await?;
+                    }
+                    (&mut both_handle).await?
+                })
+                .await
+                .inspect_err(|_| both_handle.abort())?;
+            let update = TestUpdate::BidirectionalComplete(both_result);
+            emit(orchestrator, update_tx, update).await?;
+        }
+
+        info!(result = ?orchestrator.result(), "speed test complete");
+        Ok(orchestrator.result().clone())
+    }
+}
+
+// Passes a simulated test's updates through the orchestrator like a real one's
+async fn run_mock(
+    mock: MockProvider,
+    settings: Settings,
+    orchestrator: &mut TestOrchestrator,
+    update_tx: &mpsc::Sender<TestUpdate>,
+    mut cancel_rx: mpsc::Receiver<()>,
+) -> Result<SpeedTestResult> {
+    info!(?mock, "simulated speed test starting");
+    let (mock_tx, mut mock_rx) = mpsc::channel(32);
+    let mut mock_handle = tokio::spawn(async move { mock.run(&settings, mock_tx).await });
+    let run = async {
+        while let Some(update) = mock_rx.recv().await {
+            check_cancelled(&mut cancel_rx)?;
+            emit(orchestrator, update_tx, update).await?;
+        }
+        (&mut mock_handle).await?
+    };
+    run.await.inspect_err(|_| mock_handle.abort())?;
+    Ok(orchestrator.result().clone())
+}
+
+fn check_cancelled(cancel_rx: &mut mpsc::Receiver<()>) -> Result<()> {
+    if cancel_rx.try_recv().is_ok() {
+        info!("speed test cancelled");
+        bail!("speed test cancelled");
+    }
+    Ok(())
+}
+
+// When the current phase has to be done by: its own timeout or the whole
+// test's deadline, whichever comes first
+struct Deadline {
+    at: Option<(Instant, String)>,
+}
+
+impl Deadline {
+    fn new(
+        phase: TestPhase,
+        phase_timeout: Option<Duration>,
+        test_deadline: Option<Instant>,
+    ) -> Self {
+        let phase_deadline = phase_timeout.map(|timeout| {
+            let message = format!("{:?} phase timed out after {}s", phase, timeout.as_secs_f64());
+            (Instant::now() + timeout, message)
+        });
+        let test_deadline =
+            test_deadline.map(|at| (at, "speed test ran past its deadline".to_string()));
+        let at = match (phase_deadline, test_deadline) {
+            (Some(phase), Some(test)) => Some(if phase.0 <= test.0 { phase } else { test }),
+            (phase, test) => phase.or(test),
+        };
+        Self { at }
+    }
+
+    async fn run<T>(&self, phase: impl Future<Output = Result<T>>) -> Result<T> {
+        let Some((at, message)) = &self.at else {
+            return phase.await;
+        };
+        match tokio::time::timeout_at(*at, phase).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("{}", message);
+                bail!("{}", message)
+            }
+        }
+    }
+}
+
+// Advances the orchestrator and forwards the update; a closed receiver is not an error
+async fn emit(
+    orchestrator: &mut TestOrchestrator,
+    update_tx: &mpsc::Sender<TestUpdate>,
+    update: TestUpdate,
+) -> Result<()> {
+    orchestrator
+        .apply(&update)
+        .inspect_err(|err| error!(error = %err, "invalid phase transition"))?;
+    let _ = update_tx.send(update).await;
+    Ok(())
+}
+
+/// Handle to a [`SpeedTest`] running on a background task.
+pub struct SpeedTestHandle {
+    /// Stream of progress events; closes when the test finishes.
+    pub updates: mpsc::Receiver<TestUpdate>,
+    stats: Arc<TransferStats>,
+    cancel_tx: mpsc::Sender<()>,
+    task: JoinHandle<Result<SpeedTestResult>>,
+}
+
+impl SpeedTestHandle {
+    /// Raw transfer counters for the running test.
+    pub fn stats(&self) -> Arc<TransferStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Returns a sender that cancels the test when a message is sent on it.
+    pub fn cancel_sender(&self) -> mpsc::Sender<()> {
+        self.cancel_tx.clone()
+    }
+
+    /// Requests cancellation; the test stops at its next progress event.
+    pub fn cancel(&self) {
+        let _ = self.cancel_tx.try_send(());
+    }
+
+    /// Waits for the test to finish and returns the final result.
+    pub async fn join(self) -> Result<SpeedTestResult> {
+        self.task.await?
+    }
+}
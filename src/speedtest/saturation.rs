@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Throughput levels off once this many consecutive slices of a second
+// each carry about as much
+const SLICES: u32 = 3;
+const SLICE: Duration = Duration::from_secs(1);
+/// Span a saturated throughput is measured over: the last few slices.
+pub const WINDOW: Duration = Duration::from_secs(SLICE.as_secs() * SLICES as u64);
+/// Largest difference, in percent of their mean, between the slices'
+/// throughputs for the transfer to count as saturated.
+pub const MAX_VARIATION_PERCENT: f64 = 5.0;
+
+/// Watches a transfer's throughput level off, so it can end early once
+/// carrying on would only confirm the same speed.
+#[derive(Debug, Default)]
+pub struct SaturationDetector {
+    // Bytes transferred so far and when, oldest first, reaching back a
+    // slice further than needed since slices start at the point before
+    points: VecDeque<(Instant, u64)>,
+}
+
+impl SaturationDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `bytes` had been transferred in total by `at`.
+    pub fn record(&mut self, bytes: u64, at: Instant) {
+        self.points.push_back((at, bytes));
+        let window = SLICE * (SLICES + 1);
+        while self.points.len() > 2 && at.duration_since(self.points[1].0) >= window {
+            self.points.pop_front();
+        }
+    }
+
+    /// The throughput over the last few seconds in Mbps, once each of those
+    /// seconds carried about as much as the others; `None` until then.
+    pub fn saturated_mbps(&self) -> Option<f64> {
+        let &(latest, latest_bytes) = self.points.back()?;
+        let mut rates = Vec::with_capacity(SLICES as usize);
+        let (mut end, mut end_bytes) = (latest, latest_bytes);
+        for _ in 0..SLICES {
+            let (start, start_bytes) = self.at_or_before(end.checked_sub(SLICE)?)?;
+            rates.push(mbps(end_bytes - start_bytes, end.duration_since(start))?);
+            (end, end_bytes) = (start, start_bytes);
+        }
+
+        let mean = rates.iter().sum::<f64>() / rates.len() as f64;
+        let spread = rates.iter().fold(0.0, |max, &r| f64::max(max, (r - mean).abs()));
+        if mean > 0.0 && spread / mean * 100.0 <= MAX_VARIATION_PERCENT {
+            mbps(latest_bytes - end_bytes, latest.duration_since(end))
+        } else {
+            None
+        }
+    }
+
+    // The latest point no later than `at`
+    fn at_or_before(&self, at: Instant) -> Option<(Instant, u64)> {
+        self.points.iter().rev().find(|(time, _)| *time <= at).copied()
+    }
+}
+
+fn mbps(bytes: u64, elapsed: Duration) -> Option<f64> {
+    let seconds = elapsed.as_secs_f64();
+    (seconds > 0.0).then(|| bytes as f64 * 8.0 / seconds / 1_000_000.0)
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+/// Base URL of Cloudflare's speed test service.
+pub const CLOUDFLARE_URL: &str = "https://speed.cloudflare.com";
+
+/// Response header in which Cloudflare names the location that answered,
+/// by its IATA airport code.
+pub const COLO_HEADER: &str = "cf-meta-colo";
+
+/// Response header in which Cloudflare echoes the client's public address.
+pub const IP_HEADER: &str = "cf-meta-ip";
+
+/// A download endpoint that tests can be run against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Server {
+    pub name: String,
+    /// Download URL; `{bytes}` is replaced with the requested size. URLs
+    /// without the placeholder serve a fixed-size file.
+    pub download_url: String,
+}
+
+impl Server {
+    pub fn new(name: impl Into<String>, download_url: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            download_url: download_url.into(),
+        }
+    }
+
+    /// A server exposing Cloudflare-style `__down`/`__up` endpoints under
+    /// `base_url`, such as Cloudflare itself or `ericspeed serve`.
+    pub fn from_base(name: impl Into<String>, base_url: &str) -> Self {
+        Self::new(name, format!("{}/__down?bytes={{bytes}}", base_url.trim_end_matches('/')))
+    }
+
+    /// The default server used for the main speed test.
+    pub fn cloudflare() -> Self {
+        Self::from_base("Cloudflare", CLOUDFLARE_URL)
+    }
+
+    /// Servers offered for side-by-side comparison. OVH and Tele2 serve
+    /// fixed 100 MB files, so no more than that is fetched from them, and
+    /// Tele2 only over plain HTTP.
+    pub fn builtin() -> Vec<Server> {
+        vec![
+            Self::cloudflare(),
+            Self::new("OVH", "https://proof.ovh.net/files/100Mb.dat"),
+            Self::new("Tele2", "http://speedtest.tele2.net/100MB.zip"),
+        ]
+    }
+
+    /// Whether downloads from this server skip TLS, which is cheaper for
+    /// the server and may be handled differently along the way.
+    pub fn is_plain_http(&self) -> bool {
+        self.download_url.starts_with("http://")
+    }
+
+    pub fn download_url(&self, bytes: u64) -> String {
+        self.download_url.replace("{bytes}", &bytes.to_string())
+    }
+}
+
+/// Upload endpoint of a Cloudflare-style server.
+pub fn upload_url(base_url: &str) -> String {
+    format!("{}/__up", base_url.trim_end_matches('/'))
+}
+
+/// Latency probe endpoint of a Cloudflare-style server: an empty download.
+pub fn ping_url(base_url: &str) -> String {
+    format!("{}/__down?bytes=0", base_url.trim_end_matches('/'))
+}
+
+/// `Server-Timing` metric in which Cloudflare reports how long it spent on a
+/// request before answering.
+pub const REQUEST_DURATION_METRIC: &str = "cfRequestDuration";
+
+/// Time the server spent handling a request, from the
+/// [`REQUEST_DURATION_METRIC`] of its `Server-Timing` header. `None` for
+/// servers that don't report it.
+pub fn processing_time(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    // e.g. `cfRequestDuration;dur=12.3, cache;desc="miss"`
+    let duration_ms = headers
+        .get_all("server-timing")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|metric| {
+            let mut params = metric.split(';').map(str::trim);
+            if params.next()? != REQUEST_DURATION_METRIC {
+                return None;
+            }
+            params.find_map(|param| param.strip_prefix("dur=")?.parse::<f64>().ok())
+        })?;
+    Duration::try_from_secs_f64(duration_ms / 1000.0).ok()
+}
+
+/// Adds the `colo` parameter asking Cloudflare to answer from the location
+/// with IATA code `colo`, such as "FRA"; other servers ignore it.
+pub fn with_colo(url: &str, colo: Option<&str>) -> String {
+    match colo {
+        Some(colo) => {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            format!("{}{}colo={}", url, separator, colo)
+        }
+        None => url.to_string(),
+    }
+}
+
+/// Which Cloudflare location answers `ping_url`, from its [`COLO_HEADER`].
+/// `None` if the request fails or the server doesn't say.
+pub async fn served_colo(client: &reqwest::Client, ping_url: &str) -> Option<String> {
+    let response = client.get(ping_url).send().await.ok()?;
+    let colo = response.headers().get(COLO_HEADER)?.to_str().ok()?;
+    Some(colo.to_string())
+}
+
+/// The client's public address as seen by the server answering `ping_url`,
+/// from its [`IP_HEADER`]. `None` if the request fails or it doesn't say.
+pub async fn public_ip(client: &reqwest::Client, ping_url: &str) -> Option<String> {
+    let response = client.get(ping_url).send().await.ok()?;
+    let ip = response.headers().get(IP_HEADER)?.to_str().ok()?;
+    Some(ip.to_string())
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::cloudflare()
+    }
+}
@@ -0,0 +1,154 @@
+use hyper_util::client::legacy::connect::HttpInfo;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// Counters for one logical transfer stream (e.g. "download" or "ping").
+#[derive(Debug, Clone, Default)]
+pub struct StreamStats {
+    pub label: String,
+    pub bytes: u64,
+    pub requests: u32,
+    pub active_requests: u32,
+    pub last_status: Option<u16>,
+    /// Local addresses of the connections the requests went over.
+    pub connections: HashSet<SocketAddr>,
+}
+
+/// Point-in-time copy of [`TransferStats`].
+#[derive(Debug, Clone, Default)]
+pub struct StatsSnapshot {
+    pub streams: Vec<StreamStats>,
+    pub last_status: Option<u16>,
+    pub retries: u32,
+    pub errors: u32,
+}
+
+impl StatsSnapshot {
+    pub fn active_requests(&self) -> u32 {
+        self.streams.iter().map(|s| s.active_requests).sum()
+    }
+
+    /// Local addresses of the connections used by the stream named `label`.
+    pub fn connections(&self, label: &str) -> HashSet<SocketAddr> {
+        self.streams
+            .iter()
+            .find(|s| s.label == label)
+            .map(|s| s.connections.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Raw request and byte counters shared between the tests and the UI.
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    inner: Mutex<StatsSnapshot>,
+}
+
+impl TransferStats {
+    /// Marks a request as in flight on the stream named `label`. The request
+    /// counts as active until the returned guard is dropped.
+    pub fn start_request(self: &Arc<Self>, label: &str) -> RequestGuard {
+        let id = self.update(|stats| {
+            let id = match stats.streams.iter().position(|s| s.label == label) {
+                Some(id) => id,
+                None => {
+                    stats.streams.push(StreamStats {
+                        label: label.to_string(),
+                        ..StreamStats::default()
+                    });
+                    stats.streams.len() - 1
+                }
+            };
+            let stream = &mut stats.streams[id];
+            stream.requests += 1;
+            stream.active_requests += 1;
+            id
+        });
+
+        RequestGuard {
+            stats: Arc::clone(self),
+            id,
+        }
+    }
+
+    fn record_status(&self, id: usize, status: u16) {
+        self.update(|stats| {
+            stats.last_status = Some(status);
+            if let Some(stream) = stats.streams.get_mut(id) {
+                stream.last_status = Some(status);
+            }
+        });
+    }
+
+    fn record_connection(&self, id: usize, addr: SocketAddr) {
+        self.update(|stats| {
+            if let Some(stream) = stats.streams.get_mut(id) {
+                stream.connections.insert(addr);
+            }
+        });
+    }
+
+    fn add_bytes(&self, id: usize, bytes: u64) {
+        self.update(|stats| {
+            if let Some(stream) = stats.streams.get_mut(id) {
+                stream.bytes += bytes;
+            }
+        });
+    }
+
+    fn finish_request(&self, id: usize) {
+        self.update(|stats| {
+            if let Some(stream) = stats.streams.get_mut(id) {
+                stream.active_requests = stream.active_requests.saturating_sub(1);
+            }
+        });
+    }
+
+    pub fn record_error(&self) {
+        self.update(|stats| stats.errors += 1);
+    }
+
+    pub fn record_retry(&self) {
+        self.update(|stats| stats.retries += 1);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        self.update(|stats| stats.clone())
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut StatsSnapshot) -> T) -> T {
+        let mut stats = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut stats)
+    }
+}
+
+/// An in-flight request registered with [`TransferStats::start_request`].
+#[derive(Debug)]
+pub struct RequestGuard {
+    stats: Arc<TransferStats>,
+    id: usize,
+}
+
+impl RequestGuard {
+    pub fn record_status(&self, status: u16) {
+        self.stats.record_status(self.id, status);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.stats.add_bytes(self.id, bytes);
+    }
+
+    /// Notes the connection `response` arrived over, if reqwest knows it.
+    pub fn record_connection(&self, response: &reqwest::Response) {
+        if let Some(info) = response.extensions().get::<HttpInfo>() {
+            self.stats.record_connection(self.id, info.local_addr());
+        }
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.stats.finish_request(self.id);
+    }
+}
@@ -0,0 +1,75 @@
+use super::download::{DownloadProgress, DownloadTest};
+use anyhow::Result;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info};
+
+/// Stream counts a sweep downloads at, in turn.
+pub const SWEEP_STREAMS: [usize; 5] = [1, 2, 4, 8, 16];
+/// How long each stream count downloads for unless told otherwise.
+pub const DEFAULT_STAGE: Duration = Duration::from_secs(5);
+
+/// Runs the same download at each of [`SWEEP_STREAMS`] to show how
+/// throughput scales with connections. Scaling well past one stream points
+/// at per-flow shaping or a long path one TCP window can't fill.
+pub struct StreamSweep {
+    test: DownloadTest,
+    stage: Duration,
+}
+
+/// Progress of the stage downloading over `streams` connections.
+#[derive(Debug, Clone)]
+pub struct SweepProgress {
+    pub streams: usize,
+    pub progress: DownloadProgress,
+}
+
+/// Average throughput of one stage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepPoint {
+    pub streams: usize,
+    pub mbps: f64,
+}
+
+impl StreamSweep {
+    /// Sweeps `test`, whose own stream count and duration are replaced by
+    /// each stage's.
+    pub fn new(test: DownloadTest) -> Self {
+        Self {
+            test,
+            stage: DEFAULT_STAGE,
+        }
+    }
+
+    pub fn with_stage(mut self, stage: Duration) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    /// Downloads at each stream count in turn, stopping at the first stage
+    /// that fails.
+    pub async fn run(&self, progress_tx: mpsc::Sender<SweepProgress>) -> Result<Vec<SweepPoint>> {
+        info!(stages = SWEEP_STREAMS.len(), stage = ?self.stage, "stream sweep starting");
+        let mut points = Vec::with_capacity(SWEEP_STREAMS.len());
+        for streams in SWEEP_STREAMS {
+            let (stage_tx, mut stage_rx) = mpsc::channel::<DownloadProgress>(32);
+            let mut stage = self
+                .test
+                .clone()
+                .with_ramp_up(false)
+                .with_early_exit(false)
+                .with_streams(streams)
+                .with_duration(Some(self.stage));
+            let forward = async {
+                while let Some(progress) = stage_rx.recv().await {
+                    let _ = progress_tx.send(SweepProgress { streams, progress }).await;
+                }
+            };
+            let (result, ()) = tokio::join!(stage.run(stage_tx), forward);
+            let mbps = result?.avg_speed_mbps;
+            debug!(streams, mbps, "sweep stage finished");
+            points.push(SweepPoint { streams, mbps });
+        }
+        Ok(points)
+    }
+}
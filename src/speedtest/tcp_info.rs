@@ -0,0 +1,326 @@
+use super::TestPhase;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Retransmitted share of sent segments, in percent, above which an upload
+/// counts as limited by loss rather than latency.
+pub const LOSS_LIMITED_PERCENT: f64 = 1.0;
+
+/// Kernel statistics for one TCP connection, read with `TCP_INFO`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpInfo {
+    /// Whether the connection is established, rather than opening or closing.
+    pub established: bool,
+    /// Largest segment payload the connection sends, in bytes.
+    pub send_mss: u32,
+    /// Path MTU the kernel currently assumes, in bytes.
+    pub path_mtu: u32,
+    /// Whether TCP timestamps are in use, adding 12 bytes of options to
+    /// every segment.
+    pub timestamps: bool,
+    /// Smoothed round-trip time, in microseconds.
+    pub rtt_us: u32,
+    /// Congestion window, in segments.
+    pub cwnd: u32,
+    /// Segments retransmitted over the connection's lifetime.
+    pub retransmits: u32,
+    pub data_segments_out: u32,
+    pub data_segments_in: u32,
+    /// Segments received out of order. Loss on the way in causes some, but
+    /// so does plain reordering, so it is no measure of loss.
+    pub out_of_order: u32,
+    /// The kernel's latest estimate of the delivery rate, in bytes per second.
+    pub delivery_rate: u64,
+}
+
+// struct tcp_info from linux/tcp.h, up to the fields added in 5.4; older
+// kernels fill in less and leave the rest zeroed
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Default)]
+struct RawTcpInfo {
+    state: u8,
+    ca_state: u8,
+    retransmits: u8,
+    probes: u8,
+    backoff: u8,
+    options: u8,
+    wscale: u8,
+    flags: u8,
+    rto: u32,
+    ato: u32,
+    snd_mss: u32,
+    rcv_mss: u32,
+    unacked: u32,
+    sacked: u32,
+    lost: u32,
+    retrans: u32,
+    fackets: u32,
+    last_data_sent: u32,
+    last_ack_sent: u32,
+    last_data_recv: u32,
+    last_ack_recv: u32,
+    pmtu: u32,
+    rcv_ssthresh: u32,
+    rtt: u32,
+    rttvar: u32,
+    snd_ssthresh: u32,
+    snd_cwnd: u32,
+    advmss: u32,
+    reordering: u32,
+    rcv_rtt: u32,
+    rcv_space: u32,
+    total_retrans: u32,
+    pacing_rate: u64,
+    max_pacing_rate: u64,
+    bytes_acked: u64,
+    bytes_received: u64,
+    segs_out: u32,
+    segs_in: u32,
+    notsent_bytes: u32,
+    min_rtt: u32,
+    data_segs_in: u32,
+    data_segs_out: u32,
+    delivery_rate: u64,
+    busy_time: u64,
+    rwnd_limited: u64,
+    sndbuf_limited: u64,
+    delivered: u32,
+    delivered_ce: u32,
+    bytes_sent: u64,
+    bytes_retrans: u64,
+    dsack_dups: u32,
+    reord_seen: u32,
+    rcv_ooopack: u32,
+    snd_wnd: u32,
+}
+
+impl TcpInfo {
+    /// Reads the statistics of `socket`. Only supported on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn of(socket: &impl std::os::fd::AsRawFd) -> io::Result<Self> {
+        Self::of_fd(socket.as_raw_fd())
+    }
+
+    /// Reads the statistics of `socket`. Only supported on Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn of<S>(_socket: &S) -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "TCP_INFO is only available on Linux"))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn of_fd(fd: std::os::fd::RawFd) -> io::Result<Self> {
+        // From linux/tcp.h; libc doesn't export them
+        const TCP_ESTABLISHED: u8 = 1;
+        const TCPI_OPT_TIMESTAMPS: u8 = 1;
+
+        let mut info = RawTcpInfo::default();
+        let mut len = std::mem::size_of::<RawTcpInfo>() as libc::socklen_t;
+        // SAFETY: RawTcpInfo is plain data, and getsockopt writes at most `len` bytes into it
+        let status = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::IPPROTO_TCP,
+                libc::TCP_INFO,
+                &mut info as *mut RawTcpInfo as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if status != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            established: info.state == TCP_ESTABLISHED,
+            send_mss: info.snd_mss,
+            path_mtu: info.pmtu,
+            timestamps: info.options & TCPI_OPT_TIMESTAMPS != 0,
+            rtt_us: info.rtt,
+            cwnd: info.snd_cwnd,
+            retransmits: info.total_retrans,
+            data_segments_out: info.data_segs_out,
+            data_segments_in: info.data_segs_in,
+            out_of_order: info.rcv_ooopack,
+            delivery_rate: info.delivery_rate,
+        })
+    }
+}
+
+// Local address and statistics of TCP connections, keyed by the socket's
+// inode so a reused descriptor isn't mistaken for the same socket
+type Connections = HashMap<u64, (SocketAddr, TcpInfo)>;
+
+// Every TCP connection this process has open
+#[cfg(target_os = "linux")]
+fn process_connections() -> Connections {
+    use std::os::fd::FromRawFd;
+
+    let Ok(entries) = std::fs::read_dir("/proc/self/fd") else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let target = std::fs::read_link(entry.path()).ok()?;
+            let inode = target
+                .to_str()?
+                .strip_prefix("socket:[")?
+                .strip_suffix(']')?
+                .parse()
+                .ok()?;
+            let fd = entry.file_name().to_str()?.parse().ok()?;
+            // Fails for anything that isn't a TCP socket
+            let info = TcpInfo::of_fd(fd).ok()?;
+            // SAFETY: the descriptor is only borrowed; ManuallyDrop keeps it open
+            let socket = unsafe { std::net::TcpStream::from_raw_fd(fd) };
+            let socket = std::mem::ManuallyDrop::new(socket);
+            Some((inode, (socket.local_addr().ok()?, info)))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_connections() -> Connections {
+    HashMap::new()
+}
+
+/// TCP statistics over the connections of one transfer phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TcpStats {
+    pub connections: u32,
+    /// Smoothed round-trip time, averaged over the connections weighted by
+    /// the segments each carried.
+    pub rtt_ms: f64,
+    /// Congestion window, summed over the connections, in segments.
+    pub cwnd: u64,
+    /// Segments this end retransmitted.
+    pub retransmits: u64,
+    pub data_segments_out: u64,
+    pub data_segments_in: u64,
+    /// Segments that arrived out of order, see [`TcpInfo::out_of_order`].
+    pub out_of_order: u64,
+    /// The kernel's delivery rate estimates, summed over the connections.
+    pub delivery_rate_mbps: f64,
+}
+
+/// What held a transfer's throughput back, judged from its TCP statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThroughputLimit {
+    /// Enough segments were lost to keep the congestion window small.
+    Loss,
+    /// Little loss; the window and round-trip time set the pace.
+    Latency,
+}
+
+impl TcpStats {
+    /// Share of segments lost in the direction `phase` transfers, from this
+    /// end's retransmissions. Only uploads send enough to tell; a receiver
+    /// can't tell loss from reordering, so downloads give `None`.
+    pub fn loss_percent(&self, phase: TestPhase) -> Option<f64> {
+        if phase != TestPhase::Upload {
+            return None;
+        }
+        Some(if self.data_segments_out == 0 {
+            0.0
+        } else {
+            self.retransmits as f64 / self.data_segments_out as f64 * 100.0
+        })
+    }
+
+    /// What limited the transfer, when [`loss_percent`](Self::loss_percent)
+    /// is known.
+    pub fn limit(&self, phase: TestPhase) -> Option<ThroughputLimit> {
+        let limit = if self.loss_percent(phase)? >= LOSS_LIMITED_PERCENT {
+            ThroughputLimit::Loss
+        } else {
+            ThroughputLimit::Latency
+        };
+        Some(limit)
+    }
+}
+
+/// Samples the process's TCP connections in the background while a transfer
+/// runs, to sum up the transfer's own when it ends. Connections that were
+/// already open count only what they carried after the sampler started.
+pub struct TcpSampler {
+    stop_tx: oneshot::Sender<()>,
+    handle: JoinHandle<(Connections, Connections)>,
+}
+
+impl TcpSampler {
+    pub fn spawn() -> Self {
+        let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            let baseline = process_connections();
+            let mut latest = Connections::new();
+            let mut stopped = false;
+            // Sample once more after the stop, for transfers shorter than an interval
+            while !stopped {
+                tokio::select! {
+                    _ = &mut stop_rx => stopped = true,
+                    _ = tokio::time::sleep(SAMPLE_INTERVAL) => {}
+                }
+                for (inode, connection) in process_connections() {
+                    if connection.1.established {
+                        latest.insert(inode, connection);
+                    }
+                }
+            }
+            debug!(connections = latest.len(), "TCP sampler stopped");
+            (baseline, latest)
+        });
+
+        Self { stop_tx, handle }
+    }
+
+    /// Stops sampling and sums up the connections seen with one of the local
+    /// addresses in `connections`, if any. Others, like a latency probe's,
+    /// are left out.
+    pub async fn finish(self, connections: &HashSet<SocketAddr>) -> Option<TcpStats> {
+        let _ = self.stop_tx.send(());
+        let (baseline, latest) = self.handle.await.ok()?;
+        summarize(&baseline, &latest, connections)
+    }
+}
+
+fn summarize(
+    baseline: &Connections,
+    latest: &Connections,
+    connections: &HashSet<SocketAddr>,
+) -> Option<TcpStats> {
+    let mut stats = TcpStats::default();
+    let mut weighted_rtt = 0.0;
+    for (inode, (addr, info)) in latest {
+        if !connections.contains(addr) {
+            continue;
+        }
+        let before = baseline.get(inode).map(|&(_, info)| info).unwrap_or_default();
+        let delta = |after: u32, before: u32| after.saturating_sub(before) as u64;
+        let data_in = delta(info.data_segments_in, before.data_segments_in);
+        let data_out = delta(info.data_segments_out, before.data_segments_out);
+        // Connections that sat idle, like the ping phase's, would skew the averages
+        if data_in == 0 && data_out == 0 {
+            continue;
+        }
+
+        stats.connections += 1;
+        weighted_rtt += info.rtt_us as f64 / 1000.0 * (data_in + data_out) as f64;
+        stats.cwnd += info.cwnd as u64;
+        stats.retransmits += delta(info.retransmits, before.retransmits);
+        stats.data_segments_out += data_out;
+        stats.data_segments_in += data_in;
+        stats.out_of_order += delta(info.out_of_order, before.out_of_order);
+        stats.delivery_rate_mbps += info.delivery_rate as f64 * 8.0 / 1_000_000.0;
+    }
+    if stats.connections == 0 {
+        return None;
+    }
+    stats.rtt_ms = weighted_rtt / (stats.data_segments_in + stats.data_segments_out) as f64;
+    Some(stats)
+}
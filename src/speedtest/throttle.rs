@@ -0,0 +1,76 @@
+use futures::stream;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Slice size used when pacing request bodies through the limiter
+const PACE_SLICE: usize = 64 * 1024;
+
+/// Token-bucket rate limiter shared by the transfer loops.
+///
+/// Tokens are bytes, refilled at the configured rate with up to 100 ms of
+/// burst. Transfers that run ahead of the rate sleep until the bucket
+/// catches up, so throughput settles at the cap without starving the link.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bytes_per_sec: f64,
+    state: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(mbps: u64) -> Self {
+        let bytes_per_sec = (mbps.max(1) as f64) * 1_000_000.0 / 8.0;
+        Self {
+            bytes_per_sec,
+            state: Arc::new(Mutex::new(Bucket {
+                tokens: bytes_per_sec * 0.1,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Takes `bytes` tokens, waiting if the bucket has run dry.
+    pub async fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.last_refill).as_secs_f64() * self.bytes_per_sec;
+            bucket.tokens = (bucket.tokens + refill).min(self.bytes_per_sec * 0.1);
+            bucket.last_refill = now;
+            bucket.tokens -= bytes as f64;
+
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Wraps `data` in a request body that is released no faster than the limit.
+    pub fn paced_body(&self, data: Vec<u8>) -> reqwest::Body {
+        let limiter = self.clone();
+        let slices = stream::unfold((data, 0), move |(data, offset)| {
+            let limiter = limiter.clone();
+            async move {
+                if offset >= data.len() {
+                    return None;
+                }
+                let end = (offset + PACE_SLICE).min(data.len());
+                limiter.acquire(end - offset).await;
+                let slice = data[offset..end].to_vec();
+                Some((Ok::<_, std::io::Error>(slice), (data, end)))
+            }
+        });
+        reqwest::Body::wrap_stream(slices)
+    }
+}
@@ -0,0 +1,108 @@
+use crate::samples::confidence_margin_95;
+use std::time::{Duration, Instant};
+
+/// How often transfers report progress and sample their throughput unless
+/// set otherwise.
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+// Throughput sampled a fraction of a second apart is correlated, which
+// would make its confidence interval too narrow; blocks this long are
+// close to independent
+const BLOCK: Duration = Duration::from_secs(1);
+
+/// Shortest sampling interval a transfer accepts.
+pub const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest sampling interval a transfer accepts.
+pub const MAX_SAMPLE_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Average throughput between the first byte of a transfer and the last, so
+/// connection setup before it and bookkeeping after it don't dilute the
+/// result.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThroughputMeter {
+    first: Option<Instant>,
+    last: Option<Instant>,
+    // Bytes transferred after `first`, the ones the measured span covers
+    bytes: u64,
+}
+
+impl ThroughputMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks bytes starting to leave at `at`, for transfers that only learn
+    /// when bytes are done, like uploads. The earliest start counts, and so
+    /// does every byte recorded.
+    pub fn start(&mut self, at: Instant) {
+        self.first = Some(self.first.map_or(at, |first| first.min(at)));
+    }
+
+    /// Records `bytes` having arrived by `at`. If this is the first record,
+    /// it only starts the clock: those bytes arrived before the span began.
+    pub fn record(&mut self, bytes: u64, at: Instant) {
+        match self.first {
+            None => self.first = Some(at),
+            Some(_) => self.bytes += bytes,
+        }
+        self.last = Some(self.last.map_or(at, |last| last.max(at)));
+    }
+
+    /// Time from the first byte to the last.
+    pub fn elapsed(&self) -> Duration {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => last.saturating_duration_since(first),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Average throughput over [`elapsed`](Self::elapsed), in Mbps; 0 until
+    /// two records are apart in time.
+    pub fn mbps(&self) -> f64 {
+        let elapsed = self.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.bytes as f64 * 8.0 / elapsed / 1_000_000.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Throughput of a transfer block by block, each a second or a little more,
+/// for the confidence interval of its speed.
+#[derive(Debug, Clone, Default)]
+pub struct BlockSpeeds {
+    // Bytes transferred in total when the current block began
+    start: Option<(Instant, u64)>,
+    speeds: Vec<f64>,
+}
+
+impl BlockSpeeds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `bytes` had been transferred in total by `at`. The first
+    /// record starts the first block.
+    pub fn record(&mut self, bytes: u64, at: Instant) {
+        match self.start {
+            Some((start, start_bytes)) if at.duration_since(start) >= BLOCK => {
+                let seconds = at.duration_since(start).as_secs_f64();
+                self.speeds.push((bytes - start_bytes) as f64 * 8.0 / seconds / 1_000_000.0);
+                self.start = Some((at, bytes));
+            }
+            Some(_) => {}
+            None => self.start = Some((at, bytes)),
+        }
+    }
+
+    /// Half-width of the 95% confidence interval for the speed in Mbps, from
+    /// the spread of the blocks' speeds. With `span`, only the latest blocks
+    /// that long count, for a speed measured over just the end of the
+    /// transfer. `None` with fewer than two blocks.
+    pub fn margin_95(&self, span: Option<Duration>) -> Option<f64> {
+        let count = span.map_or(self.speeds.len(), |span| {
+            (span.as_secs_f64() / BLOCK.as_secs_f64()).round() as usize
+        });
+        confidence_margin_95(&self.speeds[self.speeds.len().saturating_sub(count)..])
+    }
+}
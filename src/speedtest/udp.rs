@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+// Packet layout: sequence number, client send time, server receive time (both
+// in microseconds since the sender's own start), then padding
+const HEADER_LEN: usize = 20;
+// Echoes still in flight this long after the last send are counted as lost
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Sends a paced UDP packet stream to `ericspeed serve` and measures the
+/// echoes, the way iperf's UDP mode does.
+pub struct UdpTest {
+    target: SocketAddr,
+    packets: u32,
+    interval: Duration,
+    payload_size: usize,
+}
+
+impl UdpTest {
+    pub fn new(target: SocketAddr) -> Self {
+        // 50 packets per second of 160 bytes, roughly a G.711 voice stream
+        Self {
+            target,
+            packets: 500,
+            interval: Duration::from_millis(20),
+            payload_size: 160,
+        }
+    }
+
+    pub fn with_packets(mut self, packets: u32) -> Self {
+        self.packets = packets;
+        self
+    }
+
+    /// Time between packets; at least a millisecond, as a zero period has no ticks.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    pub fn with_payload_size(mut self, payload_size: usize) -> Self {
+        self.payload_size = payload_size.max(HEADER_LEN);
+        self
+    }
+
+    pub async fn run(&self, progress_tx: mpsc::Sender<UdpProgress>) -> Result<UdpResult> {
+        let bind_addr: SocketAddr = if self.target.is_ipv4() {
+            "0.0.0.0:0".parse()?
+        } else {
+            "[::]:0".parse()?
+        };
+        let socket = Arc::new(UdpSocket::bind(bind_addr).await?);
+        socket
+            .connect(self.target)
+            .await
+            .with_context(|| format!("failed to reach {}", self.target))?;
+        info!(target = %self.target, packets = self.packets, "udp test starting");
+
+        let start = Instant::now();
+        let sender = {
+            let socket = Arc::clone(&socket);
+            let (packets, interval, payload_size) = (self.packets, self.interval, self.payload_size);
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                let mut packet = vec![0u8; payload_size];
+                for seq in 0..packets {
+                    ticker.tick().await;
+                    packet[0..4].copy_from_slice(&seq.to_be_bytes());
+                    packet[4..12].copy_from_slice(&micros_since(start).to_be_bytes());
+                    if let Err(err) = socket.send(&packet).await {
+                        warn!(error = %err, seq, "udp send failed");
+                    }
+                }
+            })
+        };
+
+        // Unique echoes only, so duplicates can neither end the wait early
+        // nor hide loss
+        let mut echoes = Vec::with_capacity(self.packets as usize);
+        let mut seen = HashSet::with_capacity(self.packets as usize);
+        let mut highest_seq = None;
+        let mut reordered = 0;
+        let mut buf = vec![0u8; self.payload_size.max(HEADER_LEN)];
+
+        let send_time = self.interval * self.packets;
+        let deadline = tokio::time::Instant::now() + send_time + DRAIN_TIMEOUT;
+        while echoes.len() < self.packets as usize {
+            let len = match tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+                Ok(received) => received?,
+                Err(_) => break,
+            };
+            if len < HEADER_LEN {
+                continue;
+            }
+
+            let seq = u32::from_be_bytes(buf[0..4].try_into()?);
+            let sent_us = u64::from_be_bytes(buf[4..12].try_into()?);
+            // Anything else answering could send a send time from the future
+            let Some(rtt_us) = micros_since(start).checked_sub(sent_us) else {
+                debug!(seq, "udp echo with an invalid send time dropped");
+                continue;
+            };
+            if seq >= self.packets || !seen.insert(seq) {
+                continue;
+            }
+            let echo = Echo {
+                seq,
+                sent_us,
+                server_us: u64::from_be_bytes(buf[12..20].try_into()?),
+                rtt_us,
+            };
+            if highest_seq.is_some_and(|highest| echo.seq < highest) {
+                reordered += 1;
+            } else {
+                highest_seq = Some(echo.seq);
+            }
+            debug!(seq = echo.seq, rtt_us = echo.rtt_us, "udp echo");
+
+            let rtt_ms = echo.rtt_us as f64 / 1000.0;
+            echoes.push(echo);
+            let _ = progress_tx
+                .send(UdpProgress {
+                    received: echoes.len() as u32,
+                    total: self.packets,
+                    latest_rtt_ms: rtt_ms,
+                })
+                .await;
+        }
+        // A sender that died would otherwise pass for total loss
+        if sender.is_finished() {
+            sender.await.context("udp sender failed")?;
+        } else {
+            sender.abort();
+        }
+
+        let result = calculate_result(self.packets, echoes, reordered);
+        info!(
+            received = result.received,
+            loss_percent = result.loss_percent,
+            jitter_ms = result.jitter_ms,
+            "udp test finished"
+        );
+        Ok(result)
+    }
+}
+
+struct Echo {
+    seq: u32,
+    sent_us: u64,
+    server_us: u64,
+    rtt_us: u64,
+}
+
+fn calculate_result(sent: u32, mut echoes: Vec<Echo>, reordered: u32) -> UdpResult {
+    echoes.sort_by_key(|echo| echo.seq);
+
+    let received = echoes.len() as u32;
+    let loss_percent = if sent > 0 {
+        (sent - received.min(sent)) as f64 / sent as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    // RFC 3550 interarrival jitter on the client-to-server leg. The two
+    // clocks are unsynchronised, but the offset cancels out in the difference.
+    let mut jitter = 0.0;
+    for pair in echoes.windows(2) {
+        let transit = |e: &Echo| e.server_us as f64 - e.sent_us as f64;
+        let delta = (transit(&pair[1]) - transit(&pair[0])).abs() / 1000.0;
+        jitter += (delta - jitter) / 16.0;
+    }
+
+    let avg_rtt_ms = if echoes.is_empty() {
+        0.0
+    } else {
+        echoes.iter().map(|e| e.rtt_us as f64).sum::<f64>()
+            / echoes.len() as f64
+            / 1000.0
+    };
+
+    UdpResult {
+        sent,
+        received,
+        loss_percent,
+        jitter_ms: jitter,
+        reordered,
+        avg_rtt_ms,
+    }
+}
+
+/// Echoes test packets back to their sender, stamping each with its
+/// receive time. Runs until the socket fails.
+pub async fn reflect(socket: UdpSocket) -> Result<()> {
+    let start = Instant::now();
+    let mut buf = vec![0u8; 65_535];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        if len < HEADER_LEN {
+            continue;
+        }
+        buf[12..20].copy_from_slice(&micros_since(start).to_be_bytes());
+        if let Err(err) = socket.send_to(&buf[..len], peer).await {
+            warn!(error = %err, %peer, "udp echo failed");
+        }
+    }
+}
+
+fn micros_since(start: Instant) -> u64 {
+    start.elapsed().as_micros() as u64
+}
+
+/// Sent for every echo received.
+#[derive(Debug, Clone)]
+pub struct UdpProgress {
+    pub received: u32,
+    pub total: u32,
+    pub latest_rtt_ms: f64,
+}
+
+/// Outcome of a UDP test.
+#[derive(Debug, Clone)]
+pub struct UdpResult {
+    pub sent: u32,
+    pub received: u32,
+    /// Packets with no echo, covering loss in either direction.
+    pub loss_percent: f64,
+    /// One-way interarrival jitter towards the server (RFC 3550).
+    pub jitter_ms: f64,
+    /// Echoes that arrived after a later-sequenced one.
+    pub reordered: u32,
+    pub avg_rtt_ms: f64,
+}
@@ -1,87 +1,366 @@
+use super::server::{self, CLOUDFLARE_URL};
+use super::retry::RetryPolicy;
+use super::saturation::{self, SaturationDetector};
+use super::stats::TransferStats;
+use super::throttle::RateLimiter;
+use super::throughput::{
+    BlockSpeeds, ThroughputMeter, DEFAULT_SAMPLE_INTERVAL, MAX_SAMPLE_INTERVAL, MIN_SAMPLE_INTERVAL,
+};
+use crate::settings::MAX_STREAMS;
 use anyhow::Result;
+use futures::future;
 use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
 
-const UPLOAD_URL: &str = "https://speed.cloudflare.com/__up";
 const CHUNK_SIZE: usize = 1_000_000; // 1MB chunks
+// Payloads smaller than this aren't worth splitting across threads
+const PARALLEL_MIN_SIZE: usize = 8 * CHUNK_SIZE;
 
+/// Size of the random block request bodies are cut from, cycling through it
+/// as often as the upload size needs.
+pub const PAYLOAD_BLOCK_SIZE: usize = 4 * CHUNK_SIZE;
+// Smallest block and request body a memory budget can shrink them to
+const MIN_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Size of the random block to generate for an upload under
+/// `memory_budget` bytes: at most half the budget, leaving the rest for the
+/// request bodies in flight.
+pub fn payload_block_size(memory_budget: Option<usize>) -> usize {
+    memory_budget.map_or(PAYLOAD_BLOCK_SIZE, |budget| {
+        (budget / 2).clamp(MIN_BUFFER_SIZE, PAYLOAD_BLOCK_SIZE)
+    })
+}
+
+/// Fills `size` bytes with random data, split across the available CPU
+/// cores on a blocking thread so the async runtime isn't stalled.
+pub async fn random_payload(size: usize) -> Result<Arc<[u8]>> {
+    let payload = tokio::task::spawn_blocking(move || {
+        let mut data = vec![0u8; size];
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let part = if size < PARALLEL_MIN_SIZE { size.max(1) } else { size.div_ceil(threads) };
+        std::thread::scope(|scope| {
+            for part in data.chunks_mut(part) {
+                scope.spawn(|| rand::rngs::StdRng::from_entropy().fill(part));
+            }
+        });
+        data
+    })
+    .await?;
+    debug!(bytes = size, "upload payload generated");
+    Ok(payload.into())
+}
+
+/// Measures upload throughput by posting random data to the test server.
+///
+/// The data repeats a [`PAYLOAD_BLOCK_SIZE`] block, so memory use doesn't
+/// grow with the upload size.
 pub struct UploadTest {
-    data: Vec<u8>,
-    speed_samples: Vec<f64>,
+    client: reqwest::Client,
+    url: String,
+    payload: Option<Arc<[u8]>>,
     upload_size: usize,
+    memory_budget: Option<usize>,
+    streams: usize,
+    duration: Option<Duration>,
+    early_exit: bool,
+    limiter: Option<RateLimiter>,
+    sample_interval: Duration,
+    retry: RetryPolicy,
+    stats: Arc<TransferStats>,
 }
 
 impl UploadTest {
-    pub fn new(upload_size: usize) -> Self {
-        let mut rng = rand::rngs::StdRng::from_entropy();
-        let data: Vec<u8> = (0..upload_size).map(|_| rng.gen()).collect();
+    pub fn new(client: reqwest::Client, upload_size: usize) -> Self {
         Self {
-            data,
-            speed_samples: Vec::new(),
+            client,
+            url: server::upload_url(CLOUDFLARE_URL),
+            payload: None,
             upload_size,
+            memory_budget: None,
+            streams: 1,
+            duration: None,
+            early_exit: false,
+            limiter: None,
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            retry: RetryPolicy::default(),
+            stats: Arc::default(),
         }
     }
 
-    pub async fn run(&mut self, progress_tx: mpsc::Sender<UploadProgress>) -> Result<UploadResult> {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(120))
-            .connect_timeout(Duration::from_secs(10))
-            .build()?;
+    /// Posts to `url` instead of Cloudflare.
+    pub fn with_url(mut self, url: String) -> Self {
+        self.url = url;
+        self
+    }
+
+    /// Cycles through `payload`, e.g. a block prepared earlier with
+    /// [`random_payload`], instead of generating one when the upload starts.
+    pub fn with_payload(mut self, payload: Arc<[u8]>) -> Self {
+        self.payload = Some(payload).filter(|payload| !payload.is_empty());
+        self
+    }
+
+    /// Keeps the payload block and request bodies within `memory_budget`
+    /// bytes, sending smaller requests if need be.
+    pub fn with_memory_budget(mut self, memory_budget: Option<usize>) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
 
-        let start = Instant::now();
+    /// Caps throughput by pacing each request body through the limiter.
+    pub fn with_rate_limit(mut self, limiter: Option<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Retries chunks that fail transiently according to `retry`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Records request and byte counters into `stats`.
+    pub fn with_stats(mut self, stats: Arc<TransferStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Posts chunks over `streams` concurrent requests, clamped to
+    /// 1..=[`MAX_STREAMS`].
+    pub fn with_streams(mut self, streams: usize) -> Self {
+        self.streams = streams.clamp(1, MAX_STREAMS);
+        self
+    }
+
+    /// Stops the upload after `duration` even if data remains, measuring the
+    /// speed over what was transferred by then.
+    pub fn with_duration(mut self, duration: Option<Duration>) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Reports progress and samples throughput every `interval`, clamped to
+    /// [`MIN_SAMPLE_INTERVAL`]..=[`MAX_SAMPLE_INTERVAL`].
+    pub fn with_sample_interval(mut self, interval: Duration) -> Self {
+        self.sample_interval = interval.clamp(MIN_SAMPLE_INTERVAL, MAX_SAMPLE_INTERVAL);
+        self
+    }
+
+    /// Stops the upload once its throughput has levelled off, measuring the
+    /// speed over the last few seconds only.
+    pub fn with_early_exit(mut self, early_exit: bool) -> Self {
+        self.early_exit = early_exit;
+        self
+    }
+
+    pub async fn run(&mut self, progress_tx: mpsc::Sender<UploadProgress>) -> Result<UploadResult> {
+        let payload = match &self.payload {
+            Some(payload) => Arc::clone(payload),
+            None => random_payload(payload_block_size(self.memory_budget)).await?,
+        };
+        let chunk_size = self.chunk_size();
+        info!(
+            url = %self.url,
+            bytes = self.upload_size,
+            streams = self.streams,
+            chunk_size,
+            "upload starting"
+        );
+        // Timed from the first request to the last response, not from setup
+        let mut meter = ThroughputMeter::new();
+        let mut saturation = self.early_exit.then(SaturationDetector::new);
+        let mut saturated_mbps = None;
+        let mut blocks = BlockSpeeds::new();
         let mut uploaded: usize = 0;
         let mut last_update = Instant::now();
         let mut last_uploaded: usize = 0;
 
-        self.speed_samples.clear();
+        // Streams take chunks from a shared queue and report each one sent
+        let next_chunk = AtomicUsize::new(0);
+        let (sent_tx, mut sent_rx) = mpsc::unbounded_channel();
+        let workers = future::try_join_all(
+            (0..self.streams)
+                .map(|_| self.send_chunks(&payload, chunk_size, &next_chunk, sent_tx.clone())),
+        );
+        drop(sent_tx);
+        tokio::pin!(workers);
+        let time_limit = tokio::time::sleep(self.duration.unwrap_or_default());
+        tokio::pin!(time_limit);
+        let mut finished = false;
 
-        // Upload in chunks
-        for chunk in self.data.chunks(CHUNK_SIZE) {
-            let _ = client.post(UPLOAD_URL).body(chunk.to_vec()).send().await;
-            uploaded += chunk.len();
+        loop {
+            let chunk_len = tokio::select! {
+                result = &mut workers, if !finished => {
+                    result?;
+                    finished = true;
+                    continue;
+                }
+                _ = &mut time_limit, if self.duration.is_some() => {
+                    info!("upload time limit reached");
+                    break;
+                }
+                sent = sent_rx.recv() => match sent {
+                    Some(SentChunk { len, started, finished }) => {
+                        meter.start(started);
+                        meter.record(len as u64, finished);
+                        len
+                    }
+                    None => break,
+                },
+            };
+            uploaded += chunk_len;
 
             let now = Instant::now();
             let interval = now.duration_since(last_update);
 
-            if interval >= Duration::from_millis(100) {
+            if interval >= self.sample_interval {
                 let bytes_delta = uploaded - last_uploaded;
                 let mbps = (bytes_delta as f64 * 8.0) / interval.as_secs_f64() / 1_000_000.0;
-                self.speed_samples.push(mbps);
-
-                // Keep last 200 samples
-                if self.speed_samples.len() > 200 {
-                    self.speed_samples.remove(0);
-                }
+                let avg_mbps = meter.mbps();
+                blocks.record(uploaded as u64, now);
 
                 let _ = progress_tx
                     .send(UploadProgress {
                         uploaded_bytes: uploaded as u64,
                         total_bytes: self.upload_size as u64,
-                        speed_samples: self.speed_samples.clone(),
+                        speed_mbps: mbps,
+                        avg_speed_mbps: avg_mbps,
                     })
                     .await;
 
                 last_update = now;
                 last_uploaded = uploaded;
+
+                if let Some(saturation) = &mut saturation {
+                    saturation.record(uploaded as u64, now);
+                    saturated_mbps = saturation.saturated_mbps();
+                    if saturated_mbps.is_some() {
+                        info!("upload throughput levelled off");
+                        break;
+                    }
+                }
+            }
+        }
+
+        let elapsed = meter.elapsed();
+        let avg_speed = saturated_mbps.unwrap_or_else(|| meter.mbps());
+        info!(
+            bytes = uploaded,
+            elapsed_ms = elapsed.as_millis() as u64,
+            avg_mbps = avg_speed,
+            "upload finished"
+        );
+
+        Ok(UploadResult {
+            avg_speed_mbps: avg_speed,
+            margin_mbps: blocks.margin_95(saturated_mbps.map(|_| saturation::WINDOW)),
+            bytes: uploaded as u64,
+            elapsed,
+        })
+    }
+
+    // Request body size; with a memory budget, what the payload block leaves
+    // of it, split over the streams with room for two bodies each while a
+    // retry is built
+    fn chunk_size(&self) -> usize {
+        self.memory_budget.map_or(CHUNK_SIZE, |budget| {
+            let available = budget.saturating_sub(payload_block_size(Some(budget)));
+            (available / (2 * self.streams)).clamp(MIN_BUFFER_SIZE, CHUNK_SIZE)
+        })
+    }
+
+    // Posts chunks until the queue is empty, reporting each chunk's size and
+    // timing to `sent_tx`. A chunk that still fails after retrying, or that
+    // the server rejects, fails the upload.
+    async fn send_chunks(
+        &self,
+        payload: &[u8],
+        chunk_size: usize,
+        next_chunk: &AtomicUsize,
+        sent_tx: mpsc::UnboundedSender<SentChunk>,
+    ) -> Result<()> {
+        loop {
+            let offset = next_chunk.fetch_add(1, Ordering::Relaxed) * chunk_size;
+            if offset >= self.upload_size {
+                break;
             }
+            let chunk = cycled(payload, offset, chunk_size.min(self.upload_size - offset));
+            let body = || match &self.limiter {
+                Some(limiter) => limiter.paced_body(chunk.clone()),
+                None => chunk.clone().into(),
+            };
+            let request = self.stats.start_request("upload");
+            let request_start = Instant::now();
+            let response = self
+                .retry
+                .send(&self.stats, || self.client.post(&self.url).body(body()))
+                .await
+                .inspect_err(|err| {
+                    warn!(error = %err, "upload chunk failed");
+                    self.stats.record_error();
+                })?;
+            request.record_status(response.status().as_u16());
+            request.record_connection(&response);
+            debug!(
+                status = %response.status(),
+                bytes = chunk.len(),
+                elapsed_ms = request_start.elapsed().as_millis() as u64,
+                "upload chunk sent"
+            );
+            response.error_for_status()?;
+            request.add_bytes(chunk.len() as u64);
+            drop(request);
+            let _ = sent_tx.send(SentChunk {
+                len: chunk.len(),
+                started: request_start,
+                finished: Instant::now(),
+            });
         }
+        Ok(())
+    }
+}
 
-        let elapsed = start.elapsed();
-        let avg_speed = (self.upload_size as f64 * 8.0) / elapsed.as_secs_f64() / 1_000_000.0;
+// Reported by each stream for every chunk the server accepted
+struct SentChunk {
+    len: usize,
+    started: Instant,
+    finished: Instant,
+}
 
-        Ok(UploadResult { avg_speed_mbps: avg_speed })
+// Bytes `offset..offset + len` of an endless repetition of `block`
+fn cycled(block: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len);
+    while data.len() < len {
+        let start = (offset + data.len()) % block.len();
+        let take = (len - data.len()).min(block.len() - start);
+        data.extend_from_slice(&block[start..start + take]);
     }
+    data
 }
 
+/// Periodic upload progress, sent about every sampling interval.
 #[derive(Debug, Clone)]
 pub struct UploadProgress {
     pub uploaded_bytes: u64,
     pub total_bytes: u64,
-    pub speed_samples: Vec<f64>,
+    /// Throughput over the latest sampling interval, in Mbps.
+    pub speed_mbps: f64,
+    /// Average throughput since the phase started, in Mbps.
+    pub avg_speed_mbps: f64,
 }
 
+/// Outcome of a completed upload phase.
 #[derive(Debug, Clone)]
 pub struct UploadResult {
     pub avg_speed_mbps: f64,
+    /// Half-width of the speed's 95% confidence interval, like
+    /// [`DownloadResult::margin_mbps`](super::download::DownloadResult::margin_mbps).
+    pub margin_mbps: Option<f64>,
+    /// Bytes sent, and the time from the first request to the last response.
+    pub bytes: u64,
+    pub elapsed: Duration,
 }
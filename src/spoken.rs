@@ -0,0 +1,116 @@
+use ericspeed::speedtest::quality::mos_label;
+use ericspeed::{SpeedTestResult, TestUpdate};
+use std::time::{Duration, Instant};
+
+// Progress is announced at most this often, so a screen reader finishes one
+// sentence before the next arrives
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+
+// Turns test updates into sentences for a screen reader: units spelled out,
+// no symbols or bars, and progress throttled to ANNOUNCE_INTERVAL. Phase
+// results, warnings and failures are always announced
+#[derive(Debug, Default)]
+pub struct Announcer {
+    // When progress was last announced; cleared when a phase ends so the
+    // next one is announced as soon as it starts
+    last_progress: Option<Instant>,
+}
+
+impl Announcer {
+    pub fn announce(&mut self, update: &TestUpdate) -> Option<String> {
+        let progress = matches!(
+            update,
+            TestUpdate::PingProgress(_)
+                | TestUpdate::DownloadProgress(_)
+                | TestUpdate::UploadProgress(_)
+                | TestUpdate::BidirectionalProgress(_)
+        );
+        if progress {
+            if self.last_progress.is_some_and(|at| at.elapsed() < ANNOUNCE_INTERVAL) {
+                return None;
+            }
+            self.last_progress = Some(Instant::now());
+        } else {
+            self.last_progress = None;
+        }
+        describe(update)
+    }
+}
+
+fn describe(update: &TestUpdate) -> Option<String> {
+    let sentence = match update {
+        TestUpdate::PingProgress(p) => {
+            format!("Measuring latency, latest {}.", milliseconds(p.latest_ping?))
+        }
+        TestUpdate::DownloadProgress(p) => format!(
+            "Download {} percent done, {}.",
+            percent(p.downloaded_bytes, p.total_bytes),
+            megabits(p.speed_mbps)
+        ),
+        TestUpdate::UploadProgress(p) => format!(
+            "Upload {} percent done, {}.",
+            percent(p.uploaded_bytes, p.total_bytes),
+            megabits(p.speed_mbps)
+        ),
+        TestUpdate::BidirectionalProgress(p) => format!(
+            "Both directions {:.0} percent done, download {}, upload {}.",
+            p.fraction * 100.0,
+            megabits(p.download_mbps),
+            megabits(p.upload_mbps)
+        ),
+        TestUpdate::PingComplete { avg_ms, jitter_ms, loss_percent, .. } => format!(
+            "Latency finished: {}, jitter {}, {:.0} percent loss.",
+            milliseconds(*avg_ms),
+            milliseconds(*jitter_ms),
+            loss_percent
+        ),
+        TestUpdate::DownloadComplete { speed_mbps, .. } => {
+            format!("Download finished: {}.", megabits(*speed_mbps))
+        }
+        TestUpdate::UploadComplete { speed_mbps, .. } => {
+            format!("Upload finished: {}.", megabits(*speed_mbps))
+        }
+        TestUpdate::BidirectionalComplete(result) => format!(
+            "Both directions finished: download {}, upload {}.",
+            megabits(result.download_mbps),
+            megabits(result.upload_mbps)
+        ),
+        TestUpdate::Warning { message, .. } => format!("Warning: {}", message),
+        TestUpdate::Failed { error, .. } => format!("Test failed: {}", error),
+    };
+    Some(sentence)
+}
+
+// A finished test in a few sentences, the figures people ask about first
+pub fn summary(result: &SpeedTestResult) -> Vec<String> {
+    let mos = result.mos();
+    let mut sentences = vec![
+        format!("Download speed: {}.", megabits(result.download_mbps)),
+        format!("Upload speed: {}.", megabits(result.upload_mbps)),
+        format!(
+            "Latency: {}, jitter {}.",
+            milliseconds(result.ping_ms),
+            milliseconds(result.jitter_ms)
+        ),
+    ];
+    if result.ping_loss_percent > 0.0 {
+        sentences.push(format!("Packet loss: {:.0} percent.", result.ping_loss_percent));
+    }
+    sentences.push(format!("Call quality: {:.1} out of 5, {}.", mos, mos_label(mos)));
+    sentences
+}
+
+pub fn megabits(mbps: f64) -> String {
+    format!("{:.1} megabits per second", mbps)
+}
+
+pub fn milliseconds(ms: f64) -> String {
+    match ms.round() {
+        1.0 => "1 millisecond".to_string(),
+        ms => format!("{:.0} milliseconds", ms),
+    }
+}
+
+fn percent(done: u64, total: u64) -> u64 {
+    (done.min(total) * 100).checked_div(total).unwrap_or(0)
+}
@@ -0,0 +1,97 @@
+use crate::config::{SyslogConfig, SyslogTarget};
+use anyhow::{Context, Result};
+use ericspeed::SpeedTestResult;
+use std::fmt::Write;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use tracing::info;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+// facility user (1) * 8 + severity informational (6)
+const PRIORITY: u8 = 14;
+const SEVERITY_INFO: u8 = 6;
+// SD-ID of the structured data, under the private enterprise number RFC 5424
+// reserves for examples
+const SD_ID: &str = "ericspeed@32473";
+
+// Logs a finished run as one structured entry: native journal fields, or
+// RFC 5424 structured data for a plain syslog daemon
+pub fn log(config: &SyslogConfig, result: &SpeedTestResult) -> Result<()> {
+    let journald = match config.target {
+        SyslogTarget::Auto => Path::new(JOURNALD_SOCKET).exists(),
+        SyslogTarget::Journald => true,
+        SyslogTarget::Syslog => false,
+    };
+    let (socket_path, entry) = if journald {
+        (JOURNALD_SOCKET, journal_entry(config, result))
+    } else {
+        (SYSLOG_SOCKET, syslog_entry(config, result))
+    };
+
+    let socket = UnixDatagram::unbound()?;
+    socket
+        .send_to(entry.as_bytes(), socket_path)
+        .with_context(|| format!("failed to log the result to {}", socket_path))?;
+    info!(socket = socket_path, "result logged");
+    Ok(())
+}
+
+// Each metric under its own name, so `journalctl -o json` or a syslog
+// parser gets them without scraping the message
+fn fields(result: &SpeedTestResult) -> Vec<(&'static str, f64)> {
+    [
+        ("download_mbps", Some(result.download_mbps)),
+        ("upload_mbps", Some(result.upload_mbps)),
+        ("ping_ms", Some(result.ping_ms)),
+        ("jitter_ms", Some(result.jitter_ms)),
+        ("ping_loss_percent", Some(result.ping_loss_percent)),
+        ("download_loaded_ping_ms", result.download_ping_ms),
+        ("upload_loaded_ping_ms", result.upload_ping_ms),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| Some((name, value?)))
+    .collect()
+}
+
+fn message(result: &SpeedTestResult) -> String {
+    format!(
+        "download {:.1} Mbps, upload {:.1} Mbps, ping {:.1} ms, jitter {:.1} ms",
+        result.download_mbps, result.upload_mbps, result.ping_ms, result.jitter_ms
+    )
+}
+
+// journald's native protocol: one FIELD=value per line, the metrics under
+// ERICSPEED_-prefixed names
+fn journal_entry(config: &SyslogConfig, result: &SpeedTestResult) -> String {
+    let mut entry = format!(
+        "MESSAGE={}\nPRIORITY={}\nSYSLOG_IDENTIFIER={}\n",
+        message(result),
+        SEVERITY_INFO,
+        config.identifier
+    );
+    for (name, value) in fields(result) {
+        let _ = writeln!(entry, "ERICSPEED_{}={}", name.to_ascii_uppercase(), value);
+    }
+    entry
+}
+
+// <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD] MSG, leaving the
+// hostname for the daemon to fill in
+fn syslog_entry(config: &SyslogConfig, result: &SpeedTestResult) -> String {
+    let timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let mut data = format!("[{}", SD_ID);
+    for (name, value) in fields(result) {
+        let _ = write!(data, " {}=\"{}\"", name, value);
+    }
+    data.push(']');
+    format!(
+        "<{}>1 {} - {} {} result {} {}",
+        PRIORITY,
+        timestamp,
+        config.identifier,
+        std::process::id(),
+        data,
+        message(result)
+    )
+}
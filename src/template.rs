@@ -0,0 +1,156 @@
+use chrono::{DateTime, Local};
+use ericspeed::{Settings, SpeedTestResult};
+use std::str::FromStr;
+
+// Placeholders a template can use, with the decimals each shows unless the
+// template asks for others, as in {download:.2}
+const FIELDS: &[(&str, Field, usize)] = &[
+    ("download", Field::Download, 1),
+    ("upload", Field::Upload, 1),
+    ("ping", Field::Ping, 0),
+    ("jitter", Field::Jitter, 1),
+    ("loss", Field::Loss, 1),
+    ("mos", Field::Mos, 1),
+    ("download_ping", Field::DownloadPing, 0),
+    ("upload_ping", Field::UploadPing, 0),
+    ("ttfb", Field::Ttfb, 0),
+    ("server", Field::Server, 0),
+    ("tag", Field::Tag, 0),
+    ("time", Field::Time, 0),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Download,
+    Upload,
+    Ping,
+    Jitter,
+    Loss,
+    Mos,
+    DownloadPing,
+    UploadPing,
+    Ttfb,
+    Server,
+    Tag,
+    Time,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Field { field: Field, decimals: usize },
+}
+
+// A --template string such as "{download} / {upload} @ {ping}ms", parsed up
+// front so a typo fails before the test runs. {{ and }} print braces
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').ok_or("unclosed { in template")?;
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(placeholder(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err("unmatched } in template; write }} for a brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+        parts.retain(|part| !matches!(part, Part::Text(text) if text.is_empty()));
+        Ok(Self { parts })
+    }
+}
+
+// "name" or "name:.N"
+fn placeholder(spec: &str) -> Result<Part, String> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name.trim(), Some(format)),
+        None => (spec.trim(), None),
+    };
+    let &(_, field, default) = FIELDS.iter().find(|(known, ..)| *known == name).ok_or_else(|| {
+        let known: Vec<&str> = FIELDS.iter().map(|(name, ..)| *name).collect();
+        format!("unknown placeholder {{{}}}; use one of {}", name, known.join(", "))
+    })?;
+    let decimals = match format {
+        Some(format) => format
+            .strip_prefix('.')
+            .and_then(|decimals| decimals.parse().ok())
+            .ok_or_else(|| format!("expected {{{}:.N}} for N decimals", name))?,
+        None => default,
+    };
+    Ok(Part::Field { field, decimals })
+}
+
+impl Template {
+    // The template with every placeholder filled in, for a result just measured
+    pub fn render(&self, result: &SpeedTestResult, settings: &Settings) -> String {
+        self.render_at(result, settings, Local::now())
+    }
+
+    // Same, for a result measured at `time`
+    pub fn render_at(
+        &self,
+        result: &SpeedTestResult,
+        settings: &Settings,
+        time: DateTime<Local>,
+    ) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => output.push_str(text),
+                Part::Field { field, decimals } => {
+                    output.push_str(&field.render(result, settings, time, *decimals));
+                }
+            }
+        }
+        output
+    }
+}
+
+impl Field {
+    // Figures a test didn't measure, like loaded latency, print as "-"
+    fn render(
+        self,
+        result: &SpeedTestResult,
+        settings: &Settings,
+        time: DateTime<Local>,
+        decimals: usize,
+    ) -> String {
+        let value = match self {
+            Field::Download => Some(result.download_mbps),
+            Field::Upload => Some(result.upload_mbps),
+            Field::Ping => Some(result.ping_ms),
+            Field::Jitter => Some(result.jitter_ms),
+            Field::Loss => Some(result.ping_loss_percent),
+            Field::Mos => Some(result.mos()),
+            Field::DownloadPing => result.download_ping_ms,
+            Field::UploadPing => result.upload_ping_ms,
+            Field::Ttfb => result.download_ttfb_ms,
+            Field::Server => return settings.server_url.clone(),
+            Field::Tag => return settings.tag.clone().unwrap_or_else(|| "-".to_string()),
+            Field::Time => return time.format("%Y-%m-%d %H:%M").to_string(),
+        };
+        value.map_or("-".to_string(), |value| format!("{:.*}", decimals, value))
+    }
+}
@@ -0,0 +1,434 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::env;
+use std::sync::OnceLock;
+
+// Languages the TUI can be shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+}
+
+impl Locale {
+    // The language of the first of LC_ALL, LC_MESSAGES and LANG that is set,
+    // like "de_DE.UTF-8"; none for C, POSIX or a language without strings
+    pub fn from_env() -> Option<Locale> {
+        let value = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())?;
+        let language = value.split(['_', '.', '@', '-']).next().unwrap_or_default();
+        match language.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "es" => Some(Locale::Es),
+            _ => None,
+        }
+    }
+}
+
+// The user-facing text of a locale. Help lines keep key names untranslated,
+// since those are what gets pressed
+pub struct Strings {
+    pub ready: &'static str,
+    pub measuring_latency: &'static str,
+    pub testing_download: &'static str,
+    pub testing_upload: &'static str,
+    pub testing_both: &'static str,
+    pub complete: &'static str,
+    pub vs_baseline: &'static str,
+    pub vs_previous: &'static str,
+    pub retry: &'static str,
+    pub retries: &'static str,
+    pub via: &'static str,
+
+    pub download: &'static str,
+    pub upload: &'static str,
+    pub latency: &'static str,
+
+    pub tab_test: &'static str,
+    pub tab_ping: &'static str,
+    pub tab_traffic: &'static str,
+    pub tab_history: &'static str,
+    pub tab_servers: &'static str,
+    pub tab_settings: &'static str,
+
+    pub help_idle: &'static str,
+    pub help_complete: &'static str,
+    pub help_running: &'static str,
+    pub help_expanded: &'static str,
+    pub help_settings: &'static str,
+    pub help_tag_input: &'static str,
+    pub help_ping: &'static str,
+    pub help_traffic: &'static str,
+    pub help_history: &'static str,
+    pub help_time_of_day: &'static str,
+    pub help_compare: &'static str,
+    pub help_comparing: &'static str,
+
+    // Notices; the details, if any, follow after a colon
+    pub notice_busy: &'static str,
+    pub notice_profile: &'static str,
+    pub notice_profile_saved: &'static str,
+    pub notice_profile_failed: &'static str,
+    pub notice_history_load_failed: &'static str,
+    pub notice_history_save_failed: &'static str,
+    pub notice_baseline_saved: &'static str,
+    pub notice_baseline_save_failed: &'static str,
+    pub notice_baseline_cleared: &'static str,
+    pub notice_baseline_clear_failed: &'static str,
+    pub notice_samples_saved: &'static str,
+    pub notice_export_failed: &'static str,
+    pub notice_warning: &'static str,
+    pub notice_test_failed: &'static str,
+    pub notice_below_expectations: &'static str,
+    pub notice_demo_compare: &'static str,
+    pub notice_monitor_unavailable: &'static str,
+
+    // Table headings fit their columns
+    pub column_when: &'static str,
+    pub column_ping: &'static str,
+    pub column_jitter: &'static str,
+    pub column_of_plan: &'static str,
+    pub column_tag: &'static str,
+    pub column_network: &'static str,
+    pub column_server: &'static str,
+    pub column_status: &'static str,
+    pub column_internet: &'static str,
+    pub column_gateway: &'static str,
+    pub no_results: &'static str,
+    pub no_interface_counters: &'static str,
+    pub server_waiting: &'static str,
+    pub server_done: &'static str,
+
+    // Settings labels fit in 15 columns
+    pub setting_tag: &'static str,
+    pub setting_ping_count: &'static str,
+    pub setting_ping_mode: &'static str,
+    pub setting_download_size: &'static str,
+    pub setting_upload_size: &'static str,
+    pub setting_connection_reuse: &'static str,
+    pub setting_bidirectional: &'static str,
+    pub setting_early_exit: &'static str,
+    pub setting_download_streams: &'static str,
+    pub setting_ramp_up: &'static str,
+    pub setting_download_chunks: &'static str,
+    pub setting_upload_streams: &'static str,
+    pub setting_source_address: &'static str,
+    pub setting_rate_limit: &'static str,
+    pub setting_jitter: &'static str,
+    pub setting_ping_outliers: &'static str,
+    pub setting_min_download: &'static str,
+    pub setting_min_upload: &'static str,
+    pub setting_max_ping: &'static str,
+    pub setting_alert_bell: &'static str,
+    pub setting_idle_ping: &'static str,
+    pub setting_chart_marker: &'static str,
+}
+
+const ENGLISH: Strings = Strings {
+    ready: "Ready",
+    measuring_latency: "Measuring latency...",
+    testing_download: "Testing download...",
+    testing_upload: "Testing upload...",
+    testing_both: "Testing both directions...",
+    complete: "Complete",
+    vs_baseline: "vs baseline",
+    vs_previous: "vs previous",
+    retry: "retry",
+    retries: "retries",
+    via: "via",
+
+    download: "Download",
+    upload: "Upload",
+    latency: "Latency",
+
+    tab_test: "Test",
+    tab_ping: "Ping",
+    tab_traffic: "Traffic",
+    tab_history: "History",
+    tab_servers: "Servers",
+    tab_settings: "Settings",
+
+    help_idle: "enter start · 1-6 tabs · p profile · tab select · space expand · q quit",
+    help_complete: "enter start · 1-6 tabs · e export · b baseline · r qr code · tab select · \
+                    space expand · q quit",
+    help_running: "tab select · space expand · esc cancel · q quit",
+    help_expanded: "+/- zoom · ←→ pan · 0 reset · L log scale · esc close · q quit",
+    help_settings: "↑↓ select · ←→ adjust · p profile · P save profile · enter done",
+    help_tag_input: "type a label · enter save · esc cancel",
+    help_ping: "r reset · [ ] switch tab · esc back",
+    help_traffic: "[ ] switch tab · esc back",
+    help_history: "↑↓ scroll · t time of day · [ ] switch tab · esc back",
+    help_time_of_day: "t results · [ ] switch tab · esc back",
+    help_compare: "enter start · m mode · esc back",
+    help_comparing: "esc cancel",
+
+    notice_busy: "Not available while a test is running",
+    notice_profile: "Profile",
+    notice_profile_saved: "Saved custom profile to",
+    notice_profile_failed: "Failed to save profile",
+    notice_history_load_failed: "Failed to load history",
+    notice_history_save_failed: "Failed to save history",
+    notice_baseline_saved: "Saved as baseline",
+    notice_baseline_save_failed: "Failed to save baseline",
+    notice_baseline_cleared: "Baseline cleared",
+    notice_baseline_clear_failed: "Failed to clear baseline",
+    notice_samples_saved: "Saved samples to",
+    notice_export_failed: "Export failed",
+    notice_warning: "Warning",
+    notice_test_failed: "Test failed",
+    notice_below_expectations: "Below expectations",
+    notice_demo_compare: "Server comparison is off in demo mode",
+    notice_monitor_unavailable: "Latency monitor unavailable",
+
+    column_when: "When",
+    column_ping: "Ping",
+    column_jitter: "Jitter",
+    column_of_plan: "Of plan",
+    column_tag: "Tag",
+    column_network: "Network",
+    column_server: "Server",
+    column_status: "Status",
+    column_internet: "Internet",
+    column_gateway: "Gateway",
+    no_results: "No results yet",
+    no_interface_counters: "Interface counters are not available on this system",
+    server_waiting: "waiting",
+    server_done: "done",
+
+    setting_tag: "Tag",
+    setting_ping_count: "Ping samples",
+    setting_ping_mode: "Ping mode",
+    setting_download_size: "Download size",
+    setting_upload_size: "Upload size",
+    setting_connection_reuse: "Conn. reuse",
+    setting_bidirectional: "Bidirectional",
+    setting_early_exit: "Early exit",
+    setting_download_streams: "Down streams",
+    setting_ramp_up: "Down ramp-up",
+    setting_download_chunks: "Down chunks",
+    setting_upload_streams: "Up streams",
+    setting_source_address: "Source address",
+    setting_rate_limit: "Bandwidth cap",
+    setting_jitter: "Jitter",
+    setting_ping_outliers: "Ping outliers",
+    setting_min_download: "Min download",
+    setting_min_upload: "Min upload",
+    setting_max_ping: "Max ping",
+    setting_alert_bell: "Alert bell",
+    setting_idle_ping: "Idle ping",
+    setting_chart_marker: "Chart marker",
+};
+
+const GERMAN: Strings = Strings {
+    ready: "Bereit",
+    measuring_latency: "Latenz wird gemessen...",
+    testing_download: "Download wird getestet...",
+    testing_upload: "Upload wird getestet...",
+    testing_both: "Beide Richtungen werden getestet...",
+    complete: "Fertig",
+    vs_baseline: "vs. Basislinie",
+    vs_previous: "vs. vorheriger Test",
+    retry: "Wiederholung",
+    retries: "Wiederholungen",
+    via: "über",
+
+    download: "Download",
+    upload: "Upload",
+    latency: "Latenz",
+
+    tab_test: "Test",
+    tab_ping: "Ping",
+    tab_traffic: "Verkehr",
+    tab_history: "Verlauf",
+    tab_servers: "Server",
+    tab_settings: "Einstellungen",
+
+    help_idle: "enter Start · 1-6 Tabs · p Profil · tab Auswahl · space vergrößern · q Beenden",
+    help_complete: "enter Start · 1-6 Tabs · e Export · b Basislinie · r QR-Code · tab Auswahl · \
+                    space vergrößern · q Beenden",
+    help_running: "tab Auswahl · space vergrößern · esc Abbrechen · q Beenden",
+    help_expanded: "+/- Zoom · ←→ Verschieben · 0 Zurücksetzen · L Log-Skala · esc Schließen · \
+                    q Beenden",
+    help_settings: "↑↓ Auswahl · ←→ Ändern · p Profil · P Profil speichern · enter Fertig",
+    help_tag_input: "Label eingeben · enter Speichern · esc Abbrechen",
+    help_ping: "r Zurücksetzen · [ ] Tab wechseln · esc Zurück",
+    help_traffic: "[ ] Tab wechseln · esc Zurück",
+    help_history: "↑↓ Blättern · t Tageszeit · [ ] Tab wechseln · esc Zurück",
+    help_time_of_day: "t Ergebnisse · [ ] Tab wechseln · esc Zurück",
+    help_compare: "enter Start · m Modus · esc Zurück",
+    help_comparing: "esc Abbrechen",
+
+    notice_busy: "Nicht verfügbar, solange ein Test läuft",
+    notice_profile: "Profil",
+    notice_profile_saved: "Eigenes Profil gespeichert in",
+    notice_profile_failed: "Profil konnte nicht gespeichert werden",
+    notice_history_load_failed: "Verlauf konnte nicht geladen werden",
+    notice_history_save_failed: "Verlauf konnte nicht gespeichert werden",
+    notice_baseline_saved: "Als Basislinie gespeichert",
+    notice_baseline_save_failed: "Basislinie konnte nicht gespeichert werden",
+    notice_baseline_cleared: "Basislinie entfernt",
+    notice_baseline_clear_failed: "Basislinie konnte nicht entfernt werden",
+    notice_samples_saved: "Messwerte gespeichert in",
+    notice_export_failed: "Export fehlgeschlagen",
+    notice_warning: "Warnung",
+    notice_test_failed: "Test fehlgeschlagen",
+    notice_below_expectations: "Unter den Erwartungen",
+    notice_demo_compare: "Serververgleich ist im Demomodus aus",
+    notice_monitor_unavailable: "Latenzmonitor nicht verfügbar",
+
+    column_when: "Wann",
+    column_ping: "Ping",
+    column_jitter: "Jitter",
+    column_of_plan: "Vom Tarif",
+    column_tag: "Label",
+    column_network: "Netzwerk",
+    column_server: "Server",
+    column_status: "Status",
+    column_internet: "Internet",
+    column_gateway: "Gateway",
+    no_results: "Noch keine Ergebnisse",
+    no_interface_counters: "Schnittstellenzähler sind auf diesem System nicht verfügbar",
+    server_waiting: "wartet",
+    server_done: "fertig",
+
+    setting_tag: "Label",
+    setting_ping_count: "Ping-Proben",
+    setting_ping_mode: "Ping-Modus",
+    setting_download_size: "Download-Größe",
+    setting_upload_size: "Upload-Größe",
+    setting_connection_reuse: "Verb. behalten",
+    setting_bidirectional: "Bidirektional",
+    setting_early_exit: "Früher Abbruch",
+    setting_download_streams: "Down-Streams",
+    setting_ramp_up: "Down-Anlauf",
+    setting_download_chunks: "Down-Blöcke",
+    setting_upload_streams: "Up-Streams",
+    setting_source_address: "Quelladresse",
+    setting_rate_limit: "Max. Bandbreite",
+    setting_jitter: "Jitter",
+    setting_ping_outliers: "Ping-Ausreißer",
+    setting_min_download: "Min. Download",
+    setting_min_upload: "Min. Upload",
+    setting_max_ping: "Max. Ping",
+    setting_alert_bell: "Alarmton",
+    setting_idle_ping: "Leerlauf-Ping",
+    setting_chart_marker: "Diagrammpunkt",
+};
+
+const SPANISH: Strings = Strings {
+    ready: "Listo",
+    measuring_latency: "Midiendo latencia...",
+    testing_download: "Probando bajada...",
+    testing_upload: "Probando subida...",
+    testing_both: "Probando ambas direcciones...",
+    complete: "Completado",
+    vs_baseline: "vs. referencia",
+    vs_previous: "vs. anterior",
+    retry: "reintento",
+    retries: "reintentos",
+    via: "vía",
+
+    download: "Bajada",
+    upload: "Subida",
+    latency: "Latencia",
+
+    tab_test: "Prueba",
+    tab_ping: "Ping",
+    tab_traffic: "Tráfico",
+    tab_history: "Historial",
+    tab_servers: "Servidores",
+    tab_settings: "Ajustes",
+
+    help_idle: "enter iniciar · 1-6 pestañas · p perfil · tab seleccionar · space ampliar · \
+                q salir",
+    help_complete: "enter iniciar · 1-6 pestañas · e exportar · b referencia · r código qr · \
+                    tab seleccionar · space ampliar · q salir",
+    help_running: "tab seleccionar · space ampliar · esc cancelar · q salir",
+    help_expanded: "+/- zoom · ←→ desplazar · 0 restablecer · L escala log · esc cerrar · q salir",
+    help_settings: "↑↓ seleccionar · ←→ ajustar · p perfil · P guardar perfil · enter listo",
+    help_tag_input: "escribe una etiqueta · enter guardar · esc cancelar",
+    help_ping: "r restablecer · [ ] cambiar pestaña · esc volver",
+    help_traffic: "[ ] cambiar pestaña · esc volver",
+    help_history: "↑↓ desplazar · t hora del día · [ ] cambiar pestaña · esc volver",
+    help_time_of_day: "t resultados · [ ] cambiar pestaña · esc volver",
+    help_compare: "enter iniciar · m modo · esc volver",
+    help_comparing: "esc cancelar",
+
+    notice_busy: "No disponible mientras se ejecuta una prueba",
+    notice_profile: "Perfil",
+    notice_profile_saved: "Perfil personalizado guardado en",
+    notice_profile_failed: "No se pudo guardar el perfil",
+    notice_history_load_failed: "No se pudo cargar el historial",
+    notice_history_save_failed: "No se pudo guardar el historial",
+    notice_baseline_saved: "Guardado como referencia",
+    notice_baseline_save_failed: "No se pudo guardar la referencia",
+    notice_baseline_cleared: "Referencia borrada",
+    notice_baseline_clear_failed: "No se pudo borrar la referencia",
+    notice_samples_saved: "Muestras guardadas en",
+    notice_export_failed: "Error al exportar",
+    notice_warning: "Aviso",
+    notice_test_failed: "La prueba falló",
+    notice_below_expectations: "Por debajo de lo esperado",
+    notice_demo_compare: "La comparación de servidores está desactivada en modo demo",
+    notice_monitor_unavailable: "Monitor de latencia no disponible",
+
+    column_when: "Cuándo",
+    column_ping: "Ping",
+    column_jitter: "Jitter",
+    column_of_plan: "Del plan",
+    column_tag: "Etiqueta",
+    column_network: "Red",
+    column_server: "Servidor",
+    column_status: "Estado",
+    column_internet: "Internet",
+    column_gateway: "Router",
+    no_results: "Aún no hay resultados",
+    no_interface_counters: "Los contadores de interfaz no están disponibles en este sistema",
+    server_waiting: "esperando",
+    server_done: "hecho",
+
+    setting_tag: "Etiqueta",
+    setting_ping_count: "Muestras ping",
+    setting_ping_mode: "Modo ping",
+    setting_download_size: "Tamaño bajada",
+    setting_upload_size: "Tamaño subida",
+    setting_connection_reuse: "Reusar conexión",
+    setting_bidirectional: "Bidireccional",
+    setting_early_exit: "Salida rápida",
+    setting_download_streams: "Flujos bajada",
+    setting_ramp_up: "Rampa bajada",
+    setting_download_chunks: "Bloques bajada",
+    setting_upload_streams: "Flujos subida",
+    setting_source_address: "Dirección orig.",
+    setting_rate_limit: "Límite banda",
+    setting_jitter: "Jitter",
+    setting_ping_outliers: "Atípicos ping",
+    setting_min_download: "Bajada mín.",
+    setting_min_upload: "Subida mín.",
+    setting_max_ping: "Ping máx.",
+    setting_alert_bell: "Timbre alerta",
+    setting_idle_ping: "Ping en reposo",
+    setting_chart_marker: "Marcador",
+};
+
+static ACTIVE: OnceLock<&'static Strings> = OnceLock::new();
+
+// Picks the language of the UI; only the first call has an effect
+pub fn init(locale: Locale) {
+    let strings = match locale {
+        Locale::En => &ENGLISH,
+        Locale::De => &GERMAN,
+        Locale::Es => &SPANISH,
+    };
+    let _ = ACTIVE.set(strings);
+}
+
+pub fn strings() -> &'static Strings {
+    ACTIVE.get().copied().unwrap_or(&ENGLISH)
+}
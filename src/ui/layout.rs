@@ -1,67 +1,162 @@
-use crate::app::{App, AppView, Panel};
-use crate::settings::SettingsField;
-use crate::speedtest::TestPhase;
+use crate::{export, spoken};
+use crate::app::{App, AppView, ChartViewport, Panel, RowStatus, SettingsField};
+use super::i18n::strings;
+use super::theme::{
+    self, accent, alert, border, border_active, info, info_dim, success, success_dim,
+    text_muted, text_primary, text_secondary, warn,
+};
+use ericspeed::history::{TimeBucket, TimeOfDayStats};
+use ericspeed::speedtest::bidirectional::COLLAPSED_PERCENT;
+use ericspeed::samples::moving_average;
+use ericspeed::settings::ChartMarker;
+use ericspeed::speedtest::compare::CompareMode;
+use ericspeed::speedtest::monitor::MonitorStats;
+use ericspeed::speedtest::mtu;
+use ericspeed::speedtest::ping::{bufferbloat_grade, JitterMethod, PingMode, OUTLIER_MADS};
+use ericspeed::speedtest::quality::mos_label;
+use ericspeed::speedtest::server::Server;
+use ericspeed::speedtest::tcp_info::{TcpStats, ThroughputLimit};
+use ericspeed::alerts::Metric;
+use ericspeed::speedtest::{EXTREME_ASYMMETRY_RATIO, SLOW_SERVER_MS};
+use ericspeed::TestPhase;
 use ratatui::{
     layout::{Alignment, Constraint, Layout, Rect},
     style::{Color, Modifier, Style},
     symbols,
-    text::{Line, Span},
-    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph},
+    text::{Line, Span, Text},
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Cell, Chart, Clear, Dataset, GraphType,
+        Paragraph, Row, Table, TableState, Tabs,
+    },
     Frame,
 };
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+
+const SETTING_ROW_HEIGHT: u16 = 2;
+
+// Samples in the detailed charts' moving average, about a second of transfer
+const MOVING_AVERAGE_WINDOW: usize = 10;
+
+// Gateway share of the median round trip above which latency is blamed on
+// the local network
+const LOCAL_LATENCY_PERCENT: f64 = 50.0;
 
-// Color Palette - Elegant & Minimal
-const ACCENT: Color = Color::Rgb(100, 149, 237);      // Cornflower blue
-const SUCCESS: Color = Color::Rgb(134, 194, 156);     // Soft green
-const SUCCESS_DIM: Color = Color::Rgb(80, 120, 90);
-const INFO: Color = Color::Rgb(147, 180, 220);        // Soft blue
-const INFO_DIM: Color = Color::Rgb(90, 110, 140);
-const WARN: Color = Color::Rgb(220, 180, 130);        // Soft amber
-const TEXT_PRIMARY: Color = Color::Rgb(230, 230, 230);
-const TEXT_SECONDARY: Color = Color::Rgb(160, 160, 160);
-const TEXT_MUTED: Color = Color::Rgb(100, 100, 100);
-const BORDER: Color = Color::Rgb(60, 60, 65);
-const BORDER_ACTIVE: Color = Color::Rgb(100, 100, 110);
+// Values below this are clamped on log scale charts so zero samples stay plottable
+const LOG_FLOOR: f64 = 0.1;
 
 pub fn draw_ui(frame: &mut Frame, app: &App) {
-    let area = frame.area();
+    let chunks = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).split(frame.area());
+    draw_tabs(frame, chunks[0], app);
+    let area = chunks[1];
 
     match app.view {
-        AppView::Main => {
-            if app.expanded {
+        AppView::Test => {
+            if app.accessible {
+                draw_accessible_view(frame, area, app);
+            } else if app.expanded {
                 draw_expanded_view(frame, area, app);
             } else {
                 draw_normal_view(frame, area, app);
             }
         }
+        AppView::Ping => {
+            draw_ping_view(frame, area, app);
+        }
+        AppView::Traffic => {
+            draw_traffic_view(frame, area, app);
+        }
+        AppView::History => {
+            draw_history_view(frame, area, app);
+        }
+        AppView::Servers => {
+            draw_compare_view(frame, area, app);
+        }
         AppView::Settings => {
             draw_settings_view(frame, area, app);
         }
     }
+
+    if app.show_qr {
+        draw_qr_overlay(frame, area, app);
+    }
+
+    if app.debug_overlay {
+        draw_debug_overlay(frame, area, app);
+    }
+
+    theme::strip_colors(frame.buffer_mut());
+}
+
+fn draw_tabs(frame: &mut Frame, area: Rect, app: &App) {
+    let titles = AppView::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, view)| format!("{} {}", i + 1, view.title()));
+    let tabs = Tabs::new(titles)
+        .select(app.view.position())
+        .style(Style::default().fg(text_muted()))
+        .highlight_style(Style::default().fg(accent()).add_modifier(Modifier::BOLD))
+        .divider(Span::styled("·", Style::default().fg(border())));
+    frame.render_widget(tabs, area);
 }
 
 fn draw_normal_view(frame: &mut Frame, area: Rect, app: &App) {
     let chunks = Layout::vertical([
         Constraint::Length(3),
+        Constraint::Length(1),
         Constraint::Min(8),
         Constraint::Length(1),
     ])
     .split(area);
 
     draw_header(frame, chunks[0], app);
+    draw_network_summary(frame, chunks[1], app);
 
     let panels = Layout::horizontal([
         Constraint::Ratio(1, 3),
         Constraint::Ratio(1, 3),
         Constraint::Ratio(1, 3),
     ])
-    .split(chunks[1]);
+    .split(chunks[2]);
 
     draw_download_panel(frame, panels[0], app, app.selected_panel == Panel::Download);
     draw_upload_panel(frame, panels[1], app, app.selected_panel == Panel::Upload);
     draw_ping_panel(frame, panels[2], app, app.selected_panel == Panel::Ping);
 
-    draw_help(frame, chunks[2], app);
+    draw_help(frame, chunks[3], app);
+}
+
+// Screen-reader mode: the announced status lines in place of the charts,
+// newest at the bottom, so a reader following the screen hears each once
+fn draw_accessible_view(frame: &mut Frame, area: Rect, app: &App) {
+    let chunks = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .split(area);
+
+    draw_header(frame, chunks[0], app);
+    draw_network_summary(frame, chunks[1], app);
+
+    let lines: Vec<String> = if !app.announcements.is_empty() {
+        app.announcements.clone()
+    } else if let Some(entry) = app.last_run() {
+        std::iter::once("Last test:".to_string()).chain(spoken::summary(&entry.result)).collect()
+    } else {
+        vec!["Press enter to start a test.".to_string()]
+    };
+    let skip = lines.len().saturating_sub(chunks[2].height as usize);
+    let lines: Vec<Line> =
+        lines.into_iter().skip(skip).map(|line| Line::raw(format!(" {}", line))).collect();
+    frame.render_widget(
+        Paragraph::new(lines).style(Style::default().fg(text_primary())),
+        chunks[2],
+    );
+
+    draw_help(frame, chunks[3], app);
 }
 
 fn draw_expanded_view(frame: &mut Frame, area: Rect, app: &App) {
@@ -86,7 +181,7 @@ fn draw_expanded_view(frame: &mut Frame, area: Rect, app: &App) {
 fn draw_header(frame: &mut Frame, area: Rect, app: &App) {
     let block = Block::default()
         .borders(Borders::BOTTOM)
-        .border_style(Style::default().fg(BORDER));
+        .border_style(Style::default().fg(border()));
 
     let inner = block.inner(area);
     frame.render_widget(block, area);
@@ -94,43 +189,114 @@ fn draw_header(frame: &mut Frame, area: Rect, app: &App) {
     let chunks = Layout::horizontal([
         Constraint::Length(12),
         Constraint::Min(10),
-        Constraint::Length(20),
+        Constraint::Length(27),
     ])
     .split(inner);
 
     // Title
-    let title = Paragraph::new("ericspeed")
-        .style(Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD));
-    frame.render_widget(title, chunks[0]);
+    let mut title = vec![Line::styled(
+        "ericspeed",
+        Style::default().fg(text_primary()).add_modifier(Modifier::BOLD),
+    )];
+    if app.settings.accept_invalid_certs {
+        title.push(Line::styled("insecure TLS", Style::default().fg(alert())));
+    }
+    frame.render_widget(Paragraph::new(title), chunks[0]);
 
     // Status
-    let (status, color) = match app.phase {
-        TestPhase::Idle => ("Ready", TEXT_MUTED),
-        TestPhase::Ping => ("Measuring latency...", WARN),
-        TestPhase::Download => ("Testing download...", SUCCESS),
-        TestPhase::Upload => ("Testing upload...", INFO),
-        TestPhase::Complete => ("Complete", ACCENT),
+    let (status, color) = match app.phase() {
+        TestPhase::Idle => (strings().ready, text_muted()),
+        TestPhase::Ping => (strings().measuring_latency, warn()),
+        TestPhase::Download => (strings().testing_download, success()),
+        TestPhase::Upload => (strings().testing_upload, info()),
+        TestPhase::Bidirectional => (strings().testing_both, warn()),
+        TestPhase::Complete => (strings().complete, accent()),
+    };
+    let mut status = match (app.phase(), &app.baseline, &app.previous) {
+        (TestPhase::Complete, Some(_), _) => format!("{} · {}", status, strings().vs_baseline),
+        (TestPhase::Complete, None, Some(_)) => format!("{} · {}", status, strings().vs_previous),
+        _ => status.to_string(),
     };
+    let retries = app.transfer_stats.as_ref().map_or(0, |stats| stats.snapshot().retries);
+    if retries > 0 && app.phase() != TestPhase::Idle {
+        let noun = if retries == 1 { strings().retry } else { strings().retries };
+        status.push_str(&format!(" · {} {}", retries, noun));
+    }
 
-    let status_text = Paragraph::new(status)
-        .style(Style::default().fg(color))
-        .alignment(Alignment::Center);
+    let mut lines = vec![Line::styled(status, Style::default().fg(color))];
+    let mut details = Vec::new();
+    if let Some(network) = &app.active_network {
+        let via = format!("{} {}", strings().via, network);
+        details.push(Span::styled(via, Style::default().fg(text_muted())));
+    }
+    let ratio = app.result().asymmetry_ratio().filter(|_| app.phase() == TestPhase::Complete);
+    if let Some(ratio) = ratio {
+        if !details.is_empty() {
+            details.push(Span::styled(" · ", Style::default().fg(text_muted())));
+        }
+        let (text, color) = if ratio >= EXTREME_ASYMMETRY_RATIO {
+            (format!("down:up {:.1}:1 upload-starved", ratio), alert())
+        } else {
+            (format!("down:up {:.1}:1", ratio), text_muted())
+        };
+        details.push(Span::styled(text, Style::default().fg(color)));
+    }
+    if !details.is_empty() {
+        lines.push(Line::from(details));
+    }
+    let status_text = Paragraph::new(lines).alignment(Alignment::Center);
     frame.render_widget(status_text, chunks[1]);
 
     // Phase indicator
-    let phase_text = create_phase_text(app.phase);
+    let phase_text = create_phase_text(app.phase(), app.test_runs_bidirectional());
     frame.render_widget(
         Paragraph::new(phase_text).alignment(Alignment::Right),
         chunks[2],
     );
 }
 
-fn create_phase_text(phase: TestPhase) -> Line<'static> {
-    let phases = [
+// One line of context on the connection found at launch: interface,
+// gateway and its round trip, DNS resolver and public address
+fn draw_network_summary(frame: &mut Frame, area: Rect, app: &App) {
+    let muted = Style::default().fg(text_muted());
+    let Some(summary) = &app.network_summary else {
+        let line = Line::styled("checking network…", muted);
+        frame.render_widget(Paragraph::new(line).alignment(Alignment::Center), area);
+        return;
+    };
+
+    let unknown = || "—".to_string();
+    let gateway = match (summary.gateway, summary.gateway_rtt) {
+        (Some(gateway), Some(rtt)) => format!("{} {:.1} ms", gateway, rtt.as_secs_f64() * 1000.0),
+        (Some(gateway), None) => format!("{} no answer", gateway),
+        (None, _) => unknown(),
+    };
+    let fields = [
+        ("interface", summary.network.as_ref().map_or_else(unknown, |n| n.interface.clone())),
+        ("gateway", gateway),
+        ("dns", summary.resolver.map_or_else(unknown, |ip| ip.to_string())),
+        ("public ip", summary.public_ip.clone().unwrap_or_else(unknown)),
+    ];
+    let mut spans = Vec::new();
+    for (i, (label, value)) in fields.into_iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::styled(" · ", Style::default().fg(border())));
+        }
+        spans.push(Span::styled(format!("{} ", label), muted));
+        spans.push(Span::styled(value, Style::default().fg(text_secondary())));
+    }
+    frame.render_widget(Paragraph::new(Line::from(spans)).alignment(Alignment::Center), area);
+}
+
+fn create_phase_text(phase: TestPhase, bidirectional: bool) -> Line<'static> {
+    let mut phases = vec![
         (TestPhase::Ping, "ping"),
         (TestPhase::Download, "down"),
         (TestPhase::Upload, "up"),
     ];
+    if bidirectional {
+        phases.push((TestPhase::Bidirectional, "both"));
+    }
 
     let mut spans = Vec::new();
 
@@ -139,22 +305,23 @@ fn create_phase_text(phase: TestPhase) -> Line<'static> {
         let is_complete = match phase {
             TestPhase::Download => *p == TestPhase::Ping,
             TestPhase::Upload => *p == TestPhase::Ping || *p == TestPhase::Download,
+            TestPhase::Bidirectional => *p != TestPhase::Bidirectional,
             TestPhase::Complete => true,
             _ => false,
         };
 
         let style = if is_active {
-            Style::default().fg(ACCENT).add_modifier(Modifier::BOLD)
+            Style::default().fg(accent()).add_modifier(Modifier::BOLD)
         } else if is_complete {
-            Style::default().fg(TEXT_SECONDARY)
+            Style::default().fg(text_secondary())
         } else {
-            Style::default().fg(TEXT_MUTED)
+            Style::default().fg(text_muted())
         };
 
         spans.push(Span::styled(*label, style));
 
         if i < phases.len() - 1 {
-            spans.push(Span::styled(" / ", Style::default().fg(TEXT_MUTED)));
+            spans.push(Span::styled(" / ", Style::default().fg(text_muted())));
         }
     }
 
@@ -163,42 +330,74 @@ fn create_phase_text(phase: TestPhase) -> Line<'static> {
 
 // Panels
 fn draw_download_panel(frame: &mut Frame, area: Rect, app: &App, selected: bool) {
+    let value = match app.last_run() {
+        Some(entry) => last_run_text(format_speed(entry.result.download_mbps), entry.timestamp),
+        None => Text::from_iter(
+            [speed_line(
+                get_current_download_speed(app),
+                is_violated(app, Metric::Download),
+                app.result().download_margin_mbps,
+                speed_delta(app.result().download_mbps, app.reference().map(|r| r.download_mbps)),
+                app.settings.plan.map(|plan| plan.download_mbps),
+            )]
+            .into_iter()
+            .chain(bidirectional_line(app, Metric::Download)),
+        ),
+    };
     draw_metric_panel(
         frame,
         area,
-        "Download",
-        SUCCESS,
-        SUCCESS_DIM,
+        strings().download,
+        success(),
+        success_dim(),
         selected,
-        get_current_download_speed(app),
+        value,
         calculate_download_progress(app),
         &app.download_samples,
+        app.baseline.as_ref().map(|b| b.download_mbps),
+        app.settings.chart_marker,
     );
 }
 
 fn draw_upload_panel(frame: &mut Frame, area: Rect, app: &App, selected: bool) {
+    let value = match app.last_run() {
+        Some(entry) => last_run_text(format_speed(entry.result.upload_mbps), entry.timestamp),
+        None => Text::from_iter(
+            [speed_line(
+                get_current_upload_speed(app),
+                is_violated(app, Metric::Upload),
+                app.result().upload_margin_mbps,
+                speed_delta(app.result().upload_mbps, app.reference().map(|r| r.upload_mbps)),
+                app.settings.plan.map(|plan| plan.upload_mbps),
+            )]
+            .into_iter()
+            .chain(bidirectional_line(app, Metric::Upload)),
+        ),
+    };
     draw_metric_panel(
         frame,
         area,
-        "Upload",
-        INFO,
-        INFO_DIM,
+        strings().upload,
+        info(),
+        info_dim(),
         selected,
-        get_current_upload_speed(app),
+        value,
         calculate_upload_progress(app),
         &app.upload_samples,
+        app.baseline.as_ref().map(|b| b.upload_mbps),
+        app.settings.chart_marker,
     );
 }
 
 fn draw_ping_panel(frame: &mut Frame, area: Rect, app: &App, selected: bool) {
-    let border_color = if selected { BORDER_ACTIVE } else { BORDER };
+    let border_color = if selected { border_active() } else { border() };
 
     let block = Block::default()
         .borders(Borders::ALL)
         .border_style(Style::default().fg(border_color))
         .title(Span::styled(
-            " Latency ",
-            Style::default().fg(if selected { WARN } else { TEXT_SECONDARY }),
+            format!(" {} ", strings().latency),
+            Style::default().fg(if selected { warn() } else { text_secondary() }),
         ));
 
     let inner = block.inner(area);
@@ -207,44 +406,138 @@ fn draw_ping_panel(frame: &mut Frame, area: Rect, app: &App, selected: bool) {
     let chunks = Layout::vertical([
         Constraint::Length(2),
         Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Length(1),
         Constraint::Min(3),
     ])
     .split(inner);
 
     // Value
     let ping = get_current_ping(app);
-    let value = if ping > 0.0 {
+    let mut value = vec![Span::raw(if ping > 0.0 {
         format!("{:.0} ms", ping)
     } else {
         "—".to_string()
+    })];
+    if ping > 0.0 {
+        value.extend(app.result().ping_margin_ms.map(|ms| margin_span(format!("{:.1}", ms))));
+    }
+    value.extend(latency_delta(app.result().ping_ms, app.reference().map(|r| r.ping_ms)));
+    let value = match app.last_run() {
+        Some(entry) => last_run_text(format!("{:.0} ms", entry.result.ping_ms), entry.timestamp),
+        None => Text::from(Line::from(value).style(value_style(is_violated(app, Metric::Ping)))),
     };
 
-    frame.render_widget(
-        Paragraph::new(value)
-            .style(Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD))
-            .alignment(Alignment::Center),
-        chunks[0],
-    );
+    frame.render_widget(Paragraph::new(value).alignment(Alignment::Center), chunks[0]);
 
     // Jitter
-    let jitter = if app.result.jitter_ms > 0.0 {
-        format!("jitter {:.1} ms", app.result.jitter_ms)
+    let jitter = if app.result().jitter_ms > 0.0 {
+        format!(
+            "{} · jitter {:.1} ms ({})",
+            app.result().ping_mode.label(),
+            app.result().jitter_ms,
+            app.result().jitter_method.label()
+        )
     } else {
-        "jitter —".to_string()
+        format!("{} · jitter —", app.settings.ping_mode.label())
     };
     frame.render_widget(
         Paragraph::new(jitter)
-            .style(Style::default().fg(TEXT_MUTED))
+            .style(Style::default().fg(text_muted()))
             .alignment(Alignment::Center),
         chunks[1],
     );
 
-    // Chart
-    if !app.ping_samples.is_empty() {
-        draw_sparkline(frame, chunks[2], &app.ping_samples, WARN);
+    // Call quality, once the ping phase is done
+    if app.result().ping_ms > 0.0 {
+        let mos = app.result().mos();
+        let color = if mos >= 4.0 {
+            success()
+        } else if mos >= 3.6 {
+            warn()
+        } else {
+            alert()
+        };
+        let line = Line::from(vec![
+            Span::styled("call quality ", Style::default().fg(text_muted())),
+            Span::styled(
+                format!("{:.1} ", mos),
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(mos_label(mos), Style::default().fg(text_muted())),
+        ]);
+        frame.render_widget(Paragraph::new(line).alignment(Alignment::Center), chunks[2]);
     }
+
+    // Latency under load
+    if let Some(increase) = app.result().latency_increase_ms() {
+        let grade = bufferbloat_grade(increase);
+        let line = Line::from(vec![
+            Span::styled(format!("+{:.0} ms under load ", increase), Style::default().fg(text_muted())),
+            Span::styled(grade.to_string(), grade_style(grade)),
+        ]);
+        frame.render_widget(Paragraph::new(line).alignment(Alignment::Center), chunks[3]);
+    }
+
+    // Time to first byte, flagged when the server rather than the path is slow
+    if let Some(ttfb) = app.result().download_ttfb_ms {
+        let mut line = vec![Span::styled(
+            format!("ttfb {:.0} ms", ttfb),
+            Style::default().fg(text_muted()),
+        )];
+        if app.result().server_delay_ms().is_some_and(|delay| delay > SLOW_SERVER_MS) {
+            line.push(Span::styled(" · slow server", Style::default().fg(alert())));
+        }
+        let line = Paragraph::new(Line::from(line)).alignment(Alignment::Center);
+        frame.render_widget(line, chunks[4]);
+    }
+
+    // Chart, replaced by the live monitor between tests
+    let baseline = app.baseline.as_ref().map(|b| b.ping_ms);
+    if app.wants_monitor() && !app.monitor_samples.is_empty() {
+        let live = Layout::vertical([Constraint::Length(1), Constraint::Min(2)]).split(chunks[5]);
+        let latest = app.monitor_samples.last().copied().unwrap_or(0.0);
+        let mut status = format!("live {:.0} ms · {} lost", latest, app.monitor_stats.lost);
+        if let Some(gateway) = app.gateway_samples.last() {
+            status.push_str(&format!(" · gateway {:.0} ms", gateway));
+        }
+        frame.render_widget(
+            Paragraph::new(status)
+                .style(Style::default().fg(text_muted()))
+                .alignment(Alignment::Center),
+            live[0],
+        );
+        draw_sparkline(
+            frame,
+            live[1],
+            &app.monitor_samples,
+            baseline,
+            warn(),
+            app.settings.chart_marker,
+        );
+    } else if !app.ping_samples.is_empty() {
+        draw_sparkline(
+            frame,
+            chunks[5],
+            &app.ping_samples,
+            baseline,
+            warn(),
+            app.settings.chart_marker,
+        );
+    }
+}
+
+fn grade_style(grade: char) -> Style {
+    let color = match grade {
+        'A' | 'B' => success(),
+        'C' => warn(),
+        _ => alert(),
+    };
+    Style::default().fg(color).add_modifier(Modifier::BOLD)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_metric_panel(
     frame: &mut Frame,
     area: Rect,
@@ -252,18 +545,20 @@ fn draw_metric_panel(
     color: Color,
     dim_color: Color,
     selected: bool,
-    speed: f64,
+    value: Text,
     progress: f64,
     samples: &[f64],
+    baseline: Option<f64>,
+    marker: ChartMarker,
 ) {
-    let border_color = if selected { BORDER_ACTIVE } else { BORDER };
+    let border_color = if selected { border_active() } else { border() };
 
     let block = Block::default()
         .borders(Borders::ALL)
         .border_style(Style::default().fg(border_color))
         .title(Span::styled(
             format!(" {} ", title),
-            Style::default().fg(if selected { color } else { TEXT_SECONDARY }),
+            Style::default().fg(if selected { color } else { text_secondary() }),
         ));
 
     let inner = block.inner(area);
@@ -277,20 +572,22 @@ fn draw_metric_panel(
     .split(inner);
 
     // Speed value
-    let speed_text = format_speed(speed);
-    frame.render_widget(
-        Paragraph::new(speed_text)
-            .style(Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD))
-            .alignment(Alignment::Center),
-        chunks[0],
-    );
+    frame.render_widget(Paragraph::new(value).alignment(Alignment::Center), chunks[0]);
 
     // Progress bar
     draw_progress_bar(frame, chunks[1], progress, color, dim_color);
 
     // Chart
     if !samples.is_empty() {
-        draw_sparkline(frame, chunks[2], samples, color);
+        draw_sparkline(frame, chunks[2], samples, baseline, color, marker);
+    }
+}
+
+fn chart_symbol(marker: ChartMarker) -> symbols::Marker {
+    match marker {
+        ChartMarker::Braille => symbols::Marker::Braille,
+        ChartMarker::Block => symbols::Marker::Block,
+        ChartMarker::Dot => symbols::Marker::Dot,
     }
 }
 
@@ -313,12 +610,23 @@ fn draw_progress_bar(frame: &mut Frame, area: Rect, ratio: f64, color: Color, di
     frame.render_widget(Paragraph::new(bar), area);
 }
 
-fn draw_sparkline(frame: &mut Frame, area: Rect, data: &[f64], color: Color) {
+fn draw_sparkline(
+    frame: &mut Frame,
+    area: Rect,
+    data: &[f64],
+    baseline: Option<f64>,
+    color: Color,
+    marker: ChartMarker,
+) {
     if data.is_empty() || area.width < 4 || area.height < 2 {
         return;
     }
 
-    let (min_val, max_val) = get_data_range(data);
+    let (mut min_val, mut max_val) = get_data_range(data);
+    if let Some(baseline) = baseline {
+        min_val = min_val.min(baseline);
+        max_val = max_val.max(baseline);
+    }
     let range = (max_val - min_val).max(1.0);
 
     let points: Vec<(f64, f64)> = data
@@ -327,13 +635,24 @@ fn draw_sparkline(frame: &mut Frame, area: Rect, data: &[f64], color: Color) {
         .map(|(i, &v)| (i as f64, v))
         .collect();
 
-    let dataset = Dataset::default()
-        .marker(symbols::Marker::Braille)
-        .graph_type(GraphType::Line)
-        .style(Style::default().fg(color))
-        .data(&points);
+    let baseline_points = baseline
+        .map(|b| dashed_line(0.0, data.len() as f64, b))
+        .unwrap_or_default();
+
+    let datasets = vec![
+        Dataset::default()
+            .marker(chart_symbol(marker))
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(color))
+            .data(&points),
+        Dataset::default()
+            .marker(chart_symbol(marker))
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(text_secondary()))
+            .data(&baseline_points),
+    ];
 
-    let chart = Chart::new(vec![dataset])
+    let chart = Chart::new(datasets)
         .x_axis(Axis::default().bounds([0.0, data.len() as f64]))
         .y_axis(Axis::default().bounds([min_val - range * 0.1, max_val + range * 0.1]));
 
@@ -345,13 +664,19 @@ fn draw_download_expanded(frame: &mut Frame, area: Rect, app: &App) {
     draw_expanded_metric(
         frame,
         area,
-        "Download",
-        SUCCESS,
-        SUCCESS_DIM,
+        strings().download,
+        success(),
+        success_dim(),
         get_current_download_speed(app),
         calculate_download_progress(app),
         &app.download_samples,
+        &app.download_times,
+        app.baseline.as_ref().map(|b| b.download_mbps),
+        app.chart_viewport,
+        app.log_scale[Panel::Download.index()],
         "Mbps",
+        app.download_tcp.map(|stats| tcp_line(&stats, TestPhase::Download)),
+        app.settings.chart_marker,
     );
 }
 
@@ -359,21 +684,27 @@ fn draw_upload_expanded(frame: &mut Frame, area: Rect, app: &App) {
     draw_expanded_metric(
         frame,
         area,
-        "Upload",
-        INFO,
-        INFO_DIM,
+        strings().upload,
+        info(),
+        info_dim(),
         get_current_upload_speed(app),
         calculate_upload_progress(app),
         &app.upload_samples,
+        &app.upload_times,
+        app.baseline.as_ref().map(|b| b.upload_mbps),
+        app.chart_viewport,
+        app.log_scale[Panel::Upload.index()],
         "Mbps",
+        app.upload_tcp.map(|stats| tcp_line(&stats, TestPhase::Upload)),
+        app.settings.chart_marker,
     );
 }
 
 fn draw_ping_expanded(frame: &mut Frame, area: Rect, app: &App) {
     let block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(BORDER_ACTIVE))
-        .title(Span::styled(" Latency ", Style::default().fg(WARN)));
+        .border_style(Style::default().fg(border_active()))
+        .title(Span::styled(format!(" {} ", strings().latency), Style::default().fg(warn())));
 
     let inner = block.inner(area);
     frame.render_widget(block, area);
@@ -387,28 +718,75 @@ fn draw_ping_expanded(frame: &mut Frame, area: Rect, app: &App) {
     // Stats
     let ping = get_current_ping(app);
     let (avg, max, min) = get_stats(&app.ping_samples);
-    let jitter = if app.result.jitter_ms > 0.0 {
-        format!("{:.1}", app.result.jitter_ms)
+    let jitter = if app.result().jitter_ms > 0.0 {
+        format!("{:.1}", app.result().jitter_ms)
     } else {
         "—".to_string()
     };
 
-    let stats = Line::from(vec![
-        Span::styled(format!("{:.0} ms", ping), Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
-        Span::styled("  ·  ", Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("jitter {} ms", jitter), Style::default().fg(TEXT_SECONDARY)),
-        Span::styled("  ·  ", Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("avg {:.0}", avg), Style::default().fg(TEXT_MUTED)),
-        Span::styled("  ·  ", Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("max {:.0}", max), Style::default().fg(TEXT_MUTED)),
-        Span::styled("  ·  ", Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("min {:.0}", min), Style::default().fg(TEXT_MUTED)),
+    let mut stats = Line::from(vec![
+        Span::styled(format!("{:.0} ms", ping), Style::default().fg(text_primary()).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format!(" {}", app.settings.ping_mode.label()),
+            Style::default().fg(text_muted()),
+        ),
+        Span::styled("  ·  ", Style::default().fg(text_muted())),
+        Span::styled(
+            format!("jitter {} ms ({})", jitter, app.result().jitter_method.label()),
+            Style::default().fg(text_secondary()),
+        ),
+        Span::styled("  ·  ", Style::default().fg(text_muted())),
+        Span::styled(format!("avg {:.0}", avg), Style::default().fg(text_muted())),
+        Span::styled("  ·  ", Style::default().fg(text_muted())),
+        Span::styled(format!("max {:.0}", max), Style::default().fg(text_muted())),
+        Span::styled("  ·  ", Style::default().fg(text_muted())),
+        Span::styled(format!("min {:.0}", min), Style::default().fg(text_muted())),
     ]);
-    frame.render_widget(Paragraph::new(stats).alignment(Alignment::Center), chunks[0]);
+    if let Some(baseline) = &app.baseline {
+        stats.push_span(Span::styled("  ·  ", Style::default().fg(text_muted())));
+        stats.push_span(Span::styled(
+            format!("baseline {:.0}", baseline.ping_ms),
+            Style::default().fg(text_secondary()),
+        ));
+    }
+
+    let result = app.result();
+    let mut lines = vec![stats];
+    if let Some(increase) = result.latency_increase_ms() {
+        let delta = |loaded: Option<f64>| match loaded {
+            Some(ms) => format!("+{:.0} ms", (ms - result.ping_ms).max(0.0)),
+            None => "—".to_string(),
+        };
+        let grade = bufferbloat_grade(increase);
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!(
+                    "under load: down {}  ·  up {}  ·  bufferbloat ",
+                    delta(result.download_ping_ms),
+                    delta(result.upload_ping_ms)
+                ),
+                Style::default().fg(text_muted()),
+            ),
+            Span::styled(grade.to_string(), grade_style(grade)),
+        ]));
+    }
+    frame.render_widget(Paragraph::new(lines).alignment(Alignment::Center), chunks[0]);
 
-    draw_detailed_chart(frame, chunks[1], &app.ping_samples, WARN, "ms");
+    draw_detailed_chart(
+        frame,
+        chunks[1],
+        &app.ping_samples,
+        &app.ping_times,
+        app.baseline.as_ref().map(|b| b.ping_ms),
+        app.chart_viewport,
+        app.log_scale[Panel::Ping.index()],
+        app.settings.chart_marker,
+        warn(),
+        "ms",
+    );
 }
 
+#[allow(clippy::too_many_arguments)]
 fn draw_expanded_metric(
     frame: &mut Frame,
     area: Rect,
@@ -418,11 +796,17 @@ fn draw_expanded_metric(
     speed: f64,
     progress: f64,
     samples: &[f64],
+    times: &[f64],
+    baseline: Option<f64>,
+    viewport: ChartViewport,
+    log_scale: bool,
     unit: &str,
+    tcp: Option<Line<'static>>,
+    marker: ChartMarker,
 ) {
     let block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(BORDER_ACTIVE))
+        .border_style(Style::default().fg(border_active()))
         .title(Span::styled(format!(" {} ", title), Style::default().fg(color)));
 
     let inner = block.inner(area);
@@ -437,70 +821,166 @@ fn draw_expanded_metric(
 
     // Stats line
     let (avg, max, min) = get_stats(samples);
-    let stats = Line::from(vec![
-        Span::styled(format_speed(speed), Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
-        Span::styled("  ·  ", Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("avg {}", format_speed(avg)), Style::default().fg(TEXT_MUTED)),
-        Span::styled("  ·  ", Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("max {}", format_speed(max)), Style::default().fg(TEXT_MUTED)),
-        Span::styled("  ·  ", Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("min {}", format_speed(min)), Style::default().fg(TEXT_MUTED)),
+    let mut stats = Line::from(vec![
+        Span::styled(format_speed(speed), Style::default().fg(text_primary()).add_modifier(Modifier::BOLD)),
+        Span::styled("  ·  ", Style::default().fg(text_muted())),
+        Span::styled(format!("avg {}", format_speed(avg)), Style::default().fg(text_muted())),
+        Span::styled("  ·  ", Style::default().fg(text_muted())),
+        Span::styled(format!("max {}", format_speed(max)), Style::default().fg(text_muted())),
+        Span::styled("  ·  ", Style::default().fg(text_muted())),
+        Span::styled(format!("min {}", format_speed(min)), Style::default().fg(text_muted())),
     ]);
-    frame.render_widget(Paragraph::new(stats).alignment(Alignment::Center), chunks[0]);
+    if let Some(baseline) = baseline {
+        stats.push_span(Span::styled("  ·  ", Style::default().fg(text_muted())));
+        stats.push_span(Span::styled(
+            format!("baseline {}", format_speed(baseline)),
+            Style::default().fg(text_secondary()),
+        ));
+    }
+    let mut lines = vec![stats];
+    lines.extend(tcp);
+    frame.render_widget(Paragraph::new(lines).alignment(Alignment::Center), chunks[0]);
 
     // Progress
     draw_progress_bar(frame, chunks[1], progress, color, dim_color);
 
     // Chart
-    draw_detailed_chart(frame, chunks[2], samples, color, unit);
+    draw_detailed_chart(
+        frame,
+        chunks[2],
+        samples,
+        times,
+        baseline,
+        viewport,
+        log_scale,
+        marker,
+        color,
+        unit,
+    );
 }
 
-fn draw_detailed_chart(frame: &mut Frame, area: Rect, data: &[f64], color: Color, unit: &str) {
-    if data.is_empty() || area.width < 10 || area.height < 3 {
+// Retransmissions and what limited the transfer, from the kernel's TCP
+// statistics. Downloads only show reordering, which isn't loss
+fn tcp_line(stats: &TcpStats, phase: TestPhase) -> Line<'static> {
+    let lost = match stats.loss_percent(phase) {
+        Some(percent) => format!("{} retransmitted ({:.2}%)", stats.retransmits, percent),
+        None => format!("{} out of order", stats.out_of_order),
+    };
+    let mut spans = vec![Span::styled(
+        format!(
+            "tcp: {}  ·  rtt {:.1} ms  ·  cwnd {}  ·  delivery {:.0} Mbps",
+            lost, stats.rtt_ms, stats.cwnd, stats.delivery_rate_mbps
+        ),
+        Style::default().fg(text_muted()),
+    )];
+    if let Some(limit) = stats.limit(phase) {
+        let (limit, color) = match limit {
+            ThroughputLimit::Loss => ("loss-limited", alert()),
+            ThroughputLimit::Latency => ("latency-limited", text_secondary()),
+        };
+        spans.push(Span::styled("  ·  ", Style::default().fg(text_muted())));
+        spans.push(Span::styled(limit, Style::default().fg(color)));
+    }
+    Line::from(spans)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_detailed_chart(
+    frame: &mut Frame,
+    area: Rect,
+    samples: &[f64],
+    times: &[f64],
+    baseline: Option<f64>,
+    viewport: ChartViewport,
+    log_scale: bool,
+    marker: ChartMarker,
+    color: Color,
+    unit: &str,
+) {
+    if samples.is_empty() || samples.len() != times.len() || area.width < 10 || area.height < 3 {
         return;
     }
 
-    let (min_val, max_val) = get_data_range(data);
+    // The x-axis is seconds since the phase started
+    let (start, data) = viewport.visible(samples);
+    let times = &times[start..start + data.len()];
+    let x_min = times[0];
+    let x_max = times[times.len() - 1].max(x_min + 0.1);
+
+    // On log scale everything is plotted as log10 and mapped back for the labels
+    let to_axis = |v: f64| if log_scale { v.max(LOG_FLOOR).log10() } else { v };
+    let from_axis = |v: f64| if log_scale { 10f64.powf(v) } else { v };
+
+    let scaled: Vec<f64> = data.iter().map(|&v| to_axis(v)).collect();
+    let (mut min_val, mut max_val) = get_data_range(&scaled);
+    if let Some(baseline) = baseline.map(to_axis) {
+        min_val = min_val.min(baseline);
+        max_val = max_val.max(baseline);
+    }
     let range = (max_val - min_val).max(0.1);
-    let y_min = (min_val - range * 0.1).max(0.0);
+    let y_min = if log_scale {
+        min_val - range * 0.1
+    } else {
+        (min_val - range * 0.1).max(0.0)
+    };
     let y_max = max_val + range * 0.1;
 
-    let points: Vec<(f64, f64)> = data
+    let points: Vec<(f64, f64)> = times.iter().copied().zip(scaled.iter().copied()).collect();
+
+    // Averaged over all samples so the trend at the left edge has history behind it
+    let trend = moving_average(samples, MOVING_AVERAGE_WINDOW);
+    let trend_line: Vec<(f64, f64)> = times
         .iter()
-        .enumerate()
-        .map(|(i, &v)| (i as f64, v))
+        .copied()
+        .zip(trend[start..start + data.len()].iter().map(|&v| to_axis(v)))
         .collect();
-
-    let avg = if !data.is_empty() { data.iter().sum::<f64>() / data.len() as f64 } else { 0.0 };
-    let avg_line: Vec<(f64, f64)> = vec![(0.0, avg), (data.len() as f64, avg)];
+    let baseline_points = baseline
+        .map(|b| dashed_line(x_min, x_max, to_axis(b)))
+        .unwrap_or_default();
 
     let datasets = vec![
         Dataset::default()
-            .marker(symbols::Marker::Braille)
+            .marker(chart_symbol(marker))
             .graph_type(GraphType::Line)
             .style(Style::default().fg(color))
             .data(&points),
         Dataset::default()
-            .marker(symbols::Marker::Braille)
+            .marker(chart_symbol(marker))
             .graph_type(GraphType::Line)
-            .style(Style::default().fg(TEXT_MUTED))
-            .data(&avg_line),
+            .style(Style::default().fg(text_primary()))
+            .data(&trend_line),
+        Dataset::default()
+            .marker(chart_symbol(marker))
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(text_secondary()))
+            .data(&baseline_points),
     ];
 
+    let scale_suffix = if log_scale { " log" } else { "" };
     let y_labels = vec![
-        Span::styled(format!("{:.0}", y_min), Style::default().fg(TEXT_MUTED)),
-        Span::styled(format!("{:.0} {}", y_max, unit), Style::default().fg(TEXT_MUTED)),
+        Span::styled(format_axis_value(from_axis(y_min)), Style::default().fg(text_muted())),
+        Span::styled(
+            format!("{} {}{}", format_axis_value(from_axis(y_max)), unit, scale_suffix),
+            Style::default().fg(text_muted()),
+        ),
+    ];
+
+    let x_labels = vec![
+        Span::styled(format_seconds(x_min), Style::default().fg(text_muted())),
+        Span::styled(format_seconds((x_min + x_max) / 2.0), Style::default().fg(text_muted())),
+        Span::styled(format_seconds(x_max), Style::default().fg(text_muted())),
     ];
 
     let chart = Chart::new(datasets)
         .x_axis(
             Axis::default()
-                .style(Style::default().fg(BORDER))
-                .bounds([0.0, data.len() as f64]),
+                .style(Style::default().fg(border()))
+                .bounds([x_min, x_max])
+                .labels(x_labels),
         )
         .y_axis(
             Axis::default()
-                .style(Style::default().fg(BORDER))
+                .style(Style::default().fg(border()))
                 .bounds([y_min, y_max])
                 .labels(y_labels),
         );
@@ -508,6 +988,16 @@ fn draw_detailed_chart(frame: &mut Frame, area: Rect, data: &[f64], color: Color
     frame.render_widget(chart, area);
 }
 
+// Points along a horizontal line with regular gaps, drawn as a scatter so it reads as dashed
+fn dashed_line(x_min: f64, x_max: f64, y: f64) -> Vec<(f64, f64)> {
+    const DOTS: usize = 160;
+    let step = (x_max - x_min) / DOTS as f64;
+    (0..=DOTS)
+        .filter(|i| i % 8 < 5)
+        .map(|i| (x_min + i as f64 * step, y))
+        .collect()
+}
+
 // Settings
 fn draw_settings_view(frame: &mut Frame, area: Rect, app: &App) {
     let chunks = Layout::vertical([
@@ -520,14 +1010,21 @@ fn draw_settings_view(frame: &mut Frame, area: Rect, app: &App) {
     // Header
     let header_block = Block::default()
         .borders(Borders::BOTTOM)
-        .border_style(Style::default().fg(BORDER));
+        .border_style(Style::default().fg(border()));
     let header_inner = header_block.inner(chunks[0]);
     frame.render_widget(header_block, chunks[0]);
 
+    let header = Layout::horizontal([Constraint::Min(20), Constraint::Length(24)]).split(header_inner);
     frame.render_widget(
-        Paragraph::new("Settings")
-            .style(Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
-        header_inner,
+        Paragraph::new(strings().tab_settings)
+            .style(Style::default().fg(text_primary()).add_modifier(Modifier::BOLD)),
+        header[0],
+    );
+    frame.render_widget(
+        Paragraph::new(format!("profile: {}", app.profile_name().unwrap_or("modified")))
+            .style(Style::default().fg(text_secondary()))
+            .alignment(Alignment::Right),
+        header[1],
     );
 
     // Settings content
@@ -540,50 +1037,161 @@ fn draw_settings_view(frame: &mut Frame, area: Rect, app: &App) {
 
     let block = Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(BORDER));
+        .border_style(Style::default().fg(border()));
     let inner = block.inner(content_area);
     frame.render_widget(block, content_area);
 
-    let rows = Layout::vertical([
-        Constraint::Length(3),
-        Constraint::Length(3),
-        Constraint::Length(3),
-        Constraint::Min(0),
-    ])
+    // Scroll so the selected row stays visible
+    let visible = (inner.height / SETTING_ROW_HEIGHT).max(1) as usize;
+    let first = (app.selected_setting.position() + 1).saturating_sub(visible);
+    let fields = &SettingsField::ALL[first..SettingsField::ALL.len().min(first + visible)];
+
+    let rows = Layout::vertical(
+        fields
+            .iter()
+            .map(|_| Constraint::Length(SETTING_ROW_HEIGHT))
+            .chain([Constraint::Min(0)]),
+    )
     .split(inner);
 
-    draw_setting_row(
-        frame,
-        rows[0],
-        "Ping samples",
-        &format!("{}", app.settings.ping_count),
-        app.selected_setting == SettingsField::PingCount,
-    );
+    for (row, &field) in rows.iter().zip(fields) {
+        draw_setting_row(
+            frame,
+            *row,
+            setting_label(field),
+            &setting_value(app, field),
+            app.selected_setting == field,
+        );
+    }
 
-    draw_setting_row(
-        frame,
-        rows[1],
-        "Download size",
-        &format!("{} MB", app.settings.download_size_mb),
-        app.selected_setting == SettingsField::DownloadSize,
-    );
+    // Help
+    let help = if let Some(notice) = &app.notice {
+        Paragraph::new(notice.as_str()).style(Style::default().fg(accent()))
+    } else if app.tag_input.is_some() {
+        Paragraph::new(strings().help_tag_input).style(Style::default().fg(text_muted()))
+    } else {
+        Paragraph::new(strings().help_settings)
+            .style(Style::default().fg(text_muted()))
+    };
+    frame.render_widget(help.alignment(Alignment::Center), chunks[2]);
+}
 
-    draw_setting_row(
-        frame,
-        rows[2],
-        "Upload size",
-        &format!("{} MB", app.settings.upload_size_mb),
-        app.selected_setting == SettingsField::UploadSize,
-    );
+fn setting_label(field: SettingsField) -> &'static str {
+    let strings = strings();
+    match field {
+        SettingsField::Tag => strings.setting_tag,
+        SettingsField::PingCount => strings.setting_ping_count,
+        SettingsField::PingMode => strings.setting_ping_mode,
+        SettingsField::DownloadSize => strings.setting_download_size,
+        SettingsField::UploadSize => strings.setting_upload_size,
+        SettingsField::ColdConnections => strings.setting_connection_reuse,
+        SettingsField::Bidirectional => strings.setting_bidirectional,
+        SettingsField::EarlyExit => strings.setting_early_exit,
+        SettingsField::DownloadStreams => strings.setting_download_streams,
+        SettingsField::RampUp => strings.setting_ramp_up,
+        SettingsField::DownloadChunks => strings.setting_download_chunks,
+        SettingsField::UploadStreams => strings.setting_upload_streams,
+        SettingsField::SourceAddress => strings.setting_source_address,
+        SettingsField::RateLimit => strings.setting_rate_limit,
+        SettingsField::JitterMethod => strings.setting_jitter,
+        SettingsField::PingOutliers => strings.setting_ping_outliers,
+        SettingsField::MinDownload => strings.setting_min_download,
+        SettingsField::MinUpload => strings.setting_min_upload,
+        SettingsField::MaxPing => strings.setting_max_ping,
+        SettingsField::AlertBell => strings.setting_alert_bell,
+        SettingsField::IdleMonitor => strings.setting_idle_ping,
+        SettingsField::ChartMarker => strings.setting_chart_marker,
+    }
+}
 
-    // Help
-    let help = "↑↓ select · ←→ adjust · enter done";
-    frame.render_widget(
-        Paragraph::new(help)
-            .style(Style::default().fg(TEXT_MUTED))
-            .alignment(Alignment::Center),
-        chunks[2],
-    );
+fn setting_value(app: &App, field: SettingsField) -> String {
+    let settings = &app.settings;
+    match field {
+        SettingsField::Tag => match (&app.tag_input, &settings.tag) {
+            (Some(input), _) => format!("{}▏", input),
+            (None, Some(tag)) => tag.clone(),
+            (None, None) => "none (enter to edit)".to_string(),
+        },
+        SettingsField::PingCount => format!("{}", settings.ping_count),
+        SettingsField::PingMode => match settings.ping_mode {
+            PingMode::Warm => "warm (request round trip)".to_string(),
+            PingMode::Cold => "cold (new connection each)".to_string(),
+            PingMode::Tcp => "tcp (connect time only)".to_string(),
+        },
+        SettingsField::DownloadSize => format!("{} MB", settings.download_size_mb),
+        SettingsField::UploadSize => format!("{} MB", settings.upload_size_mb),
+        SettingsField::ColdConnections => {
+            if settings.cold_connections {
+                "cold (new per phase)".to_string()
+            } else {
+                "warm (shared)".to_string()
+            }
+        }
+        SettingsField::Bidirectional => {
+            if settings.bidirectional {
+                "on (down and up at once)".to_string()
+            } else {
+                "off".to_string()
+            }
+        }
+        SettingsField::EarlyExit => {
+            if settings.early_exit { "on (stop once speed is steady)" } else { "off" }.to_string()
+        }
+        SettingsField::DownloadStreams => format_streams(settings.download_streams),
+        SettingsField::RampUp => {
+            if settings.ramp_up { "on (try 1, 2, 4, 8 streams)" } else { "off" }.to_string()
+        }
+        SettingsField::DownloadChunks => match settings.download_chunk_mb {
+            None => "off (one request per stream)".to_string(),
+            Some(mb) => format!("{} MB requests", mb),
+        },
+        SettingsField::UploadStreams => format_streams(settings.upload_streams),
+        SettingsField::SourceAddress => match settings.local_address {
+            None => "auto".to_string(),
+            Some(addr) => match app.interfaces.iter().find(|iface| iface.addr == addr) {
+                Some(iface) => format!("{} ({})", addr, iface.name),
+                None => addr.to_string(),
+            },
+        },
+        SettingsField::RateLimit => match settings.rate_limit_mbps {
+            None => "off".to_string(),
+            Some(mbps) => format!("{} Mbps", mbps),
+        },
+        SettingsField::JitterMethod => match settings.jitter_method {
+            JitterMethod::StdDev => "std dev of all samples".to_string(),
+            JitterMethod::Ipdv => "ipdv (RFC 3550 style)".to_string(),
+        },
+        SettingsField::PingOutliers => {
+            if settings.filter_ping_outliers {
+                format!("drop beyond {}× MAD", OUTLIER_MADS)
+            } else {
+                "keep".to_string()
+            }
+        }
+        SettingsField::MinDownload => format_limit(settings.thresholds.min_download_mbps, "Mbps"),
+        SettingsField::MinUpload => format_limit(settings.thresholds.min_upload_mbps, "Mbps"),
+        SettingsField::MaxPing => format_limit(settings.thresholds.max_ping_ms, "ms"),
+        SettingsField::AlertBell => if settings.alert_bell { "on" } else { "off" }.to_string(),
+        SettingsField::IdleMonitor => {
+            if settings.idle_monitor { "on (1 probe/s between tests)" } else { "off" }.to_string()
+        }
+        SettingsField::ChartMarker => settings.chart_marker.label().to_string(),
+    }
+}
+
+fn format_streams(streams: usize) -> String {
+    if streams == 1 {
+        "1 connection".to_string()
+    } else {
+        format!("{} connections", streams)
+    }
+}
+
+fn format_limit(limit: Option<f64>, unit: &str) -> String {
+    match limit {
+        Some(value) => format!("{:.0} {}", value, unit),
+        None => "off".to_string(),
+    }
 }
 
 fn draw_setting_row(frame: &mut Frame, area: Rect, label: &str, value: &str, selected: bool) {
@@ -594,9 +1202,9 @@ fn draw_setting_row(frame: &mut Frame, area: Rect, label: &str, value: &str, sel
     .split(area);
 
     let label_style = if selected {
-        Style::default().fg(ACCENT)
+        Style::default().fg(accent())
     } else {
-        Style::default().fg(TEXT_SECONDARY)
+        Style::default().fg(text_secondary())
     };
 
     frame.render_widget(
@@ -611,52 +1219,815 @@ fn draw_setting_row(frame: &mut Frame, area: Rect, label: &str, value: &str, sel
     };
 
     let value_style = if selected {
-        Style::default().fg(TEXT_PRIMARY)
+        Style::default().fg(text_primary())
     } else {
-        Style::default().fg(TEXT_MUTED)
+        Style::default().fg(text_muted())
     };
 
     frame.render_widget(Paragraph::new(value_text).style(value_style), chunks[1]);
 }
 
+// History
+fn draw_ping_view(frame: &mut Frame, area: Rect, app: &App) {
+    let chunks = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(6),
+        Constraint::Length(1),
+    ])
+    .split(area);
+    let stats = &app.monitor_stats;
+    let gateway_stats = &app.gateway_stats;
+
+    // Header
+    let header_block = Block::default()
+        .borders(Borders::BOTTOM)
+        .border_style(Style::default().fg(border()));
+    let header_inner = header_block.inner(chunks[0]);
+    frame.render_widget(header_block, chunks[0]);
+
+    let header = Layout::horizontal([Constraint::Min(20), Constraint::Length(32)]).split(header_inner);
+    let mut title = vec![Line::from(vec![
+        Span::styled(
+            strings().tab_ping,
+            Style::default().fg(text_primary()).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!("  {}", app.settings.server_url),
+            Style::default().fg(text_secondary()),
+        ),
+    ])];
+    title.extend(latency_split_line(stats, gateway_stats));
+    frame.render_widget(Paragraph::new(title), header[0]);
+    let loss_color = if stats.lost > 0 { alert() } else { text_secondary() };
+    frame.render_widget(
+        Paragraph::new(format!(
+            "{} sent · {} lost ({:.1}%)",
+            stats.sent,
+            stats.lost,
+            stats.loss_percent()
+        ))
+        .style(Style::default().fg(loss_color))
+        .alignment(Alignment::Right),
+        header[1],
+    );
+
+    // Chart and percentiles
+    let content = Layout::horizontal([
+        Constraint::Length(2),
+        Constraint::Min(30),
+        Constraint::Length(1),
+        Constraint::Length(30),
+        Constraint::Length(2),
+    ])
+    .split(chunks[1]);
+
+    // The gateway gets a chart of its own once it has been probed
+    let probed_gateway = gateway_stats.sent > 0;
+    let charts = if probed_gateway {
+        Layout::vertical([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).split(content[1])
+    } else {
+        Layout::vertical([Constraint::Min(0)]).split(content[1])
+    };
+    let mut series = vec![("internet", &app.monitor_samples, warn())];
+    if probed_gateway {
+        series.push(("gateway", &app.gateway_samples, accent()));
+    }
+    for ((name, samples, color), area) in series.into_iter().zip(charts.iter()) {
+        let latest = samples
+            .last()
+            .map_or("waiting for replies".to_string(), |ms| format!("latest {:.0} ms", ms));
+        let chart_block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border()))
+            .title(Span::styled(format!(" {} {} ", name, latest), Style::default().fg(color)));
+        let chart_area = chart_block.inner(*area);
+        frame.render_widget(chart_block, *area);
+        draw_sparkline(frame, chart_area, samples, None, color, app.settings.chart_marker);
+    }
+
+    let format_ms = |rtts: &[f64], ms: f64| {
+        if rtts.is_empty() {
+            "—".to_string()
+        } else {
+            format!("{:.1} ms", ms)
+        }
+    };
+    let header = Row::new(vec!["", strings().column_internet, strings().column_gateway])
+        .style(Style::default().fg(text_muted()));
+    let rows = [
+        ("Min", stats.min_ms, gateway_stats.min_ms),
+        ("p50", stats.p50_ms, gateway_stats.p50_ms),
+        ("p90", stats.p90_ms, gateway_stats.p90_ms),
+        ("p99", stats.p99_ms, gateway_stats.p99_ms),
+        ("Max", stats.max_ms, gateway_stats.max_ms),
+    ]
+    .into_iter()
+    .map(|(label, ms, gateway_ms)| {
+        Row::new(vec![
+            Cell::from(label).style(Style::default().fg(text_secondary())),
+            Cell::from(format_ms(&app.monitor_rtts, ms)).style(Style::default().fg(text_primary())),
+            Cell::from(format_ms(&app.gateway_rtts, gateway_ms))
+                .style(Style::default().fg(text_primary())),
+        ])
+    });
+    let widths = [Constraint::Length(4), Constraint::Length(10), Constraint::Length(10)];
+    let table = Table::new(rows, widths).header(header).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border())),
+    );
+    frame.render_widget(table, content[3]);
+
+    // Help
+    let help = match &app.notice {
+        Some(notice) => Paragraph::new(notice.as_str()).style(Style::default().fg(accent())),
+        None => Paragraph::new(strings().help_ping).style(Style::default().fg(text_muted())),
+    };
+    frame.render_widget(help.alignment(Alignment::Center), chunks[2]);
+}
+
+// Share of the median round trip spent getting to the gateway: a large one
+// points at the Wi-Fi or LAN, a small one at the path beyond the router
+fn latency_split_line(internet: &MonitorStats, gateway: &MonitorStats) -> Option<Line<'static>> {
+    if internet.p50_ms <= 0.0 || gateway.sent == gateway.lost {
+        return None;
+    }
+    let local_percent = (gateway.p50_ms / internet.p50_ms * 100.0).min(100.0);
+    let (verdict, color) = if local_percent >= LOCAL_LATENCY_PERCENT {
+        ("mostly on the local network", alert())
+    } else {
+        ("mostly beyond the router", text_secondary())
+    };
+    Some(Line::from(vec![
+        Span::styled(
+            format!("{:.0}% of latency is local · ", local_percent),
+            Style::default().fg(text_muted()),
+        ),
+        Span::styled(verdict, Style::default().fg(color)),
+    ]))
+}
+
+fn draw_traffic_view(frame: &mut Frame, area: Rect, app: &App) {
+    let chunks = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(6),
+        Constraint::Length(1),
+    ])
+    .split(area);
+
+    // Header
+    let header_block = Block::default()
+        .borders(Borders::BOTTOM)
+        .border_style(Style::default().fg(border()));
+    let header_inner = header_block.inner(chunks[0]);
+    frame.render_widget(header_block, chunks[0]);
+
+    let header = Layout::horizontal([Constraint::Min(20), Constraint::Length(32)]).split(header_inner);
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::styled(
+                strings().tab_traffic,
+                Style::default().fg(text_primary()).add_modifier(Modifier::BOLD),
+            ),
+            Span::styled("  all applications", Style::default().fg(text_secondary())),
+        ])),
+        header[0],
+    );
+    frame.render_widget(
+        Paragraph::new(app.traffic_interfaces.join(" · "))
+            .style(Style::default().fg(text_secondary()))
+            .alignment(Alignment::Right),
+        header[1],
+    );
+
+    let content_area = Layout::horizontal([
+        Constraint::Length(2),
+        Constraint::Min(30),
+        Constraint::Length(2),
+    ])
+    .split(chunks[1])[1];
+
+    if app.traffic_interfaces.is_empty() {
+        frame.render_widget(
+            Paragraph::new(strings().no_interface_counters)
+                .style(Style::default().fg(text_muted()))
+                .alignment(Alignment::Center)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(border())),
+                ),
+            content_area,
+        );
+    } else {
+        let rows = Layout::vertical([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).split(content_area);
+        for (area, title, samples, color) in [
+            (rows[0], strings().download, &app.traffic_down, success()),
+            (rows[1], strings().upload, &app.traffic_up, info()),
+        ] {
+            let current = samples.last().copied().unwrap_or(0.0);
+            let peak = samples.iter().copied().fold(0.0, f64::max);
+            let block = Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(border()))
+                .title(Line::from(vec![
+                    Span::styled(format!(" {} ", title), Style::default().fg(color)),
+                    Span::styled(
+                        format!("{} · peak {} ", format_speed(current), format_speed(peak)),
+                        Style::default().fg(text_secondary()),
+                    ),
+                ]));
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            draw_sparkline(frame, inner, samples, None, color, app.settings.chart_marker);
+        }
+    }
+
+    // Help
+    let help = match &app.notice {
+        Some(notice) => Paragraph::new(notice.as_str()).style(Style::default().fg(accent())),
+        None => Paragraph::new(strings().help_traffic).style(Style::default().fg(text_muted())),
+    };
+    frame.render_widget(help.alignment(Alignment::Center), chunks[2]);
+}
+
+fn draw_history_view(frame: &mut Frame, area: Rect, app: &App) {
+    let chunks = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(6),
+        Constraint::Length(1),
+    ])
+    .split(area);
+
+    // Header
+    let header_block = Block::default()
+        .borders(Borders::BOTTOM)
+        .border_style(Style::default().fg(border()));
+    let header_inner = header_block.inner(chunks[0]);
+    frame.render_widget(header_block, chunks[0]);
+
+    let plan = app.settings.plan;
+    let mut summary = format!("{} results", app.history_entries.len());
+    if let Some(plan) = plan.filter(|_| !app.history_entries.is_empty()) {
+        let delivered = app
+            .history_entries
+            .iter()
+            .filter(|entry| plan.delivered(&entry.result))
+            .count();
+        summary.push_str(&format!(" · plan met {}/{}", delivered, app.history_entries.len()));
+    }
+    let header = Layout::horizontal([Constraint::Min(20), Constraint::Length(36)]).split(header_inner);
+    frame.render_widget(
+        Paragraph::new(strings().tab_history)
+            .style(Style::default().fg(text_primary()).add_modifier(Modifier::BOLD)),
+        header[0],
+    );
+    frame.render_widget(
+        Paragraph::new(summary)
+            .style(Style::default().fg(text_secondary()))
+            .alignment(Alignment::Right),
+        header[1],
+    );
+
+    // Table
+    let content_area = Layout::horizontal([
+        Constraint::Length(2),
+        Constraint::Min(30),
+        Constraint::Length(2),
+    ])
+    .split(chunks[1])[1];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(border()));
+
+    if let (Some(stats), false) = (&app.time_of_day, app.history_entries.is_empty()) {
+        draw_time_of_day(frame, content_area, stats);
+    } else if app.history_entries.is_empty() {
+        frame.render_widget(
+            Paragraph::new(strings().no_results)
+                .style(Style::default().fg(text_muted()))
+                .alignment(Alignment::Center)
+                .block(block),
+            content_area,
+        );
+    } else {
+        let rows: Vec<Row> = app
+            .history_entries
+            .iter()
+            .map(|entry| {
+                let when = format_timestamp(entry.timestamp);
+                let result = &entry.result;
+                let mut cells = vec![
+                    Cell::from(when).style(Style::default().fg(text_secondary())),
+                    Cell::from(format_speed(result.download_mbps)),
+                    Cell::from(format_speed(result.upload_mbps)),
+                    Cell::from(format!("{:.0} ms", result.ping_ms)),
+                    Cell::from(format!("{:.1} ms", result.jitter_ms)),
+                ];
+                if let Some(plan) = plan {
+                    let (download, upload) = plan.percent_of_plan(result);
+                    let color = if plan.delivered(result) { success() } else { alert() };
+                    cells.push(
+                        Cell::from(format!("{:.0}/{:.0}%", download, upload))
+                            .style(Style::default().fg(color)),
+                    );
+                }
+                cells.extend([
+                    Cell::from(entry.tag.clone().unwrap_or_default())
+                        .style(Style::default().fg(text_secondary())),
+                    Cell::from(entry.network.as_ref().map(|n| n.to_string()).unwrap_or_default())
+                        .style(Style::default().fg(text_secondary())),
+                ]);
+                Row::new(cells).style(Style::default().fg(text_primary()))
+            })
+            .collect();
+
+        let mut widths = vec![
+            Constraint::Length(17),
+            Constraint::Length(13),
+            Constraint::Length(13),
+            Constraint::Length(8),
+            Constraint::Length(9),
+        ];
+        let strings = strings();
+        let mut titles = vec![
+            strings.column_when,
+            strings.download,
+            strings.upload,
+            strings.column_ping,
+            strings.column_jitter,
+        ];
+        if plan.is_some() {
+            widths.push(Constraint::Length(11));
+            titles.push(strings.column_of_plan);
+        }
+        widths.extend([Constraint::Min(8), Constraint::Min(8)]);
+        titles.extend([strings.column_tag, strings.column_network]);
+
+        let table = Table::new(rows, widths)
+            .header(
+                Row::new(titles)
+                    .style(Style::default().fg(text_muted()))
+                    .bottom_margin(1),
+            )
+        .row_highlight_style(Style::default().fg(accent()).add_modifier(Modifier::BOLD))
+        .block(block);
+        let mut state = TableState::default().with_selected(Some(app.history_selected));
+        frame.render_stateful_widget(table, content_area, &mut state);
+    }
+
+    // Help
+    let help = match &app.notice {
+        Some(notice) => Paragraph::new(notice.as_str()).style(Style::default().fg(accent())),
+        None if app.time_of_day.is_some() => Paragraph::new(strings().help_time_of_day)
+            .style(Style::default().fg(text_muted())),
+        None => Paragraph::new(strings().help_history)
+            .style(Style::default().fg(text_muted())),
+    };
+    frame.render_widget(help.alignment(Alignment::Center), chunks[2]);
+}
+
+// Average download and upload by hour of day and by weekday, as grouped bars
+fn draw_time_of_day(frame: &mut Frame, area: Rect, stats: &TimeOfDayStats) {
+    const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    let rows = Layout::vertical([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).split(area);
+
+    let group = |label: String, bucket: &TimeBucket| {
+        let bar = |mbps: f64, color: Color| {
+            Bar::default()
+                .value(mbps.round() as u64)
+                .text_value(String::new())
+                .style(Style::default().fg(color))
+        };
+        BarGroup::default()
+            .label(Line::from(label).style(Style::default().fg(text_secondary())))
+            .bars(&[bar(bucket.download_mbps, success()), bar(bucket.upload_mbps, info())])
+    };
+    let title = |title: &str| {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border()))
+            .title(Line::from(vec![
+                Span::styled(format!(" {} · ", title), Style::default().fg(text_primary())),
+                Span::styled("download", Style::default().fg(success())),
+                Span::styled(" / ", Style::default().fg(text_muted())),
+                Span::styled("upload ", Style::default().fg(info())),
+            ]))
+    };
+
+    // Two one-column bars and a gap per hour; labels only fit on every other hour
+    let mut hours = BarChart::default()
+        .block(title("By hour"))
+        .bar_width(1)
+        .bar_gap(0)
+        .group_gap(1);
+    for (hour, bucket) in stats.by_hour.iter().enumerate() {
+        let label = if hour % 2 == 0 { format!("{:02}", hour) } else { String::new() };
+        hours = hours.data(group(label, bucket));
+    }
+    frame.render_widget(hours, rows[0]);
+
+    let bar_width = (rows[1].width.saturating_sub(2) / 7).saturating_sub(2) / 2;
+    let mut weekdays = BarChart::default()
+        .block(title("By weekday"))
+        .bar_width(bar_width.max(1))
+        .bar_gap(0)
+        .group_gap(2);
+    for (weekday, bucket) in WEEKDAYS.iter().zip(&stats.by_weekday) {
+        weekdays = weekdays.data(group(weekday.to_string(), bucket));
+    }
+    frame.render_widget(weekdays, rows[1]);
+}
+
+// Server comparison
+fn draw_compare_view(frame: &mut Frame, area: Rect, app: &App) {
+    let chunks = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(6),
+        Constraint::Length(1),
+    ])
+    .split(area);
+
+    // Header
+    let header_block = Block::default()
+        .borders(Borders::BOTTOM)
+        .border_style(Style::default().fg(border()));
+    let header_inner = header_block.inner(chunks[0]);
+    frame.render_widget(header_block, chunks[0]);
+
+    let header = Layout::horizontal([Constraint::Min(20), Constraint::Length(24)]).split(header_inner);
+    frame.render_widget(
+        Paragraph::new(strings().tab_servers)
+            .style(Style::default().fg(text_primary()).add_modifier(Modifier::BOLD)),
+        header[0],
+    );
+    let mode = match app.compare_mode {
+        CompareMode::Concurrent => "concurrent",
+        CompareMode::Sequential => "sequential",
+    };
+    frame.render_widget(
+        Paragraph::new(format!("mode: {}", mode))
+            .style(Style::default().fg(text_secondary()))
+            .alignment(Alignment::Right),
+        header[1],
+    );
+
+    // Table
+    let content_area = Layout::horizontal([
+        Constraint::Length(2),
+        Constraint::Min(30),
+        Constraint::Length(2),
+    ])
+    .split(chunks[1])[1];
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(border()));
+
+    let fastest = app
+        .comparison
+        .iter()
+        .filter(|row| row.status == RowStatus::Done)
+        .map(|row| row.speed_mbps)
+        .fold(0.0, f64::max);
+    let quickest = app
+        .comparison
+        .iter()
+        .filter_map(|row| row.latency_ms)
+        .fold(f64::INFINITY, f64::min);
+
+    let rows: Vec<Row> = app
+        .comparison
+        .iter()
+        .map(|row| {
+            let (status, status_color) = match &row.status {
+                RowStatus::Pending => (strings().server_waiting.to_string(), text_muted()),
+                RowStatus::Running => (format!("{:.0}%", row.progress * 100.0), success()),
+                RowStatus::Done => (strings().server_done.to_string(), text_secondary()),
+                RowStatus::Failed(err) => (err.clone(), warn()),
+            };
+            let speed_style = if row.status == RowStatus::Done && row.speed_mbps >= fastest {
+                Style::default().fg(accent()).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(text_primary())
+            };
+            let (latency, latency_style) = match row.latency_ms {
+                Some(ms) if ms <= quickest => (
+                    format!("{:.1} ms", ms),
+                    Style::default().fg(accent()).add_modifier(Modifier::BOLD),
+                ),
+                Some(ms) => (format!("{:.1} ms", ms), Style::default().fg(text_primary())),
+                None => ("-".to_string(), Style::default().fg(text_muted())),
+            };
+
+            Row::new(vec![
+                Cell::from(server_label(&row.server)).style(Style::default().fg(text_secondary())),
+                Cell::from(latency).style(latency_style),
+                Cell::from(format_speed(row.speed_mbps)).style(speed_style),
+                Cell::from(status).style(Style::default().fg(status_color)),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(16),
+            Constraint::Length(11),
+            Constraint::Length(14),
+            Constraint::Min(10),
+        ],
+    )
+    .header(
+        Row::new(vec![
+            strings().column_server,
+            strings().latency,
+            strings().download,
+            strings().column_status,
+        ])
+            .style(Style::default().fg(text_muted()))
+            .bottom_margin(1),
+    )
+    .block(block);
+    frame.render_widget(table, content_area);
+
+    // Help
+    let help = match &app.notice {
+        Some(notice) => Paragraph::new(notice.as_str()).style(Style::default().fg(accent())),
+        None if app.comparing => {
+            Paragraph::new(strings().help_comparing).style(Style::default().fg(text_muted()))
+        }
+        None => Paragraph::new(strings().help_compare).style(Style::default().fg(text_muted())),
+    };
+    frame.render_widget(help.alignment(Alignment::Center), chunks[2]);
+}
+
+// Plain HTTP is marked, since skipping TLS can make a server look faster
+fn server_label(server: &Server) -> String {
+    if server.is_plain_http() {
+        format!("{} (http)", server.name)
+    } else {
+        server.name.clone()
+    }
+}
+
+fn draw_debug_overlay(frame: &mut Frame, area: Rect, app: &App) {
+    let stats = app
+        .transfer_stats
+        .as_ref()
+        .map(|stats| stats.snapshot())
+        .unwrap_or_default();
+
+    let label = Style::default().fg(text_muted());
+    let value = Style::default().fg(text_primary());
+    let field = |name: &str, text: String| {
+        Line::from(vec![
+            Span::styled(format!("{:<16}", name), label),
+            Span::styled(text, value),
+        ])
+    };
+    let status = |status: Option<u16>| status.map_or("-".to_string(), |s| s.to_string());
+    let path_mtu = match app.result().path_mtu {
+        Some(size) => match mtu::overhead_hint(size) {
+            Some(hint) => format!("{} B ({})", size, hint),
+            None => format!("{} B", size),
+        },
+        None => "-".to_string(),
+    };
+
+    let mut lines = vec![
+        field("phase", format!("{:?}", app.phase())),
+        field("channel backlog", app.update_backlog.to_string()),
+        field("active requests", stats.active_requests().to_string()),
+        field("last status", status(stats.last_status)),
+        field("retries", stats.retries.to_string()),
+        field("errors", stats.errors.to_string()),
+        field("path mtu", path_mtu),
+        Line::from(""),
+    ];
+    if stats.streams.is_empty() {
+        lines.push(Line::styled("no transfers yet", label));
+    }
+    for stream in &stats.streams {
+        lines.push(field(
+            &stream.label,
+            format!(
+                "{} B · {} req ({} active) · {}",
+                stream.bytes,
+                stream.requests,
+                stream.active_requests,
+                status(stream.last_status)
+            ),
+        ));
+    }
+
+    let width = 60.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    let block = Block::default()
+        .title(" debug ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(warn()));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(Paragraph::new(lines).block(block), popup);
+}
+
+fn draw_qr_overlay(frame: &mut Frame, area: Rect, app: &App) {
+    let text = export::share_text(app.result(), app.settings.tag.as_deref());
+    let block = Block::default()
+        .title(" scan to share ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(accent()));
+    let Ok(code) = QrCode::new(text.as_bytes()) else {
+        return;
+    };
+    // Light modules are drawn so the code reads right on a dark background
+    let rendered = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    let lines: Vec<Line> = rendered.lines().map(|line| Line::from(line.to_string())).collect();
+
+    let width = (code.width() as u16 + 10).min(area.width);
+    let height = (lines.len() as u16 + 4).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    frame.render_widget(Clear, popup);
+    let mut content = lines;
+    content.push(Line::from(""));
+    content.push(Line::styled("any key to close", Style::default().fg(text_muted())));
+    frame.render_widget(
+        Paragraph::new(content)
+            .style(Style::default().fg(text_primary()))
+            .alignment(Alignment::Center)
+            .block(block),
+        popup,
+    );
+}
+
 fn draw_help(frame: &mut Frame, area: Rect, app: &App) {
+    if let Some(notice) = &app.notice {
+        frame.render_widget(
+            Paragraph::new(notice.as_str())
+                .style(Style::default().fg(accent()))
+                .alignment(Alignment::Center),
+            area,
+        );
+        return;
+    }
+
     let help = if app.expanded {
-        "esc close · q quit"
+        strings().help_expanded
     } else {
-        match app.phase {
-            TestPhase::Idle | TestPhase::Complete => "enter start · s settings · tab select · space expand · q quit",
-            _ => "tab select · space expand · esc cancel · q quit",
+        match app.phase() {
+            TestPhase::Idle => strings().help_idle,
+            TestPhase::Complete => strings().help_complete,
+            _ => strings().help_running,
         }
     };
 
     frame.render_widget(
         Paragraph::new(help)
-            .style(Style::default().fg(TEXT_MUTED))
+            .style(Style::default().fg(text_muted()))
             .alignment(Alignment::Center),
         area,
     );
 }
 
 // Helpers
+fn is_violated(app: &App, metric: Metric) -> bool {
+    app.phase() == TestPhase::Complete && app.settings.thresholds.violates(app.result(), metric)
+}
+
+fn speed_line(
+    speed: f64,
+    violated: bool,
+    margin_mbps: Option<f64>,
+    delta: Option<Span<'static>>,
+    plan_mbps: Option<f64>,
+) -> Line<'static> {
+    let mut spans = vec![Span::raw(format_speed(speed))];
+    spans.extend(margin_mbps.map(|margin| margin_span(format_margin(speed, margin))));
+    spans.extend(delta);
+    if let Some(plan) = plan_mbps.filter(|_| speed > 0.0) {
+        spans.push(Span::styled(
+            format!("  {:.0}% of plan", speed / plan * 100.0),
+            Style::default().fg(text_muted()).remove_modifier(Modifier::BOLD),
+        ));
+    }
+    Line::from(spans).style(value_style(violated))
+}
+
+// Speed of one direction while the other ran too, and the percentage of its
+// one-way speed it kept; flagged when the link collapsed under the load
+fn bidirectional_line(app: &App, metric: Metric) -> Option<Line<'static>> {
+    let pick = |download: f64, upload: f64| match metric {
+        Metric::Upload => upload,
+        _ => download,
+    };
+    let speed = match (app.phase(), app.result().bidirectional) {
+        (TestPhase::Bidirectional, _) => {
+            app.bidirectional_progress.map(|p| pick(p.download_mbps, p.upload_mbps))?
+        }
+        (_, Some(both)) => pick(both.download_mbps, both.upload_mbps),
+        _ => return None,
+    };
+
+    let mut spans = vec![Span::styled(
+        format!("both ways {}", format_speed(speed)),
+        Style::default().fg(text_secondary()),
+    )];
+    if let Some((download, upload)) = app.result().bidirectional_retained_percent() {
+        let retained = pick(download, upload);
+        let color = if retained < COLLAPSED_PERCENT { alert() } else { text_muted() };
+        spans.push(Span::styled(
+            format!("  {:.0}%", retained),
+            Style::default().fg(color),
+        ));
+    }
+    Some(Line::from(spans))
+}
+
+// Change against the previous run, shown once the metric's final value is in
+fn speed_delta(current: f64, previous: Option<f64>) -> Option<Span<'static>> {
+    let previous = previous.filter(|&p| p > 0.0)?;
+    if current <= 0.0 {
+        return None;
+    }
+    let change = (current - previous) / previous * 100.0;
+    Some(delta_span(format!("{:+.0}%", change), change >= 0.0))
+}
+
+fn latency_delta(current: f64, previous: Option<f64>) -> Option<Span<'static>> {
+    let previous = previous.filter(|&p| p > 0.0)?;
+    if current <= 0.0 {
+        return None;
+    }
+    let change = current - previous;
+    Some(delta_span(format!("{:+.0} ms", change), change <= 0.0))
+}
+
+fn delta_span(text: String, improved: bool) -> Span<'static> {
+    let color = if improved { success_dim() } else { alert() };
+    let style = Style::default().fg(color).remove_modifier(Modifier::BOLD);
+    Span::styled(format!("  {}", text.replace('-', "−")), style)
+}
+
+// The 95% confidence margin next to a headline value, kept faint so it
+// reads as a footnote to the number
+fn margin_span(text: String) -> Span<'static> {
+    let style = Style::default().fg(text_muted()).remove_modifier(Modifier::BOLD);
+    Span::styled(format!(" ±{}", text), style)
+}
+
+// A value from the last saved run, dimmed and dated so it isn't taken for
+// a fresh measurement
+fn last_run_text(value: String, timestamp: u64) -> Text<'static> {
+    Text::from(vec![
+        Line::styled(value, Style::default().fg(text_muted()).add_modifier(Modifier::BOLD)),
+        Line::styled(
+            format!("last test {}", format_timestamp(timestamp)),
+            Style::default().fg(text_muted()),
+        ),
+    ])
+}
+
+fn value_style(violated: bool) -> Style {
+    let color = if violated { alert() } else { text_primary() };
+    Style::default().fg(color).add_modifier(Modifier::BOLD)
+}
+
 fn get_current_download_speed(app: &App) -> f64 {
-    if app.result.download_mbps > 0.0 {
-        app.result.download_mbps
+    if app.result().download_mbps > 0.0 {
+        app.result().download_mbps
     } else {
-        app.download_samples.last().copied().unwrap_or(0.0)
+        app.download_display.value().unwrap_or(0.0)
     }
 }
 
 fn get_current_upload_speed(app: &App) -> f64 {
-    if app.result.upload_mbps > 0.0 {
-        app.result.upload_mbps
+    if app.result().upload_mbps > 0.0 {
+        app.result().upload_mbps
     } else {
-        app.upload_samples.last().copied().unwrap_or(0.0)
+        app.upload_display.value().unwrap_or(0.0)
     }
 }
 
 fn get_current_ping(app: &App) -> f64 {
-    if app.result.ping_ms > 0.0 {
-        app.result.ping_ms
+    if app.result().ping_ms > 0.0 {
+        app.result().ping_ms
     } else {
         app.ping_samples.last().copied().unwrap_or(0.0)
     }
@@ -679,7 +2050,7 @@ fn get_stats(data: &[f64]) -> (f64, f64, f64) {
 }
 
 fn calculate_download_progress(app: &App) -> f64 {
-    match app.phase {
+    match app.phase() {
         TestPhase::Download => app.download_progress,
         TestPhase::Upload | TestPhase::Complete => 1.0,
         _ => 0.0,
@@ -687,13 +2058,46 @@ fn calculate_download_progress(app: &App) -> f64 {
 }
 
 fn calculate_upload_progress(app: &App) -> f64 {
-    match app.phase {
+    match app.phase() {
         TestPhase::Upload => app.upload_progress,
         TestPhase::Complete => 1.0,
         _ => 0.0,
     }
 }
 
+fn format_axis_value(value: f64) -> String {
+    if value < 10.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{:.0}", value)
+    }
+}
+
+fn format_seconds(seconds: f64) -> String {
+    if seconds < 10.0 {
+        format!("{:.1}s", seconds)
+    } else {
+        format!("{:.0}s", seconds)
+    }
+}
+
+// A margin on `speed` in the unit `format_speed` shows it in, without the unit
+fn format_margin(speed: f64, margin_mbps: f64) -> String {
+    if speed >= 1000.0 {
+        format!("{:.1}", margin_mbps / 1000.0)
+    } else if speed >= 1.0 {
+        format!("{:.1}", margin_mbps)
+    } else {
+        format!("{:.0}", margin_mbps * 1000.0)
+    }
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
 fn format_speed(mbps: f64) -> String {
     if mbps >= 1000.0 {
         format!("{:.1} Gbps", mbps / 1000.0)
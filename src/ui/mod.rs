@@ -1,3 +1,5 @@
+pub mod i18n;
 mod layout;
+pub mod theme;
 
 pub use layout::draw_ui;
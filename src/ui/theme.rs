@@ -0,0 +1,153 @@
+use clap::ValueEnum;
+use ratatui::buffer::Buffer;
+use ratatui::style::{Color, Modifier};
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+// Color schemes the TUI can be drawn in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    Default,
+    // Download, upload and latency in blue, orange and purple, which stay
+    // apart under deuteranopia and protanopia, unlike green, blue and amber
+    ColorBlind,
+}
+
+// The colors of a theme. Download, upload and latency are drawn in success,
+// info and warn
+pub struct Palette {
+    pub accent: Color,
+    pub success: Color,
+    pub success_dim: Color,
+    pub info: Color,
+    pub info_dim: Color,
+    pub warn: Color,
+    pub text_primary: Color,
+    pub text_secondary: Color,
+    pub text_muted: Color,
+    pub border: Color,
+    pub border_active: Color,
+    pub alert: Color,
+}
+
+// Elegant & minimal
+const DEFAULT: Palette = Palette {
+    accent: Color::Rgb(100, 149, 237),      // Cornflower blue
+    success: Color::Rgb(134, 194, 156),     // Soft green
+    success_dim: Color::Rgb(80, 120, 90),
+    info: Color::Rgb(147, 180, 220),        // Soft blue
+    info_dim: Color::Rgb(90, 110, 140),
+    warn: Color::Rgb(220, 180, 130),        // Soft amber
+    text_primary: Color::Rgb(230, 230, 230),
+    text_secondary: Color::Rgb(160, 160, 160),
+    text_muted: Color::Rgb(100, 100, 100),
+    border: Color::Rgb(60, 60, 65),
+    border_active: Color::Rgb(100, 100, 110),
+    alert: Color::Rgb(220, 120, 120),       // Soft red
+};
+
+// After the Okabe-Ito palette
+const COLOR_BLIND: Palette = Palette {
+    success: Color::Rgb(86, 180, 233),      // Sky blue
+    success_dim: Color::Rgb(50, 105, 140),
+    info: Color::Rgb(230, 159, 0),          // Orange
+    info_dim: Color::Rgb(140, 95, 10),
+    warn: Color::Rgb(190, 150, 230),        // Soft purple
+    alert: Color::Rgb(213, 94, 0),          // Vermillion
+    ..DEFAULT
+};
+
+struct Active {
+    palette: &'static Palette,
+    // Whether colors are stripped after drawing, leaving bold and dim
+    monochrome: bool,
+}
+
+static ACTIVE: OnceLock<Active> = OnceLock::new();
+
+// Picks the theme the UI is drawn in, or none at all without `color`; only
+// the first call has an effect
+pub fn init(theme: Theme, color: bool) {
+    let palette = match theme {
+        Theme::ColorBlind if color => &COLOR_BLIND,
+        // Monochrome tells colors apart by the default palette's
+        _ => &DEFAULT,
+    };
+    let _ = ACTIVE.set(Active {
+        palette,
+        monochrome: !color,
+    });
+}
+
+fn palette() -> &'static Palette {
+    ACTIVE.get().map_or(&DEFAULT, |active| active.palette)
+}
+
+// Without color, swaps every color drawn into `buffer` for the terminal's
+// default: muted text and borders turn dim, accents and alerts bold
+pub fn strip_colors(buffer: &mut Buffer) {
+    if !ACTIVE.get().is_some_and(|active| active.monochrome) {
+        return;
+    }
+    let dim = [DEFAULT.text_muted, DEFAULT.border, DEFAULT.success_dim, DEFAULT.info_dim];
+    let bold = [DEFAULT.accent, DEFAULT.alert];
+    for cell in buffer.content.iter_mut() {
+        if dim.contains(&cell.fg) {
+            cell.modifier.insert(Modifier::DIM);
+        } else if bold.contains(&cell.fg) {
+            cell.modifier.insert(Modifier::BOLD);
+        }
+        cell.fg = Color::Reset;
+        cell.bg = Color::Reset;
+    }
+}
+
+pub fn accent() -> Color {
+    palette().accent
+}
+
+pub fn success() -> Color {
+    palette().success
+}
+
+pub fn success_dim() -> Color {
+    palette().success_dim
+}
+
+pub fn info() -> Color {
+    palette().info
+}
+
+pub fn info_dim() -> Color {
+    palette().info_dim
+}
+
+pub fn warn() -> Color {
+    palette().warn
+}
+
+pub fn text_primary() -> Color {
+    palette().text_primary
+}
+
+pub fn text_secondary() -> Color {
+    palette().text_secondary
+}
+
+pub fn text_muted() -> Color {
+    palette().text_muted
+}
+
+pub fn border() -> Color {
+    palette().border
+}
+
+pub fn border_active() -> Color {
+    palette().border_active
+}
+
+pub fn alert() -> Color {
+    palette().alert
+}
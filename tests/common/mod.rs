@@ -0,0 +1,192 @@
+// A local stand-in for the test server, serving `__down` and `__up` like
+// Cloudflare but with the latency and bandwidth a test asks for
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+const CHUNK_SIZE: usize = 16 * 1024;
+
+static ZEROS: [u8; CHUNK_SIZE] = [0; CHUNK_SIZE];
+
+// How the stub behaves; the default answers at once at full speed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Link {
+    // Added before every response
+    pub latency: Duration,
+    // Caps each request's body in either direction
+    pub bandwidth_mbps: Option<f64>,
+    // Requests answered with a 503 before any is served
+    pub failures: u32,
+}
+
+impl Link {
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_bandwidth(mut self, mbps: f64) -> Self {
+        self.bandwidth_mbps = Some(mbps);
+        self
+    }
+
+    pub fn with_failures(mut self, failures: u32) -> Self {
+        self.failures = failures;
+        self
+    }
+
+    // Time a chunk of `len` bytes takes at the capped bandwidth
+    fn pace(&self, len: usize) -> Option<Duration> {
+        let mbps = self.bandwidth_mbps?;
+        Some(Duration::from_secs_f64(len as f64 * 8.0 / (mbps * 1_000_000.0)))
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    downloaded: AtomicU64,
+    uploaded: AtomicU64,
+    requests: AtomicU32,
+    // Client addresses downloads came from, one per connection
+    peers: Mutex<HashSet<SocketAddr>>,
+}
+
+#[derive(Clone)]
+struct Shared {
+    link: Link,
+    counters: Arc<Counters>,
+}
+
+impl Shared {
+    // Whether this request is one of the failures the link asks for
+    fn fails(&self) -> bool {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed) < self.link.failures
+    }
+}
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    bytes: Option<u64>,
+}
+
+pub struct StubServer {
+    addr: SocketAddr,
+    counters: Arc<Counters>,
+    task: JoinHandle<()>,
+}
+
+impl StubServer {
+    pub async fn start(link: Link) -> Self {
+        let counters = Arc::new(Counters::default());
+        let shared = Shared {
+            link,
+            counters: Arc::clone(&counters),
+        };
+        let app = Router::new()
+            .route("/__down", get(download))
+            .route("/__up", post(upload))
+            .with_state(shared);
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind stub server");
+        let addr = listener.local_addr().expect("stub server address");
+        let task = tokio::spawn(async move {
+            let app = app.into_make_service_with_connect_info::<SocketAddr>();
+            axum::serve(listener, app).await.expect("stub server failed");
+        });
+        Self {
+            addr,
+            counters,
+            task,
+        }
+    }
+
+    // Base URL to use as the test server
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.counters.downloaded.load(Ordering::Relaxed)
+    }
+
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.counters.uploaded.load(Ordering::Relaxed)
+    }
+
+    // Connections that made at least one download request
+    pub fn download_connections(&self) -> usize {
+        self.counters.peers.lock().unwrap().len()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn download(
+    State(shared): State<Shared>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Query(query): Query<DownloadQuery>,
+) -> Response {
+    shared.counters.peers.lock().unwrap().insert(peer);
+    if shared.fails() {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    tokio::time::sleep(shared.link.latency).await;
+    let bytes = query.bytes.unwrap_or(0);
+    let chunks = stream::iter((0..bytes).step_by(CHUNK_SIZE)).then(move |offset| {
+        let shared = shared.clone();
+        async move {
+            let len = (bytes - offset).min(CHUNK_SIZE as u64) as usize;
+            if let Some(pace) = shared.link.pace(len) {
+                tokio::time::sleep(pace).await;
+            }
+            shared.counters.downloaded.fetch_add(len as u64, Ordering::Relaxed);
+            Ok::<_, Infallible>(Bytes::from_static(&ZEROS[..len]))
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, bytes.to_string()),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+async fn upload(State(shared): State<Shared>, body: Body) -> StatusCode {
+    if shared.fails() {
+        return StatusCode::SERVICE_UNAVAILABLE;
+    }
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let Ok(chunk) = chunk else {
+            return StatusCode::BAD_REQUEST;
+        };
+        if let Some(pace) = shared.link.pace(chunk.len()) {
+            tokio::time::sleep(pace).await;
+        }
+        shared.counters.uploaded.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    }
+    tokio::time::sleep(shared.link.latency).await;
+    StatusCode::OK
+}
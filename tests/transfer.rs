@@ -0,0 +1,355 @@
+mod common;
+
+use common::{Link, StubServer};
+use ericspeed::speedtest::download::DownloadTest;
+use ericspeed::speedtest::mock::MockProvider;
+use ericspeed::speedtest::ping::{PingMode, PingTest};
+use ericspeed::speedtest::retry::RetryPolicy;
+use ericspeed::speedtest::server::{self, Server};
+use ericspeed::speedtest::stats::TransferStats;
+use ericspeed::speedtest::upload::UploadTest;
+use ericspeed::{Settings, SpeedTest, TestPhase, TestUpdate};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+const MB: u64 = 1_000_000;
+
+// Settings for a quick test against `stub`
+fn settings(stub: &StubServer) -> Settings {
+    Settings {
+        server_url: stub.url(),
+        ping_count: 5,
+        download_size_mb: 2,
+        upload_size_mb: 1,
+        detect_captive_portal: false,
+        ..Settings::default()
+    }
+}
+
+// Drains progress from a phase running alongside, returning the latest update
+async fn last<T>(mut progress_rx: mpsc::Receiver<T>) -> Option<T> {
+    let mut last = None;
+    while let Some(progress) = progress_rx.recv().await {
+        last = Some(progress);
+    }
+    last
+}
+
+#[tokio::test]
+async fn download_fetches_every_byte() {
+    let stub = StubServer::start(Link::default()).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, 3 * MB).with_streams(3);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    let result = result.expect("download failed");
+    assert_eq!(result.streams, 3);
+    assert!(result.avg_speed_mbps > 0.0);
+    assert_eq!(stub.downloaded_bytes(), 3 * MB);
+}
+
+#[tokio::test]
+async fn download_in_chunks_fetches_every_byte() {
+    let stub = StubServer::start(Link::default()).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, 3 * MB)
+        .with_streams(2)
+        .with_chunk_size(Some(MB / 2));
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    result.expect("download failed");
+    assert_eq!(stub.downloaded_bytes(), 3 * MB);
+}
+
+#[tokio::test]
+async fn download_retries_server_errors() {
+    let stub = StubServer::start(Link::default().with_failures(2)).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let stats = Arc::new(TransferStats::default());
+    let retry = RetryPolicy {
+        retries: 2,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(10),
+    };
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, MB)
+        .with_retry(retry)
+        .with_stats(Arc::clone(&stats));
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    result.expect("download failed");
+    assert_eq!(stats.snapshot().retries, 2);
+    assert_eq!(stub.downloaded_bytes(), MB);
+}
+
+#[tokio::test]
+async fn download_reports_progress() {
+    let stub = StubServer::start(Link::default().with_bandwidth(40.0)).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, 2 * MB);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, last) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    result.expect("download failed");
+    let last = last.expect("no download progress");
+    assert_eq!(last.total_bytes, 2 * MB);
+    assert!(last.downloaded_bytes > 0 && last.downloaded_bytes <= 2 * MB);
+    assert!(last.speed_mbps > 0.0);
+}
+
+#[tokio::test]
+async fn download_samples_at_the_set_interval() {
+    let stub = StubServer::start(Link::default().with_bandwidth(40.0)).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, 4 * MB)
+        .with_sample_interval(Duration::from_millis(300));
+
+    let (progress_tx, mut progress_rx) = mpsc::channel(32);
+    let count = async move {
+        let mut count = 0;
+        while progress_rx.recv().await.is_some() {
+            count += 1;
+        }
+        count
+    };
+    let (result, count) = tokio::join!(test.run(progress_tx), count);
+
+    // About 0.8 s at the cap, where the default interval would report 8 times
+    result.expect("download failed");
+    assert!((1..=3).contains(&count), "{} progress updates", count);
+}
+
+#[tokio::test]
+async fn download_measures_capped_bandwidth() {
+    let stub = StubServer::start(Link::default().with_bandwidth(40.0)).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, 4 * MB);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    // Loose bounds: the stub only paces roughly, and CI machines are noisy
+    let mbps = result.expect("download failed").avg_speed_mbps;
+    assert!((20.0..=60.0).contains(&mbps), "measured {:.1} Mbps at a 40 Mbps cap", mbps);
+}
+
+#[tokio::test]
+async fn download_stops_at_duration() {
+    let stub = StubServer::start(Link::default().with_bandwidth(8.0)).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, 100 * MB)
+        .with_duration(Some(Duration::from_millis(500)));
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    result.expect("download failed");
+    assert!(stub.downloaded_bytes() < 10 * MB);
+}
+
+#[tokio::test]
+async fn download_times_first_byte() {
+    let stub = StubServer::start(Link::default().with_latency(Duration::from_millis(50))).await;
+    let url = Server::from_base("Stub", &stub.url()).download_url;
+    let mut test = DownloadTest::new(reqwest::Client::new(), url, MB);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    let ttfb = result.expect("download failed").ttfb_ms.expect("no time to first byte");
+    assert!(ttfb >= 50.0, "first byte after {:.1} ms with 50 ms added", ttfb);
+}
+
+#[tokio::test]
+async fn upload_sends_every_byte() {
+    let stub = StubServer::start(Link::default()).await;
+    let mut test = UploadTest::new(reqwest::Client::new(), 2 * MB as usize)
+        .with_url(server::upload_url(&stub.url()))
+        .with_streams(2);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    assert!(result.expect("upload failed").avg_speed_mbps > 0.0);
+    assert_eq!(stub.uploaded_bytes(), 2 * MB);
+}
+
+#[tokio::test]
+async fn upload_fails_when_the_server_rejects_it() {
+    let stub = StubServer::start(Link::default().with_failures(u32::MAX)).await;
+    let mut test = UploadTest::new(reqwest::Client::new(), MB as usize)
+        .with_url(server::upload_url(&stub.url()))
+        .with_retry(RetryPolicy::NONE);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, progress) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    assert!(result.is_err());
+    assert!(progress.is_none_or(|progress| progress.uploaded_bytes == 0));
+    assert_eq!(stub.uploaded_bytes(), 0);
+}
+
+#[tokio::test]
+async fn ping_includes_server_latency() {
+    let stub = StubServer::start(Link::default().with_latency(Duration::from_millis(30))).await;
+    let mut test =
+        PingTest::new(reqwest::Client::new(), 5).with_url(server::ping_url(&stub.url()));
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    let result = result.expect("ping failed");
+    assert!(result.avg_ms >= 30.0, "measured {:.1} ms with 30 ms added", result.avg_ms);
+    assert_eq!(result.loss_percent, 0.0);
+}
+
+#[tokio::test]
+async fn concurrent_pings_shorten_the_phase() {
+    let stub = StubServer::start(Link::default().with_latency(Duration::from_millis(30))).await;
+    let mut test = PingTest::new(reqwest::Client::new(), 8)
+        .with_url(server::ping_url(&stub.url()))
+        .with_interval(Duration::from_millis(100))
+        .with_concurrency(4);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let started = Instant::now();
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    // One after another, 8 probes would take over a second
+    let elapsed = started.elapsed();
+    assert!(elapsed < Duration::from_millis(700), "took {:?}", elapsed);
+    let result = result.expect("ping failed");
+    assert!(result.avg_ms >= 30.0, "measured {:.1} ms with 30 ms added", result.avg_ms);
+    assert_eq!(result.loss_percent, 0.0);
+}
+
+#[tokio::test]
+async fn cold_pings_open_a_connection_each() {
+    for (mode, connections) in [(PingMode::Warm, 1), (PingMode::Cold, 5)] {
+        let stub = StubServer::start(Link::default()).await;
+        let mut test = PingTest::new(reqwest::Client::new(), 5)
+            .with_url(server::ping_url(&stub.url()))
+            .with_interval(Duration::ZERO)
+            .with_mode(mode);
+
+        let (progress_tx, progress_rx) = mpsc::channel(32);
+        let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+        assert_eq!(result.expect("ping failed").loss_percent, 0.0);
+        assert_eq!(stub.download_connections(), connections, "{:?} mode", mode);
+    }
+}
+
+#[tokio::test]
+async fn tcp_pings_skip_the_server() {
+    let stub = StubServer::start(Link::default().with_latency(Duration::from_millis(50))).await;
+    let mut test = PingTest::new(reqwest::Client::new(), 5)
+        .with_url(server::ping_url(&stub.url()))
+        .with_interval(Duration::ZERO)
+        .with_mode(PingMode::Tcp);
+
+    let (progress_tx, progress_rx) = mpsc::channel(32);
+    let (result, _) = tokio::join!(test.run(progress_tx), last(progress_rx));
+
+    // Connects complete without waiting on the server's 50 ms
+    let result = result.expect("ping failed");
+    assert!(result.avg_ms < 50.0, "measured {:.1} ms", result.avg_ms);
+    assert_eq!(result.loss_percent, 0.0);
+    assert_eq!(stub.download_connections(), 0);
+}
+
+#[tokio::test]
+async fn speed_test_runs_every_phase() {
+    let stub = StubServer::start(Link::default()).await;
+    let mut handle = SpeedTest::new(settings(&stub)).spawn();
+
+    let mut completed = Vec::new();
+    while let Some(update) = handle.updates.recv().await {
+        match update {
+            TestUpdate::PingComplete { .. }
+            | TestUpdate::DownloadComplete { .. }
+            | TestUpdate::UploadComplete { .. } => completed.push(update.phase()),
+            TestUpdate::Failed { error, .. } => panic!("test failed: {}", error),
+            _ => {}
+        }
+    }
+
+    let result = handle.join().await.expect("speed test failed");
+    assert_eq!(completed, [TestPhase::Ping, TestPhase::Download, TestPhase::Upload]);
+    assert!(result.download_mbps > 0.0);
+    assert!(result.upload_mbps > 0.0);
+    assert_eq!(stub.downloaded_bytes(), 2 * MB);
+    assert_eq!(stub.uploaded_bytes(), MB);
+}
+
+#[tokio::test]
+async fn demo_test_replays_every_phase() {
+    let settings = Settings {
+        bidirectional: true,
+        ..Settings::default()
+    };
+    let mock = MockProvider::new()
+        .with_link(100.0, 20.0, 10.0)
+        .with_pace(Duration::ZERO);
+
+    let mut results = Vec::new();
+    for _ in 0..2 {
+        let mut handle = SpeedTest::new(settings.clone()).with_mock(mock.clone()).spawn();
+        let mut completed = Vec::new();
+        while let Some(update) = handle.updates.recv().await {
+            match update {
+                TestUpdate::PingComplete { .. }
+                | TestUpdate::DownloadComplete { .. }
+                | TestUpdate::UploadComplete { .. }
+                | TestUpdate::BidirectionalComplete(_) => completed.push(update.phase()),
+                TestUpdate::Failed { error, .. } => panic!("test failed: {}", error),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            completed,
+            [TestPhase::Ping, TestPhase::Download, TestPhase::Upload, TestPhase::Bidirectional]
+        );
+        results.push(handle.join().await.expect("demo test failed"));
+    }
+
+    let result = &results[0];
+    assert!((80.0..120.0).contains(&result.download_mbps), "{}", result.download_mbps);
+    assert!((15.0..25.0).contains(&result.upload_mbps), "{}", result.upload_mbps);
+    assert!((8.0..12.0).contains(&result.ping_ms), "{}", result.ping_ms);
+    assert!(result.bidirectional.is_some());
+    // The same seed replays the same test
+    assert_eq!(results[1].download_mbps, result.download_mbps);
+    assert_eq!(results[1].upload_mbps, result.upload_mbps);
+    assert_eq!(results[1].ping_ms, result.ping_ms);
+}
+
+#[tokio::test]
+async fn cancelling_stops_the_download() {
+    let stub = StubServer::start(Link::default().with_bandwidth(8.0)).await;
+    let settings = Settings {
+        download_size_mb: 100,
+        ..settings(&stub)
+    };
+    let mut handle = SpeedTest::new(settings).spawn();
+
+    let mut failed_phase = None;
+    while let Some(update) = handle.updates.recv().await {
+        match update {
+            TestUpdate::DownloadProgress(_) => handle.cancel(),
+            TestUpdate::Failed { phase, .. } => failed_phase = Some(phase),
+            _ => {}
+        }
+    }
+
+    let err = handle.join().await.expect_err("cancelled test succeeded");
+    assert!(format!("{:#}", err).contains("cancelled"));
+    assert_eq!(failed_phase, Some(TestPhase::Download));
+    assert!(stub.downloaded_bytes() < 100 * MB);
+}